@@ -0,0 +1,204 @@
+use bytes::Bytes;
+use conv::ValueFrom;
+use errors::*;
+use protocol::{ConnectionId, Readable, Writable};
+use rand::{OsRng, Rng};
+use ring::aead::{self, OpeningKey, SealingKey};
+use std::io::Cursor;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const NONCE_LEN: usize = 12;
+
+/// What a server remembers about a client in the address validation tokens it hands out.
+///
+/// Tokens are sealed with a key only the server knows and bound to the client's IP address, so a
+/// client presenting one has shown it can receive packets at that address.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct AddressValidationToken {
+    /// The destination connection id of the client's first Initial packet, only set in the
+    /// tokens of Retry packets.
+    pub original_destination_connection_id: Option<ConnectionId>,
+    pub expires_at: SystemTime,
+}
+
+impl AddressValidationToken {
+    /// Creates a token which expires `lifetime` from now.
+    pub fn new(
+        original_destination_connection_id: Option<ConnectionId>,
+        lifetime: Duration,
+    ) -> Self {
+        Self {
+            original_destination_connection_id,
+            expires_at: SystemTime::now() + lifetime,
+        }
+    }
+
+    /// Generates a random key to seal tokens with.
+    pub fn generate_key() -> Result<[u8; 32]> {
+        let mut rng =
+            OsRng::new().chain_err(|| ErrorKind::FailedToCreateCryptographicRandomNumberGenerator)?;
+
+        let mut key = [0; 32];
+        rng.fill_bytes(&mut key);
+
+        Ok(key)
+    }
+
+    /// Seals the token for the client at `client_address`, the result is a random nonce followed
+    /// by the sealed token.
+    pub fn seal(&self, key: &[u8; 32], client_address: SocketAddr) -> Result<Bytes> {
+        let mut rng =
+            OsRng::new().chain_err(|| ErrorKind::FailedToCreateCryptographicRandomNumberGenerator)?;
+
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let expires_at = self.expires_at
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs())
+            .unwrap_or(0);
+
+        let mut in_out = nonce.to_vec();
+        expires_at.write(&mut in_out)?;
+        match self.original_destination_connection_id {
+            Some(connection_id) => {
+                u8::value_from(connection_id.len())
+                    .chain_err(|| ErrorKind::FailedToWriteConnectionId(connection_id))?
+                    .write(&mut in_out)?;
+                connection_id.write(&mut in_out)?;
+            }
+            None => 0u8.write(&mut in_out)?,
+        }
+
+        let sealing_key = SealingKey::new(&aead::AES_256_GCM, &key[..])
+            .chain_err(|| ErrorKind::FailedToBuildCryptoState)?;
+        let tag_len = sealing_key.algorithm().tag_len();
+
+        let new_in_out_len = in_out.len() + tag_len;
+        in_out.resize(new_in_out_len, 0);
+
+        let out_len = aead::seal_in_place(
+            &sealing_key,
+            &nonce[..],
+            &address_bytes(client_address),
+            &mut in_out[NONCE_LEN..],
+            tag_len,
+        ).chain_err(|| ErrorKind::FailedToSealData)?;
+
+        in_out.truncate(NONCE_LEN + out_len);
+
+        Ok(in_out.into())
+    }
+
+    /// Opens a `token` sealed for the client at `client_address`.
+    ///
+    /// # Errors
+    /// `InvalidAddressValidationToken` if the token was not sealed with `key` for this client or
+    /// it has expired by `now`.
+    pub fn open(
+        token: &[u8],
+        key: &[u8; 32],
+        client_address: SocketAddr,
+        now: SystemTime,
+    ) -> Result<Self> {
+        if token.len() < NONCE_LEN {
+            bail!(ErrorKind::InvalidAddressValidationToken);
+        }
+
+        let (nonce, sealed) = token.split_at(NONCE_LEN);
+        let mut sealed = sealed.to_vec();
+
+        let opening_key = OpeningKey::new(&aead::AES_256_GCM, &key[..])
+            .chain_err(|| ErrorKind::FailedToBuildCryptoState)?;
+
+        let plaintext = aead::open_in_place(
+            &opening_key,
+            nonce,
+            &address_bytes(client_address),
+            0,
+            &mut sealed,
+        ).chain_err(|| ErrorKind::InvalidAddressValidationToken)?;
+
+        let mut reader = Cursor::new(&plaintext[..]);
+
+        let expires_at = UNIX_EPOCH + Duration::from_secs(u64::read(&mut reader)?);
+        if expires_at < now {
+            bail!(ErrorKind::InvalidAddressValidationToken);
+        }
+
+        let original_destination_connection_id = match u8::read(&mut reader)? {
+            0 => None,
+            len => Some(ConnectionId::read_with_len(&mut reader, len.into())?),
+        };
+
+        // anything after the connection id means the token was not sealed by us
+        if reader.position() != plaintext.len() as u64 {
+            bail!(ErrorKind::InvalidAddressValidationToken);
+        }
+
+        Ok(Self {
+            original_destination_connection_id,
+            expires_at,
+        })
+    }
+}
+
+/// Tokens are bound to the client's IP address, but not its port which NATs may change.
+fn address_bytes(address: SocketAddr) -> Vec<u8> {
+    match address.ip() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AddressValidationToken;
+    use protocol::ConnectionId;
+    use std::net::SocketAddr;
+    use std::time::{Duration, SystemTime};
+
+    fn address() -> SocketAddr {
+        "10.0.0.1:443".parse().unwrap()
+    }
+
+    #[test]
+    fn open_sealed_token() {
+        let key = AddressValidationToken::generate_key().unwrap();
+        let token = AddressValidationToken::new(
+            Some(ConnectionId::generate().unwrap()),
+            Duration::from_secs(10),
+        );
+
+        let sealed = token.seal(&key, address()).unwrap();
+        let opened =
+            AddressValidationToken::open(&sealed, &key, address(), SystemTime::now()).unwrap();
+
+        assert_eq!(
+            opened.original_destination_connection_id,
+            token.original_destination_connection_id
+        );
+    }
+
+    #[test]
+    fn open_token_sealed_for_other_address_fails() {
+        let key = AddressValidationToken::generate_key().unwrap();
+        let token = AddressValidationToken::new(None, Duration::from_secs(10));
+
+        let sealed = token.seal(&key, "10.0.0.2:443".parse().unwrap()).unwrap();
+
+        assert!(AddressValidationToken::open(&sealed, &key, address(), SystemTime::now()).is_err());
+    }
+
+    #[test]
+    fn open_expired_token_fails() {
+        let key = AddressValidationToken::generate_key().unwrap();
+        let token = AddressValidationToken::new(None, Duration::from_secs(10));
+
+        let sealed = token.seal(&key, address()).unwrap();
+        let later = SystemTime::now() + Duration::from_secs(60);
+
+        assert!(AddressValidationToken::open(&sealed, &key, address(), later).is_err());
+    }
+}
@@ -0,0 +1,14 @@
+use std::fmt::Debug;
+
+/// Picks the application protocol a server speaks with a client.
+///
+/// Without a selector a server picks the first of its `ServerConfiguration::alpn_protocols`
+/// which the client offered.
+pub trait AlpnSelector: Debug + Send + Sync {
+    /// Picks one of the `offered` protocols, which are in the client's order of preference,
+    /// `supported` are the server's configured protocols.
+    ///
+    /// # Returns
+    /// `None` if none of the `offered` protocols are acceptable, the handshake then fails.
+    fn select(&self, offered: &[String], supported: &[String]) -> Option<String>;
+}
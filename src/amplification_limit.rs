@@ -0,0 +1,65 @@
+/// The bytes a server sends to a client whose address it has not validated are limited to this
+/// many times the bytes it received from the client.
+const AMPLIFICATION_FACTOR: usize = 3;
+
+/// A packet smaller than this is not sent, it would have no room for any frames.
+const MIN_PACKET_SIZE: usize = 128;
+
+/// Counts the bytes a server has sent to and received from a client whose address is not yet
+/// validated, so its packets can not be used to flood a spoofed address.
+#[derive(Debug, Default)]
+pub struct AmplificationLimit {
+    received: usize,
+    sent: usize,
+}
+
+impl AmplificationLimit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_packet_received(&mut self, size: usize) {
+        self.received = self.received.saturating_add(size);
+    }
+
+    pub fn on_packet_sent(&mut self, size: usize) {
+        self.sent = self.sent.saturating_add(size);
+    }
+
+    /// The size of the next packet, no larger than `max_packet_size`.
+    ///
+    /// # Returns
+    /// `None` if nothing more may be sent until more is received.
+    pub fn max_packet_size(&self, max_packet_size: usize) -> Option<usize> {
+        let allowed = self.received.saturating_mul(AMPLIFICATION_FACTOR);
+        let remaining = allowed.saturating_sub(self.sent);
+
+        if remaining < MIN_PACKET_SIZE {
+            return None;
+        }
+
+        Some(remaining.min(max_packet_size))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AmplificationLimit;
+
+    #[test]
+    fn sends_at_most_three_times_the_bytes_received() {
+        let mut amplification_limit = AmplificationLimit::new();
+
+        assert_eq!(amplification_limit.max_packet_size(1200), None);
+
+        amplification_limit.on_packet_received(1000);
+        assert_eq!(amplification_limit.max_packet_size(1200), Some(1200));
+
+        amplification_limit.on_packet_sent(1200);
+        amplification_limit.on_packet_sent(1200);
+        assert_eq!(amplification_limit.max_packet_size(1200), Some(600));
+
+        amplification_limit.on_packet_sent(600);
+        assert_eq!(amplification_limit.max_packet_size(1200), None);
+    }
+}
@@ -1,12 +1,13 @@
 use errors::*;
+use futures::future::{self, Either};
 use futures::{Future, IntoFuture};
 use protocol::{ConnectionId, ServerId, StreamType};
 use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::sync::Arc;
 use tokio_core::net::UdpSocket;
-use tokio_core::reactor::Handle;
-use {ClientConfiguration, ClientPerspective, Connection, DataStream, NewClient, NewDataStreams,
-     SharedConnection};
+use tokio_core::reactor::{Handle, Remote};
+use {ClientConfiguration, ClientPerspective, Connection, DataStream, EarlyData, NewClient,
+     NewDataStreams, RttEstimator, SharedConnection};
 
 #[derive(Debug)]
 pub struct Client {
@@ -35,11 +36,14 @@ fn new_connection(
     server_id: ServerId,
     udp_socket: UdpSocket,
     client_configuration: ClientConfiguration,
+    remote: Remote,
 ) -> Result<Connection<ClientPerspective>> {
-    let local_connection_id = ConnectionId::generate()?;
-    let remote_connection_id = ConnectionId::generate()?;
+    let connection_id_len = client_configuration.connection_id_len;
+    let local_connection_id = ConnectionId::generate_with_len(connection_id_len)?;
+    let remote_connection_id = ConnectionId::generate_with_len(connection_id_len)?;
 
-    let client_perspective = ClientPerspective::new(udp_socket, client_configuration, server_id);
+    let client_perspective =
+        ClientPerspective::new(udp_socket, client_configuration, server_id, remote)?;
 
     let connection = Connection::new(
         local_connection_id,
@@ -51,6 +55,38 @@ fn new_connection(
     Ok(connection)
 }
 
+/// Waits for the `handshake` of `connection` to complete. If the server does not support our
+/// version, the connection is started again in the version picked from the server's Version
+/// Negotiation packet and `on_restart` is called with the new connection.
+fn complete_handshake<F>(
+    connection: Arc<Connection<ClientPerspective>>,
+    handshake: Box<Future<Item = (), Error = Error> + Send>,
+    mut on_restart: F,
+) -> Box<Future<Item = Arc<Connection<ClientPerspective>>, Error = Error> + Send>
+where
+    F: FnMut(&Arc<Connection<ClientPerspective>>) + Send + 'static,
+{
+    let connection_for_restart = connection.clone();
+
+    let future = handshake
+        .map(move |_| connection)
+        .or_else(move |error| {
+            match connection_for_restart.restart_in_negotiated_version() {
+                Ok(Some(restarted_connection)) => {
+                    let restarted_connection = Arc::new(restarted_connection);
+                    let handshake = restarted_connection.clone().handshake();
+                    on_restart(&restarted_connection);
+
+                    Either::A(handshake.map(move |_| restarted_connection))
+                }
+                Ok(None) => Either::B(future::err(error)),
+                Err(restart_error) => Either::B(future::err(restart_error)),
+            }
+        });
+
+    Box::new(future)
+}
+
 impl Client {
     pub fn connect(
         server_address: SocketAddr,
@@ -58,17 +94,79 @@ impl Client {
         client_configuration: ClientConfiguration,
         handle: &Handle,
     ) -> NewClient {
+        let remote = handle.remote().clone();
+
         let future = bind_udp_socket(handle, server_address)
             .and_then(|udp_socket| {
-                new_connection(server_address, server_id, udp_socket, client_configuration)
+                new_connection(
+                    server_address,
+                    server_id,
+                    udp_socket,
+                    client_configuration,
+                    remote,
+                )
             })
             .into_future()
             .and_then(|connection| {
                 let connection = Arc::new(connection);
-                let connection_copy = connection.clone();
-                connection.handshake().map(|_| Client {
-                    connection: connection_copy,
-                })
+                let handshake = connection.clone().handshake();
+
+                complete_handshake(connection, handshake, |_| {})
+                    .map(|connection| Client { connection })
+            });
+
+        NewClient::new(Box::new(future))
+    }
+
+    /// Connects to the server like `connect`, calling `write_early_data` with the client before
+    /// the handshake completes so the streams it opens and the data it writes on them are sent
+    /// in 0-RTT packets.
+    ///
+    /// 0-RTT data can only be sent to a server this client has connected to before with the
+    /// same `ClientConfiguration::session_store`, and whose ticket from that connection allows it.
+    /// Otherwise, or if the server rejects it, the data is sent once the handshake completes.
+    /// `write_early_data` is called again if the server asks for another version, as the
+    /// connection is started over in that version.
+    ///
+    /// Whether the server accepted the data is reported by `early_data` once it has acknowledged
+    /// a packet.
+    pub fn connect_with_early_data<F>(
+        server_address: SocketAddr,
+        server_id: ServerId,
+        client_configuration: ClientConfiguration,
+        handle: &Handle,
+        mut write_early_data: F,
+    ) -> NewClient
+    where
+        F: FnMut(&Client) + Send + 'static,
+    {
+        let remote = handle.remote().clone();
+
+        let future = bind_udp_socket(handle, server_address)
+            .and_then(|udp_socket| {
+                new_connection(
+                    server_address,
+                    server_id,
+                    udp_socket,
+                    client_configuration,
+                    remote,
+                )
+            })
+            .into_future()
+            .and_then(move |connection| {
+                let connection = Arc::new(connection);
+
+                // starting the handshake installs the 0-RTT keys if there are any
+                let handshake = connection.clone().handshake();
+                write_early_data(&Client {
+                    connection: connection.clone(),
+                });
+
+                complete_handshake(connection, handshake, move |restarted_connection| {
+                    write_early_data(&Client {
+                        connection: restarted_connection.clone(),
+                    })
+                }).map(|connection| Client { connection })
             });
 
         NewClient::new(Box::new(future))
@@ -91,4 +189,20 @@ impl Client {
     pub fn incoming_streams(&self) -> NewDataStreams<ClientPerspective> {
         NewDataStreams::new(self.connection.clone())
     }
+
+    /// The application protocol agreed with the server, `None` if
+    /// `ClientConfiguration::alpn_protocols` is empty.
+    pub fn application_protocol(&self) -> Option<String> {
+        self.connection.application_protocol()
+    }
+
+    /// Whether the server accepted the data sent before the handshake completed.
+    pub fn early_data(&self) -> EarlyData {
+        self.connection.early_data()
+    }
+
+    /// The current estimate of the round trip time on this connection.
+    pub fn rtt_estimate(&self) -> RttEstimator {
+        self.connection.rtt_estimate()
+    }
 }
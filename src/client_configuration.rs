@@ -1,18 +1,59 @@
 use debugit::DebugIt;
+use packets::AckManager;
+use protocol::{ConnectionId, Version};
+use recovery::DEFAULT_BURST_DATAGRAMS;
 use rustls::ClientConfig as TlsConfig;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::sync::Arc;
-use ConnectionTerminationMode;
+use std::time::Duration;
+use {CongestionControl, ConnectionTerminationMode, InMemorySessionStore, KeyUpdate,
+     SessionStore};
 
 lazy_static! {
     static ref DEFAULT_TLS_CONFIG: Arc<TlsConfig> = Arc::new(TlsConfig::new());
+    static ref DEFAULT_SESSION_STORE: Arc<SessionStore> = Arc::new(InMemorySessionStore::new());
 }
 
+#[derive(Clone)]
 pub struct ClientConfiguration {
     pub connection_termination_mode: ConnectionTerminationMode,
     pub tls_config: Arc<TlsConfig>,
     pub max_incoming_data_per_stream: u32,
     pub max_incoming_data: u32,
+    /// The QUIC version used to connect to the server.
+    pub version: Version,
+    /// The versions the server may move the connection to from `version` without another
+    /// round trip (RFC 9368), those which do not share the wire format of `version` are
+    /// ignored.
+    pub compatible_versions: Vec<Version>,
+    /// The length of the connection ids generated for new connections, this is limited by
+    /// `ConnectionId::MAX_LEN`.
+    pub connection_id_len: usize,
+    /// The exponent applied to the ACK delay of the ACK frames we send.
+    pub ack_delay_exponent: u8,
+    /// The longest we wait before acknowledging a packet.
+    pub max_ack_delay: Duration,
+    /// The congestion controller each connection limits its sending with.
+    pub congestion_control: CongestionControl,
+    /// Whether packets are spread out over the round trip rather than sent in bursts, turning
+    /// this off suits benchmarks over loopback.
+    pub pacing_enabled: bool,
+    /// The number of datagrams which may be sent back to back while pacing.
+    pub pacing_burst_datagrams: usize,
+    /// The 1-RTT keys are updated after sealing this many packets with them.
+    pub key_update_interval_packets: u64,
+    /// The 1-RTT keys are updated after sealing this many bytes with them.
+    pub key_update_interval_bytes: u64,
+    /// Remembers the session tickets and transport parameters of the servers connected to, so
+    /// later connections to them resume the session and `Client::connect_with_early_data` can
+    /// send 0-RTT data. This persists sessions in place of `tls_config`'s own persistence,
+    /// `None` performs a full handshake every time.
+    ///
+    /// The default is a bounded `InMemorySessionStore` shared by every default configuration.
+    pub session_store: Option<Arc<SessionStore>>,
+    /// The application protocols offered to the server, in order of preference. The handshake
+    /// fails if the server speaks none of them, when empty no protocol is negotiated.
+    pub alpn_protocols: Vec<String>,
 }
 
 impl Debug for ClientConfiguration {
@@ -28,6 +69,21 @@ impl Debug for ClientConfiguration {
                 &self.max_incoming_data_per_stream,
             )
             .field("max_incoming_data", &self.max_incoming_data)
+            .field("version", &self.version)
+            .field("compatible_versions", &self.compatible_versions)
+            .field("connection_id_len", &self.connection_id_len)
+            .field("ack_delay_exponent", &self.ack_delay_exponent)
+            .field("max_ack_delay", &self.max_ack_delay)
+            .field("congestion_control", &self.congestion_control)
+            .field("pacing_enabled", &self.pacing_enabled)
+            .field("pacing_burst_datagrams", &self.pacing_burst_datagrams)
+            .field(
+                "key_update_interval_packets",
+                &self.key_update_interval_packets,
+            )
+            .field("key_update_interval_bytes", &self.key_update_interval_bytes)
+            .field("session_store", &self.session_store)
+            .field("alpn_protocols", &self.alpn_protocols)
             .finish()
     }
 }
@@ -45,6 +101,18 @@ impl Default for ClientConfiguration {
             tls_config: DEFAULT_TLS_CONFIG.clone(),
             max_incoming_data_per_stream: 8192,
             max_incoming_data: 65536,
+            version: Version::V1,
+            compatible_versions: vec![Version::V2],
+            connection_id_len: ConnectionId::DEFAULT_LEN,
+            ack_delay_exponent: AckManager::DEFAULT_ACK_DELAY_EXPONENT,
+            max_ack_delay: Duration::from_millis(AckManager::DEFAULT_MAX_ACK_DELAY_MILLIS),
+            congestion_control: CongestionControl::default(),
+            pacing_enabled: true,
+            pacing_burst_datagrams: DEFAULT_BURST_DATAGRAMS,
+            key_update_interval_packets: KeyUpdate::DEFAULT_INTERVAL_PACKETS,
+            key_update_interval_bytes: KeyUpdate::DEFAULT_INTERVAL_BYTES,
+            session_store: Some(DEFAULT_SESSION_STORE.clone()),
+            alpn_protocols: Vec::new(),
         }
     }
 }
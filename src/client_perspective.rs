@@ -1,28 +1,47 @@
+use bytes::Bytes;
+use crypto::{HeaderProtectionKeys, StatelessResetTokens};
 use debugit::DebugIt;
 use errors::*;
 use futures::sink::Sink;
-use futures::{Async, Future, IntoFuture, Poll, Stream};
+use futures::{Async, Poll, Stream};
 use lz_shared_udp::{SharedUdpFramed, SharedUdpSocket};
 use packets::{IncomingPacket, OutgoingPacket, PacketCodec};
 use protocol::{ClientHelloMessageParameters, ClientSpecificTransportParameters, ConnectionId,
-               EncryptedExtensionsMessageParameters, Role, ServerId, TransportParameters, Version,
-               Writable};
-use rustls::quic::ClientQuicExt;
-use rustls::ClientSession;
+               EncryptedExtensionsMessageParameters, Role, ServerId,
+               ServerSpecificTransportParameters, StatelessResetToken, TransportParameters,
+               Version, VersionInformation, Writable};
+use rustls::quic::{ClientQuicExt, QuicExt};
+use rustls::{ClientConfig as TlsConfig, ClientSession, Session};
 use smallvec::SmallVec;
 use std::net::SocketAddr;
 use std::sync::{Arc, RwLock};
+use std::time::Duration;
 use tokio_core::net::UdpSocket;
-use tokio_rustls::{self, TlsStream};
+use tokio_core::reactor::Remote;
 use webpki::DNSNameRef;
-use {AddressConnectionIds, ClientConfiguration, ConnectionMap, DataStream, Perspective, StreamMap};
+use {AddressConnectionIds, ClientConfiguration, ClientHello, CongestionControl, Connection,
+     ConnectionMap, Perspective, ServerSessionStore, StreamMap};
 
 #[derive(Debug)]
 pub struct ClientPerspective {
+    /// Kept so the connection can be started again on the same socket in another version.
+    udp_socket: Arc<UdpSocket>,
     packets: DebugIt<SharedUdpFramed<Arc<UdpSocket>, PacketCodec>>,
+    header_protection_keys: Arc<HeaderProtectionKeys>,
+    stateless_reset_tokens: Arc<StatelessResetTokens>,
     server_id: Arc<ServerId>,
     client_configuration: Arc<ClientConfiguration>,
+    /// The version we first tried to connect in, before any version negotiation.
+    initial_version: Version,
+    /// The configured TLS config, which persists sessions in the session store if there is one
+    /// and offers the configured application protocols.
+    tls_config: DebugIt<Arc<TlsConfig>>,
+    /// The server's session in the configured session store.
+    session_store: Option<Arc<ServerSessionStore>>,
+    /// The token the server gave us on an earlier connection, empty if there is none.
+    address_validation_token: Bytes,
     connection_map: RwLock<ConnectionMap>,
+    remote: Remote,
 }
 
 impl ClientPerspective {
@@ -30,18 +49,107 @@ impl ClientPerspective {
         udp_socket: UdpSocket,
         client_configuration: ClientConfiguration,
         server_id: ServerId,
-    ) -> Self {
-        Self {
-            packets: DebugIt(Arc::new(udp_socket).framed(PacketCodec::default())),
+        remote: Remote,
+    ) -> Result<Self> {
+        let initial_version = client_configuration.version;
+
+        Self::with_initial_version(
+            Arc::new(udp_socket),
+            client_configuration,
+            initial_version,
+            server_id,
+            remote,
+        )
+    }
+
+    /// Creates the perspective of a connection which replaces this one in `version`, after the
+    /// server answered with a Version Negotiation packet.
+    pub(crate) fn restart_in_version(&self, version: Version) -> Result<Self> {
+        let client_configuration = ClientConfiguration {
+            version,
+            ..(*self.client_configuration).clone()
+        };
+
+        Self::with_initial_version(
+            self.udp_socket.clone(),
+            client_configuration,
+            self.initial_version,
+            (*self.server_id).clone(),
+            self.remote.clone(),
+        )
+    }
+
+    fn with_initial_version(
+        udp_socket: Arc<UdpSocket>,
+        client_configuration: ClientConfiguration,
+        initial_version: Version,
+        server_id: ServerId,
+        remote: Remote,
+    ) -> Result<Self> {
+        let version = client_configuration.version;
+        let wire_format = version
+            .wire_format()
+            .ok_or_else(|| ErrorKind::UnsupportedVersion(version))?;
+
+        let header_protection_keys = Arc::new(HeaderProtectionKeys::new());
+        let stateless_reset_tokens = Arc::new(StatelessResetTokens::new());
+
+        // the server addresses us with the connection id we generated for ourselves
+        let packet_codec = PacketCodec::new(
+            Role::Client,
+            wire_format,
+            client_configuration.connection_id_len,
+            header_protection_keys.clone(),
+            stateless_reset_tokens.clone(),
+        );
+
+        let session_store = client_configuration
+            .session_store
+            .as_ref()
+            .map(|session_store| {
+                Arc::new(ServerSessionStore::new(
+                    session_store.clone(),
+                    server_id.clone(),
+                ))
+            });
+
+        // tokens are only used once, so it is gone even if this connection fails
+        let address_validation_token = session_store
+            .as_ref()
+            .and_then(|session_store| session_store.take_address_validation_token())
+            .map(Bytes::from)
+            .unwrap_or_else(Bytes::new);
+
+        // a resumed session sends 0-RTT data whenever its ticket allows it
+        let mut tls_config = (*client_configuration.tls_config).clone();
+        if let Some(session_store) = &session_store {
+            tls_config.set_persistence(session_store.clone());
+            tls_config.enable_early_data = true;
+        }
+        if !client_configuration.alpn_protocols.is_empty() {
+            tls_config.set_protocols(&client_configuration.alpn_protocols);
+        }
+
+        Ok(Self {
+            packets: DebugIt(udp_socket.clone().framed(packet_codec)),
+            udp_socket,
+            header_protection_keys,
+            stateless_reset_tokens,
             server_id: Arc::new(server_id),
             client_configuration: Arc::new(client_configuration),
+            initial_version,
+            tls_config: DebugIt(Arc::new(tls_config)),
+            session_store,
+            address_validation_token,
             connection_map: RwLock::new(ConnectionMap::with_capacity(1)),
-        }
+            remote,
+        })
     }
 
-    fn local_address(&self) -> SocketAddr {
-        // TODO LH Add methods to SharedUdpFramed to get the Arc<UdpSocket>
-        unimplemented!()
+    fn local_address(&self) -> Result<SocketAddr> {
+        self.udp_socket
+            .local_addr()
+            .chain_err(|| ErrorKind::FailedToGetLocalAddress)
     }
 
     fn get_connection_id_for_incoming_packet(
@@ -57,9 +165,9 @@ impl ClientPerspective {
                 .read()
                 .expect("failed to lock connection_map");
 
-            let local_address = self.local_address();
-
-            connection_map.get_connection_id(local_address, incoming_packet.source_address)
+            self.local_address().ok().and_then(|local_address| {
+                connection_map.get_connection_id(local_address, incoming_packet.source_address)
+            })
         }
     }
 
@@ -82,9 +190,19 @@ impl ClientPerspective {
     fn build_transport_parameters(
         &self,
     ) -> TransportParameters<ClientHelloMessageParameters, ClientSpecificTransportParameters> {
+        let version = self.client_configuration.version;
+
+        let mut available_versions = vec![version];
+        available_versions.extend(
+            self.client_configuration
+                .compatible_versions
+                .iter()
+                .filter(|&&compatible_version| compatible_version != version),
+        );
+
         TransportParameters {
             message_parameters: ClientHelloMessageParameters {
-                initial_version: Version::DRAFT_IETF_08,
+                initial_version: self.initial_version,
             },
             initial_max_stream_data: self.client_configuration.max_incoming_data_per_stream,
             initial_max_data: self.client_configuration.max_incoming_data,
@@ -92,71 +210,115 @@ impl ClientPerspective {
             initial_max_bidi_streams: None,
             initial_max_uni_streams: None,
             max_packet_size: Some(65527),
-            ack_delay_exponent: None,
+            ack_delay_exponent: Some(self.client_configuration.ack_delay_exponent),
+            max_ack_delay: Some(self.client_configuration.max_ack_delay),
             disable_migration: false,
+            version_information: Some(VersionInformation {
+                chosen_version: version,
+                available_versions,
+            }),
             role_specific_transport_parameters: ClientSpecificTransportParameters,
         }
     }
+
+    /// Starts sending 0-RTT data on `connection` if `tls_session` resumed a session with a
+    /// ticket which allows it.
+    fn try_start_early_data(&self, connection: &Connection<Self>, tls_session: &ClientSession) {
+        let early_secret = match tls_session.get_early_secret() {
+            Some(early_secret) => early_secret,
+            None => return,
+        };
+
+        let session_store = match &self.session_store {
+            Some(session_store) => session_store,
+            None => return,
+        };
+
+        let (supported_cipher_suite, remembered_transport_parameters) = match (
+            session_store.cipher_suite(),
+            session_store.transport_parameters(),
+        ) {
+            (Some(supported_cipher_suite), Some(remembered_transport_parameters)) => {
+                (supported_cipher_suite, remembered_transport_parameters)
+            }
+            _ => return,
+        };
+
+        // 0-RTT is only an optimisation, the data is sent once the handshake completes instead
+        if let Err(error) = connection.start_early_data(
+            early_secret,
+            supported_cipher_suite,
+            &remembered_transport_parameters,
+        ) {
+            debug!(
+                "connection {}: not sending 0-RTT data: {}",
+                connection.description(),
+                error
+            );
+        }
+    }
 }
 
 impl Perspective for ClientPerspective {
     type TlsSession = ClientSession;
-    type HandshakeFuture =
-        Box<Future<Item = TlsStream<DataStream<Self>, Self::TlsSession>, Error = Error> + Send>;
     type IncomingTransportMessageParameters = EncryptedExtensionsMessageParameters;
-    type RoleSpecificTransportParameters = ClientSpecificTransportParameters;
+    type IncomingRoleSpecificTransportParameters = ServerSpecificTransportParameters;
 
-    fn handshake(&self, crypto_stream: DataStream<Self>) -> Self::HandshakeFuture {
-        let connection_description = crypto_stream.connection().description();
+    fn tls_session(&self, _client_hello: Option<&ClientHello>) -> Result<Self::TlsSession> {
         trace!(
-            "connection {}: performing TLS handshake from client to server {:?}",
-            connection_description,
+            "performing TLS handshake from client to server {:?}",
             self.server_id
         );
 
         let host = self.server_id.host();
-        let tls_config = self.client_configuration.tls_config.clone();
+        let dns_name = DNSNameRef::try_from_ascii_str(host)
+            .map_err(|_| Error::from_kind(ErrorKind::HostIsNotAValidDomainName(host.to_owned())))?;
 
-        let server_id_for_error = self.server_id.clone();
-        let server_id_for_success = self.server_id.clone();
+        let quic_transport_parameters = self.build_transport_parameters().bytes_vec()?;
 
-        let when_connected = DNSNameRef::try_from_ascii_str(host)
-            .map_err(|_| Error::from_kind(ErrorKind::HostIsNotAValidDomainName(host.to_owned())))
-            .and_then(|dns_name| {
-                let quic_transport_parameters = self.build_transport_parameters();
+        Ok(ClientSession::new_quic(
+            &self.tls_config.0,
+            dns_name,
+            quic_transport_parameters,
+        ))
+    }
 
-                quic_transport_parameters
-                    .bytes_vec()
-                    .map(|quic_transport_parameters| {
-                        ClientSession::new_quic(&tls_config, dns_name, quic_transport_parameters)
-                    })
-            })
-            .map(|client_session| {
-                tokio_rustls::connect_async_with_session(crypto_stream, client_session).chain_err(
-                    move || {
-                        ErrorKind::FailedToPerformTlsHandshakeWithServer(
-                            server_id_for_error.host().to_owned(),
-                        )
-                    },
-                )
-            })
-            .into_future()
-            .flatten()
-            .and_then(move |tls_stream| {
-                info!(
-                    "connection {}: performed TLS handshake from client to server {:?}",
-                    connection_description, server_id_for_success
-                );
-
-                {
-                    let (stream, session) = tls_stream.get_ref();
-                    stream.connection().handle_negotiated_session(session)?;
-                }
+    fn on_handshake_started(&self, connection: &Connection<Self>, tls_session: &ClientSession) {
+        self.try_start_early_data(connection, tls_session);
+    }
 
-                Ok(tls_stream)
-            });
+    fn on_handshake_complete(
+        &self,
+        connection: &Connection<Self>,
+        tls_session: &ClientSession,
+    ) -> Result<()> {
+        info!(
+            "connection {}: performed TLS handshake from client to server {:?}",
+            connection.description(),
+            self.server_id
+        );
 
-        Box::new(when_connected)
+        connection.handle_negotiated_session(tls_session)?;
+        connection.check_connection_id_transport_parameters()?;
+        connection.check_version_transport_parameters()?;
+        connection.check_version_information()?;
+
+        // the next connection to the server may send 0-RTT data within these limits, with keys
+        // in the same cipher suite
+        if let Some(session_store) = &self.session_store {
+            if let Some(transport_parameters) = tls_session.get_quic_transport_parameters() {
+                session_store.remember_transport_parameters(transport_parameters.to_vec());
+            }
+            if let Some(supported_cipher_suite) = tls_session.get_negotiated_ciphersuite() {
+                session_store.remember_cipher_suite(supported_cipher_suite);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handshake_error(&self) -> ErrorKind {
+        ErrorKind::FailedToPerformTlsHandshakeWithServer(self.server_id.host().to_owned())
     }
 
     fn client_connection_id(
@@ -166,6 +328,31 @@ impl Perspective for ClientPerspective {
         local_connection_id
     }
 
+    fn version(&self) -> Version {
+        self.client_configuration.version
+    }
+
+    fn initial_version(&self) -> Version {
+        self.initial_version
+    }
+
+    fn compatible_versions(&self) -> &[Version] {
+        &self.client_configuration.compatible_versions
+    }
+
+    fn header_protection_keys(&self) -> Arc<HeaderProtectionKeys> {
+        self.header_protection_keys.clone()
+    }
+
+    fn stateless_reset_tokens(&self) -> Arc<StatelessResetTokens> {
+        self.stateless_reset_tokens.clone()
+    }
+
+    fn issue_connection_id(&self) -> Result<Option<(ConnectionId, StatelessResetToken)>> {
+        // packets reach a client by its socket, it never sends stateless resets
+        Ok(None)
+    }
+
     fn handshake_send_label() -> &'static str {
         "client hs"
     }
@@ -174,12 +361,28 @@ impl Perspective for ClientPerspective {
         "server hs"
     }
 
-    fn update_secret_send_label() -> &'static str {
-        "client 1rtt"
+    fn initial_send_label() -> &'static str {
+        "client in"
+    }
+
+    fn initial_receive_label() -> &'static str {
+        "server in"
+    }
+
+    fn update_secret_send_label(version: Version) -> &'static str {
+        match version {
+            Version::DRAFT_IETF_08 => "client 1rtt",
+            Version::V2 => "quicv2 ku",
+            _ => "quic ku",
+        }
     }
 
-    fn update_secret_receive_label() -> &'static str {
-        "server 1rtt"
+    fn update_secret_receive_label(version: Version) -> &'static str {
+        match version {
+            Version::DRAFT_IETF_08 => "server 1rtt",
+            Version::V2 => "quicv2 ku",
+            _ => "quic ku",
+        }
     }
 
     fn tls_exporter_send_label() -> &'static str {
@@ -249,4 +452,64 @@ impl Perspective for ClientPerspective {
     fn max_incoming_data(&self) -> u32 {
         self.client_configuration.max_incoming_data
     }
+
+    fn ack_delay_exponent(&self) -> u8 {
+        self.client_configuration.ack_delay_exponent
+    }
+
+    fn max_ack_delay(&self) -> Duration {
+        self.client_configuration.max_ack_delay
+    }
+
+    fn remote(&self) -> &Remote {
+        &self.remote
+    }
+
+    fn congestion_control(&self) -> &CongestionControl {
+        &self.client_configuration.congestion_control
+    }
+
+    fn pacing_enabled(&self) -> bool {
+        self.client_configuration.pacing_enabled
+    }
+
+    fn pacing_burst_datagrams(&self) -> usize {
+        self.client_configuration.pacing_burst_datagrams
+    }
+
+    fn key_update_interval_packets(&self) -> u64 {
+        self.client_configuration.key_update_interval_packets
+    }
+
+    fn key_update_interval_bytes(&self) -> u64 {
+        self.client_configuration.key_update_interval_bytes
+    }
+
+    fn max_early_data_size(&self) -> usize {
+        0
+    }
+
+    fn address_validated(&self) -> bool {
+        true
+    }
+
+    fn alpn_protocols(&self) -> &[String] {
+        &self.client_configuration.alpn_protocols
+    }
+
+    fn address_validation_token(&self) -> Bytes {
+        self.address_validation_token.clone()
+    }
+
+    fn new_token(&self) -> Option<Bytes> {
+        None
+    }
+
+    fn on_new_token(&self, token: Bytes) -> Result<()> {
+        if let Some(session_store) = &self.session_store {
+            session_store.remember_address_validation_token(token.to_vec());
+        }
+
+        Ok(())
+    }
 }
@@ -1,43 +1,100 @@
 use bytes::Bytes;
 use crypto::CryptoState;
+use debugit::DebugIt;
 use errors::*;
-use frames::StreamFrame;
+use frames::{AckFrame, ConnectionCloseFrame, Frame, MaxStreamDataFrame, NewConnectionIdFrame,
+             NewTokenFrame, PathResponseFrame, StreamFrame};
 use futures::{Async, Future, Poll};
-use packets::{LongHeader, LongHeaderPacketType, OutgoingPacket, PacketHeader, PacketNumber,
-              PartialPacketNumber};
-use protocol::{ConnectionId, EncryptionLevel, FlowControl, Readable, StreamId, StreamType,
-               TransportParameters, VarInt, Version};
-use rustls::Session;
-use std::collections::VecDeque;
+use packets::{remove_deferred_header_protection, AckManager, IncomingPacket, LongHeader,
+              LongHeaderPacketType, OutgoingPacket, Packet, PacketContent, PacketHeader,
+              PacketNumber, PacketNumberSpace, PacketPacker, PartialPacketNumber, RetryPacket,
+              ShortHeader, VersionNegotiationPacket};
+use protocol::{ConnectionId, EncryptionLevel, ErrorCode, FlowControl, Readable, Role,
+               RoleSpecificTransportParameters, StreamId, StreamOffset, StreamType,
+               TransportParameters, Version, WireFormat, Writable};
+use recovery::{is_persistent_congestion, CongestionController, Pacer, RttEstimator, SentPacket};
+use rustls::quic::{QuicExt, Secrets};
+use rustls::{Session, SupportedCipherSuite};
+use std::cmp;
+use std::collections::{HashSet, VecDeque};
+use std::mem;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
-use {DataStream, DequeueWriteResult, Perspective, StreamMap, StreamMapEntry, StreamState};
+use std::time::{Duration, Instant};
+use tokio_core::reactor::Timeout;
+use {AeadPair, AmplificationLimit, ClientHello, ClientHelloBuffer, ClientPerspective,
+     DequeueWriteResult, EarlyData, PacketNumberSpaces, Perspective, ReadKeyPhase, StreamMap,
+     StreamMapEntry, StreamState};
 
-#[derive(Debug)]
-struct AeadPair {
-    write: CryptoState,
-    read: CryptoState,
-}
+/// Packets are kept below this size until the path MTU has been discovered.
+const DEFAULT_MAX_PACKET_SIZE: usize = 1200;
 
-#[derive(Debug)]
-enum State {
-    Initializing,
-    Established { aead_protected: AeadPair },
-}
+/// The probe timeout stops doubling after this many consecutive probes.
+const MAX_PROBE_TIMEOUT_BACKOFF: u32 = 16;
 
 /// The connection exists so a single client-server connection may span multiple physical connections.
 #[derive(Debug)]
 pub struct Connection<P: Perspective> {
     local_connection_id: ConnectionId,
-    remote_connection_id: ConnectionId,
+    /// Changed by a client to the connection id the server picked in its Retry packet.
+    remote_connection_id: Mutex<ConnectionId>,
+    /// The remote connection id the connection was created with, a client checks the server's
+    /// Retry packet and transport parameters against it.
+    original_remote_connection_id: ConnectionId,
+    /// The Retry packet a client answered, its token is sent in every later Initial packet.
+    retry: Mutex<Option<RetryPacket>>,
+    /// The version a client picked from the server's Version Negotiation packet, the connection
+    /// fails and is started again in this version.
+    negotiated_version: Mutex<Option<Version>>,
     perspective: P,
+    /// Changed by a client when the server moves it to a compatible version, which shares the
+    /// wire format of the version the connection was created in.
+    version: Mutex<Version>,
+    wire_format: WireFormat,
     stream_map: Mutex<StreamMap>,
-    aead_clear: AeadPair,
-    state: Arc<Mutex<State>>,
+    packet_number_spaces: Arc<Mutex<PacketNumberSpaces>>,
     incoming_flow_control: Mutex<FlowControl>,
     outgoing_flow_control: Mutex<FlowControl>,
     pending_stream_frames: Mutex<VecDeque<StreamFrame>>,
+    pending_control_frames: Mutex<VecDeque<Frame>>,
+    unsent_packet: Mutex<Option<OutgoingPacket>>,
+    rtt_estimator: Mutex<RttEstimator>,
+    /// The number of probe timeouts since an ACK frame last acknowledged anything.
+    probe_timeout_count: Mutex<u32>,
+    /// Fires when packets are lost by the time threshold or a probe should be sent.
+    loss_detection_timer: Mutex<DebugIt<Option<Timeout>>>,
+    congestion_controller: Mutex<Box<CongestionController>>,
+    /// The number of probe packets which may still be sent when the congestion window is full.
+    pending_probe_packets: Mutex<usize>,
+    /// `None` when pacing is disabled.
+    pacer: Mutex<Option<Pacer>>,
+    /// Fires when the pacer next allows a packet to be sent.
+    pacing_timer: Mutex<DebugIt<Option<Timeout>>>,
+    /// Fires when a delayed ACK frame must be sent.
+    ack_timer: Mutex<DebugIt<Option<Timeout>>>,
+    new_incoming_streams: Mutex<VecDeque<(StreamId, Arc<Mutex<StreamState>>)>>,
+    max_packet_size: usize,
+    /// Limits what a server sends to a client until the client's address is validated, `None`
+    /// once it is.
+    amplification_limit: Mutex<Option<AmplificationLimit>>,
     remote_address: SocketAddr,
+    /// The ClientHello a server received, kept until the handshake completes to decide whether
+    /// the client's 0-RTT data is accepted. `Some(None)` if the start of the crypto data could
+    /// not be parsed as a ClientHello.
+    client_hello: Mutex<Option<Option<ClientHello>>>,
+    /// The crypto data a server received before the whole ClientHello has arrived.
+    client_hello_buffer: Mutex<ClientHelloBuffer>,
+    tls_handshake: Mutex<DebugIt<TlsHandshake<P::TlsSession>>>,
+    /// The application protocol agreed in the handshake.
+    application_protocol: Mutex<Option<String>>,
+    remote_transport_parameters: Mutex<
+        Option<
+            TransportParameters<
+                P::IncomingTransportMessageParameters,
+                P::IncomingRoleSpecificTransportParameters,
+            >,
+        >,
+    >,
 }
 
 impl<P: Perspective + 'static> Connection<P> {
@@ -47,196 +104,2627 @@ impl<P: Perspective + 'static> Connection<P> {
         perspective: P,
         remote_address: SocketAddr,
     ) -> Result<Self> {
-        let client_connection_id =
-            P::client_connection_id(local_connection_id, remote_connection_id);
+        let version = perspective.version();
+        let wire_format = version
+            .wire_format()
+            .ok_or_else(|| ErrorKind::UnsupportedVersion(version))?;
+
+        let initial_keys = match wire_format {
+            WireFormat::Draft08 => {
+                let client_connection_id =
+                    P::client_connection_id(local_connection_id, remote_connection_id);
+
+                AeadPair {
+                    write: CryptoState::for_handshake(
+                        client_connection_id,
+                        P::handshake_send_label(),
+                    )?,
+                    read: CryptoState::for_handshake(
+                        client_connection_id,
+                        P::handshake_receive_label(),
+                    )?,
+                }
+            }
+            WireFormat::V1 => {
+                // a client has yet to send its first Initial packet, and a server answers from
+                // the connection id the client sent it to
+                let initial_connection_id = match P::role() {
+                    Role::Client => remote_connection_id,
+                    Role::Server => local_connection_id,
+                };
+
+                initial_keys::<P>(initial_connection_id, version)?
+            }
+        };
+
+        let packet_number_spaces = PacketNumberSpaces::new(
+            initial_keys,
+            perspective.ack_delay_exponent(),
+            perspective.max_ack_delay(),
+        );
+
+        let incoming_flow_control =
+            FlowControl::with_initial_max(perspective.max_incoming_data().into());
+
+        let congestion_controller = perspective
+            .congestion_control()
+            .build(DEFAULT_MAX_PACKET_SIZE);
+
+        let pacer = if perspective.pacing_enabled() {
+            Some(Pacer::new(
+                DEFAULT_MAX_PACKET_SIZE,
+                perspective.pacing_burst_datagrams(),
+            ))
+        } else {
+            None
+        };
+
+        let amplification_limit = if perspective.address_validated() {
+            None
+        } else {
+            Some(AmplificationLimit::new())
+        };
+
+        let connection = Self {
+            local_connection_id,
+            remote_connection_id: Mutex::new(remote_connection_id),
+            original_remote_connection_id: remote_connection_id,
+            retry: Mutex::default(),
+            negotiated_version: Mutex::default(),
+            perspective,
+            version: Mutex::new(version),
+            wire_format,
+            stream_map: Mutex::new(P::create_stream_map()),
+            packet_number_spaces: Arc::new(Mutex::new(packet_number_spaces)),
+            incoming_flow_control: Mutex::new(incoming_flow_control),
+            outgoing_flow_control: Mutex::default(),
+            pending_stream_frames: Mutex::default(),
+            pending_control_frames: Mutex::default(),
+            unsent_packet: Mutex::default(),
+            rtt_estimator: Mutex::default(),
+            probe_timeout_count: Mutex::default(),
+            loss_detection_timer: Mutex::new(DebugIt(None)),
+            congestion_controller: Mutex::new(congestion_controller),
+            pending_probe_packets: Mutex::default(),
+            pacer: Mutex::new(pacer),
+            pacing_timer: Mutex::new(DebugIt(None)),
+            ack_timer: Mutex::new(DebugIt(None)),
+            new_incoming_streams: Mutex::default(),
+            max_packet_size: DEFAULT_MAX_PACKET_SIZE,
+            amplification_limit: Mutex::new(amplification_limit),
+            remote_address,
+            client_hello: Mutex::default(),
+            client_hello_buffer: Mutex::default(),
+            tls_handshake: Mutex::new(DebugIt(TlsHandshake {
+                session: None,
+                write_space: PacketNumberSpace::Initial,
+                complete: false,
+            })),
+            application_protocol: Mutex::default(),
+            remote_transport_parameters: Mutex::default(),
+        };
+
+        debug!("created new connection {}", connection.description());
+
+        Ok(connection)
+    }
+
+    pub fn description(&self) -> String {
+        format!(
+            "[{:?}] connection {:?}->{:?}",
+            P::role(),
+            self.local_connection_id,
+            self.remote_connection_id()
+        )
+    }
+
+    /// Drives the handshake until it completes.
+    pub fn poll_handshake(&self) -> Poll<(), Error> {
+        loop {
+            self.process_crypto_data()?;
+
+            if self.tls_handshake
+                .lock()
+                .expect("failed to lock tls_handshake")
+                .0
+                .complete
+            {
+                return Ok(().into());
+            }
+
+            self.poll_try_transmit()?;
+
+            try_ready!(self.poll_process_incoming_packets());
+        }
+    }
+
+    /// Hands the crypto data received so far to the TLS session and queues the crypto data it
+    /// writes in return, the keys of each packet number space are installed as the session hands
+    /// out their secrets.
+    ///
+    /// A server only starts its session once the ClientHello has arrived.
+    pub fn process_crypto_data(&self) -> Result<()> {
+        let mut tls_handshake = self.tls_handshake
+            .lock()
+            .expect("failed to lock tls_handshake");
+        let tls_handshake = &mut tls_handshake.0;
+
+        if tls_handshake.session.is_none() {
+            let client_hello = match P::role() {
+                Role::Client => None,
+                Role::Server => match &*self.client_hello
+                    .lock()
+                    .expect("failed to lock client_hello")
+                {
+                    Some(client_hello) => client_hello.clone(),
+                    None => return Ok(()),
+                },
+            };
+
+            let tls_session = match self.perspective.tls_session(client_hello.as_ref()) {
+                Ok(tls_session) => tls_session,
+                Err(error) => {
+                    if let ErrorKind::NoCommonApplicationProtocol(_) = *error.kind() {
+                        // the client is told rather than left waiting for a handshake
+                        self.close(
+                            ErrorCode::CryptoError(ErrorCode::NO_APPLICATION_PROTOCOL_ALERT),
+                            "no application protocol",
+                        )?;
+                    }
+                    return Err(error);
+                }
+            };
+
+            tls_handshake.session = Some(tls_session);
+
+            // a client writes its ClientHello straight away
+            self.write_crypto_data(tls_handshake)?;
+
+            if let Some(tls_session) = &tls_handshake.session {
+                self.perspective.on_handshake_started(self, tls_session);
+            }
+        }
+
+        loop {
+            if !tls_handshake.complete {
+                let handshaking = tls_handshake
+                    .session
+                    .as_ref()
+                    .map_or(true, Session::is_handshaking);
+
+                // anything the session writes from now on is sent in 1-RTT packets
+                if !handshaking {
+                    tls_handshake.complete = true;
+
+                    if let Some(tls_session) = &tls_handshake.session {
+                        self.complete_handshake(tls_session)?;
+                    }
+                }
+            }
+
+            self.write_crypto_data(tls_handshake)?;
+
+            if !self.read_crypto_data(tls_handshake)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Queues the crypto data the TLS session writes in the packet number space of its
+    /// encryption level, installing the keys of the next space whenever it hands out secrets.
+    fn write_crypto_data(&self, tls_handshake: &mut TlsHandshake<P::TlsSession>) -> Result<()> {
+        loop {
+            let mut data = Vec::new();
+            let secrets = match tls_handshake.session.as_mut() {
+                Some(tls_session) => tls_session.write_hs(&mut data),
+                None => return Ok(()),
+            };
+
+            if !data.is_empty() {
+                self.enqueue_crypto_data(tls_handshake.write_space, Bytes::from(data));
+            } else if secrets.is_none() {
+                return Ok(());
+            }
+
+            let secrets = match secrets {
+                Some(secrets) => secrets,
+                None => continue,
+            };
+
+            let packet_number_space = match tls_handshake.write_space {
+                PacketNumberSpace::Initial => PacketNumberSpace::Handshake,
+                _ => PacketNumberSpace::ApplicationData,
+            };
+            tls_handshake.write_space = packet_number_space;
+
+            // draft 08 protects the whole handshake with the keys derived from the connection id
+            if self.wire_format == WireFormat::V1 {
+                let keys = match &tls_handshake.session {
+                    Some(tls_session) => self.keys_from_secrets(tls_session, &secrets)?,
+                    None => unreachable!("only a session hands out secrets"),
+                };
+
+                self.install_keys(packet_number_space, keys)?;
+            }
+        }
+    }
+
+    /// Hands the TLS session the crypto data received in order in each packet number space.
+    ///
+    /// # Returns
+    /// Whether there was any.
+    fn read_crypto_data(&self, tls_handshake: &mut TlsHandshake<P::TlsSession>) -> Result<bool> {
+        // a server has not answered the ClientHello until it writes in the Handshake space
+        let reading_client_hello =
+            P::role() == Role::Server && tls_handshake.write_space == PacketNumberSpace::Initial;
+
+        let tls_session = match tls_handshake.session.as_mut() {
+            Some(tls_session) => tls_session,
+            None => return Ok(false),
+        };
+
+        let mut read = false;
+
+        for &packet_number_space in PacketNumberSpace::all().iter() {
+            let data = {
+                let mut packet_number_spaces = self.packet_number_spaces
+                    .lock()
+                    .expect("failed to lock packet_number_spaces");
+
+                packet_number_spaces
+                    .get_mut(packet_number_space)
+                    .crypto_stream
+                    .read()
+            };
+
+            let data = match data {
+                Some(data) => data,
+                None => continue,
+            };
+
+            read = true;
+
+            if let Err(error) = tls_session.read_hs(&data) {
+                // the peer learns which alert failed the handshake
+                if let Some(alert) = tls_session.get_alert() {
+                    self.close(ErrorCode::CryptoError(alert.get_u8()), "TLS handshake failed")?;
+                }
+
+                return Err(error).chain_err(|| self.perspective.handshake_error());
+            }
+
+            if reading_client_hello && packet_number_space == PacketNumberSpace::Initial {
+                if let Some(early_secret) = tls_session.get_early_secret() {
+                    self.install_early_data_keys(tls_session, early_secret)?;
+                }
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Opens the 0-RTT packets from the client with the keys derived from the
+    /// `client_early_traffic_secret` the TLS session hands out once it has accepted them, the
+    /// server configured the session to reject 0-RTT data it does not accept.
+    fn install_early_data_keys(
+        &self,
+        tls_session: &P::TlsSession,
+        early_secret: &[u8],
+    ) -> Result<()> {
+        let read_keys = CryptoState::from_tls_secret(tls_session, early_secret, self.version())?;
+
+        let buffered_packets = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let early_data = &mut packet_number_spaces
+                .get_mut(PacketNumberSpace::ApplicationData)
+                .early_data;
+
+            early_data.on_read_keys_installed(read_keys);
+            early_data.take_buffered_packets()
+        };
+
+        for incoming_packet in buffered_packets {
+            self.open_zero_rtt_packet(incoming_packet)?;
+        }
+
+        Ok(())
+    }
+
+    /// Queues crypto `data` the TLS session wrote at the encryption level of
+    /// `packet_number_space`.
+    fn enqueue_crypto_data(&self, packet_number_space: PacketNumberSpace, data: Bytes) {
+        let mut packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        // draft 08 sends the handshake in long header packets on its single crypto stream, the
+        // crypto data written once it completes goes in 1-RTT packets
+        let sending_space = match (self.wire_format, packet_number_space) {
+            (WireFormat::Draft08, PacketNumberSpace::Handshake) => PacketNumberSpace::Initial,
+            _ => packet_number_space,
+        };
+
+        let crypto_stream_space = packet_number_spaces.crypto_stream_space(packet_number_space);
+        let stream_frame = packet_number_spaces
+            .get_mut(crypto_stream_space)
+            .crypto_stream
+            .write(data);
+
+        packet_number_spaces
+            .get_mut(sending_space)
+            .pending_crypto_frames
+            .push_back(stream_frame);
+    }
+
+    /// Derives the keys of an encryption level from the `secrets` the TLS session handed out.
+    fn keys_from_secrets(
+        &self,
+        tls_session: &P::TlsSession,
+        secrets: &Secrets,
+    ) -> Result<AeadPair> {
+        let (write_secret, read_secret) = match P::role() {
+            Role::Client => (&secrets.client, &secrets.server),
+            Role::Server => (&secrets.server, &secrets.client),
+        };
+
+        let version = self.version();
+
+        Ok(AeadPair {
+            write: CryptoState::from_tls_secret(tls_session, write_secret, version)?,
+            read: CryptoState::from_tls_secret(tls_session, read_secret, version)?,
+        })
+    }
+
+    /// Installs the `keys` of `packet_number_space` and processes the packets which arrived
+    /// before them.
+    fn install_keys(&self, packet_number_space: PacketNumberSpace, keys: AeadPair) -> Result<()> {
+        debug!(
+            "connection {}: installing {:?} keys",
+            self.description(),
+            packet_number_space
+        );
+
+        let pending_packets = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let space_state = packet_number_spaces.get_mut(packet_number_space);
+
+            if packet_number_space == PacketNumberSpace::ApplicationData {
+                // incoming short header packets can only be read once their key is known
+                if let Some(header_protection_key) = keys.read.header_protection_key() {
+                    self.perspective
+                        .header_protection_keys()
+                        .insert(self.local_connection_id, header_protection_key);
+                }
+
+                space_state
+                    .key_update
+                    .on_keys_installed(&keys, P::update_secret_receive_label(self.version()))?;
+            }
+
+            space_state.keys = Some(keys);
+
+            mem::replace(&mut space_state.pending_packets, Vec::new())
+        };
+
+        for incoming_packet in pending_packets {
+            self.process_incoming_packet(incoming_packet)?;
+        }
+
+        Ok(())
+    }
+
+    /// Discards the keys of `packet_number_space` and stops tracking the packets in flight in
+    /// it, along with the congestion controller.
+    fn discard_keys(
+        &self,
+        packet_number_spaces: &mut PacketNumberSpaces,
+        packet_number_space: PacketNumberSpace,
+    ) {
+        if packet_number_spaces.get(packet_number_space).keys_discarded {
+            return;
+        }
+
+        debug!(
+            "connection {}: discarding {:?} keys",
+            self.description(),
+            packet_number_space
+        );
+
+        let discarded_packets = packet_number_spaces.discard_keys(packet_number_space);
+
+        let mut congestion_controller = self.congestion_controller
+            .lock()
+            .expect("failed to lock congestion_controller");
+
+        on_packets_discarded(
+            &mut **congestion_controller,
+            packet_number_space,
+            &discarded_packets,
+        );
+    }
+
+    /// Installs the draft 08 1-RTT keys and checks what was agreed once the TLS handshake of
+    /// `tls_session` has completed.
+    fn complete_handshake(&self, tls_session: &P::TlsSession) -> Result<()> {
+        if self.wire_format == WireFormat::Draft08 {
+            // a client may have been moved to a compatible version during the handshake
+            let version = self.version();
+
+            let keys = AeadPair {
+                write: CryptoState::from_tls(tls_session, P::tls_exporter_send_label(), version)?,
+                read: CryptoState::from_tls(
+                    tls_session,
+                    P::tls_exporter_receive_label(),
+                    version,
+                )?,
+            };
+
+            self.install_keys(PacketNumberSpace::ApplicationData, keys)?;
+        }
+
+        self.perspective.on_handshake_complete(self, tls_session)?;
+
+        self.on_handshake_complete()
+    }
+
+    /// Stops sending 0-RTT packets now that 1-RTT packets can be sent, and on a server reports
+    /// whether the 0-RTT packets from the client were accepted.
+    fn on_handshake_complete(&self) -> Result<()> {
+        let client_hello = self.client_hello
+            .lock()
+            .expect("failed to lock client_hello")
+            .take()
+            .and_then(|client_hello| client_hello);
+        // only a server has a ClientHello
+        let offered = client_hello.map_or(false, |client_hello| client_hello.offers_early_data());
+
+        {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let early_data = &mut packet_number_spaces
+                .get_mut(PacketNumberSpace::ApplicationData)
+                .early_data;
+
+            early_data.on_handshake_complete();
+
+            // the read keys are installed once the ClientHello has been read if the 0-RTT
+            // packets are accepted
+            if early_data.read_keys().is_some() {
+                early_data.on_accepted();
+            } else if offered {
+                early_data.on_rejected();
+            }
+        }
+
+        // a server's handshake is confirmed once it completes and a client's once it receives
+        // HANDSHAKE_DONE, neither needs the Handshake keys after that (RFC 9001 section 4.9.2)
+        if P::role() == Role::Server && self.wire_format == WireFormat::V1 {
+            self.enqueue_control_frame(Frame::HandshakeDone);
+
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+            self.discard_keys(&mut packet_number_spaces, PacketNumberSpace::Handshake);
+        }
+
+        // the token can only be sent once 1-RTT packets can be
+        if let Some(token) = self.perspective.new_token() {
+            self.enqueue_control_frame(Frame::NewToken(NewTokenFrame { token }));
+        }
+
+        // the connection id of the handshake is the first in the sequence
+        if let Some((connection_id, stateless_reset_token)) =
+            self.perspective.issue_connection_id()?
+        {
+            self.enqueue_control_frame(Frame::NewConnectionId(NewConnectionIdFrame {
+                sequence: 1,
+                retire_prior_to: 0,
+                connection_id,
+                stateless_reset_token,
+            }));
+        }
+
+        Ok(())
+    }
+}
+
+impl<P: Perspective> Connection<P> {
+    pub fn new_stream(&self, stream_type: StreamType) -> (StreamId, Arc<Mutex<StreamState>>) {
+        let mut stream_map = self.stream_map
+            .lock()
+            .expect("failed to obtain stream_map lock");
+
+        // nothing may be sent on a stream until the peer has told us how much it will accept
+        let max_outgoing_data_per_stream = self.remote_transport_parameters
+            .lock()
+            .expect("failed to lock remote_transport_parameters")
+            .as_ref()
+            .map_or(0, |transport_parameters| {
+                transport_parameters.initial_max_stream_data
+            });
+
+        stream_map.next_outgoing_stream(
+            stream_type,
+            self.perspective.max_incoming_data_per_stream().into(),
+            max_outgoing_data_per_stream.into(),
+        )
+    }
+
+    pub fn local_connection_id(&self) -> ConnectionId {
+        self.local_connection_id
+    }
+
+    pub fn remote_connection_id(&self) -> ConnectionId {
+        *self.remote_connection_id
+            .lock()
+            .expect("failed to lock remote_connection_id")
+    }
+
+    /// The QUIC version the connection's packets are in.
+    pub fn version(&self) -> Version {
+        *self.version.lock().expect("failed to lock version")
+    }
+
+    /// The current estimate of the round trip time to the remote endpoint.
+    pub fn rtt_estimate(&self) -> RttEstimator {
+        *self.rtt_estimator
+            .lock()
+            .expect("failed to lock rtt_estimator")
+    }
+
+    fn should_transmit(&self, stream_frames: &VecDeque<StreamFrame>) -> bool {
+        if !stream_frames.is_empty() {
+            return true;
+        }
+
+        {
+            let packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let now = Instant::now();
+
+            let ack_due = PacketNumberSpace::all().iter().any(|&packet_number_space| {
+                packet_number_spaces
+                    .get(packet_number_space)
+                    .ack_manager
+                    .should_send_ack(now)
+            });
+
+            if ack_due {
+                return true;
+            }
+
+            // packets lost by the time threshold are retransmitted
+            let has_lost_packets = PacketNumberSpace::all().iter().any(|&packet_number_space| {
+                packet_number_spaces
+                    .get(packet_number_space)
+                    .loss_detector
+                    .loss_time()
+                    .map_or(false, |loss_time| loss_time <= now)
+            });
+
+            if has_lost_packets || packet_number_spaces.has_pending_crypto_frames() {
+                return true;
+            }
+        }
+
+        let control_frames = self.pending_control_frames
+            .lock()
+            .expect("failed to lock pending_control_frames");
+
+        !control_frames.is_empty()
+    }
+
+    /// Sends the peer a CONNECTION_CLOSE frame with `error_code`, so it learns why the connection
+    /// failed rather than waiting for it to time out.
+    pub fn close(&self, error_code: ErrorCode, reason_phrase: &str) -> Result<()> {
+        debug!(
+            "connection {}: closing with error code {:?}: {}",
+            self.description(),
+            error_code,
+            reason_phrase
+        );
+
+        self.enqueue_control_frame(Frame::ConnectionClose(ConnectionCloseFrame {
+            error_code,
+            reason_phrase: reason_phrase.to_owned(),
+        }));
+
+        // nothing drives the connection once it has failed, so the frame is not sent later
+        if self.poll_transmit()?.is_not_ready() {
+            warn!(
+                "connection {}: failed to send CONNECTION_CLOSE frame",
+                self.description()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Queues a frame which is not associated with any stream to be sent in the next packet.
+    pub fn enqueue_control_frame(&self, frame: Frame) {
+        let mut control_frames = self.pending_control_frames
+            .lock()
+            .expect("failed to lock pending_control_frames");
+
+        control_frames.push_back(frame);
+    }
+
+    pub fn poll_try_transmit(&self) -> Poll<(), Error> {
+        trace!("determining whether to transmit a new packet");
+
+        let mut stream_frames = self.pending_stream_frames
+            .lock()
+            .expect("failed to lock pending_stream_frames");
+
+        if self.should_transmit(&*stream_frames) {
+            self.poll_transmit_pending_frames(&mut *stream_frames)
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn poll_transmit(&self) -> Poll<(), Error> {
+        let mut stream_frames = self.pending_stream_frames
+            .lock()
+            .expect("failed to lock pending_stream_frames");
+
+        self.poll_transmit_pending_frames(&mut *stream_frames)
+    }
+
+    fn poll_send_packet(&self, outgoing_packet: OutgoingPacket) -> Poll<(), Error> {
+        if self.perspective
+            .poll_send_packet(outgoing_packet.clone())?
+            .is_not_ready()
+        {
+            // hold on to the packet so it is the first to be sent when the socket is ready
+            let mut unsent_packet = self.unsent_packet
+                .lock()
+                .expect("failed to lock unsent_packet");
+            *unsent_packet = Some(outgoing_packet);
+
+            return Ok(Async::NotReady);
+        }
+
+        Ok(().into())
+    }
+
+    fn poll_transmit_pending_frames(
+        &self,
+        stream_frames: &mut VecDeque<StreamFrame>,
+    ) -> Poll<(), Error> {
+        let unsent_packet = {
+            let mut unsent_packet = self.unsent_packet
+                .lock()
+                .expect("failed to lock unsent_packet");
+            unsent_packet.take()
+        };
+
+        if let Some(unsent_packet) = unsent_packet {
+            trace!("retransmitting previously unsent packet");
+
+            try_ready!(self.poll_send_packet(unsent_packet));
+        }
+
+        self.detect_lost_packets(stream_frames);
+
+        while let Some(outgoing_packet) = self.pack_packet(stream_frames)? {
+            trace!("transmitting new packet");
+
+            try_ready!(self.poll_send_packet(outgoing_packet));
+
+            debug!("transmitted new packet");
+        }
+
+        if self.has_pending_frames(stream_frames) {
+            match self.pacing_deadline() {
+                Some(deadline) => {
+                    trace!("pacing outgoing packets");
+
+                    self.poll_timer(&self.pacing_timer, deadline)?;
+                }
+                None => trace!("congestion window is full, waiting for acknowledgements"),
+            }
+
+            return Ok(Async::NotReady);
+        }
+
+        Ok(().into())
+    }
+
+    /// When the pacer next allows a packet to be sent, `None` if one may be sent now.
+    fn pacing_deadline(&self) -> Option<Instant> {
+        let rtt_estimator = self.rtt_estimator
+            .lock()
+            .expect("failed to lock rtt_estimator");
+        let congestion_controller = self.congestion_controller
+            .lock()
+            .expect("failed to lock congestion_controller");
+
+        self.pacing_send_time(&**congestion_controller, &rtt_estimator, Instant::now())
+    }
+
+    /// When the pacer allows a full sized packet to be sent, `None` if it may be sent at `now` or
+    /// pacing is disabled.
+    fn pacing_send_time(
+        &self,
+        congestion_controller: &CongestionController,
+        rtt_estimator: &RttEstimator,
+        now: Instant,
+    ) -> Option<Instant> {
+        let mut pacer = self.pacer.lock().expect("failed to lock pacer");
+        let pacer = pacer.as_mut()?;
+
+        let congestion_window = congestion_controller.congestion_window();
+        let rate = congestion_controller
+            .pacing_rate()
+            .unwrap_or_else(|| Pacer::rate(congestion_window, rtt_estimator.smoothed_rtt()));
+
+        pacer.send_time(self.max_packet_size, rate, congestion_window, now)
+    }
+
+    fn has_pending_frames(&self, stream_frames: &VecDeque<StreamFrame>) -> bool {
+        if !stream_frames.is_empty()
+            || self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces")
+                .has_pending_crypto_frames()
+        {
+            return true;
+        }
+
+        let control_frames = self.pending_control_frames
+            .lock()
+            .expect("failed to lock pending_control_frames");
+
+        !control_frames.is_empty()
+    }
+
+    fn build_packet_header(
+        &self,
+        packet_number_space: PacketNumberSpace,
+        partial_packet_number: PartialPacketNumber,
+        key_phase: bool,
+        zero_rtt: bool,
+    ) -> PacketHeader {
+        let packet_type = match (P::role(), self.wire_format, packet_number_space) {
+            _ if zero_rtt => Some(LongHeaderPacketType::ZeroRttProtected),
+            // draft-08 servers send their side of the handshake in Handshake packets protected
+            // with the same keys as the client's Initial packets
+            (Role::Server, WireFormat::Draft08, PacketNumberSpace::Initial) => {
+                Some(LongHeaderPacketType::Handshake)
+            }
+            _ => packet_number_space.long_header_packet_type(),
+        };
+
+        let remote_connection_id = self.remote_connection_id();
+
+        match packet_type {
+            Some(packet_type) => PacketHeader::Long(LongHeader {
+                packet_type,
+                version: self.version(),
+                destination_connection_id: Some(remote_connection_id),
+                source_connection_id: Some(self.local_connection_id),
+                token: if packet_type == LongHeaderPacketType::Initial {
+                    self.initial_token()
+                } else {
+                    Bytes::new()
+                },
+                payload_length: 0u32.into(),
+                partial_packet_number,
+            }),
+            None => PacketHeader::Short(ShortHeader {
+                key_phase,
+                destination_connection_id: Some(remote_connection_id),
+                partial_packet_number,
+                wire_format: self.wire_format,
+            }),
+        }
+    }
+
+    /// The token a client's Initial packets carry, from the Retry packet it answered or else
+    /// from an earlier connection.
+    fn initial_token(&self) -> Bytes {
+        let retry = self.retry.lock().expect("failed to lock retry");
+
+        match retry.as_ref() {
+            Some(retry_packet) => retry_packet.retry_token.clone(),
+            None => self.perspective.address_validation_token(),
+        }
+    }
+
+    /// The space incoming packets with `packet_header` belong to.
+    fn packet_number_space(&self, packet_header: &PacketHeader) -> Option<PacketNumberSpace> {
+        match (self.wire_format, packet_header) {
+            // draft-08 long header packets all share the keys derived from the connection id
+            (WireFormat::Draft08, PacketHeader::Long(_)) => Some(PacketNumberSpace::Initial),
+            _ => PacketNumberSpace::for_packet_header(packet_header),
+        }
+    }
+
+    /// Packs as many of the pending control and stream frames as will fit into a single packet.
+    ///
+    /// # Returns
+    /// `None` if there were no pending frames.
+    fn pack_packet(
+        &self,
+        stream_frames: &mut VecDeque<StreamFrame>,
+    ) -> Result<Option<OutgoingPacket>> {
+        // until a client's address is validated the server sends it no more than three times the
+        // bytes it received
+        let max_packet_size = {
+            let amplification_limit = self.amplification_limit
+                .lock()
+                .expect("failed to lock amplification_limit");
+
+            match amplification_limit.as_ref() {
+                Some(amplification_limit) => {
+                    match amplification_limit.max_packet_size(self.max_packet_size) {
+                        Some(max_packet_size) => max_packet_size,
+                        None => {
+                            trace!("waiting for the client's address to be validated");
+                            return Ok(None);
+                        }
+                    }
+                }
+                None => self.max_packet_size,
+            }
+        };
+
+        let mut packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        let now = Instant::now();
+
+        // until the handshake completes stream data is sent in 0-RTT packets, once any crypto
+        // data and acknowledgements of the handshake have gone out. The server discards 0-RTT
+        // packets which arrive before the connection exists, so they follow the first Initial
+        let zero_rtt = {
+            let application_data = packet_number_spaces.get(PacketNumberSpace::ApplicationData);
+            let sent_initial = packet_number_spaces
+                .get(PacketNumberSpace::Initial)
+                .next_packet_number != PacketNumber::from(0u32);
+
+            application_data.keys.is_none() && application_data.early_data.write_keys().is_some()
+                && sent_initial
+                && packet_number_spaces.pending_handshake_space(now).is_none()
+                && !stream_frames.is_empty()
+        };
+
+        let packet_number_space = if zero_rtt {
+            PacketNumberSpace::ApplicationData
+        } else {
+            packet_number_spaces.sending_space(now)
+        };
+
+        let space_state = packet_number_spaces.get_mut(packet_number_space);
+
+        let packet_number = space_state.next_packet_number;
+        let partial_packet_number = PartialPacketNumber::from_packet_number_in_format(
+            packet_number,
+            space_state.lowest_unacknowledged,
+            self.wire_format,
+        )?;
+
+        // the keys are updated between packets so every packet is sealed with one key phase
+        if packet_number_space == PacketNumberSpace::ApplicationData {
+            if let Some(keys) = space_state.keys.as_mut() {
+                space_state.key_update.prepare_to_seal(
+                    keys,
+                    self.perspective.key_update_interval_packets(),
+                    self.perspective.key_update_interval_bytes(),
+                    P::update_secret_send_label(self.version()),
+                )?;
+            }
+        }
+
+        let packet_header = self.build_packet_header(
+            packet_number_space,
+            partial_packet_number,
+            space_state.key_update.key_phase(),
+            zero_rtt,
+        );
+
+        let crypto_state = match (&space_state.keys, space_state.early_data.write_keys()) {
+            (_, Some(zero_rtt_keys)) if zero_rtt => zero_rtt_keys,
+            (Some(keys), _) => &keys.write,
+            _ => unreachable!("packets are only sent in spaces with keys"),
+        };
+
+        let mut packet_packer =
+            PacketPacker::new(packet_header, max_packet_size, crypto_state.tag_len())?;
+
+        let mut sent_ack_frame = None;
+
+        // an ACK which is due goes ahead of everything else
+        if space_state.ack_manager.should_send_ack(now) {
+            if let Some(ack_frame) = space_state.ack_manager.build_ack_frame(now) {
+                if packet_packer
+                    .try_push_frame(Frame::Ack(ack_frame.clone()))?
+                    .is_none()
+                {
+                    sent_ack_frame = Some(ack_frame);
+                }
+            }
+        }
+
+        // a probe is sent even when the congestion window is full or the pacer holds it back
+        let (congestion_limited, sending_probe) = {
+            let rtt_estimator = self.rtt_estimator
+                .lock()
+                .expect("failed to lock rtt_estimator");
+            let congestion_controller = self.congestion_controller
+                .lock()
+                .expect("failed to lock congestion_controller");
+            let pending_probe_packets = self.pending_probe_packets
+                .lock()
+                .expect("failed to lock pending_probe_packets");
+
+            let can_send = congestion_controller.can_send(self.max_packet_size)
+                && self.pacing_send_time(&**congestion_controller, &rtt_estimator, now)
+                    .is_none();
+
+            (
+                !can_send && *pending_probe_packets == 0,
+                !can_send && *pending_probe_packets > 0,
+            )
+        };
+
+        if congestion_limited {
+            trace!("congestion window is full or paced, only acknowledgements are sent");
+        } else {
+            // crypto data is only sent in the space of its encryption level, never in 0-RTT
+            // packets
+            if !zero_rtt {
+                while let Some(stream_frame) = space_state.pending_crypto_frames.pop_front() {
+                    if let Some(remainder) = packet_packer.push_stream_frame(stream_frame)? {
+                        space_state.pending_crypto_frames.push_front(remainder);
+                        break;
+                    }
+                }
+            }
+
+            // version 1 Initial and Handshake packets only carry the frames of the handshake, the
+            // rest wait for 1-RTT packets (RFC 9000 section 12.4)
+            let handshake_packet = self.wire_format == WireFormat::V1
+                && packet_number_space != PacketNumberSpace::ApplicationData;
+
+            {
+                let mut control_frames = self.pending_control_frames
+                    .lock()
+                    .expect("failed to lock pending_control_frames");
+                let mut deferred_control_frames = Vec::new();
+
+                while let Some(control_frame) = control_frames.pop_front() {
+                    if handshake_packet && !control_frame.is_allowed_in_handshake_packets() {
+                        deferred_control_frames.push(control_frame);
+                        continue;
+                    }
+
+                    if let Some(control_frame) = packet_packer.try_push_frame(control_frame)? {
+                        control_frames.push_front(control_frame);
+                        break;
+                    }
+                }
+
+                for control_frame in deferred_control_frames.into_iter().rev() {
+                    control_frames.push_front(control_frame);
+                }
+            }
+
+            // stream data waits for 0-RTT or 1-RTT keys
+            if packet_number_space == PacketNumberSpace::ApplicationData {
+                while let Some(stream_frame) = stream_frames.pop_front() {
+                    if let Some(remainder) = packet_packer.push_stream_frame(stream_frame)? {
+                        stream_frames.push_front(remainder);
+                        break;
+                    }
+                }
+            }
+        }
+
+        if packet_packer.is_empty() {
+            trace!("no pending frames to pack");
+            return Ok(None);
+        }
+
+        // otherwise acknowledgements are sent along with packets which are being sent anyway
+        if sent_ack_frame.is_none() {
+            if let Some(ack_frame) = space_state.ack_manager.build_ack_frame(now) {
+                if packet_packer
+                    .try_push_frame(Frame::Ack(ack_frame.clone()))?
+                    .is_none()
+                {
+                    sent_ack_frame = Some(ack_frame);
+                }
+            }
+        }
+
+        // both version 1 and draft 08 require datagrams carrying client Initial packets to be at
+        // least 1200 bytes, servers do not answer smaller ones with a Version Negotiation
+        if P::role() == Role::Client && packet_number_space == PacketNumberSpace::Initial {
+            packet_packer.pad_to_max();
+        }
+
+        let retransmittable_frames: Vec<_> = packet_packer
+            .frames()
+            .iter()
+            .filter(|frame| frame.is_retransmittable())
+            .cloned()
+            .collect();
+        let ack_eliciting = packet_packer.frames().iter().any(Frame::is_ack_eliciting);
+
+        let encryption_level = if zero_rtt {
+            EncryptionLevel::NonForwardSecure
+        } else {
+            packet_number_space.encryption_level()
+        };
+
+        let outgoing_packet = packet_packer.pack_packet(
+            packet_number,
+            crypto_state,
+            self.remote_address,
+            encryption_level,
+        )?;
+
+        if let Some(ack_frame) = sent_ack_frame {
+            space_state.ack_manager.on_ack_sent(packet_number, &ack_frame);
+        }
+
+        let size = outgoing_packet.packet_header.bytes()?.len() + outgoing_packet.data.len();
+
+        if let Some(amplification_limit) = self.amplification_limit
+            .lock()
+            .expect("failed to lock amplification_limit")
+            .as_mut()
+        {
+            amplification_limit.on_packet_sent(size);
+        }
+
+        if zero_rtt {
+            space_state.early_data.on_packet_sent(packet_number);
+        } else {
+            space_state.key_update.on_packet_sealed(packet_number, size);
+        }
+
+        space_state.loss_detector.on_packet_sent(SentPacket {
+            packet_number,
+            time_sent: now,
+            ack_eliciting,
+            size,
+            retransmittable_frames,
+        });
+
+        // packets carrying only ACK frames do not count towards the bytes in flight
+        if ack_eliciting {
+            let mut congestion_controller = self.congestion_controller
+                .lock()
+                .expect("failed to lock congestion_controller");
+            congestion_controller.on_packet_sent(packet_number_space, packet_number, size, now);
+
+            if let Some(pacer) = self.pacer.lock().expect("failed to lock pacer").as_mut() {
+                pacer.on_packet_sent(size);
+            }
+
+            if sending_probe {
+                let mut pending_probe_packets = self.pending_probe_packets
+                    .lock()
+                    .expect("failed to lock pending_probe_packets");
+                *pending_probe_packets = pending_probe_packets.saturating_sub(1);
+            }
+        }
+
+        space_state.advance_next_packet_number()?;
+
+        // a client stops sending Initial packets once it sends a Handshake packet, the server
+        // can read everything it sends from then on (RFC 9001 section 4.9.1)
+        if P::role() == Role::Client && self.wire_format == WireFormat::V1
+            && packet_number_space == PacketNumberSpace::Handshake
+        {
+            self.discard_keys(&mut packet_number_spaces, PacketNumberSpace::Initial);
+        }
+
+        Ok(Some(outgoing_packet))
+    }
+
+    /// # Returns
+    /// `Async::Ready` if any incoming packets were processed.
+    pub fn poll_process_incoming_packets(&self) -> Poll<(), Error> {
+        trace!("checking for a new incoming packets");
+
+        self.poll_loss_detection_timer()?;
+
+        let mut processed_incoming_packets = false;
+
+        while let Async::Ready(incoming_packets) = self.perspective
+            .poll_incoming_packets(self.local_connection_id())?
+        {
+            for incoming_packet in incoming_packets {
+                trace!("found new incoming packet");
+                self.process_incoming_packet(incoming_packet)?;
+                processed_incoming_packets = true;
+            }
+        }
+
+        trace!("no more incoming packets");
+
+        if processed_incoming_packets {
+            // post-handshake messages such as session tickets arrive after the handshake
+            self.process_crypto_data()?;
+
+            // the packets may need acknowledging straight away, if the socket is not ready the
+            // packet is sent with the next transmission
+            self.poll_try_transmit()?;
+        }
+
+        self.poll_ack_timer()?;
+
+        if processed_incoming_packets {
+            Ok(().into())
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+
+    /// When the earliest delayed ACK frame must be sent, `None` if nothing is waiting to be
+    /// acknowledged.
+    fn ack_deadline(&self) -> Option<Instant> {
+        let packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        PacketNumberSpace::all()
+            .iter()
+            .filter_map(|&packet_number_space| {
+                packet_number_spaces
+                    .get(packet_number_space)
+                    .ack_manager
+                    .ack_deadline()
+            })
+            .min()
+    }
+
+    /// Sends the delayed ACK frames which are due and arms the ACK timer for the next deadline,
+    /// an ACK frame is only delayed until max_ack_delay has passed without another packet to
+    /// send it with.
+    fn poll_ack_timer(&self) -> Result<()> {
+        let mut deadline = self.ack_deadline();
+
+        if deadline.map_or(false, |deadline| deadline <= Instant::now()) {
+            trace!("connection {}: ACK timer fired", self.description());
+
+            // an ACK frame which cannot be sent yet goes with the next transmission, which the
+            // socket wakes the task for
+            self.poll_try_transmit()?;
+
+            deadline = self.ack_deadline()
+                .filter(|&deadline| deadline > Instant::now());
+        }
+
+        match deadline {
+            Some(deadline) => self.poll_timer(&self.ack_timer, deadline),
+            None => {
+                let mut ack_timer = self.ack_timer.lock().expect("failed to lock ack_timer");
+                ack_timer.0 = None;
+
+                Ok(())
+            }
+        }
+    }
+
+    fn process_incoming_packet(&self, incoming_packet: IncomingPacket) -> Result<()> {
+        match &incoming_packet.packet_header {
+            PacketHeader::Retry(retry_packet) => return self.handle_retry_packet(retry_packet),
+            PacketHeader::VersionNegotiation(version_negotiation_packet) => {
+                return self.handle_version_negotiation_packet(version_negotiation_packet)
+            }
+            PacketHeader::StatelessReset(_) => {
+                bail!(ErrorKind::StatelessResetReceived(self.local_connection_id()))
+            }
+            _ => {}
+        }
+
+        let zero_rtt = match &incoming_packet.packet_header {
+            PacketHeader::Long(long_header) => {
+                long_header.packet_type == LongHeaderPacketType::ZeroRttProtected
+            }
+            _ => false,
+        };
+
+        // the header protection of 0-RTT packets is removed with the 0-RTT keys once the
+        // ClientHello has been read
+        let incoming_packet = if incoming_packet.header_protected && !zero_rtt {
+            match self.remove_header_protection(incoming_packet)? {
+                Some(incoming_packet) => incoming_packet,
+                None => return Ok(()),
+            }
+        } else {
+            incoming_packet
+        };
+
+        if let Some(amplification_limit) = self.amplification_limit
+            .lock()
+            .expect("failed to lock amplification_limit")
+            .as_mut()
+        {
+            amplification_limit.on_packet_received(
+                incoming_packet.packet_header_bytes.len() + incoming_packet.data.len(),
+            );
+        }
+
+        if zero_rtt {
+            return self.process_zero_rtt_packet(incoming_packet);
+        }
+
+        let packet_number_space = match self.packet_number_space(&incoming_packet.packet_header) {
+            Some(packet_number_space) => packet_number_space,
+            None => {
+                debug!("discarding packet without a packet number");
+                return Ok(());
+            }
+        };
+
+        // the keys from before a key update are kept until reordered packets stop arriving
+        let discard_delay = self.rtt_estimate()
+            .probe_timeout(self.remote_max_ack_delay(PacketNumberSpace::ApplicationData))
+            * 3;
+        let now = Instant::now();
+
+        let packet = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            if let PacketHeader::Long(long_header) = &incoming_packet.packet_header {
+                let in_version = match packet_number_space {
+                    PacketNumberSpace::Initial => {
+                        self.prepare_initial_keys(long_header.version, &mut packet_number_spaces)?
+                    }
+                    // Handshake packets are in the version the Initial packets settled on
+                    _ => packet_number_spaces
+                        .get(packet_number_space)
+                        .keys
+                        .as_ref()
+                        .map_or(true, |keys| keys.read.version() == long_header.version),
+                };
+
+                if !in_version {
+                    debug!(
+                        "connection {}: discarding {:?} packet in version {}",
+                        self.description(),
+                        packet_number_space,
+                        long_header.version
+                    );
+                    return Ok(());
+                }
+            }
+
+            let space_state = packet_number_spaces.get_mut(packet_number_space);
+
+            space_state.key_update.discard_expired_keys(now);
+
+            let keys = match space_state.keys.as_mut() {
+                Some(keys) => keys,
+                None => {
+                    debug!(
+                        "discarding {:?} packet received before its keys were available",
+                        packet_number_space
+                    );
+                    return Ok(());
+                }
+            };
+
+            // the key phase bit picks out the keys either side of a key update
+            let read_key_phase = match &incoming_packet.packet_header {
+                PacketHeader::Short(short_header) => {
+                    match space_state
+                        .packet_unpacker
+                        .infer_packet_number(&incoming_packet)
+                    {
+                        Ok(packet_number) => space_state
+                            .key_update
+                            .read_key_phase(short_header.key_phase, packet_number),
+                        Err(error) => {
+                            warn!(
+                                "discarding packet from {:?} without a packet number: {}",
+                                incoming_packet.source_address, error
+                            );
+                            return Ok(());
+                        }
+                    }
+                }
+                _ => ReadKeyPhase::Current,
+            };
+
+            let unpacked = match space_state.key_update.read_keys(keys, read_key_phase) {
+                Some(crypto_state) => space_state
+                    .packet_unpacker
+                    .unpack_packet(&incoming_packet, crypto_state),
+                None => {
+                    debug!("discarding packet sealed with keys which have been discarded");
+                    return Ok(());
+                }
+            };
+
+            match unpacked {
+                Ok(packet) => {
+                    space_state.key_update.on_packet_opened(
+                        keys,
+                        read_key_phase,
+                        packet.packet_number,
+                        P::update_secret_send_label(self.version()),
+                        P::update_secret_receive_label(self.version()),
+                        discard_delay,
+                        now,
+                    )?;
+
+                    // the client sends no more 0-RTT packets once it sends a 1-RTT packet
+                    if let PacketHeader::Short(_) = incoming_packet.packet_header {
+                        space_state.early_data.discard_read_keys();
+                    }
+
+                    // only a client which received our packets at its address can answer them
+                    // beyond the Initial packets
+                    if packet_number_space != PacketNumberSpace::Initial {
+                        let mut amplification_limit = self.amplification_limit
+                            .lock()
+                            .expect("failed to lock amplification_limit");
+                        *amplification_limit = None;
+                    }
+
+                    packet
+                }
+                Err(error) => {
+                    if let ErrorKind::FailedToOpenSealedData = *error.kind() {
+                        space_state
+                            .key_update
+                            .on_open_failed(keys.read.integrity_limit())?;
+                    }
+
+                    warn!(
+                        "discarding packet from {:?} which could not be unpacked: {}",
+                        incoming_packet.source_address, error
+                    );
+                    return Ok(());
+                }
+            }
+        };
+
+        // a server stops sending Initial packets once it opens a Handshake packet, the client
+        // can read everything it sends from then on (RFC 9001 section 4.9.1)
+        if P::role() == Role::Server && self.wire_format == WireFormat::V1
+            && packet_number_space == PacketNumberSpace::Handshake
+        {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+            self.discard_keys(&mut packet_number_spaces, PacketNumberSpace::Initial);
+        }
+
+        self.handle_packet(
+            packet_number_space,
+            packet,
+            incoming_packet.received_at,
+            false,
+        )
+    }
+
+    /// Removes the header protection the codec left on `incoming_packet`, whose key only the
+    /// connection knows.
+    ///
+    /// # Returns
+    /// `None` if the packet was discarded, or kept until its keys are available.
+    fn remove_header_protection(
+        &self,
+        incoming_packet: IncomingPacket,
+    ) -> Result<Option<IncomingPacket>> {
+        let packet_number_space = match self.packet_number_space(&incoming_packet.packet_header) {
+            Some(packet_number_space) => packet_number_space,
+            None => return Ok(None),
+        };
+
+        let header_protection_key = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            // the server may have moved a client to a compatible version
+            if let PacketHeader::Long(long_header) = &incoming_packet.packet_header {
+                if packet_number_space == PacketNumberSpace::Initial
+                    && !self.prepare_initial_keys(long_header.version, &mut packet_number_spaces)?
+                {
+                    debug!(
+                        "discarding Initial packet in version {}",
+                        long_header.version
+                    );
+                    return Ok(None);
+                }
+            }
+
+            let space_state = packet_number_spaces.get_mut(packet_number_space);
+
+            match space_state
+                .keys
+                .as_ref()
+                .and_then(|keys| keys.read.header_protection_key())
+            {
+                Some(header_protection_key) => header_protection_key,
+                None if space_state.keys_discarded => {
+                    debug!(
+                        "discarding {:?} packet received after its keys were discarded",
+                        packet_number_space
+                    );
+                    return Ok(None);
+                }
+                None => {
+                    // the peer's packets often arrive in the same datagram as the crypto data
+                    // their keys are derived from
+                    space_state.buffer_packet(incoming_packet);
+                    return Ok(None);
+                }
+            }
+        };
+
+        match remove_deferred_header_protection(incoming_packet, &header_protection_key) {
+            Ok(incoming_packet) => Ok(Some(incoming_packet)),
+            Err(error) => {
+                debug!(
+                    "discarding {:?} packet whose header protection could not be removed: {}",
+                    packet_number_space, error
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Sends everything sent so far again to the connection id the server picked in
+    /// `retry_packet`, with the token the server needs to validate our address.
+    fn handle_retry_packet(&self, retry_packet: &RetryPacket) -> Result<()> {
+        if P::role() != Role::Client {
+            debug!("discarding retry packet sent to a server");
+            return Ok(());
+        }
+
+        // a Retry is only answered before anything has been received from the server, which
+        // stops an attacker replaying one later in the handshake
+        let received_initial = self.received_initial_packet();
+
+        let retry_source_connection_id = {
+            let mut retry = self.retry.lock().expect("failed to lock retry");
+
+            if retry.is_some() || received_initial || retry_packet.retry_token.is_empty()
+                || !retry_packet.is_valid(self.original_remote_connection_id)
+            {
+                debug!(
+                    "connection {}: discarding unexpected retry packet",
+                    self.description()
+                );
+                return Ok(());
+            }
+
+            let retry_source_connection_id = retry_packet
+                .source_connection_id
+                .ok_or_else(|| ErrorKind::PacketHeaderHasNoConnectionId)?;
+
+            *retry = Some(retry_packet.clone());
+
+            retry_source_connection_id
+        };
+
+        *self.remote_connection_id
+            .lock()
+            .expect("failed to lock remote_connection_id") = retry_source_connection_id;
+
+        debug!(
+            "connection {}: answering retry from {:?}",
+            self.description(),
+            retry_source_connection_id
+        );
+
+        let mut stream_frames = self.pending_stream_frames
+            .lock()
+            .expect("failed to lock pending_stream_frames");
+
+        // the server kept nothing from the Initial and 0-RTT packets it was sent
+        let discarded_packets: Vec<(PacketNumberSpace, Vec<SentPacket>)> = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            // the Initial packets sent from now on are protected with keys derived from the
+            // connection id the server picked
+            if self.wire_format == WireFormat::V1 {
+                packet_number_spaces.get_mut(PacketNumberSpace::Initial).keys =
+                    Some(initial_keys::<P>(retry_source_connection_id, self.version())?);
+            }
+
+            let mut congestion_controller = self.congestion_controller
+                .lock()
+                .expect("failed to lock congestion_controller");
+
+            [
+                PacketNumberSpace::Initial,
+                PacketNumberSpace::ApplicationData,
+            ].iter()
+                .map(|&packet_number_space| {
+                    let space_state = packet_number_spaces.get_mut(packet_number_space);
+                    let next_packet_number = space_state.next_packet_number;
+
+                    let discarded_packets = space_state
+                        .loss_detector
+                        .discard_packets_up_to(next_packet_number);
+
+                    on_packets_discarded(
+                        &mut **congestion_controller,
+                        packet_number_space,
+                        &discarded_packets,
+                    );
+
+                    (packet_number_space, discarded_packets)
+                })
+                .collect()
+        };
+
+        for (packet_number_space, discarded_packets) in discarded_packets {
+            let discarded_frames = discarded_packets
+                .into_iter()
+                .flat_map(|sent_packet| sent_packet.retransmittable_frames)
+                .collect();
+            self.requeue_frames(packet_number_space, discarded_frames, &mut stream_frames);
+        }
+
+        Ok(())
+    }
+
+    /// Picks the version to connect in again from the versions the server listed in
+    /// `version_negotiation_packet`, then fails the connection so it can be started again in
+    /// that version.
+    fn handle_version_negotiation_packet(
+        &self,
+        version_negotiation_packet: &VersionNegotiationPacket,
+    ) -> Result<()> {
+        if P::role() != Role::Client {
+            debug!("discarding version negotiation packet sent to a server");
+            return Ok(());
+        }
+
+        // the server only answers our first Initial packets this way, and a connection started
+        // again in the version it asked for must not negotiate again
+        let answered_retry = self.retry.lock().expect("failed to lock retry").is_some();
+        if self.received_initial_packet() || answered_retry
+            || self.perspective.initial_version() != self.perspective.version()
+            || version_negotiation_packet.destination_connection_id
+                != Some(self.local_connection_id)
+            || version_negotiation_packet.source_connection_id
+                != Some(self.original_remote_connection_id)
+            || version_negotiation_packet
+                .supported_versions
+                .contains(&self.perspective.version())
+        {
+            debug!(
+                "connection {}: discarding unexpected version negotiation packet",
+                self.description()
+            );
+            return Ok(());
+        }
+
+        let supported_versions: HashSet<Version> = version_negotiation_packet
+            .supported_versions
+            .iter()
+            .cloned()
+            .collect();
+        let version = Version::find_highest_supported(&supported_versions).ok_or_else(|| {
+            ErrorKind::NoCommonVersion(version_negotiation_packet.supported_versions.clone())
+        })?;
+
+        *self.negotiated_version
+            .lock()
+            .expect("failed to lock negotiated_version") = Some(version);
+
+        bail!(ErrorKind::VersionNegotiationRequired(version))
+    }
+
+    /// Derives the Initial keys of `version`, the version of an incoming Initial packet, if
+    /// they are not the ones in `packet_number_spaces`.
+    ///
+    /// The first Initial packet a client receives moves it to the compatible version the server
+    /// picked (RFC 9368 section 2.3), the keys of the later spaces are derived in that version
+    /// once the TLS session hands out their secrets. A server reads the client's Initial packets
+    /// in the version the client started in until the client follows it.
+    ///
+    /// # Returns
+    /// `false` if the packet is not in a version the connection may be in.
+    fn prepare_initial_keys(
+        &self,
+        version: Version,
+        packet_number_spaces: &mut PacketNumberSpaces,
+    ) -> Result<bool> {
+        let initial_connection_id = self.initial_connection_id();
+
+        {
+            let space_state = packet_number_spaces.get_mut(PacketNumberSpace::Initial);
+            let received_initial = space_state.packet_unpacker.largest_received().is_some();
+
+            let keys = match space_state.keys.as_mut() {
+                Some(keys) => keys,
+                None => return Ok(true),
+            };
+
+            if keys.read.version() == version {
+                return Ok(true);
+            }
+
+            match P::role() {
+                Role::Client => {
+                    if received_initial || version.wire_format() != Some(self.wire_format)
+                        || !self.perspective.compatible_versions().contains(&version)
+                    {
+                        return Ok(false);
+                    }
+
+                    debug!(
+                        "connection {}: moving to compatible version {}",
+                        self.description(),
+                        version
+                    );
+
+                    *keys = initial_keys::<P>(initial_connection_id, version)?;
+
+                    *self.version.lock().expect("failed to lock version") = version;
+
+                    Ok(true)
+                }
+                Role::Server => {
+                    if version != self.version() && version != self.perspective.initial_version()
+                    {
+                        return Ok(false);
+                    }
+
+                    keys.read = CryptoState::for_initial(
+                        initial_connection_id,
+                        P::initial_receive_label(),
+                        version,
+                    )?;
+
+                    Ok(true)
+                }
+            }
+        }
+    }
+
+    /// The connection id the Initial keys are derived from, the destination connection id of the
+    /// client's first Initial packet or the one a Retry packet moved it to (RFC 9001 section
+    /// 5.2). Draft 08 derives them from the client's connection id.
+    fn initial_connection_id(&self) -> ConnectionId {
+        if self.wire_format == WireFormat::Draft08 {
+            return P::client_connection_id(
+                self.local_connection_id,
+                self.original_remote_connection_id,
+            );
+        }
+
+        match P::role() {
+            Role::Client => self.retry
+                .lock()
+                .expect("failed to lock retry")
+                .as_ref()
+                .and_then(|retry_packet| retry_packet.source_connection_id)
+                .unwrap_or(self.original_remote_connection_id),
+            Role::Server => self.local_connection_id,
+        }
+    }
+
+    /// Whether a packet has been received from the peer in the Initial packet number space.
+    fn received_initial_packet(&self) -> bool {
+        let packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        packet_number_spaces
+            .get(PacketNumberSpace::Initial)
+            .packet_unpacker
+            .largest_received()
+            .is_some()
+    }
+
+    /// Counts a 0-RTT packet received by a server towards the maximum early data size and
+    /// opens it, packets received before the ClientHello has been read are buffered until it is
+    /// known whether they are accepted.
+    fn process_zero_rtt_packet(&self, incoming_packet: IncomingPacket) -> Result<()> {
+        {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let early_data = &mut packet_number_spaces
+                .get_mut(PacketNumberSpace::ApplicationData)
+                .early_data;
+
+            let max_size = self.perspective.max_early_data_size();
+
+            if P::role() != Role::Server
+                || !early_data.on_packet_received(incoming_packet.data.len(), max_size)
+            {
+                debug!(
+                    "connection {}: discarding 0-RTT packet",
+                    self.description()
+                );
+                return Ok(());
+            }
+
+            if early_data.read_keys().is_none() {
+                early_data.buffer_packet(incoming_packet);
+                return Ok(());
+            }
+        }
+
+        self.open_zero_rtt_packet(incoming_packet)
+    }
+
+    fn open_zero_rtt_packet(&self, incoming_packet: IncomingPacket) -> Result<()> {
+        let (packet, received_at) = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let space_state = packet_number_spaces.get_mut(PacketNumberSpace::ApplicationData);
+
+            let read_keys = match space_state.early_data.read_keys() {
+                Some(read_keys) => read_keys,
+                None => {
+                    debug!("discarding 0-RTT packet received after its keys were discarded");
+                    return Ok(());
+                }
+            };
+
+            let incoming_packet = match read_keys.header_protection_key() {
+                Some(ref header_protection_key) if incoming_packet.header_protected => {
+                    match remove_deferred_header_protection(incoming_packet, header_protection_key)
+                    {
+                        Ok(incoming_packet) => incoming_packet,
+                        Err(error) => {
+                            debug!(
+                                "discarding 0-RTT packet whose header protection could not be \
+                                 removed: {}",
+                                error
+                            );
+                            return Ok(());
+                        }
+                    }
+                }
+                _ => incoming_packet,
+            };
+
+            let unpacked = space_state
+                .packet_unpacker
+                .unpack_packet(&incoming_packet, read_keys);
+
+            match unpacked {
+                Ok(packet) => (packet, incoming_packet.received_at),
+                Err(error) => {
+                    warn!(
+                        "discarding 0-RTT packet from {:?} which could not be unpacked: {}",
+                        incoming_packet.source_address, error
+                    );
+                    return Ok(());
+                }
+            }
+        };
+
+        self.handle_packet(PacketNumberSpace::ApplicationData, packet, received_at, true)
+    }
+
+    /// Handles the frames of an opened packet, `early_data` is set for 0-RTT packets.
+    fn handle_packet(
+        &self,
+        packet_number_space: PacketNumberSpace,
+        packet: Packet,
+        received_at: Instant,
+        early_data: bool,
+    ) -> Result<()> {
+        let frames = match packet.content {
+            PacketContent::Regular { frames } => frames,
+            PacketContent::Initial { frames, .. } => frames.into_iter().map(Frame::from).collect(),
+            PacketContent::VersionNegotiation { .. } => {
+                unreachable!("unpacked packets should only ever contain frames")
+            }
+        };
+
+        {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let ack_eliciting = frames.iter().any(Frame::is_ack_eliciting);
+
+            if !packet_number_spaces
+                .get_mut(packet_number_space)
+                .on_packet_received(packet.packet_number, ack_eliciting, received_at)
+            {
+                debug!(
+                    "discarding duplicate {:?} packet {:?}",
+                    packet_number_space, packet.packet_number
+                );
+                return Ok(());
+            }
+        }
+
+        for frame in frames {
+            self.handle_frame(packet_number_space, frame, early_data)?;
+        }
+
+        Ok(())
+    }
+
+    fn handle_frame(
+        &self,
+        packet_number_space: PacketNumberSpace,
+        frame: Frame,
+        early_data: bool,
+    ) -> Result<()> {
+        trace!("handling frame {:?}", frame);
+
+        match frame {
+            Frame::Padding | Frame::Ping => {}
+            Frame::Stream(stream_frame) => {
+                self.handle_stream_frame(packet_number_space, stream_frame, early_data)?
+            }
+            Frame::Crypto(crypto_frame) => {
+                self.handle_crypto_data(packet_number_space, crypto_frame.offset, crypto_frame.data)
+            }
+            Frame::MaxData(max_data_frame) => {
+                let mut outgoing_flow_control = self.outgoing_flow_control
+                    .lock()
+                    .expect("failed to lock outgoing_flow_control");
+
+                if outgoing_flow_control.advance_max(max_data_frame.maximum_data) {
+                    debug!(
+                        "connection {}: advanced outgoing max data to {}",
+                        self.description(),
+                        max_data_frame.maximum_data
+                    );
+                }
+            }
+            Frame::MaxStreamData(max_stream_data_frame) => {
+                self.handle_max_stream_data_frame(max_stream_data_frame)?
+            }
+            Frame::Ack(ack_frame) => self.handle_ack_frame(packet_number_space, &ack_frame)?,
+            Frame::ResetStream(reset_stream_frame) => {
+                debug!(
+                    "connection {}: stream {:?} was reset by the remote endpoint",
+                    self.description(),
+                    reset_stream_frame.stream_id
+                );
+
+                let mut stream_map = self.stream_map
+                    .lock()
+                    .expect("failed to obtain stream_map lock");
+
+                // the stream may have already been forgotten locally
+                let _ = stream_map.forget_stream(reset_stream_frame.stream_id);
+            }
+            Frame::PathChallenge(path_challenge_frame) => {
+                self.enqueue_control_frame(Frame::PathResponse(PathResponseFrame {
+                    data: path_challenge_frame.data,
+                }));
+            }
+            Frame::ConnectionClose(connection_close_frame) => {
+                bail!(ErrorKind::ConnectionClosedByPeer(
+                    connection_close_frame.error_code,
+                    connection_close_frame.reason_phrase
+                ));
+            }
+            Frame::ApplicationClose(application_close_frame) => {
+                bail!(ErrorKind::ConnectionClosedByPeerApplication(
+                    application_close_frame.application_error_code,
+                    application_close_frame.reason_phrase
+                ));
+            }
+            Frame::NewToken(new_token_frame) => {
+                self.perspective.on_new_token(new_token_frame.token)?
+            }
+            Frame::HandshakeDone if P::role() == Role::Client => {
+                // the server has confirmed the handshake (RFC 9001 section 4.9.2)
+                let mut packet_number_spaces = self.packet_number_spaces
+                    .lock()
+                    .expect("failed to lock packet_number_spaces");
+                self.discard_keys(&mut packet_number_spaces, PacketNumberSpace::Handshake);
+            }
+            Frame::NewConnectionId(new_connection_id_frame) => {
+                // TODO LH move to the new connection id once migration is supported, until then
+                // only its stateless reset token is used
+                self.perspective.stateless_reset_tokens().insert(
+                    self.local_connection_id(),
+                    new_connection_id_frame.stateless_reset_token,
+                );
+            }
+            Frame::PathResponse(_)
+            | Frame::Blocked(_)
+            | Frame::StreamBlocked(_)
+            | Frame::StreamIdBlocked(_)
+            | Frame::StreamsBlocked(_)
+            | Frame::MaxStreamId(_)
+            | Frame::MaxStreams(_)
+            | Frame::RetireConnectionId(_)
+            | Frame::StopSending(_)
+            | Frame::HandshakeDone => {
+                debug!(
+                    "connection {}: ignoring frame {:?}",
+                    self.description(),
+                    frame
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_stream_frame(
+        &self,
+        packet_number_space: PacketNumberSpace,
+        stream_frame: StreamFrame,
+        early_data: bool,
+    ) -> Result<()> {
+        let stream_id = stream_frame.stream_id;
+
+        if stream_id.is_crypto_stream() {
+            self.handle_crypto_data(packet_number_space, stream_frame.offset, stream_frame.data);
+            return Ok(());
+        }
+
+        let (stream_map_entry, is_new) = {
+            let mut stream_map = self.stream_map
+                .lock()
+                .expect("failed to obtain stream_map lock");
+
+            stream_map.get_or_open_incoming_stream(
+                stream_id,
+                self.perspective.max_incoming_data_per_stream().into(),
+            )
+        };
+
+        match stream_map_entry {
+            StreamMapEntry::Dead => {
+                debug!(
+                    "stream {:?}: discarding frame for dead stream",
+                    stream_id
+                );
+            }
+            StreamMapEntry::Live(stream_state) => {
+                {
+                    let mut stream_state = stream_state
+                        .lock()
+                        .expect("failed to obtain stream_state lock");
+
+                    stream_state.enqueue_read(
+                        stream_frame.offset,
+                        stream_frame.data,
+                        stream_frame.finished,
+                    )?;
+
+                    if early_data {
+                        stream_state.on_early_data_received();
+                    }
+                }
+
+                if is_new {
+                    debug!("stream {:?}: opened by the remote endpoint", stream_id);
+
+                    let mut new_incoming_streams = self.new_incoming_streams
+                        .lock()
+                        .expect("failed to lock new_incoming_streams");
+
+                    new_incoming_streams.push_back((stream_id, stream_state));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Buffers crypto data received in `packet_number_space` until the TLS session reads it.
+    fn handle_crypto_data(
+        &self,
+        packet_number_space: PacketNumberSpace,
+        offset: StreamOffset,
+        data: Bytes,
+    ) {
+        let crypto_stream_space = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let crypto_stream_space = packet_number_spaces.crypto_stream_space(packet_number_space);
+            packet_number_spaces
+                .get_mut(crypto_stream_space)
+                .crypto_stream
+                .on_data_received(offset.into(), data.clone());
+
+            crypto_stream_space
+        };
+
+        if crypto_stream_space == PacketNumberSpace::Initial {
+            self.on_crypto_data(offset, data);
+        }
+    }
+
+    /// Keeps the ClientHello from the start of the Initial crypto data a server receives.
+    fn on_crypto_data(&self, offset: StreamOffset, data: Bytes) {
+        if P::role() != Role::Server {
+            return;
+        }
+
+        let mut client_hello = self.client_hello
+            .lock()
+            .expect("failed to lock client_hello");
+
+        // a retransmitted ClientHello is the same as the first
+        if client_hello.is_some() {
+            return;
+        }
+
+        let mut client_hello_buffer = self.client_hello_buffer
+            .lock()
+            .expect("failed to lock client_hello_buffer");
+
+        *client_hello = match client_hello_buffer.push(offset.into(), data) {
+            None => return,
+            Some(message) => match ClientHello::parse(message) {
+                Ok(parsed) => Some(Some(parsed)),
+                Err(error) => {
+                    debug!(
+                        "connection {}: 0-RTT data will be rejected, {}",
+                        self.description(),
+                        error
+                    );
+                    Some(None)
+                }
+            },
+        };
+
+        *client_hello_buffer = ClientHelloBuffer::new();
+    }
+
+    fn handle_max_stream_data_frame(&self, max_stream_data_frame: MaxStreamDataFrame) -> Result<()> {
+        let stream_map_entry = {
+            let stream_map = self.stream_map
+                .lock()
+                .expect("failed to obtain stream_map lock");
+            stream_map.get_stream(max_stream_data_frame.stream_id)?
+        };
+
+        if let StreamMapEntry::Live(stream_state) = stream_map_entry {
+            let mut stream_state = stream_state
+                .lock()
+                .expect("failed to obtain stream_state lock");
+
+            if stream_state.advance_max_outgoing_data(max_stream_data_frame.maximum_stream_data) {
+                debug!(
+                    "stream {:?}: advanced outgoing max data to {}",
+                    max_stream_data_frame.stream_id, max_stream_data_frame.maximum_stream_data
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_ack_frame(
+        &self,
+        packet_number_space: PacketNumberSpace,
+        ack_frame: &AckFrame,
+    ) -> Result<()> {
+        let now = Instant::now();
+        let ack_delay = ack_frame.decoded_ack_delay(self.remote_ack_delay_exponent());
+        let max_ack_delay = self.remote_max_ack_delay(packet_number_space);
+
+        let (lost_packets, rejected_packets) = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            // an ACK frame only acknowledges packets in the space it was received in
+            let space_state = packet_number_spaces.get_mut(packet_number_space);
+
+            let acknowledged_packets = space_state.on_ack_frame_received(ack_frame)?;
+
+            // 0-RTT packets the server rejected will never be acknowledged, their frames are
+            // sent again in 1-RTT packets
+            let rejected_packets = match space_state
+                .early_data
+                .on_packets_acknowledged(&acknowledged_packets)
+            {
+                Some(largest_zero_rtt_packet) => space_state
+                    .loss_detector
+                    .discard_packets_up_to(largest_zero_rtt_packet),
+                None => Vec::new(),
+            };
+
+            let mut rtt_estimator = self.rtt_estimator
+                .lock()
+                .expect("failed to lock rtt_estimator");
+
+            // the RTT is only sampled when the largest acknowledged is newly acknowledged
+            if let Some(largest_newly_acknowledged) = acknowledged_packets.last() {
+                let is_largest_acknowledged = ack_frame.largest_acknowledged()
+                    == Some(u64::from(largest_newly_acknowledged.packet_number));
+
+                if is_largest_acknowledged
+                    && acknowledged_packets
+                        .iter()
+                        .any(|sent_packet| sent_packet.ack_eliciting)
+                {
+                    rtt_estimator.update(
+                        now.duration_since(largest_newly_acknowledged.time_sent),
+                        ack_delay,
+                        max_ack_delay,
+                    );
+                }
+            }
+
+            if !acknowledged_packets.is_empty() {
+                let mut probe_timeout_count = self.probe_timeout_count
+                    .lock()
+                    .expect("failed to lock probe_timeout_count");
+                *probe_timeout_count = 0;
+            }
+
+            let lost_packets = space_state
+                .loss_detector
+                .detect_lost_packets(now, &rtt_estimator);
 
-        let write_clear =
-            CryptoState::for_handshake(client_connection_id, P::handshake_send_label())?;
+            let mut congestion_controller = self.congestion_controller
+                .lock()
+                .expect("failed to lock congestion_controller");
 
-        let read_clear =
-            CryptoState::for_handshake(client_connection_id, P::handshake_receive_label())?;
+            for sent_packet in acknowledged_packets
+                .iter()
+                .filter(|sent_packet| sent_packet.ack_eliciting)
+            {
+                congestion_controller.on_packet_acknowledged(
+                    packet_number_space,
+                    sent_packet.packet_number,
+                    sent_packet.size,
+                    sent_packet.time_sent,
+                    &rtt_estimator,
+                    now,
+                );
+            }
 
-        let aead_clear = AeadPair {
-            write: write_clear,
-            read: read_clear,
+            on_packets_lost(
+                &mut **congestion_controller,
+                packet_number_space,
+                &lost_packets,
+                &rtt_estimator,
+                max_ack_delay,
+                now,
+            );
+
+            on_packets_discarded(
+                &mut **congestion_controller,
+                packet_number_space,
+                &rejected_packets,
+            );
+
+            (lost_packets, rejected_packets)
         };
 
-        let incoming_flow_control =
-            FlowControl::with_initial_max(perspective.max_incoming_data().into());
+        if !lost_packets.is_empty() || !rejected_packets.is_empty() {
+            let mut stream_frames = self.pending_stream_frames
+                .lock()
+                .expect("failed to lock pending_stream_frames");
 
-        let connection = Self {
-            local_connection_id,
-            remote_connection_id,
-            perspective,
-            stream_map: Mutex::new(P::create_stream_map()),
-            aead_clear,
-            state: Arc::new(Mutex::new(State::Initializing)),
-            incoming_flow_control: Mutex::new(incoming_flow_control),
-            outgoing_flow_control: Mutex::default(),
-            pending_stream_frames: Mutex::default(),
-            remote_address,
-        };
+            self.requeue_lost_frames(packet_number_space, lost_packets, &mut stream_frames);
 
-        debug!("created new connection {}", connection.description());
+            // the rejected data was written first so it goes ahead of the lost frames
+            let rejected_frames = rejected_packets
+                .into_iter()
+                .flat_map(|sent_packet| sent_packet.retransmittable_frames)
+                .collect();
+            self.requeue_frames(packet_number_space, rejected_frames, &mut stream_frames);
+        }
 
-        Ok(connection)
+        Ok(())
     }
 
-    pub fn description(&self) -> String {
-        format!(
-            "[{:?}] connection {:?}->{:?}",
-            P::role(),
-            self.local_connection_id,
-            self.remote_connection_id
-        )
+    /// The exponent the remote endpoint applies to the ACK delay of the ACK frames it sends.
+    fn remote_ack_delay_exponent(&self) -> u8 {
+        let remote_transport_parameters = self.remote_transport_parameters
+            .lock()
+            .expect("failed to lock remote_transport_parameters");
+
+        remote_transport_parameters
+            .as_ref()
+            .and_then(|transport_parameters| transport_parameters.ack_delay_exponent)
+            .unwrap_or(AckManager::DEFAULT_ACK_DELAY_EXPONENT)
     }
 
-    pub fn handshake(
-        &self,
-        crypto_stream: DataStream<P>,
-    ) -> impl Future<Item = (), Error = Error> + Send
-    where
-        P::TlsSession: 'static,
-    {
-        let state = self.state.clone();
+    /// The longest the remote endpoint delays acknowledging packets in `packet_number_space`.
+    fn remote_max_ack_delay(&self, packet_number_space: PacketNumberSpace) -> Duration {
+        match packet_number_space {
+            PacketNumberSpace::ApplicationData => {
+                let remote_transport_parameters = self.remote_transport_parameters
+                    .lock()
+                    .expect("failed to lock remote_transport_parameters");
+
+                remote_transport_parameters
+                    .as_ref()
+                    .and_then(|transport_parameters| transport_parameters.max_ack_delay)
+                    .unwrap_or_else(|| {
+                        Duration::from_millis(AckManager::DEFAULT_MAX_ACK_DELAY_MILLIS)
+                    })
+            }
+            PacketNumberSpace::Initial | PacketNumberSpace::Handshake => Duration::from_secs(0),
+        }
+    }
+
+    /// When the loss detection timer should next fire, `None` if nothing is waiting to be
+    /// acknowledged.
+    fn loss_detection_deadline(&self) -> Option<Instant> {
+        let packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        // packets waiting on the time threshold are declared lost before any probe is sent
+        let loss_time = PacketNumberSpace::all()
+            .iter()
+            .filter_map(|&packet_number_space| {
+                packet_number_spaces
+                    .get(packet_number_space)
+                    .loss_detector
+                    .loss_time()
+            })
+            .min();
 
-        self.perspective
-            .handshake(crypto_stream)
-            .and_then(move |tls_stream| {
-                let (_, session) = tls_stream.get_ref();
+        if loss_time.is_some() {
+            return loss_time;
+        }
 
-                let crypto_write = CryptoState::from_tls(session, P::tls_exporter_send_label())?;
-                let crypto_read = CryptoState::from_tls(session, P::tls_exporter_receive_label())?;
+        let rtt_estimator = self.rtt_estimator
+            .lock()
+            .expect("failed to lock rtt_estimator");
+        let probe_timeout_count = *self.probe_timeout_count
+            .lock()
+            .expect("failed to lock probe_timeout_count");
 
-                let mut state = state.lock().expect("failed to lock state");
+        let backoff = 1u32 << cmp::min(probe_timeout_count, MAX_PROBE_TIMEOUT_BACKOFF);
 
-                *state = State::Established {
-                    aead_protected: AeadPair {
-                        write: crypto_write,
-                        read: crypto_read,
-                    },
-                };
+        PacketNumberSpace::all()
+            .iter()
+            .filter_map(|&packet_number_space| {
+                let time_of_last_ack_eliciting_packet = packet_number_spaces
+                    .get(packet_number_space)
+                    .loss_detector
+                    .time_of_last_ack_eliciting_packet()?;
 
-                Ok(())
+                let probe_timeout = rtt_estimator
+                    .probe_timeout(self.remote_max_ack_delay(packet_number_space))
+                    * backoff;
+
+                Some(time_of_last_ack_eliciting_packet + probe_timeout)
             })
+            .min()
     }
-}
 
-impl<P: Perspective> Connection<P> {
-    pub fn crypto_stream(&self) -> (StreamId, StreamMapEntry) {
-        let mut stream_map = self.stream_map
+    /// Declares packets lost by the time threshold or queues a probe when the loss detection
+    /// timer fires.
+    fn on_loss_detection_timeout(&self) {
+        let now = Instant::now();
+
+        let mut stream_frames = self.pending_stream_frames
             .lock()
-            .expect("failed to obtain stream_map lock");
+            .expect("failed to lock pending_stream_frames");
+
+        let probe_frames = {
+            let packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            let has_lost_packets = PacketNumberSpace::all().iter().any(|&packet_number_space| {
+                packet_number_spaces
+                    .get(packet_number_space)
+                    .loss_detector
+                    .loss_time()
+                    .map_or(false, |loss_time| loss_time <= now)
+            });
+
+            if has_lost_packets {
+                None
+            } else {
+                let mut probe_timeout_count = self.probe_timeout_count
+                    .lock()
+                    .expect("failed to lock probe_timeout_count");
+                *probe_timeout_count += 1;
 
-        stream_map.crypto_stream()
+                let mut pending_probe_packets = self.pending_probe_packets
+                    .lock()
+                    .expect("failed to lock pending_probe_packets");
+                *pending_probe_packets = 1;
+
+                debug!(
+                    "connection {}: probe timeout {}",
+                    self.description(),
+                    *probe_timeout_count
+                );
+
+                // the oldest unacknowledged data of the lowest space waiting on an ACK frame is
+                // sent again, if there is none a PING still elicits an ACK frame
+                let probe_space = packet_number_spaces.probe_space();
+                let probe_frames = probe_space
+                    .and_then(|packet_number_space| {
+                        packet_number_spaces
+                            .get(packet_number_space)
+                            .loss_detector
+                            .oldest_retransmittable_frames()
+                    })
+                    .map_or_else(|| vec![Frame::Ping], |frames| frames.to_vec());
+
+                Some((
+                    probe_space.unwrap_or(PacketNumberSpace::ApplicationData),
+                    probe_frames,
+                ))
+            }
+        };
+
+        match probe_frames {
+            Some((probe_space, probe_frames)) => {
+                self.requeue_frames(probe_space, probe_frames, &mut stream_frames)
+            }
+            None => self.detect_lost_packets(&mut stream_frames),
+        }
     }
 
-    pub fn new_stream(&self, stream_type: StreamType) -> (StreamId, Arc<Mutex<StreamState>>) {
-        let mut stream_map = self.stream_map
-            .lock()
-            .expect("failed to obtain stream_map lock");
+    /// Fires the loss detection timer if it is due and rearms it for the next deadline.
+    fn poll_loss_detection_timer(&self) -> Result<()> {
+        let deadline = match self.loss_detection_deadline() {
+            Some(deadline) => deadline,
+            None => {
+                let mut loss_detection_timer = self.loss_detection_timer
+                    .lock()
+                    .expect("failed to lock loss_detection_timer");
+                loss_detection_timer.0 = None;
 
-        // TODO LH Use the transport parameters to determine how much can be sent on each newly created stream
-        let max_outgoing_data_per_stream: u32 = unimplemented!();
+                return Ok(());
+            }
+        };
 
-        stream_map.next_outgoing_stream(
-            stream_type,
-            self.perspective.max_incoming_data_per_stream().into(),
-            max_outgoing_data_per_stream.into(),
-        )
-    }
+        let deadline = if deadline <= Instant::now() {
+            trace!("connection {}: loss detection timer fired", self.description());
 
-    pub fn local_connection_id(&self) -> ConnectionId {
-        self.local_connection_id
-    }
+            self.on_loss_detection_timeout();
 
-    pub fn remote_connection_id(&self) -> ConnectionId {
-        self.remote_connection_id
-    }
+            // a probe which cannot be sent yet stays queued for the next transmission
+            self.poll_try_transmit()?;
 
-    fn should_transmit(&self, stream_frames: &VecDeque<StreamFrame>) -> bool {
-        // TODO LH Write the actual logic over whether we should transmit
-        !stream_frames.is_empty()
+            match self.loss_detection_deadline() {
+                Some(deadline) => deadline,
+                None => return Ok(()),
+            }
+        } else {
+            deadline
+        };
+
+        self.poll_timer(&self.loss_detection_timer, deadline)
     }
 
-    pub fn poll_try_transmit(&self) -> Poll<(), Error> {
-        trace!("determining whether to transmit a new packet");
+    /// Arms `timer` to wake the current task at `deadline`.
+    ///
+    /// The timer is only registered when polled on the reactor's thread.
+    fn poll_timer(&self, timer: &Mutex<DebugIt<Option<Timeout>>>, deadline: Instant) -> Result<()> {
+        let mut timer = timer.lock().expect("failed to lock timer");
 
-        let mut stream_frames = self.pending_stream_frames
-            .lock()
-            .expect("failed to lock pending_stream_frames");
+        if let Some(timeout) = timer.0.as_mut() {
+            timeout.reset(deadline);
+        }
 
-        if self.should_transmit(&*stream_frames) {
-            self.poll_transmit_stream_frames(&mut *stream_frames)
-        } else {
-            Ok(Async::NotReady)
+        if timer.0.is_none() {
+            let handle = match self.perspective.remote().handle() {
+                Some(handle) => handle,
+                None => {
+                    trace!("not on the reactor thread, the timer is not armed");
+                    return Ok(());
+                }
+            };
+
+            let timeout =
+                Timeout::new_at(deadline, &handle).chain_err(|| ErrorKind::FailedToCreateTimer)?;
+            timer.0 = Some(timeout);
+        }
+
+        if let Some(timeout) = timer.0.as_mut() {
+            // polling registers the current task to be woken once the deadline passes
+            if timeout
+                .poll()
+                .chain_err(|| ErrorKind::FailedToPollTimer)?
+                .is_ready()
+            {
+                ::futures::task::current().notify();
+            }
         }
+
+        Ok(())
     }
 
-    fn poll_transmit(&self) -> Poll<(), Error> {
-        let mut stream_frames = self.pending_stream_frames
-            .lock()
-            .expect("failed to lock pending_stream_frames");
+    /// Requeues the frames of packets which have passed the time threshold since an ACK frame was
+    /// last received.
+    fn detect_lost_packets(&self, stream_frames: &mut VecDeque<StreamFrame>) {
+        let now = Instant::now();
+
+        let lost_packets: Vec<(PacketNumberSpace, Vec<SentPacket>)> = {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+            let rtt_estimator = self.rtt_estimator
+                .lock()
+                .expect("failed to lock rtt_estimator");
+            let mut congestion_controller = self.congestion_controller
+                .lock()
+                .expect("failed to lock congestion_controller");
+
+            PacketNumberSpace::all()
+                .iter()
+                .map(|&packet_number_space| {
+                    let loss_detector = &mut packet_number_spaces
+                        .get_mut(packet_number_space)
+                        .loss_detector;
+
+                    if loss_detector
+                        .loss_time()
+                        .map_or(false, |loss_time| loss_time <= now)
+                    {
+                        let lost_packets = loss_detector.detect_lost_packets(now, &rtt_estimator);
+
+                        on_packets_lost(
+                            &mut **congestion_controller,
+                            packet_number_space,
+                            &lost_packets,
+                            &rtt_estimator,
+                            self.remote_max_ack_delay(packet_number_space),
+                            now,
+                        );
+
+                        (packet_number_space, lost_packets)
+                    } else {
+                        (packet_number_space, Vec::new())
+                    }
+                })
+                .collect()
+        };
 
-        self.poll_transmit_stream_frames(&mut *stream_frames)
+        for (packet_number_space, lost_packets) in lost_packets {
+            self.requeue_lost_frames(packet_number_space, lost_packets, stream_frames);
+        }
     }
 
-    fn poll_transmit_stream_frames(
+    /// Queues the retransmittable frames of `lost_packets` to be sent again ahead of any new
+    /// data.
+    fn requeue_lost_frames(
         &self,
+        packet_number_space: PacketNumberSpace,
+        lost_packets: Vec<SentPacket>,
         stream_frames: &mut VecDeque<StreamFrame>,
-    ) -> Poll<(), Error> {
-        // TODO LH Actually write the stream frames
+    ) {
+        let mut lost_frames = Vec::new();
 
-        while !stream_frames.is_empty() {
-            trace!("transmitting new packet");
+        for lost_packet in lost_packets {
+            debug!(
+                "connection {}: packet {:?} was lost",
+                self.description(),
+                lost_packet.packet_number
+            );
 
-            stream_frames.clear();
-
-            let outgoing_packet = OutgoingPacket {
-                destination_address: self.remote_address,
-                packet_header: PacketHeader::Long(LongHeader {
-                    packet_type: LongHeaderPacketType::Initial,
-                    version: Version::DRAFT_IETF_08,
-                    destination_connection_id: Some(self.remote_connection_id),
-                    source_connection_id: Some(self.local_connection_id),
-                    payload_length: 0u32.into(),
-                    partial_packet_number: PartialPacketNumber::from_packet_number(
-                        0u32.into(),
-                        0u32.into(),
-                    )?,
-                }),
-                data: Bytes::new(),
-                encryption_level: EncryptionLevel::Unencrypted,
-            };
+            lost_frames.extend(lost_packet.retransmittable_frames);
+        }
 
-            if self.perspective
-                .poll_send_packet(outgoing_packet)?
-                .is_not_ready()
-            {
-                return Ok(Async::NotReady);
+        self.requeue_frames(packet_number_space, lost_frames, stream_frames);
+    }
+
+    /// Queues `frames` sent in `packet_number_space` to be sent again ahead of any new data.
+    fn requeue_frames(
+        &self,
+        packet_number_space: PacketNumberSpace,
+        frames: Vec<Frame>,
+        stream_frames: &mut VecDeque<StreamFrame>,
+    ) {
+        let mut requeued_stream_frames = Vec::new();
+        let mut requeued_crypto_frames = Vec::new();
+
+        for frame in frames {
+            match frame {
+                Frame::Stream(stream_frame) => if stream_frame.stream_id.is_crypto_stream() {
+                    requeued_crypto_frames.push(stream_frame)
+                } else {
+                    requeued_stream_frames.push(stream_frame)
+                },
+                Frame::Crypto(crypto_frame) => requeued_crypto_frames.push(StreamFrame {
+                    finished: false,
+                    offset: crypto_frame.offset,
+                    stream_id: StreamId::crypto_stream_id(),
+                    data: crypto_frame.data,
+                }),
+                frame => self.enqueue_control_frame(frame),
             }
+        }
 
-            debug!("transmitted new packet");
+        if !requeued_crypto_frames.is_empty() {
+            let mut packet_number_spaces = self.packet_number_spaces
+                .lock()
+                .expect("failed to lock packet_number_spaces");
+
+            // crypto data is sent again at the encryption level it was first sent at
+            let pending_crypto_frames = &mut packet_number_spaces
+                .get_mut(packet_number_space)
+                .pending_crypto_frames;
+
+            for crypto_frame in requeued_crypto_frames.into_iter().rev() {
+                pending_crypto_frames.push_front(crypto_frame);
+            }
         }
 
-        Ok(().into())
+        for stream_frame in requeued_stream_frames.into_iter().rev() {
+            stream_frames.push_front(stream_frame);
+        }
     }
 
-    pub fn poll_process_incoming_packets(&self) -> Poll<(), Error> {
-        trace!("checking for a new incoming packets");
+    /// Whether any data written to `stream_id` has yet to be acknowledged.
+    fn has_unacknowledged_stream_data(&self, stream_id: StreamId) -> bool {
+        let carries_stream_data = |frame: &Frame| match frame {
+            Frame::Stream(stream_frame) => stream_frame.stream_id == stream_id,
+            Frame::Crypto(_) => stream_id.is_crypto_stream(),
+            _ => false,
+        };
 
-        while let Async::Ready(incoming_packets) = self.perspective
-            .poll_incoming_packets(self.local_connection_id())?
         {
-            for incoming_packet in incoming_packets {
-                trace!("found new incoming packet");
-                // TODO LH Do something with the packet
+            let stream_frames = self.pending_stream_frames
+                .lock()
+                .expect("failed to lock pending_stream_frames");
+
+            if stream_frames
+                .iter()
+                .any(|stream_frame| stream_frame.stream_id == stream_id)
+            {
+                return true;
             }
         }
 
-        trace!("no more incoming packets");
+        let packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        PacketNumberSpace::all().iter().any(|&packet_number_space| {
+            packet_number_spaces
+                .get(packet_number_space)
+                .loss_detector
+                .has_unacknowledged_frame(&carries_stream_data)
+        })
+    }
+
+    /// Polls for the next stream opened by the remote endpoint.
+    pub fn poll_next_incoming_stream(&self) -> Poll<(StreamId, Arc<Mutex<StreamState>>), Error> {
+        loop {
+            let new_incoming_stream = {
+                let mut new_incoming_streams = self.new_incoming_streams
+                    .lock()
+                    .expect("failed to lock new_incoming_streams");
+                new_incoming_streams.pop_front()
+            };
 
-        Ok(Async::NotReady)
+            if let Some(new_incoming_stream) = new_incoming_stream {
+                return Ok(Async::Ready(new_incoming_stream));
+            }
+
+            try_ready!(self.poll_process_incoming_packets());
+        }
     }
 
     pub fn poll_flush_stream(&self, stream_id: StreamId) -> Poll<(), Error> {
@@ -249,22 +2737,30 @@ impl<P: Perspective> Connection<P> {
 
         self.enqueue_stream_frames_from_stream_map_entry(&stream_map_entry);
 
-        while self.poll_transmit()?.is_ready() {
-            // TODO LH Stop when all frames for this stream have been sent
+        // the stream frames are transmitted in order so once everything pending has been
+        // transmitted the frames for this stream have been too
+        while self.poll_transmit()?.is_not_ready() {
+            // the ACK frames which open the congestion window have to be processed before
+            // anything more can be transmitted
+            try_ready!(self.poll_process_incoming_packets());
         }
 
-        Ok(Async::NotReady)
+        Ok(().into())
     }
 
     /// This also guarantees that the remote end acknowledged all of the stream
     /// data.
     pub fn poll_flush_stream_and_wait_for_ack(&self, stream_id: StreamId) -> Poll<(), Error> {
-        self.poll_flush_stream(stream_id)?;
+        loop {
+            // any lost data is requeued so flushing again retransmits it
+            try_ready!(self.poll_flush_stream(stream_id));
 
-        // TODO LH Wait for acknowledgement
-        unimplemented!();
+            if !self.has_unacknowledged_stream_data(stream_id) {
+                return Ok(().into());
+            }
 
-        Ok(().into())
+            try_ready!(self.poll_process_incoming_packets());
+        }
     }
 
     fn enqueue_stream_frames(&self) {
@@ -347,7 +2843,7 @@ impl<P: Perspective> Connection<P> {
         Ok(().into())
     }
 
-    pub fn handle_negotiated_session<S: Session>(&self, tls_session: &S) -> Result<()>
+    pub fn handle_negotiated_session<S: Session + QuicExt>(&self, tls_session: &S) -> Result<()>
     where
         <<P as Perspective>::IncomingTransportMessageParameters as Readable>::Context: Default,
     {
@@ -355,12 +2851,191 @@ impl<P: Perspective> Connection<P> {
             .get_quic_transport_parameters()
             .ok_or_else(|| ErrorKind::TransportParametersAreRequired)?;
 
+        self.set_remote_transport_parameters(transport_parameter_bytes)?;
+        self.register_remote_stateless_reset_token();
+
+        let application_protocol = tls_session.get_alpn_protocol().map(str::to_owned);
+        let alpn_protocols = self.perspective.alpn_protocols();
+
+        if application_protocol.is_none() && !alpn_protocols.is_empty() {
+            self.close(
+                ErrorCode::CryptoError(ErrorCode::NO_APPLICATION_PROTOCOL_ALERT),
+                "no application protocol",
+            )?;
+            bail!(ErrorKind::NoCommonApplicationProtocol(alpn_protocols.to_vec()));
+        }
+
+        debug!(
+            "connection {}: negotiated application protocol {:?}",
+            self.description(),
+            application_protocol
+        );
+
+        let mut negotiated_application_protocol = self.application_protocol
+            .lock()
+            .expect("failed to lock application_protocol");
+        *negotiated_application_protocol = application_protocol;
+
+        Ok(())
+    }
+
+    /// Checks the peer's version_information transport parameter agrees with the versions the
+    /// connection was in, so an attacker cannot have moved it to another compatible version.
+    pub fn check_version_information(&self) -> Result<()> {
+        let remote_transport_parameters = self.remote_transport_parameters
+            .lock()
+            .expect("failed to lock remote_transport_parameters");
+
+        let version_information = &remote_transport_parameters
+            .as_ref()
+            .ok_or_else(|| ErrorKind::TransportParametersAreRequired)?
+            .version_information;
+
+        let version = self.version();
+        let initial_version = self.perspective.initial_version();
+
+        // peers without compatible version negotiation do not send it, their connections stay in
+        // the version they started in
+        let version_information = match version_information {
+            Some(version_information) => version_information,
+            None if P::role() == Role::Server && version != initial_version => {
+                bail!(ErrorKind::CompatibleVersionNotOffered(version, Vec::new()))
+            }
+            None => return Ok(()),
+        };
+
+        // a client chose the version of its first Initial packet and a server the version it
+        // answered in
+        let chosen_version = match P::role() {
+            Role::Client => version,
+            Role::Server => initial_version,
+        };
+        if version_information.chosen_version != chosen_version {
+            bail!(ErrorKind::ChosenVersionMismatch(
+                chosen_version,
+                version_information.chosen_version
+            ));
+        }
+
+        let available_versions = &version_information.available_versions;
+        if P::role() == Role::Server && version != initial_version
+            && !available_versions.contains(&version)
+        {
+            bail!(ErrorKind::CompatibleVersionNotOffered(
+                version,
+                available_versions.clone()
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The application protocol agreed in the handshake, `None` until it completes or if no
+    /// application protocols were configured.
+    pub fn application_protocol(&self) -> Option<String> {
+        let application_protocol = self.application_protocol
+            .lock()
+            .expect("failed to lock application_protocol");
+
+        application_protocol.clone()
+    }
+
+    /// Starts sending stream data in 0-RTT packets sealed with keys derived from the
+    /// `client_early_traffic_secret` the TLS session handed out when it resumed a session of
+    /// `supported_cipher_suite`.
+    ///
+    /// The transport parameters the server sent in the previous connection limit what may be
+    /// sent until the handshake completes.
+    pub fn start_early_data(
+        &self,
+        early_secret: &[u8],
+        supported_cipher_suite: &SupportedCipherSuite,
+        remembered_transport_parameters: &[u8],
+    ) -> Result<()>
+    where
+        <<P as Perspective>::IncomingTransportMessageParameters as Readable>::Context: Default,
+    {
+        let zero_rtt_keys =
+            CryptoState::from_secret(supported_cipher_suite, early_secret, self.version())?;
+
+        self.set_remote_transport_parameters(remembered_transport_parameters)?;
+
+        let mut packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        packet_number_spaces
+            .get_mut(PacketNumberSpace::ApplicationData)
+            .early_data
+            .on_keys_installed(zero_rtt_keys);
+
+        debug!("connection {}: sending 0-RTT data", self.description());
+
+        Ok(())
+    }
+
+    /// Whether the data sent before the handshake completed was accepted.
+    pub fn early_data(&self) -> EarlyData {
+        let packet_number_spaces = self.packet_number_spaces
+            .lock()
+            .expect("failed to lock packet_number_spaces");
+
+        packet_number_spaces
+            .get(PacketNumberSpace::ApplicationData)
+            .early_data
+            .status()
+    }
+
+    /// Registers the stateless reset token the remote endpoint sent in its transport parameters,
+    /// so its stateless resets fail the connection.
+    fn register_remote_stateless_reset_token(&self) {
+        let remote_transport_parameters = self.remote_transport_parameters
+            .lock()
+            .expect("failed to lock remote_transport_parameters");
+
+        let stateless_reset_token = remote_transport_parameters
+            .as_ref()
+            .and_then(|transport_parameters| {
+                transport_parameters
+                    .role_specific_transport_parameters
+                    .stateless_reset_token()
+            });
+
+        if let Some(stateless_reset_token) = stateless_reset_token {
+            self.perspective
+                .stateless_reset_tokens()
+                .insert(self.local_connection_id(), stateless_reset_token);
+        }
+    }
+
+    fn set_remote_transport_parameters(&self, transport_parameter_bytes: &[u8]) -> Result<()>
+    where
+        <<P as Perspective>::IncomingTransportMessageParameters as Readable>::Context: Default,
+    {
         let transport_parameters: TransportParameters<
             P::IncomingTransportMessageParameters,
-            P::RoleSpecificTransportParameters,
+            P::IncomingRoleSpecificTransportParameters,
         > = TransportParameters::from_bytes(transport_parameter_bytes)?;
 
-        unimplemented!()
+        debug!(
+            "connection {}: negotiated transport parameters {:?}",
+            self.description(),
+            transport_parameters
+        );
+
+        {
+            let mut outgoing_flow_control = self.outgoing_flow_control
+                .lock()
+                .expect("failed to lock outgoing_flow_control");
+            outgoing_flow_control.advance_max(transport_parameters.initial_max_data.into());
+        }
+
+        let mut remote_transport_parameters = self.remote_transport_parameters
+            .lock()
+            .expect("failed to lock remote_transport_parameters");
+        *remote_transport_parameters = Some(transport_parameters);
+
+        Ok(())
     }
 
     pub fn incoming_flow_control(&self) -> &Mutex<FlowControl> {
@@ -371,3 +3046,210 @@ impl<P: Perspective> Connection<P> {
         &self.outgoing_flow_control
     }
 }
+
+impl Connection<ClientPerspective> {
+    /// Checks the server's transport parameters name the connection ids we addressed it with,
+    /// which shows no attacker changed them with a Retry packet of its own.
+    pub fn check_connection_id_transport_parameters(&self) -> Result<()> {
+        let remote_transport_parameters = self.remote_transport_parameters
+            .lock()
+            .expect("failed to lock remote_transport_parameters");
+
+        let server_transport_parameters = &remote_transport_parameters
+            .as_ref()
+            .ok_or_else(|| ErrorKind::TransportParametersAreRequired)?
+            .role_specific_transport_parameters;
+
+        let original_destination_connection_id =
+            server_transport_parameters.original_destination_connection_id;
+        if original_destination_connection_id != Some(self.original_remote_connection_id) {
+            bail!(ErrorKind::OriginalDestinationConnectionIdMismatch(
+                self.original_remote_connection_id,
+                original_destination_connection_id
+            ));
+        }
+
+        let expected_retry_source_connection_id = self.retry
+            .lock()
+            .expect("failed to lock retry")
+            .as_ref()
+            .and_then(|retry_packet| retry_packet.source_connection_id);
+
+        let retry_source_connection_id = server_transport_parameters.retry_source_connection_id;
+        if retry_source_connection_id != expected_retry_source_connection_id {
+            bail!(ErrorKind::RetrySourceConnectionIdMismatch(
+                expected_retry_source_connection_id,
+                retry_source_connection_id
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the versions in the server's transport parameters agree with the version we
+    /// connected in, which shows no attacker forced us into it with a Version Negotiation
+    /// packet of its own.
+    pub fn check_version_transport_parameters(&self) -> Result<()> {
+        let remote_transport_parameters = self.remote_transport_parameters
+            .lock()
+            .expect("failed to lock remote_transport_parameters");
+
+        let message_parameters = &remote_transport_parameters
+            .as_ref()
+            .ok_or_else(|| ErrorKind::TransportParametersAreRequired)?
+            .message_parameters;
+
+        let version = self.version();
+        if message_parameters.negotiated_version != version {
+            bail!(ErrorKind::NegotiatedVersionMismatch(
+                version,
+                message_parameters.negotiated_version
+            ));
+        }
+
+        // had the server really sent the Version Negotiation packet, the version we tried first
+        // is not one it supports and we would pick the same version from those it does
+        let initial_version = self.perspective.initial_version();
+        let negotiated_version = self.perspective.version();
+        let supported_versions = &message_parameters.supported_versions;
+        if initial_version != negotiated_version
+            && (supported_versions.contains(&initial_version)
+                || Version::find_highest_supported(supported_versions)
+                    != Some(negotiated_version))
+        {
+            let mut supported_versions: Vec<_> = supported_versions.iter().cloned().collect();
+            supported_versions.sort();
+
+            bail!(ErrorKind::VersionDowngradeDetected(
+                negotiated_version,
+                supported_versions
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Creates the connection which replaces this one in the version the server asked for in
+    /// its Version Negotiation packet, `None` if it sent none.
+    pub fn restart_in_negotiated_version(&self) -> Result<Option<Self>> {
+        let version = match *self.negotiated_version
+            .lock()
+            .expect("failed to lock negotiated_version")
+        {
+            Some(version) => version,
+            None => return Ok(None),
+        };
+
+        debug!(
+            "connection {}: connecting again in version {}",
+            self.description(),
+            version
+        );
+
+        // nothing is kept from this connection, the server has not seen its connection ids
+        let local_connection_id = ConnectionId::generate_with_len(self.local_connection_id.len())?;
+        let remote_connection_id =
+            ConnectionId::generate_with_len(self.original_remote_connection_id.len())?;
+
+        let connection = Connection::new(
+            local_connection_id,
+            remote_connection_id,
+            self.perspective.restart_in_version(version)?,
+            self.remote_address,
+        )?;
+
+        Ok(Some(connection))
+    }
+}
+
+/// Derives the Initial keys of `version` from `initial_connection_id`, the destination connection
+/// id of the client's Initial packets.
+fn initial_keys<P: Perspective>(
+    initial_connection_id: ConnectionId,
+    version: Version,
+) -> Result<AeadPair> {
+    Ok(AeadPair {
+        write: CryptoState::for_initial(initial_connection_id, P::initial_send_label(), version)?,
+        read: CryptoState::for_initial(initial_connection_id, P::initial_receive_label(), version)?,
+    })
+}
+
+/// Informs `congestion_controller` of `lost_packets`, which were sent in `packet_number_space`
+/// where the peer delays acknowledgements by up to `max_ack_delay`.
+fn on_packets_lost(
+    congestion_controller: &mut CongestionController,
+    packet_number_space: PacketNumberSpace,
+    lost_packets: &[SentPacket],
+    rtt_estimator: &RttEstimator,
+    max_ack_delay: Duration,
+    now: Instant,
+) {
+    let mut lost_ack_eliciting = lost_packets
+        .iter()
+        .filter(|sent_packet| sent_packet.ack_eliciting);
+
+    let earliest_lost = match lost_ack_eliciting.next() {
+        Some(earliest_lost) => earliest_lost,
+        None => return,
+    };
+    let largest_lost = lost_ack_eliciting.last().unwrap_or(earliest_lost);
+
+    let (packet_numbers, lost_bytes) = ack_eliciting_packets(lost_packets);
+
+    congestion_controller.on_packets_lost(
+        packet_number_space,
+        &packet_numbers,
+        lost_bytes,
+        largest_lost.time_sent,
+        now,
+    );
+
+    if is_persistent_congestion(
+        earliest_lost.time_sent,
+        largest_lost.time_sent,
+        rtt_estimator,
+        max_ack_delay,
+    ) {
+        congestion_controller.on_persistent_congestion();
+    }
+}
+
+/// Informs `congestion_controller` of `discarded_packets`, which were sent in
+/// `packet_number_space` and will never be acknowledged or declared lost.
+fn on_packets_discarded(
+    congestion_controller: &mut CongestionController,
+    packet_number_space: PacketNumberSpace,
+    discarded_packets: &[SentPacket],
+) {
+    let (packet_numbers, discarded_bytes) = ack_eliciting_packets(discarded_packets);
+
+    if !packet_numbers.is_empty() {
+        congestion_controller.on_packets_discarded(
+            packet_number_space,
+            &packet_numbers,
+            discarded_bytes,
+        );
+    }
+}
+
+/// The packet numbers of the ack-eliciting packets in `sent_packets` and their total size, the
+/// only ones counted towards the bytes in flight.
+fn ack_eliciting_packets(sent_packets: &[SentPacket]) -> (Vec<PacketNumber>, usize) {
+    sent_packets
+        .iter()
+        .filter(|sent_packet| sent_packet.ack_eliciting)
+        .fold((Vec::new(), 0), |(mut packet_numbers, size), sent_packet| {
+            packet_numbers.push(sent_packet.packet_number);
+            (packet_numbers, size + sent_packet.size)
+        })
+}
+
+/// The TLS session of a connection and how far its handshake has got.
+struct TlsHandshake<S> {
+    /// `None` until the handshake starts, a server starts it once the ClientHello has arrived.
+    session: Option<S>,
+    /// The space the crypto data the session writes next is sent in, it moves on each time the
+    /// session hands out secrets.
+    write_space: PacketNumberSpace,
+    complete: bool,
+}
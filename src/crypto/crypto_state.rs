@@ -1,16 +1,18 @@
 use bytes::{Bytes, BytesMut};
 use conv::ValueFrom;
+use crypto::HeaderProtectionKey;
 use debugit::DebugIt;
 use errors::*;
 use frames::Frame;
 use packets::PacketNumber;
-use protocol::{ConnectionId, Readable, Writable};
+use protocol::{ConnectionId, InFormat, Readable, Version, WireFormat, Writable};
 use ring::aead::{self, OpeningKey, SealingKey};
 use ring::digest;
 use ring::hkdf;
 use ring::hmac::SigningKey;
-use rustls::Session;
+use rustls::{Session, SupportedCipherSuite};
 use std::io::Write;
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct CryptoState {
@@ -18,6 +20,9 @@ pub struct CryptoState {
     sealing_key: DebugIt<SealingKey>,
     opening_key: DebugIt<OpeningKey>,
     iv: Bytes,
+    header_protection_key: Option<Arc<HeaderProtectionKey>>,
+    version: Version,
+    wire_format: WireFormat,
 }
 
 static HANDSHAKE_SALT: [u8; 20] = [
@@ -25,7 +30,20 @@ static HANDSHAKE_SALT: [u8; 20] = [
     0xe0, 0x6d, 0x6c, 0x38,
 ];
 
+/// The salt used to derive the initial secrets of version 1 (RFC 9001 section 5.2).
+static V1_INITIAL_SALT: [u8; 20] = [
+    0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8, 0x0c, 0xad,
+    0xcc, 0xbb, 0x7f, 0x0a,
+];
+
+/// The salt used to derive the initial secrets of version 2 (RFC 9369 section 3.3.1).
+static V2_INITIAL_SALT: [u8; 20] = [
+    0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26, 0x9d, 0xcb,
+    0xf9, 0xbd, 0x2e, 0xd9,
+];
+
 impl CryptoState {
+    /// Creates the draft 08 `CryptoState` for the handshake.
     pub fn for_handshake(
         destination_connection_id: ConnectionId,
         label: &str,
@@ -51,7 +69,7 @@ impl CryptoState {
             qhkdf_expand(&handshake_secret, label, hash_algorithm.output_len)?;
         let signing_key = SigningKey::new(hash_algorithm, &our_handshake_secret[..]);
 
-        let crypto_state = Self::new(signing_key, aead_algorithm)?;
+        let crypto_state = Self::new(signing_key, aead_algorithm, Version::DRAFT_IETF_08)?;
 
         debug!(
             "created new crypto state for handshake to connection {:?} with label {}",
@@ -61,7 +79,52 @@ impl CryptoState {
         Ok(crypto_state)
     }
 
-    pub fn from_tls<S: Session>(session: &S, label: &str) -> Result<CryptoState> {
+    /// Creates the `CryptoState` for Initial packets of `version` addressed to
+    /// `destination_connection_id`, `label` is either `client in` or `server in`.
+    pub fn for_initial(
+        destination_connection_id: ConnectionId,
+        label: &str,
+        version: Version,
+    ) -> Result<CryptoState> {
+        trace!(
+            "creating new initial crypto state for connection {:?} in version {} with label {}",
+            destination_connection_id,
+            version,
+            label
+        );
+
+        let hash_algorithm = &digest::SHA256;
+        let aead_algorithm = &aead::AES_128_GCM;
+
+        let initial_salt = match version {
+            Version::V2 => &V2_INITIAL_SALT,
+            _ => &V1_INITIAL_SALT,
+        };
+
+        let salt = SigningKey::new(hash_algorithm, &initial_salt[..]);
+        let initial_secret = hkdf::extract(&salt, destination_connection_id.bytes());
+
+        let our_initial_secret =
+            hkdf_expand_label(&initial_secret, label, hash_algorithm.output_len)?;
+        let signing_key = SigningKey::new(hash_algorithm, &our_initial_secret[..]);
+
+        let crypto_state = Self::new(signing_key, aead_algorithm, version)?;
+
+        debug!(
+            "created new initial crypto state for connection {:?} with label {}",
+            destination_connection_id, label
+        );
+
+        Ok(crypto_state)
+    }
+
+    /// Creates the draft 08 `CryptoState` for 1-RTT packets from keying material exported out of
+    /// the completed handshake of `session` with `label`.
+    pub fn from_tls<S: Session>(
+        session: &S,
+        label: &str,
+        version: Version,
+    ) -> Result<CryptoState> {
         trace!(
             "creating new crypto state using TLS session with label {}",
             label
@@ -78,7 +141,7 @@ impl CryptoState {
             .chain_err(|| ErrorKind::FailedToExportTlsKeyingMaterial)?;
 
         let secret = SigningKey::new(hash_algorithm, &secret[..]);
-        let crypto_state = Self::new(secret, supported_cipher_suite.get_aead_alg())?;
+        let crypto_state = Self::new(secret, supported_cipher_suite.get_aead_alg(), version)?;
 
         debug!(
             "created new crypto state using TLS session with label {}",
@@ -88,15 +151,68 @@ impl CryptoState {
         Ok(crypto_state)
     }
 
-    fn new(secret: SigningKey, aead_algorithm: &'static aead::Algorithm) -> Result<CryptoState> {
-        let key = qhkdf_expand(&secret, "key", aead_algorithm.key_len())?;
+    /// Creates the `CryptoState` for the encryption level of the traffic `secret` the TLS
+    /// `session` handed out, in the cipher suite it negotiated (RFC 9001 section 5.1).
+    pub fn from_tls_secret<S: Session>(
+        session: &S,
+        secret: &[u8],
+        version: Version,
+    ) -> Result<CryptoState> {
+        trace!("creating new crypto state from TLS traffic secret");
+
+        let supported_cipher_suite = session
+            .get_negotiated_ciphersuite()
+            .ok_or_else(|| ErrorKind::FailedToBuildCryptoState)?;
+
+        Self::from_secret(supported_cipher_suite, secret, version)
+    }
+
+    /// Creates the `CryptoState` for the traffic `secret` of `supported_cipher_suite`, which a
+    /// client resuming a session takes from that session as nothing is negotiated before its
+    /// 0-RTT packets are sent.
+    pub fn from_secret(
+        supported_cipher_suite: &SupportedCipherSuite,
+        secret: &[u8],
+        version: Version,
+    ) -> Result<CryptoState> {
+        let secret = SigningKey::new(supported_cipher_suite.get_hash(), secret);
+        let crypto_state = Self::new(secret, supported_cipher_suite.get_aead_alg(), version)?;
+
+        debug!("created new crypto state from TLS traffic secret");
+
+        Ok(crypto_state)
+    }
+
+    fn new(
+        secret: SigningKey,
+        aead_algorithm: &'static aead::Algorithm,
+        version: Version,
+    ) -> Result<CryptoState> {
+        let wire_format = version
+            .wire_format()
+            .ok_or_else(|| ErrorKind::UnsupportedVersion(version))?;
+
+        let (key_label, iv_label, hp_label) = match version {
+            Version::DRAFT_IETF_08 => ("key", "iv", None),
+            Version::V2 => ("quicv2 key", "quicv2 iv", Some("quicv2 hp")),
+            _ => ("quic key", "quic iv", Some("quic hp")),
+        };
+
+        let key = expand(&secret, key_label, aead_algorithm.key_len(), wire_format)?;
 
         // As defined in Section 5.3 of [TLS13], the IV length is the larger of
         // 8 or N_MIN (see Section 4 of [AEAD]; all ciphersuites defined in
         // [TLS13] have N_MIN set to 12)
-        let iv = qhkdf_expand(&secret, "iv", 12)?;
-
-        // TODO LH pn_key = QHKDF-Expand(S, "pn", pn_key_length)
+        let iv = expand(&secret, iv_label, 12, wire_format)?;
+
+        // draft 08 leaves the packet number unprotected
+        let header_protection_key = match hp_label {
+            None => None,
+            Some(hp_label) => {
+                let hp = expand(&secret, hp_label, aead_algorithm.key_len(), wire_format)?;
+                Some(Arc::new(HeaderProtectionKey::new(aead_algorithm, &hp[..])?))
+            }
+        };
 
         let sealing_key = SealingKey::new(aead_algorithm, &key[..])
             .chain_err(|| ErrorKind::FailedToBuildCryptoState)?;
@@ -108,6 +224,9 @@ impl CryptoState {
             sealing_key: DebugIt(sealing_key),
             opening_key: DebugIt(opening_key),
             iv,
+            header_protection_key,
+            version,
+            wire_format,
         };
 
         Ok(crypto_state)
@@ -119,10 +238,14 @@ impl CryptoState {
         let secret = &self.secret.0;
 
         let hash_algorithm = secret.digest_algorithm();
-        let new_secret = qhkdf_expand(secret, label, hash_algorithm.output_len)?;
+        let new_secret = expand(secret, label, hash_algorithm.output_len, self.wire_format)?;
 
         let new_secret = SigningKey::new(hash_algorithm, &new_secret[..]);
-        let crypto_state = Self::new(new_secret, self.opening_key.0.algorithm())?;
+        let mut crypto_state =
+            Self::new(new_secret, self.opening_key.0.algorithm(), self.version)?;
+
+        // the header protection key is not changed by key updates
+        crypto_state.header_protection_key = self.header_protection_key.clone();
 
         debug!("created new crypto state with label {}", label);
 
@@ -156,6 +279,47 @@ impl CryptoState {
         Ok(nonce)
     }
 
+    /// The `Version` whose keys this `CryptoState` holds.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// The `WireFormat` frames are sealed and opened in.
+    pub fn wire_format(&self) -> WireFormat {
+        self.wire_format
+    }
+
+    /// The key protecting the headers of packets sealed or opened with this `CryptoState`, there is
+    /// no header protection in draft 08.
+    pub fn header_protection_key(&self) -> Option<Arc<HeaderProtectionKey>> {
+        self.header_protection_key.clone()
+    }
+
+    /// The number of bytes the authentication tag adds to sealed data.
+    pub fn tag_len(&self) -> usize {
+        self.sealing_key().algorithm().tag_len()
+    }
+
+    /// The number of packets which may be sealed with this key before it must be updated (RFC
+    /// 9001 section 6.6).
+    pub fn confidentiality_limit(&self) -> u64 {
+        if ::std::ptr::eq(self.sealing_key().algorithm(), &aead::CHACHA20_POLY1305) {
+            1 << 62
+        } else {
+            1 << 23
+        }
+    }
+
+    /// The number of packets which may fail to open with this key before the connection must be
+    /// closed (RFC 9001 section 6.6).
+    pub fn integrity_limit(&self) -> u64 {
+        if ::std::ptr::eq(self.opening_key().algorithm(), &aead::CHACHA20_POLY1305) {
+            1 << 36
+        } else {
+            1 << 52
+        }
+    }
+
     fn sealing_key(&self) -> &SealingKey {
         &self.sealing_key.0
     }
@@ -175,7 +339,7 @@ impl CryptoState {
         let nonce = self.make_nonce(packet_number)?;
         let sealing_key = self.sealing_key();
 
-        let mut in_out = frames.bytes()?;
+        let mut in_out = InFormat(frames, self.wire_format).bytes()?;
 
         let tag_len = sealing_key.algorithm().tag_len();
 
@@ -216,7 +380,7 @@ impl CryptoState {
             &mut ciphertext[..],
         ).chain_err(|| ErrorKind::FailedToOpenSealedData)?;
 
-        let frames = Readable::collect_from_bytes(plaintext)?;
+        let frames = Readable::collect_from_bytes_with_context(plaintext, &self.wire_format)?;
 
         debug!("opened frames for packet {:?}", packet_number);
 
@@ -283,13 +447,74 @@ fn qhkdf_expand(signing_key: &SigningKey, label: &str, out_len: usize) -> Result
     Ok(out.freeze())
 }
 
+#[derive(Debug)]
+struct HkdfLabel<'a> {
+    label: &'a str,
+    out_len: u16,
+}
+
+impl<'a> Writable for HkdfLabel<'a> {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing hkdf label {:?}", &self);
+
+        self.out_len.write(writer)?;
+
+        let label_prefix = "tls13 ";
+        let label_len = label_prefix.len() + self.label.len();
+
+        u8::value_from(label_len)
+            .expect("the label length should fit in a u8")
+            .write(writer)?;
+
+        label_prefix.write(writer)?;
+        self.label.write(writer)?;
+
+        // the context is always empty
+        0u8.write(writer)?;
+
+        debug!("written hkdf label {:?}", self);
+
+        Ok(())
+    }
+}
+
+/// HKDF-Expand-Label as defined in Section 7.1 of [TLS13], used by version 1.
+fn hkdf_expand_label(signing_key: &SigningKey, label: &str, out_len: usize) -> Result<Bytes> {
+    let hkdf_label = HkdfLabel {
+        label,
+        out_len: u16::value_from(out_len).expect("the output length must fit in a u16"),
+    };
+
+    let info = hkdf_label.bytes()?;
+
+    let mut out = BytesMut::with_capacity(out_len);
+    out.resize(out_len, 0);
+
+    hkdf::expand(&signing_key, &info[..], &mut out[..]);
+
+    Ok(out.freeze())
+}
+
+fn expand(
+    signing_key: &SigningKey,
+    label: &str,
+    out_len: usize,
+    wire_format: WireFormat,
+) -> Result<Bytes> {
+    match wire_format {
+        WireFormat::Draft08 => qhkdf_expand(signing_key, label, out_len),
+        WireFormat::V1 => hkdf_expand_label(signing_key, label, out_len),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use frames::Frame;
-    use protocol::ConnectionId;
+    use protocol::{ConnectionId, Version};
     use ring::digest;
     use ring::hmac::SigningKey;
+    use rustls;
 
     #[test]
     fn encode_hkdf_info_encodes_correctly() {
@@ -317,6 +542,78 @@ mod tests {
         CryptoState::for_handshake(ConnectionId::generate().unwrap(), "my test label").unwrap();
     }
 
+    #[test]
+    fn crypto_state_for_handshake_uses_the_connection_id_length() {
+        let connection_id = ConnectionId::from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        let zero_extended_connection_id =
+            ConnectionId::from_slice(&[0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        let crypto_state = CryptoState::for_handshake(connection_id, "my test label").unwrap();
+        let zero_extended_crypto_state =
+            CryptoState::for_handshake(zero_extended_connection_id, "my test label").unwrap();
+
+        assert_ne!(crypto_state.iv, zero_extended_crypto_state.iv);
+    }
+
+    #[test]
+    fn crypto_state_for_initial_matches_rfc_9001_test_vector() {
+        // RFC 9001 appendix A.1
+        let connection_id =
+            ConnectionId::from_slice(&[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08]).unwrap();
+
+        let client_crypto_state =
+            CryptoState::for_initial(connection_id, "client in", Version::V1).unwrap();
+        let server_crypto_state =
+            CryptoState::for_initial(connection_id, "server in", Version::V1).unwrap();
+
+        assert_eq!(
+            &client_crypto_state.iv[..],
+            &[0xfa, 0x04, 0x4b, 0x2f, 0x42, 0xa3, 0xfd, 0x3b, 0x46, 0xfb, 0x25, 0x5c][..]
+        );
+        assert_eq!(
+            &server_crypto_state.iv[..],
+            &[0x0a, 0xc1, 0x49, 0x3c, 0xa1, 0x90, 0x58, 0x53, 0xb0, 0xbb, 0xa0, 0x3e][..]
+        );
+    }
+
+    #[test]
+    fn crypto_state_for_initial_matches_rfc_9369_test_vector() {
+        // RFC 9369 appendix A.1
+        let connection_id =
+            ConnectionId::from_slice(&[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08]).unwrap();
+
+        let client_crypto_state =
+            CryptoState::for_initial(connection_id, "client in", Version::V2).unwrap();
+        let server_crypto_state =
+            CryptoState::for_initial(connection_id, "server in", Version::V2).unwrap();
+
+        assert_eq!(
+            &client_crypto_state.iv[..],
+            &[0x91, 0xf7, 0x3e, 0x23, 0x51, 0xd8, 0xfa, 0x91, 0x66, 0x0e, 0x90, 0x9f][..]
+        );
+        assert_eq!(
+            &server_crypto_state.iv[..],
+            &[0xdd, 0x13, 0xc2, 0x76, 0x49, 0x9c, 0x02, 0x49, 0xd3, 0x31, 0x06, 0x52][..]
+        );
+    }
+
+    #[test]
+    fn crypto_state_for_handshake_has_no_header_protection_key() {
+        let crypto_state =
+            CryptoState::for_handshake(ConnectionId::generate().unwrap(), "my test label").unwrap();
+
+        assert!(crypto_state.header_protection_key().is_none());
+    }
+
+    #[test]
+    fn crypto_state_for_initial_has_header_protection_key() {
+        let crypto_state =
+            CryptoState::for_initial(ConnectionId::generate().unwrap(), "client in", Version::V1)
+                .unwrap();
+
+        assert!(crypto_state.header_protection_key().is_some());
+    }
+
     #[test]
     fn crypto_state_seal_open() {
         let crypto_state =
@@ -336,4 +633,57 @@ mod tests {
 
         assert_eq!(opened_frames, original_frames);
     }
+
+    #[test]
+    fn crypto_state_for_initial_seal_open() {
+        let crypto_state =
+            CryptoState::for_initial(ConnectionId::generate().unwrap(), "client in", Version::V1)
+                .unwrap();
+
+        let packet_number = 1254u32.into();
+        let packet_header_bytes = b"some packet header bytes";
+
+        let original_frames = vec![Frame::Ping, Frame::HandshakeDone, Frame::Padding];
+        let sealed = crypto_state
+            .seal(packet_number, packet_header_bytes, &original_frames[..])
+            .unwrap();
+
+        let opened_frames = crypto_state
+            .open(packet_number, packet_header_bytes, &sealed[..])
+            .unwrap();
+
+        assert_eq!(opened_frames, original_frames);
+    }
+
+    #[test]
+    fn crypto_state_from_secret_only_opens_with_the_same_secret() {
+        let supported_cipher_suite = &rustls::ALL_CIPHERSUITES[0];
+        let client_crypto_state =
+            CryptoState::from_secret(supported_cipher_suite, &[1; 32], Version::V1).unwrap();
+        let server_crypto_state =
+            CryptoState::from_secret(supported_cipher_suite, &[1; 32], Version::V1).unwrap();
+        let other_crypto_state =
+            CryptoState::from_secret(supported_cipher_suite, &[2; 32], Version::V1).unwrap();
+
+        let packet_number = 1254u32.into();
+        let packet_header_bytes = b"some packet header bytes";
+
+        let original_frames = vec![Frame::Ping, Frame::Padding];
+        let sealed = client_crypto_state
+            .seal(packet_number, packet_header_bytes, &original_frames[..])
+            .unwrap();
+
+        assert!(client_crypto_state.header_protection_key().is_some());
+        assert_eq!(
+            server_crypto_state
+                .open(packet_number, packet_header_bytes, &sealed[..])
+                .unwrap(),
+            original_frames
+        );
+        assert!(
+            other_crypto_state
+                .open(packet_number, packet_header_bytes, &sealed[..])
+                .is_err()
+        );
+    }
 }
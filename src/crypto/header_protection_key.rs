@@ -0,0 +1,229 @@
+use aes::block_cipher_trait::generic_array::GenericArray;
+use aes::block_cipher_trait::BlockCipher;
+use aes::{Aes128, Aes256};
+use chacha20::stream_cipher::{NewStreamCipher, SyncStreamCipher, SyncStreamCipherSeek};
+use chacha20::ChaCha20;
+use debugit::DebugIt;
+use errors::*;
+use ring::aead;
+
+/// The header protection mask covers the flags and up to 4 packet number bytes.
+const MASK_LEN: usize = 5;
+
+const LONG_HEADER: u8 = 0x80;
+
+/// Long headers have the 4 least significant bits of the flags protected, short headers have 5.
+const LONG_HEADER_FLAGS_MASK: u8 = 0x0f;
+const SHORT_HEADER_FLAGS_MASK: u8 = 0x1f;
+
+const PACKET_NUMBER_LENGTH_MASK: u8 = 0x03;
+
+enum Cipher {
+    Aes128(Aes128),
+    Aes256(Aes256),
+    ChaCha20([u8; 32]),
+}
+
+/// Protects the flags and packet number of version 1 packet headers (RFC 9001 section 5.4).
+#[derive(Debug)]
+pub struct HeaderProtectionKey {
+    cipher: DebugIt<Cipher>,
+}
+
+impl HeaderProtectionKey {
+    /// The number of bytes of ciphertext sampled to create the header protection mask.
+    pub const SAMPLE_LEN: usize = 16;
+
+    /// Creates the `HeaderProtectionKey` to accompany packets protected with `aead_algorithm`.
+    pub fn new(aead_algorithm: &'static aead::Algorithm, key: &[u8]) -> Result<Self> {
+        let cipher = if aead_algorithm == &aead::AES_128_GCM {
+            Cipher::Aes128(
+                Aes128::new_varkey(key)
+                    .map_err(|_| ErrorKind::FailedToBuildHeaderProtectionKey)?,
+            )
+        } else if aead_algorithm == &aead::AES_256_GCM {
+            Cipher::Aes256(
+                Aes256::new_varkey(key)
+                    .map_err(|_| ErrorKind::FailedToBuildHeaderProtectionKey)?,
+            )
+        } else if aead_algorithm == &aead::CHACHA20_POLY1305 {
+            if key.len() != 32 {
+                bail!(ErrorKind::FailedToBuildHeaderProtectionKey);
+            }
+
+            let mut chacha20_key = [0u8; 32];
+            chacha20_key.copy_from_slice(key);
+            Cipher::ChaCha20(chacha20_key)
+        } else {
+            bail!(ErrorKind::FailedToBuildHeaderProtectionKey);
+        };
+
+        Ok(Self {
+            cipher: DebugIt(cipher),
+        })
+    }
+
+    fn mask(&self, sample: &[u8]) -> Result<[u8; MASK_LEN]> {
+        if sample.len() != Self::SAMPLE_LEN {
+            bail!(ErrorKind::PacketIsTooShortForHeaderProtectionSample);
+        }
+
+        let mut mask = [0u8; MASK_LEN];
+
+        match &self.cipher.0 {
+            Cipher::Aes128(aes) => {
+                let mut block = GenericArray::clone_from_slice(sample);
+                aes.encrypt_block(&mut block);
+                mask.copy_from_slice(&block[..MASK_LEN]);
+            }
+            Cipher::Aes256(aes) => {
+                let mut block = GenericArray::clone_from_slice(sample);
+                aes.encrypt_block(&mut block);
+                mask.copy_from_slice(&block[..MASK_LEN]);
+            }
+            Cipher::ChaCha20(key) => {
+                // the first 4 bytes of the sample are the block counter, the rest is the nonce
+                let counter = u64::from(sample[0]) | u64::from(sample[1]) << 8
+                    | u64::from(sample[2]) << 16 | u64::from(sample[3]) << 24;
+
+                let mut chacha20 = ChaCha20::new_var(&key[..], &sample[4..])
+                    .map_err(|_| ErrorKind::FailedToBuildHeaderProtectionKey)?;
+                chacha20.seek(counter * 64);
+                chacha20.apply_keystream(&mut mask);
+            }
+        }
+
+        Ok(mask)
+    }
+
+    /// Finds the mask for the packet whose packet number begins at `packet_number_offset`.
+    fn packet_mask(&self, packet: &[u8], packet_number_offset: usize) -> Result<[u8; MASK_LEN]> {
+        // the sample is taken as though the packet number was always 4 bytes long
+        let sample_offset = packet_number_offset + 4;
+
+        let sample = packet
+            .get(sample_offset..sample_offset + Self::SAMPLE_LEN)
+            .ok_or_else(|| ErrorKind::PacketIsTooShortForHeaderProtectionSample)?;
+
+        self.mask(sample)
+    }
+
+    /// Protects the flags and packet number of the sealed `packet` in place.
+    pub fn protect(&self, packet: &mut [u8], packet_number_offset: usize) -> Result<()> {
+        let mask = self.packet_mask(packet, packet_number_offset)?;
+
+        // the packet number length must be read before it is masked
+        let packet_number_len = usize::from(packet[0] & PACKET_NUMBER_LENGTH_MASK) + 1;
+
+        packet[0] ^= mask[0] & flags_mask(packet[0]);
+
+        mask_packet_number(packet, packet_number_offset, packet_number_len, &mask);
+
+        Ok(())
+    }
+
+    /// Removes the protection from the flags and packet number of `packet` in place.
+    pub fn unprotect(&self, packet: &mut [u8], packet_number_offset: usize) -> Result<()> {
+        let mask = self.packet_mask(packet, packet_number_offset)?;
+
+        packet[0] ^= mask[0] & flags_mask(packet[0]);
+
+        // the packet number length can only be read once the flags are unmasked
+        let packet_number_len = usize::from(packet[0] & PACKET_NUMBER_LENGTH_MASK) + 1;
+
+        mask_packet_number(packet, packet_number_offset, packet_number_len, &mask);
+
+        Ok(())
+    }
+}
+
+fn flags_mask(flags: u8) -> u8 {
+    // the long header bit is never protected so this is the same before and after masking
+    if flags & LONG_HEADER != 0 {
+        LONG_HEADER_FLAGS_MASK
+    } else {
+        SHORT_HEADER_FLAGS_MASK
+    }
+}
+
+fn mask_packet_number(
+    packet: &mut [u8],
+    packet_number_offset: usize,
+    packet_number_len: usize,
+    mask: &[u8; MASK_LEN],
+) {
+    let packet_number_end = packet_number_offset + packet_number_len;
+    let packet_number = &mut packet[packet_number_offset..packet_number_end];
+
+    for (byte, mask) in packet_number.iter_mut().zip(&mask[1..]) {
+        *byte ^= mask;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderProtectionKey;
+    use hex;
+    use ring::aead;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        hex::FromHex::from_hex(hex).unwrap()
+    }
+
+    #[test]
+    fn aes_128_mask_matches_rfc_9001_test_vector() {
+        // RFC 9001 appendix A.2
+        let key = from_hex("9f50449e04a0e810283a1e9933adedd2");
+        let sample = from_hex("d1b1c98dd7689fb8ec11d242b123dc9b");
+
+        let header_protection_key = HeaderProtectionKey::new(&aead::AES_128_GCM, &key).unwrap();
+
+        assert_eq!(
+            header_protection_key.mask(&sample).unwrap(),
+            [0x43, 0x7b, 0x9a, 0xec, 0x36]
+        );
+    }
+
+    #[test]
+    fn chacha20_mask_matches_rfc_9001_test_vector() {
+        // RFC 9001 appendix A.5
+        let key = from_hex("25a282b9e82f06f21f488917a4fc8f1b73573685608597d0efcb076b0ab7a7a4");
+        let sample = from_hex("5e5cd55c41f69080575d7999c25a5bfb");
+
+        let header_protection_key =
+            HeaderProtectionKey::new(&aead::CHACHA20_POLY1305, &key).unwrap();
+
+        assert_eq!(
+            header_protection_key.mask(&sample).unwrap(),
+            [0xae, 0xfe, 0xfe, 0x7d, 0x03]
+        );
+    }
+
+    #[test]
+    fn unprotect_reverses_protect() {
+        let header_protection_key =
+            HeaderProtectionKey::new(&aead::AES_128_GCM, &[0x42; 16]).unwrap();
+
+        // a short header with a 2 byte packet number followed by the sealed payload
+        let mut packet = vec![0x41, 0x12, 0x34];
+        packet.extend((0..20).map(|i| i as u8));
+        let original = packet.clone();
+
+        header_protection_key.protect(&mut packet, 1).unwrap();
+        assert_ne!(packet[..3], original[..3]);
+        assert_eq!(packet[3..], original[3..]);
+
+        header_protection_key.unprotect(&mut packet, 1).unwrap();
+        assert_eq!(packet, original);
+    }
+
+    #[test]
+    fn protect_fails_when_packet_is_too_short_to_sample() {
+        let header_protection_key =
+            HeaderProtectionKey::new(&aead::AES_128_GCM, &[0x42; 16]).unwrap();
+
+        let mut packet = vec![0x41, 0x12, 0x34, 0x00];
+
+        assert!(header_protection_key.protect(&mut packet, 1).is_err());
+    }
+}
@@ -0,0 +1,81 @@
+use crypto::HeaderProtectionKey;
+use protocol::ConnectionId;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// The keys for removing the header protection of incoming short header packets by the
+/// connection they are destined for.
+///
+/// These are shared between the `PacketCodec` reading a socket and the connections using it, the
+/// connections register their keys once the handshake has completed.
+#[derive(Debug, Default)]
+pub struct HeaderProtectionKeys {
+    keys: RwLock<HashMap<ConnectionId, Arc<HeaderProtectionKey>>>,
+}
+
+impl HeaderProtectionKeys {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(
+        &self,
+        connection_id: ConnectionId,
+        header_protection_key: Arc<HeaderProtectionKey>,
+    ) {
+        let mut keys = self.keys.write().expect("failed to lock keys");
+
+        keys.insert(connection_id, header_protection_key);
+
+        debug!(
+            "registered header protection key for connection {:?}",
+            connection_id
+        );
+    }
+
+    pub fn remove(&self, connection_id: ConnectionId) {
+        let mut keys = self.keys.write().expect("failed to lock keys");
+
+        if keys.remove(&connection_id).is_some() {
+            debug!(
+                "removed header protection key for connection {:?}",
+                connection_id
+            );
+        }
+    }
+
+    pub fn get(&self, connection_id: ConnectionId) -> Option<Arc<HeaderProtectionKey>> {
+        let keys = self.keys.read().expect("failed to lock keys");
+
+        keys.get(&connection_id).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HeaderProtectionKeys;
+    use crypto::HeaderProtectionKey;
+    use protocol::ConnectionId;
+    use ring::aead;
+    use std::sync::Arc;
+
+    #[test]
+    fn get_returns_inserted_key_until_removed() {
+        let header_protection_keys = HeaderProtectionKeys::new();
+        let connection_id = ConnectionId::generate().unwrap();
+        let header_protection_key =
+            Arc::new(HeaderProtectionKey::new(&aead::AES_128_GCM, &[0x42; 16]).unwrap());
+
+        assert!(header_protection_keys.get(connection_id).is_none());
+
+        header_protection_keys.insert(connection_id, header_protection_key.clone());
+        assert!(
+            header_protection_keys
+                .get(connection_id)
+                .map_or(false, |key| Arc::ptr_eq(&key, &header_protection_key))
+        );
+
+        header_protection_keys.remove(connection_id);
+        assert!(header_protection_keys.get(connection_id).is_none());
+    }
+}
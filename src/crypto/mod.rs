@@ -1,2 +1,11 @@
 mod crypto_state;
 pub use self::crypto_state::CryptoState;
+
+mod header_protection_key;
+pub use self::header_protection_key::HeaderProtectionKey;
+
+mod header_protection_keys;
+pub use self::header_protection_keys::HeaderProtectionKeys;
+
+mod stateless_reset_tokens;
+pub use self::stateless_reset_tokens::StatelessResetTokens;
@@ -0,0 +1,82 @@
+use protocol::{ConnectionId, StatelessResetToken};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// The stateless reset tokens remote endpoints have issued, by the connection they reset.
+///
+/// These are shared between the `PacketCodec` reading a socket and the connections using it, the
+/// connections register the tokens of their peers as they learn them.
+#[derive(Debug, Default)]
+pub struct StatelessResetTokens {
+    connection_ids: RwLock<HashMap<StatelessResetToken, ConnectionId>>,
+}
+
+impl StatelessResetTokens {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, connection_id: ConnectionId, stateless_reset_token: StatelessResetToken) {
+        let mut connection_ids = self.connection_ids
+            .write()
+            .expect("failed to lock connection_ids");
+
+        connection_ids.insert(stateless_reset_token, connection_id);
+
+        debug!(
+            "registered stateless reset token {:?} for connection {:?}",
+            stateless_reset_token, connection_id
+        );
+    }
+
+    /// Removes every token registered for `connection_id`.
+    pub fn remove(&self, connection_id: ConnectionId) {
+        let mut connection_ids = self.connection_ids
+            .write()
+            .expect("failed to lock connection_ids");
+
+        let len_before = connection_ids.len();
+        connection_ids.retain(|_, &mut registered| registered != connection_id);
+
+        if connection_ids.len() != len_before {
+            debug!(
+                "removed stateless reset tokens for connection {:?}",
+                connection_id
+            );
+        }
+    }
+
+    /// The connection `stateless_reset_token` resets, `None` if no connection registered it.
+    pub fn get(&self, stateless_reset_token: StatelessResetToken) -> Option<ConnectionId> {
+        let connection_ids = self.connection_ids
+            .read()
+            .expect("failed to lock connection_ids");
+
+        connection_ids.get(&stateless_reset_token).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatelessResetTokens;
+    use protocol::{ConnectionId, StatelessResetToken};
+
+    #[test]
+    fn get_returns_inserted_connection_until_removed() {
+        let stateless_reset_tokens = StatelessResetTokens::new();
+        let connection_id = ConnectionId::generate().unwrap();
+        let first_token = StatelessResetToken::from([0x42; 16]);
+        let second_token = StatelessResetToken::from([0x24; 16]);
+
+        assert_eq!(stateless_reset_tokens.get(first_token), None);
+
+        stateless_reset_tokens.insert(connection_id, first_token);
+        stateless_reset_tokens.insert(connection_id, second_token);
+        assert_eq!(stateless_reset_tokens.get(first_token), Some(connection_id));
+        assert_eq!(stateless_reset_tokens.get(second_token), Some(connection_id));
+
+        stateless_reset_tokens.remove(connection_id);
+        assert_eq!(stateless_reset_tokens.get(first_token), None);
+        assert_eq!(stateless_reset_tokens.get(second_token), None);
+    }
+}
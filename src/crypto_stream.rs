@@ -0,0 +1,89 @@
+use bytes::Bytes;
+use frames::StreamFrame;
+use protocol::{StreamId, StreamOffset};
+use utils::DataQueue;
+
+/// The crypto data of one encryption level, each has its own offsets in both directions.
+#[derive(Debug)]
+pub struct CryptoStream {
+    incoming: DataQueue,
+    write_offset: StreamOffset,
+}
+
+impl CryptoStream {
+    pub fn new() -> Self {
+        Self {
+            incoming: DataQueue::new(),
+            write_offset: StreamOffset::from(0u32),
+        }
+    }
+
+    /// Buffers the crypto `data` received at `offset`, which may arrive out of order.
+    pub fn on_data_received(&mut self, offset: u64, data: Bytes) {
+        self.incoming.insert_chunk(offset, false, data);
+    }
+
+    /// The crypto data received in order since the last read, `None` if there is none.
+    pub fn read(&mut self) -> Option<Vec<u8>> {
+        let mut data = Vec::new();
+        let mut buf = [0; 4096];
+
+        loop {
+            let read = self.incoming.read(&mut buf);
+            if read == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..read]);
+        }
+
+        if data.is_empty() {
+            None
+        } else {
+            Some(data)
+        }
+    }
+
+    /// Wraps `data` in a frame at the end of the crypto data written so far.
+    pub fn write(&mut self, data: Bytes) -> StreamFrame {
+        let offset = self.write_offset;
+        self.write_offset += data.len();
+
+        StreamFrame {
+            finished: false,
+            offset,
+            stream_id: StreamId::crypto_stream_id(),
+            data,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CryptoStream;
+    use bytes::Bytes;
+    use protocol::StreamOffset;
+
+    #[test]
+    fn read_returns_data_once_it_is_in_order() {
+        let mut crypto_stream = CryptoStream::new();
+
+        crypto_stream.on_data_received(5, Bytes::from_static(b" world"));
+        assert_eq!(crypto_stream.read(), None);
+
+        crypto_stream.on_data_received(0, Bytes::from_static(b"hello"));
+        assert_eq!(crypto_stream.read(), Some(b"hello world".to_vec()));
+        assert_eq!(crypto_stream.read(), None);
+    }
+
+    #[test]
+    fn write_continues_from_the_end_of_the_written_data() {
+        let mut crypto_stream = CryptoStream::new();
+
+        let first = crypto_stream.write(Bytes::from_static(b"hello"));
+        let second = crypto_stream.write(Bytes::from_static(b" world"));
+
+        assert_eq!(first.offset, StreamOffset::from(0u32));
+        assert_eq!(second.offset, StreamOffset::from(5u32));
+        assert!(second.stream_id.is_crypto_stream());
+    }
+}
@@ -36,6 +36,18 @@ impl<P: Perspective> DataStream<P> {
         &self.connection
     }
 
+    /// Whether any data on this stream was received as 0-RTT data.
+    ///
+    /// 0-RTT data may have been replayed by an attacker, so a request which arrived as 0-RTT
+    /// data should only be acted upon if repeating it does no harm.
+    pub fn is_early_data(&self) -> bool {
+        let stream_state = self.stream_state
+            .lock()
+            .expect("failed to obtain stream_state lock");
+
+        stream_state.is_early_data()
+    }
+
     fn enqueue_write(&self, buf: Bytes) -> usize {
         let mut stream_state = self.stream_state
             .lock()
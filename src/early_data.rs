@@ -0,0 +1,278 @@
+use crypto::CryptoState;
+use packets::{IncomingPacket, PacketNumber};
+use recovery::SentPacket;
+use std::mem;
+
+/// Whether the server accepted the data a client sent in 0-RTT packets.
+///
+/// A server reports `NotAttempted` until the handshake completes, as only then is it decided
+/// whether 0-RTT packets from the client are accepted.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum EarlyData {
+    /// No 0-RTT packets were sent, either nothing was remembered from a previous connection or
+    /// nothing was written before the handshake completed.
+    NotAttempted,
+    /// 0-RTT packets were sent but the server has not acknowledged any packets since.
+    Pending,
+    /// The server acknowledged a 0-RTT packet.
+    Accepted,
+    /// The server acknowledged a later packet but none of the 0-RTT packets, their data is sent
+    /// again in 1-RTT packets.
+    Rejected,
+}
+
+impl Default for EarlyData {
+    fn default() -> Self {
+        EarlyData::NotAttempted
+    }
+}
+
+/// The 0-RTT keys of an endpoint and the packets sent or received with them.
+///
+/// 0-RTT packets share the application data packet number space and are all sent before the
+/// first 1-RTT packet, so the packets up to `largest_sent` are exactly the 0-RTT packets.
+#[derive(Debug, Default)]
+pub struct EarlyDataState {
+    /// `None` once the 1-RTT keys are available.
+    write_keys: Option<CryptoState>,
+    largest_sent: Option<PacketNumber>,
+    /// The keys a server opens accepted 0-RTT packets with, `None` once the client has
+    /// switched to 1-RTT packets.
+    read_keys: Option<CryptoState>,
+    /// The 0-RTT packets received before the server had the keys to open them.
+    buffered_packets: Vec<IncomingPacket>,
+    /// The size of all the 0-RTT packets received.
+    received_bytes: usize,
+    handshake_complete: bool,
+    status: EarlyData,
+}
+
+impl EarlyDataState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn status(&self) -> EarlyData {
+        self.status
+    }
+
+    /// The keys 0-RTT packets are sealed with, `None` if they may not be sent.
+    pub fn write_keys(&self) -> Option<&CryptoState> {
+        self.write_keys.as_ref()
+    }
+
+    pub fn on_keys_installed(&mut self, write_keys: CryptoState) {
+        self.write_keys = Some(write_keys);
+        self.status = EarlyData::Pending;
+    }
+
+    pub fn on_packet_sent(&mut self, packet_number: PacketNumber) {
+        self.largest_sent = Some(packet_number);
+    }
+
+    /// Stops sending 0-RTT packets once the 1-RTT keys are available.
+    pub fn on_handshake_complete(&mut self) {
+        self.write_keys = None;
+        self.handshake_complete = true;
+
+        if self.largest_sent.is_none() {
+            self.status = EarlyData::NotAttempted;
+        }
+    }
+
+    /// Opens the 0-RTT packets from the client with `read_keys` from now on, a server installs
+    /// them once its TLS session has accepted the early data offered in the ClientHello.
+    pub fn on_read_keys_installed(&mut self, read_keys: CryptoState) {
+        self.read_keys = Some(read_keys);
+    }
+
+    /// Reports the 0-RTT packets from the client as accepted once the handshake completes.
+    pub fn on_accepted(&mut self) {
+        debug!("accepting 0-RTT data");
+
+        self.status = EarlyData::Accepted;
+    }
+
+    /// Rejects the 0-RTT packets from the client, any which were buffered are discarded.
+    pub fn on_rejected(&mut self) {
+        debug!("rejecting 0-RTT data");
+
+        self.buffered_packets.clear();
+        self.status = EarlyData::Rejected;
+    }
+
+    /// The keys 0-RTT packets from the client are opened with, `None` if they are not (or not
+    /// yet) accepted.
+    pub fn read_keys(&self) -> Option<&CryptoState> {
+        self.read_keys.as_ref()
+    }
+
+    /// Stops accepting 0-RTT packets once the client has sent a 1-RTT packet, it never sends
+    /// another 0-RTT packet after one.
+    pub fn discard_read_keys(&mut self) {
+        // a reordered 1-RTT packet can arrive before the handshake completes, until then the
+        // keys tell whether the 0-RTT packets were accepted
+        if self.handshake_complete {
+            self.read_keys = None;
+        }
+    }
+
+    /// Counts a 0-RTT packet of `size` bytes received by a server.
+    ///
+    /// # Returns
+    /// `false` if the packet should be discarded, either because the 0-RTT data has been
+    /// rejected or because it would take the 0-RTT data beyond `max_size` bytes.
+    pub fn on_packet_received(&mut self, size: usize, max_size: usize) -> bool {
+        if self.handshake_complete && self.read_keys.is_none() {
+            return false;
+        }
+
+        if self.received_bytes + size > max_size {
+            return false;
+        }
+
+        self.received_bytes += size;
+
+        true
+    }
+
+    /// Keeps a 0-RTT packet until its keys are installed or the handshake completes without
+    /// them.
+    pub fn buffer_packet(&mut self, incoming_packet: IncomingPacket) {
+        self.buffered_packets.push(incoming_packet);
+    }
+
+    pub fn take_buffered_packets(&mut self) -> Vec<IncomingPacket> {
+        mem::replace(&mut self.buffered_packets, Vec::new())
+    }
+
+    /// Decides whether the server accepted the 0-RTT packets from the packets it has
+    /// acknowledged.
+    ///
+    /// # Returns
+    /// The largest 0-RTT packet number if the 0-RTT packets have just been found to be rejected,
+    /// the packets up to it have to be sent again.
+    pub fn on_packets_acknowledged(
+        &mut self,
+        acknowledged_packets: &[SentPacket],
+    ) -> Option<PacketNumber> {
+        if self.status != EarlyData::Pending {
+            return None;
+        }
+
+        let largest_sent = self.largest_sent?;
+
+        if acknowledged_packets
+            .iter()
+            .any(|sent_packet| sent_packet.packet_number <= largest_sent)
+        {
+            debug!("the server accepted 0-RTT data");
+
+            self.status = EarlyData::Accepted;
+            None
+        } else if !acknowledged_packets.is_empty() {
+            debug!("the server rejected 0-RTT data");
+
+            self.status = EarlyData::Rejected;
+            Some(largest_sent)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EarlyData, EarlyDataState};
+    use packets::{IncomingPacket, PacketNumber};
+    use recovery::SentPacket;
+    use std::time::Instant;
+
+    fn sent_packet(packet_number: u32) -> SentPacket {
+        SentPacket {
+            packet_number: PacketNumber::from(packet_number),
+            time_sent: Instant::now(),
+            ack_eliciting: true,
+            size: 1200,
+            retransmittable_frames: Vec::new(),
+        }
+    }
+
+    fn sent_early_data(packet_count: u32) -> EarlyDataState {
+        let mut early_data = EarlyDataState::new();
+        early_data.status = EarlyData::Pending;
+
+        for packet_number in 0..packet_count {
+            early_data.on_packet_sent(PacketNumber::from(packet_number));
+        }
+
+        early_data
+    }
+
+    #[test]
+    fn acknowledging_zero_rtt_packet_accepts() {
+        let mut early_data = sent_early_data(3);
+
+        assert_eq!(early_data.on_packets_acknowledged(&[sent_packet(1)]), None);
+        assert_eq!(early_data.status(), EarlyData::Accepted);
+    }
+
+    #[test]
+    fn acknowledging_only_later_packets_rejects() {
+        let mut early_data = sent_early_data(3);
+
+        assert_eq!(
+            early_data.on_packets_acknowledged(&[sent_packet(3), sent_packet(4)]),
+            Some(PacketNumber::from(2u32))
+        );
+        assert_eq!(early_data.status(), EarlyData::Rejected);
+
+        // the rejection is only reported once
+        assert_eq!(early_data.on_packets_acknowledged(&[sent_packet(5)]), None);
+    }
+
+    #[test]
+    fn handshake_without_zero_rtt_packets_is_not_attempted() {
+        let mut early_data = sent_early_data(0);
+
+        early_data.on_handshake_complete();
+
+        assert_eq!(early_data.status(), EarlyData::NotAttempted);
+        assert_eq!(early_data.on_packets_acknowledged(&[sent_packet(0)]), None);
+    }
+
+    #[test]
+    fn rejected_zero_rtt_packets_are_discarded() {
+        let mut early_data = EarlyDataState::new();
+
+        assert!(early_data.on_packet_received(1200, 2400));
+        assert!(early_data.on_packet_received(1200, 2400));
+        assert!(!early_data.on_packet_received(1200, 2400));
+
+        early_data.on_handshake_complete();
+        early_data.on_rejected();
+
+        assert_eq!(early_data.status(), EarlyData::Rejected);
+        assert!(!early_data.on_packet_received(100, 2400));
+    }
+
+    #[test]
+    fn accepted_zero_rtt_packets_are_received_until_read_keys_are_discarded() {
+        let mut early_data = EarlyDataState::new();
+        let read_keys =
+            CryptoState::for_handshake(ConnectionId::generate().unwrap(), "test label").unwrap();
+
+        early_data.on_read_keys_installed(read_keys);
+        early_data.on_handshake_complete();
+        early_data.on_accepted();
+
+        assert_eq!(early_data.status(), EarlyData::Accepted);
+        assert!(early_data.read_keys().is_some());
+        assert!(early_data.on_packet_received(1200, 16384));
+
+        early_data.discard_read_keys();
+
+        assert!(early_data.read_keys().is_none());
+        assert!(!early_data.on_packet_received(1200, 16384));
+    }
+}
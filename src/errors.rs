@@ -1,5 +1,6 @@
 use futures::{Async, Future, Poll, Stream};
-use protocol::{ConnectionId, StreamId, StreamOffset, Version};
+use packets::LongHeaderPacketType;
+use protocol::{ConnectionId, ErrorCode, StreamId, StreamOffset, Version, WireFormat};
 use std::error::Error as StdError;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::net::SocketAddr;
@@ -66,6 +67,10 @@ error_chain! {
             description("failed to write QUIC connection id")
             display("failed to write QUIC connection id '{:?}'", connection_id)
         }
+        FailedToReadConnectionId(length: usize) {
+            description("failed to read QUIC connection id")
+            display("failed to read QUIC connection id of length '{}'", length)
+        }
         FailedToWriteQuicVersion(version: Version) {
             description("failed to write QUIC version")
             display("failed to write QUIC version '{}'", version)
@@ -193,6 +198,38 @@ error_chain! {
         FailedToWriteStreamIdBlockedFrame {
             description("failed to write stream id blocked frame")
         }
+        FailedToReadMaxStreamsFrame {
+            description("failed to read max streams frame")
+        }
+        FailedToWriteMaxStreamsFrame {
+            description("failed to write max streams frame")
+        }
+        FailedToReadStreamsBlockedFrame {
+            description("failed to read streams blocked frame")
+        }
+        FailedToWriteStreamsBlockedFrame {
+            description("failed to write streams blocked frame")
+        }
+        FailedToReadRetireConnectionIdFrame {
+            description("failed to read retire connection id frame")
+        }
+        FailedToWriteRetireConnectionIdFrame {
+            description("failed to write retire connection id frame")
+        }
+        FailedToReadNewTokenFrame {
+            description("failed to read new token frame")
+        }
+        FailedToWriteNewTokenFrame {
+            description("failed to write new token frame")
+        }
+        FailedToWriteHandshakeDoneFrame {
+            description("failed to write handshake done frame")
+        }
+        FrameIsNotSupportedInWireFormat(frame_type: &'static str, wire_format: WireFormat) {
+            description("frame is not supported in the wire format")
+            display("{} frames are not supported in the wire format '{:?}'",
+                    frame_type, wire_format)
+        }
         FailedToReadNewConnectionIdFrame {
             description("failed to read new connection id frame")
         }
@@ -257,6 +294,32 @@ error_chain! {
             description("invalid long header packet type")
             display("invalid long header packet type '{}'", packet_type)
         }
+        UnsupportedLongHeaderPacketType(packet_type: LongHeaderPacketType) {
+            description("unsupported long header packet type")
+            display("unsupported long header packet type '{:?}'", packet_type)
+        }
+        UnsupportedVersion(version: Version) {
+            description("unsupported version")
+            display("unsupported version '{:?}'", version)
+        }
+        FixedBitIsNotSet {
+            description("the fixed bit of the packet header is not set")
+        }
+        ConnectionIdIsTooLong(length: usize) {
+            description("connection id is too long")
+            display("connection id of length '{}' is too long", length)
+        }
+        ConnectionIdLengthIsNotSupportedInWireFormat(length: usize, wire_format: WireFormat) {
+            description("connection id length is not supported in the wire format")
+            display("connection id of length '{}' is not supported in {:?}", length, wire_format)
+        }
+        UnexpectedConnectionIdLength(expected: usize, actual: usize) {
+            description("unexpected connection id length")
+            display("expected a connection id of length '{}' but got '{}'", expected, actual)
+        }
+        PacketLengthIsShorterThanPacketNumber {
+            description("the packet length is shorter than the packet number")
+        }
         ReachedMaximumPacketNumber {
             description("reached maximum packet number")
         }
@@ -294,6 +357,35 @@ error_chain! {
         FailedToBuildCryptoState {
             description("failed to build crypto state")
         }
+        FailedToBuildHeaderProtectionKey {
+            description("failed to build header protection key")
+        }
+        PacketIsTooShortForHeaderProtectionSample {
+            description("the packet is too short to sample for header protection")
+        }
+        NoHeaderProtectionKey(connection_id: Option<ConnectionId>) {
+            description("there is no header protection key for the packet")
+            display("there is no header protection key for connection '{:?}'", connection_id)
+        }
+        FailedToCreateTimer {
+            description("failed to create a timer")
+        }
+        FailedToPollTimer {
+            description("failed to poll a timer")
+        }
+        AeadConfidentialityLimitReached {
+            description("too many packets have been sealed with the same key")
+        }
+        AeadIntegrityLimitReached {
+            description("too many packets have failed authentication")
+        }
+        FailedToParseClientHello {
+            description("failed to parse TLS ClientHello message")
+        }
+        NoCommonApplicationProtocol(offered: Vec<String>) {
+            description("no application protocol is supported by both endpoints")
+            display("none of the offered application protocols {:?} are supported", offered)
+        }
         FailedToSealData {
             description("failed to seal data")
         }
@@ -328,6 +420,87 @@ error_chain! {
         FailedToSendPacketToUdpSocket {
             description("failed to send packet to udp socket")
         }
+        PacketHeaderHasNoPacketNumber {
+            description("packet header has no packet number")
+        }
+        PayloadLengthExceedsDatagramLength {
+            description("payload length exceeds the length of the datagram")
+        }
+        StreamFlowControlLimitExceeded(stream_id: StreamId) {
+            description("stream flow control limit exceeded")
+            display("stream flow control limit exceeded for stream '{}'", stream_id)
+        }
+        ConnectionClosedByPeer(error_code: ErrorCode, reason_phrase: String) {
+            description("connection closed by peer")
+            display("connection closed by peer with error code '{:?}': {}", error_code, reason_phrase)
+        }
+        ConnectionClosedByPeerApplication(application_error_code: u16, reason_phrase: String) {
+            description("connection closed by peer application")
+            display("connection closed by peer application with error code '{}': {}", application_error_code, reason_phrase)
+        }
+        MaximumPacketSizeIsTooSmall(max_packet_size: usize) {
+            description("maximum packet size is too small")
+            display("maximum packet size '{}' is too small to fit a packet header", max_packet_size)
+        }
+        UnexpectedNewTokenFrame {
+            description("clients can not send NEW_TOKEN frames")
+        }
+        InvalidAddressValidationToken {
+            description("address validation token is invalid or has expired")
+        }
+        OriginalDestinationConnectionIdMismatch(expected: ConnectionId, actual: Option<ConnectionId>) {
+            description("original destination connection id transport parameter does not match")
+            display("expected original destination connection id '{:?}' but the server sent '{:?}'", expected, actual)
+        }
+        RetrySourceConnectionIdMismatch(expected: Option<ConnectionId>, actual: Option<ConnectionId>) {
+            description("retry source connection id transport parameter does not match")
+            display("expected retry source connection id '{:?}' but the server sent '{:?}'", expected, actual)
+        }
+        VersionNegotiationRequired(version: Version) {
+            description("the server asked for the connection to be started again in another version")
+            display("the server does not support our version, the connection must be started again in version '{:?}'", version)
+        }
+        NoCommonVersion(supported_versions: Vec<Version>) {
+            description("no version is supported by both endpoints")
+            display("none of the versions {:?} the server supports are supported", supported_versions)
+        }
+        NegotiatedVersionMismatch(expected: Version, actual: Version) {
+            description("negotiated version transport parameter does not match")
+            display("expected negotiated version '{:?}' but the server sent '{:?}'", expected, actual)
+        }
+        VersionDowngradeDetected(version: Version, supported_versions: Vec<Version>) {
+            description("version negotiation was not performed by the server")
+            display("connected in version '{:?}' after version negotiation but the server supports {:?}", version, supported_versions)
+        }
+        ChosenVersionMismatch(expected: Version, actual: Version) {
+            description("chosen version of the version information transport parameter does not match")
+            display("expected chosen version '{:?}' but the peer sent '{:?}'", expected, actual)
+        }
+        CompatibleVersionNotOffered(version: Version, available_versions: Vec<Version>) {
+            description("the connection was moved to a compatible version the client did not offer")
+            display("moved to version '{:?}' but the client only offered {:?}", version, available_versions)
+        }
+        PacketHeaderIsOnlyEverRead {
+            description("packet header can only be read and never written")
+        }
+        StatelessResetReceived(connection_id: ConnectionId) {
+            description("the remote endpoint reset the connection as it has lost its state")
+            display("connection '{:?}' was reset by a stateless reset from the remote endpoint", connection_id)
+        }
+        RetryPacketIsTooShort {
+            description("retry packet is too short to hold an integrity tag")
+        }
+        PacketHeaderHasNoConnectionId {
+            description("packet header has no connection id")
+        }
+        ConnectionIdAlreadyInUse(connection_id: ConnectionId) {
+            description("connection id is already in use")
+            display("connection id '{:?}' is already in use", connection_id)
+        }
+        UnknownConnectionId(connection_id: ConnectionId) {
+            description("unknown connection id")
+            display("unknown connection id '{:?}'", connection_id)
+        }
     }
 }
 
@@ -3,6 +3,7 @@ use errors::*;
 use protocol::{Readable, VarInt, Writable};
 use std::io::{Read, Write};
 use std::ops::Range;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct AckFrame {
@@ -10,6 +11,24 @@ pub struct AckFrame {
     pub ack_ranges_descending: Vec<Range<u64>>,
 }
 
+impl AckFrame {
+    pub fn largest_acknowledged(&self) -> Option<u64> {
+        self.ack_ranges_descending
+            .first()
+            .and_then(|range| range.end.checked_sub(1))
+    }
+
+    /// The time the sender held back this ACK frame, `ack_delay_exponent` is the exponent the
+    /// sender announced in its transport parameters.
+    pub fn decoded_ack_delay(&self, ack_delay_exponent: u8) -> Duration {
+        let micros = self.ack_delay
+            .checked_shl(u32::from(ack_delay_exponent))
+            .unwrap_or(u64::max_value());
+
+        Duration::from_micros(micros)
+    }
+}
+
 impl Readable for AckFrame {
     type Context = ();
 
@@ -153,6 +172,7 @@ impl Writable for AckFrame {
 mod tests {
     use super::AckFrame;
     use protocol;
+    use std::time::Duration;
 
     #[test]
     fn write_read_ack_frame() {
@@ -163,4 +183,14 @@ mod tests {
 
         protocol::test_write_read(&ack_frame).unwrap();
     }
+
+    #[test]
+    fn decoded_ack_delay_applies_exponent() {
+        let ack_frame = AckFrame {
+            ack_delay: 125,
+            ack_ranges_descending: vec![(0..1)],
+        };
+
+        assert_eq!(ack_frame.decoded_ack_delay(3), Duration::from_millis(1));
+    }
 }
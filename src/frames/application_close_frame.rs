@@ -1,22 +1,30 @@
-use conv::ValueInto;
+use conv::{ValueFrom, ValueInto};
 use errors::*;
-use protocol::{Readable, StreamId, VarInt, Writable};
+use protocol::{Readable, VarInt, WireFormat, Writable, WritableInFormat};
 use std::io::{Read, Write};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct ApplicationCloseFrame {
-    application_error_code: u16,
-    reason_phrase: String,
+    pub application_error_code: u16,
+    pub reason_phrase: String,
 }
 
 impl Readable for ApplicationCloseFrame {
-    type Context = ();
+    type Context = WireFormat;
 
-    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+    fn read_with_context<R: Read>(reader: &mut R, wire_format: &Self::Context) -> Result<Self> {
         trace!("reading application close frame");
 
-        let application_error_code =
-            Readable::read(reader).chain_err(|| ErrorKind::FailedToReadApplicationCloseFrame)?;
+        let application_error_code = match wire_format {
+            WireFormat::Draft08 => Readable::read(reader)
+                .chain_err(|| ErrorKind::FailedToReadApplicationCloseFrame)?,
+            WireFormat::V1 => {
+                let application_error_code = VarInt::read(reader)
+                    .chain_err(|| ErrorKind::FailedToReadApplicationCloseFrame)?;
+                u16::value_from(application_error_code.into_inner())
+                    .chain_err(|| ErrorKind::FailedToReadApplicationCloseFrame)?
+            }
+        };
 
         let reason_phrase_len =
             VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadApplicationCloseFrame)?;
@@ -35,13 +43,14 @@ impl Readable for ApplicationCloseFrame {
     }
 }
 
-impl Writable for ApplicationCloseFrame {
-    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+impl WritableInFormat for ApplicationCloseFrame {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()> {
         trace!("writing application close frame {:?}", self);
 
-        self.application_error_code
-            .write(writer)
-            .chain_err(|| ErrorKind::FailedToWriteApplicationCloseFrame)?;
+        match wire_format {
+            WireFormat::Draft08 => self.application_error_code.write(writer),
+            WireFormat::V1 => VarInt::from(self.application_error_code).write(writer),
+        }.chain_err(|| ErrorKind::FailedToWriteApplicationCloseFrame)?;
 
         let reason_phrase_len: VarInt = self.reason_phrase
             .len()
@@ -60,3 +69,9 @@ impl Writable for ApplicationCloseFrame {
         Ok(())
     }
 }
+
+impl Writable for ApplicationCloseFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_in_format(writer, WireFormat::Draft08)
+    }
+}
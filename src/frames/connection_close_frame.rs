@@ -1,22 +1,34 @@
-use conv::ValueInto;
+use conv::{ValueFrom, ValueInto};
 use errors::*;
-use protocol::{ErrorCode, Readable, StreamId, VarInt, Writable};
+use protocol::{ErrorCode, Readable, VarInt, WireFormat, Writable, WritableInFormat};
 use std::io::{Read, Write};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct ConnectionCloseFrame {
-    error_code: ErrorCode,
-    reason_phrase: String,
+    pub error_code: ErrorCode,
+    pub reason_phrase: String,
 }
 
 impl Readable for ConnectionCloseFrame {
-    type Context = ();
+    type Context = WireFormat;
 
-    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+    fn read_with_context<R: Read>(reader: &mut R, wire_format: &Self::Context) -> Result<Self> {
         trace!("reading connection close frame");
 
-        let error_code =
-            Readable::read(reader).chain_err(|| ErrorKind::FailedToReadConnectionCloseFrame)?;
+        let error_code = match wire_format {
+            WireFormat::Draft08 => {
+                Readable::read(reader).chain_err(|| ErrorKind::FailedToReadConnectionCloseFrame)?
+            }
+            WireFormat::V1 => {
+                let code = VarInt::read(reader)
+                    .chain_err(|| ErrorKind::FailedToReadConnectionCloseFrame)?;
+                let frame_type = VarInt::read(reader)
+                    .chain_err(|| ErrorKind::FailedToReadConnectionCloseFrame)?;
+
+                ErrorCode::from_v1_code(code.into_inner(), frame_type.into_inner())
+                    .chain_err(|| ErrorKind::FailedToReadConnectionCloseFrame)?
+            }
+        };
 
         let reason_phrase_len =
             VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadConnectionCloseFrame)?;
@@ -35,13 +47,21 @@ impl Readable for ConnectionCloseFrame {
     }
 }
 
-impl Writable for ConnectionCloseFrame {
-    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+impl WritableInFormat for ConnectionCloseFrame {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()> {
         trace!("writing connection close frame {:?}", self);
 
-        self.error_code
-            .write(writer)
-            .chain_err(|| ErrorKind::FailedToWriteConnectionCloseFrame)?;
+        match wire_format {
+            WireFormat::Draft08 => self.error_code.write(writer),
+            WireFormat::V1 => {
+                let (code, frame_type) = self.error_code.v1_code();
+
+                VarInt::value_from(code)
+                    .and_then(|code| code.write(writer))
+                    .and_then(|_| VarInt::value_from(frame_type))
+                    .and_then(|frame_type| frame_type.write(writer))
+            }
+        }.chain_err(|| ErrorKind::FailedToWriteConnectionCloseFrame)?;
 
         let reason_phrase_len: VarInt = self.reason_phrase
             .len()
@@ -60,3 +80,9 @@ impl Writable for ConnectionCloseFrame {
         Ok(())
     }
 }
+
+impl Writable for ConnectionCloseFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_in_format(writer, WireFormat::Draft08)
+    }
+}
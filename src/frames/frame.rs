@@ -2,10 +2,11 @@ use conv::ValueInto;
 use errors::*;
 use frames::{AckFrame, ApplicationCloseFrame, BlockedFrame, ConnectionCloseFrame, CryptoFrame,
              InitialPacketFrame, MaxDataFrame, MaxStreamDataFrame, MaxStreamIdFrame,
-             NewConnectionIdFrame, PathChallengeFrame, PathResponseFrame, ReadStreamFrameContext,
-             ResetStreamFrame, StopSendingFrame, StreamBlockedFrame, StreamFrame,
-             StreamIdBlockedFrame};
-use protocol::{Readable, VarInt, Writable};
+             MaxStreamsFrame, NewConnectionIdFrame, NewTokenFrame, PathChallengeFrame,
+             PathResponseFrame, ReadStreamFrameContext, ResetStreamFrame,
+             RetireConnectionIdFrame, StopSendingFrame, StreamBlockedFrame, StreamFrame,
+             StreamIdBlockedFrame, StreamsBlockedFrame};
+use protocol::{Readable, StreamType, VarInt, WireFormat, Writable, WritableInFormat};
 use std::io::{Read, Write};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -16,18 +17,30 @@ pub enum Frame {
     ApplicationClose(ApplicationCloseFrame),
     MaxData(MaxDataFrame),
     MaxStreamData(MaxStreamDataFrame),
+    /// Only available in draft 08, replaced by `MaxStreams` in version 1.
     MaxStreamId(MaxStreamIdFrame),
+    /// Only available in version 1.
+    MaxStreams(MaxStreamsFrame),
     Ping,
     Blocked(BlockedFrame),
     StreamBlocked(StreamBlockedFrame),
+    /// Only available in draft 08, replaced by `StreamsBlocked` in version 1.
     StreamIdBlocked(StreamIdBlockedFrame),
+    /// Only available in version 1.
+    StreamsBlocked(StreamsBlockedFrame),
     NewConnectionId(NewConnectionIdFrame),
+    /// Only available in version 1.
+    RetireConnectionId(RetireConnectionIdFrame),
+    /// Only available in version 1, and only sent by servers.
+    NewToken(NewTokenFrame),
     StopSending(StopSendingFrame),
     Ack(AckFrame),
     PathChallenge(PathChallengeFrame),
     PathResponse(PathResponseFrame),
     Stream(StreamFrame),
     Crypto(CryptoFrame),
+    /// Only available in version 1.
+    HandshakeDone,
 }
 
 impl From<InitialPacketFrame> for Frame {
@@ -69,54 +82,176 @@ bitflags!(
     }
 );
 
+/// The frame types of version 1 (RFC 9000), these do not share the draft 08 numbering.
+const V1_PADDING: u8 = 0x00;
+const V1_PING: u8 = 0x01;
+const V1_ACK: u8 = 0x02;
+const V1_ACK_ECN: u8 = 0x03;
+const V1_RESET_STREAM: u8 = 0x04;
+const V1_STOP_SENDING: u8 = 0x05;
+const V1_CRYPTO: u8 = 0x06;
+const V1_NEW_TOKEN: u8 = 0x07;
+const V1_STREAM: u8 = 0x08;
+const V1_STREAM_MAX: u8 = 0x0f;
+const V1_MAX_DATA: u8 = 0x10;
+const V1_MAX_STREAM_DATA: u8 = 0x11;
+const V1_MAX_STREAMS_BIDI: u8 = 0x12;
+const V1_MAX_STREAMS_UNI: u8 = 0x13;
+const V1_DATA_BLOCKED: u8 = 0x14;
+const V1_STREAM_DATA_BLOCKED: u8 = 0x15;
+const V1_STREAMS_BLOCKED_BIDI: u8 = 0x16;
+const V1_STREAMS_BLOCKED_UNI: u8 = 0x17;
+const V1_NEW_CONNECTION_ID: u8 = 0x18;
+const V1_RETIRE_CONNECTION_ID: u8 = 0x19;
+const V1_PATH_CHALLENGE: u8 = 0x1a;
+const V1_PATH_RESPONSE: u8 = 0x1b;
+const V1_CONNECTION_CLOSE: u8 = 0x1c;
+const V1_APPLICATION_CLOSE: u8 = 0x1d;
+const V1_HANDSHAKE_DONE: u8 = 0x1e;
+
+fn read_stream_frame<R: Read>(reader: &mut R, flags: u8) -> Result<StreamFrame> {
+    let stream_frame_flags = StreamFrameFlags::from_bits_truncate(flags);
+    let read_stream_frame_context = ReadStreamFrameContext {
+        is_offset_present: stream_frame_flags.contains(STREAM_FRAME_OFFSET_PRESENT),
+        is_length_present: stream_frame_flags.contains(STREAM_FRAME_LEN_PRESENT),
+        finished: stream_frame_flags.contains(STREAM_FRAME_FIN),
+    };
+
+    Readable::read_with_context(reader, &read_stream_frame_context)
+}
+
+fn stream_frame_flags(stream_frame: &StreamFrame) -> StreamFrameFlags {
+    let mut flags = StreamFrameFlags::empty();
+    if stream_frame.has_offset() {
+        flags |= STREAM_FRAME_OFFSET_PRESENT;
+    }
+
+    flags |= STREAM_FRAME_LEN_PRESENT;
+    if stream_frame.finished {
+        flags |= STREAM_FRAME_FIN;
+    }
+
+    flags
+}
+
+fn read_draft_08_frame<R: Read>(reader: &mut R, flags: u8) -> Result<Frame> {
+    let wire_format = WireFormat::Draft08;
+
+    let frame = if flags == 0 {
+        Frame::Padding
+    } else if flags >= 0x10 && flags <= 0x17 {
+        Frame::Stream(read_stream_frame(reader, flags)?)
+    } else {
+        let flags = FrameTypeFlags::from_bits_truncate(flags);
+
+        match flags {
+            RESET_STREAM => Frame::ResetStream(Readable::read_with_context(reader, &wire_format)?),
+            CONNECTION_CLOSE => {
+                Frame::ConnectionClose(Readable::read_with_context(reader, &wire_format)?)
+            }
+            APPLICATION_CLOSE => {
+                Frame::ApplicationClose(Readable::read_with_context(reader, &wire_format)?)
+            }
+            MAX_DATA => Frame::MaxData(Readable::read(reader)?),
+            MAX_STREAM_DATA => Frame::MaxStreamData(Readable::read(reader)?),
+            MAX_STREAM_ID => Frame::MaxStreamId(Readable::read(reader)?),
+            PING => Frame::Ping,
+            BLOCKED => Frame::Blocked(Readable::read(reader)?),
+            STREAM_BLOCKED => Frame::StreamBlocked(Readable::read(reader)?),
+            STREAM_ID_BLOCKED => Frame::StreamIdBlocked(Readable::read(reader)?),
+            NEW_CONNECTION_ID => {
+                Frame::NewConnectionId(Readable::read_with_context(reader, &wire_format)?)
+            }
+            STOP_SENDING => Frame::StopSending(Readable::read_with_context(reader, &wire_format)?),
+            ACK => Frame::Ack(Readable::read(reader)?),
+            PATH_CHALLENGE => Frame::PathChallenge(Readable::read(reader)?),
+            PATH_RESPONSE => Frame::PathResponse(Readable::read(reader)?),
+            CRYPTO => Frame::Crypto(Readable::read(reader)?),
+            _ => bail!(ErrorKind::FailedToReadFrame),
+        }
+    };
+
+    Ok(frame)
+}
+
+fn read_v1_frame<R: Read>(reader: &mut R, frame_type: u8) -> Result<Frame> {
+    let wire_format = WireFormat::V1;
+
+    let frame = match frame_type {
+        V1_PADDING => Frame::Padding,
+        V1_PING => Frame::Ping,
+        V1_ACK => Frame::Ack(Readable::read(reader)?),
+        V1_ACK_ECN => {
+            let ack_frame = Readable::read(reader)?;
+
+            // the ECN counts are not used yet
+            for _ in 0..3 {
+                VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadAckFrame)?;
+            }
+
+            Frame::Ack(ack_frame)
+        }
+        V1_RESET_STREAM => {
+            Frame::ResetStream(Readable::read_with_context(reader, &wire_format)?)
+        }
+        V1_STOP_SENDING => {
+            Frame::StopSending(Readable::read_with_context(reader, &wire_format)?)
+        }
+        V1_CRYPTO => Frame::Crypto(Readable::read(reader)?),
+        V1_NEW_TOKEN => Frame::NewToken(Readable::read(reader)?),
+        V1_STREAM...V1_STREAM_MAX => Frame::Stream(read_stream_frame(reader, frame_type)?),
+        V1_MAX_DATA => Frame::MaxData(Readable::read(reader)?),
+        V1_MAX_STREAM_DATA => Frame::MaxStreamData(Readable::read(reader)?),
+        V1_MAX_STREAMS_BIDI => {
+            Frame::MaxStreams(Readable::read_with_context(reader, &StreamType::Bidirectional)?)
+        }
+        V1_MAX_STREAMS_UNI => {
+            Frame::MaxStreams(Readable::read_with_context(reader, &StreamType::Unidirectional)?)
+        }
+        V1_DATA_BLOCKED => Frame::Blocked(Readable::read(reader)?),
+        V1_STREAM_DATA_BLOCKED => Frame::StreamBlocked(Readable::read(reader)?),
+        V1_STREAMS_BLOCKED_BIDI => Frame::StreamsBlocked(Readable::read_with_context(
+            reader,
+            &StreamType::Bidirectional,
+        )?),
+        V1_STREAMS_BLOCKED_UNI => Frame::StreamsBlocked(Readable::read_with_context(
+            reader,
+            &StreamType::Unidirectional,
+        )?),
+        V1_NEW_CONNECTION_ID => {
+            Frame::NewConnectionId(Readable::read_with_context(reader, &wire_format)?)
+        }
+        V1_RETIRE_CONNECTION_ID => Frame::RetireConnectionId(Readable::read(reader)?),
+        V1_PATH_CHALLENGE => Frame::PathChallenge(Readable::read(reader)?),
+        V1_PATH_RESPONSE => Frame::PathResponse(Readable::read(reader)?),
+        V1_CONNECTION_CLOSE => {
+            Frame::ConnectionClose(Readable::read_with_context(reader, &wire_format)?)
+        }
+        V1_APPLICATION_CLOSE => {
+            Frame::ApplicationClose(Readable::read_with_context(reader, &wire_format)?)
+        }
+        V1_HANDSHAKE_DONE => Frame::HandshakeDone,
+        _ => bail!(ErrorKind::FailedToReadFrame),
+    };
+
+    Ok(frame)
+}
+
 impl Readable for Frame {
-    type Context = ();
+    type Context = WireFormat;
 
-    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+    fn read_with_context<R: Read>(reader: &mut R, wire_format: &Self::Context) -> Result<Self> {
         trace!("reading frame");
 
-        let flags: u8 = VarInt::read(reader)
+        let frame_type: u8 = VarInt::read(reader)
             .chain_err(|| ErrorKind::FailedToReadFrame)?
             .into_inner()
             .value_into()
-            .unwrap();
-
-        let read_frame = if flags == 0 {
-            Frame::Padding
-        } else if flags >= 0x10 && flags <= 0x17 {
-            let stream_frame_flags = StreamFrameFlags::from_bits_truncate(flags);
-            let read_stream_frame_context = ReadStreamFrameContext {
-                is_offset_present: stream_frame_flags.contains(STREAM_FRAME_OFFSET_PRESENT),
-                is_length_present: stream_frame_flags.contains(STREAM_FRAME_LEN_PRESENT),
-                finished: stream_frame_flags.contains(STREAM_FRAME_FIN),
-            };
+            .chain_err(|| ErrorKind::FailedToReadFrame)?;
 
-            Frame::Stream(Readable::read_with_context(
-                reader,
-                &read_stream_frame_context,
-            )?)
-        } else {
-            let flags = FrameTypeFlags::from_bits_truncate(flags);
-
-            match flags {
-                RESET_STREAM => Frame::ResetStream(Readable::read(reader)?),
-                CONNECTION_CLOSE => Frame::ConnectionClose(Readable::read(reader)?),
-                APPLICATION_CLOSE => Frame::ApplicationClose(Readable::read(reader)?),
-                MAX_DATA => Frame::MaxData(Readable::read(reader)?),
-                MAX_STREAM_DATA => Frame::MaxStreamData(Readable::read(reader)?),
-                MAX_STREAM_ID => Frame::MaxStreamId(Readable::read(reader)?),
-                PING => Frame::Ping,
-                BLOCKED => Frame::Blocked(Readable::read(reader)?),
-                STREAM_BLOCKED => Frame::StreamBlocked(Readable::read(reader)?),
-                STREAM_ID_BLOCKED => Frame::StreamIdBlocked(Readable::read(reader)?),
-                NEW_CONNECTION_ID => Frame::NewConnectionId(Readable::read(reader)?),
-                STOP_SENDING => Frame::StopSending(Readable::read(reader)?),
-                ACK => Frame::Ack(Readable::read(reader)?),
-                PATH_CHALLENGE => Frame::PathChallenge(Readable::read(reader)?),
-                PATH_RESPONSE => Frame::PathResponse(Readable::read(reader)?),
-                CRYPTO => Frame::Crypto(Readable::read(reader)?),
-                _ => bail!(ErrorKind::FailedToReadFrame),
-            }
+        let read_frame = match wire_format {
+            WireFormat::Draft08 => read_draft_08_frame(reader, frame_type)?,
+            WireFormat::V1 => read_v1_frame(reader, frame_type)?,
         };
 
         debug!("read frame {:?}", read_frame);
@@ -125,129 +260,358 @@ impl Readable for Frame {
     }
 }
 
-impl Writable for Frame {
-    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
-        trace!("writing frame {:?}", self);
+impl Frame {
+    fn name(&self) -> &'static str {
+        match self {
+            Frame::Padding => "padding",
+            Frame::ResetStream(_) => "reset stream",
+            Frame::ConnectionClose(_) => "connection close",
+            Frame::ApplicationClose(_) => "application close",
+            Frame::MaxData(_) => "max data",
+            Frame::MaxStreamData(_) => "max stream data",
+            Frame::MaxStreamId(_) => "max stream id",
+            Frame::MaxStreams(_) => "max streams",
+            Frame::Ping => "ping",
+            Frame::Blocked(_) => "blocked",
+            Frame::StreamBlocked(_) => "stream blocked",
+            Frame::StreamIdBlocked(_) => "stream id blocked",
+            Frame::StreamsBlocked(_) => "streams blocked",
+            Frame::NewConnectionId(_) => "new connection id",
+            Frame::RetireConnectionId(_) => "retire connection id",
+            Frame::NewToken(_) => "new token",
+            Frame::StopSending(_) => "stop sending",
+            Frame::Ack(_) => "ack",
+            Frame::PathChallenge(_) => "path challenge",
+            Frame::PathResponse(_) => "path response",
+            Frame::Stream(_) => "stream",
+            Frame::Crypto(_) => "crypto",
+            Frame::HandshakeDone => "handshake done",
+        }
+    }
 
+    /// Whether the receipt of this frame must be acknowledged.
+    pub fn is_ack_eliciting(&self) -> bool {
         match self {
-            Frame::Padding => {
-                let type_flags = VarInt::from(FrameTypeFlags::empty().bits());
+            Frame::Padding
+            | Frame::Ack(_)
+            | Frame::ConnectionClose(_)
+            | Frame::ApplicationClose(_) => false,
+            _ => true,
+        }
+    }
 
-                type_flags
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWritePaddingFrame)?;
-            }
-            Frame::ResetStream(reset_stream_frame) => {
-                VarInt::from(RESET_STREAM.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteResetStreamFrame)?;
-                reset_stream_frame.write(writer)?;
-            }
-            Frame::ConnectionClose(connection_close_frame) => {
-                VarInt::from(CONNECTION_CLOSE.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteConnectionCloseFrame)?;
-                connection_close_frame.write(writer)?;
-            }
-            Frame::ApplicationClose(application_close_frame) => {
-                VarInt::from(APPLICATION_CLOSE.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteApplicationCloseFrame)?;
-                application_close_frame.write(writer)?;
-            }
-            Frame::MaxData(max_data_frame) => {
-                VarInt::from(MAX_DATA.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteMaxDataFrame)?;
-                max_data_frame.write(writer)?;
-            }
-            Frame::MaxStreamData(max_stream_data_frame) => {
-                VarInt::from(MAX_STREAM_DATA.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteMaxStreamDataFrame)?;
-                max_stream_data_frame.write(writer)?;
-            }
-            Frame::MaxStreamId(max_stream_id_frame) => {
-                VarInt::from(MAX_STREAM_ID.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteMaxStreamIdFrame)?;
-                max_stream_id_frame.write(writer)?;
-            }
-            Frame::Ping => {
-                VarInt::from(PING.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWritePingFrame)?;
-            }
-            Frame::Blocked(blocked_frame) => {
-                VarInt::from(BLOCKED.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteBlockedFrame)?;
-                blocked_frame.write(writer)?;
-            }
-            Frame::StreamBlocked(stream_blocked_frame) => {
-                VarInt::from(STREAM_BLOCKED.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteStreamBlockedFrame)?;
-                stream_blocked_frame.write(writer)?;
-            }
-            Frame::StreamIdBlocked(stream_id_blocked_frame) => {
-                VarInt::from(STREAM_ID_BLOCKED.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteStreamIdBlockedFrame)?;
-                stream_id_blocked_frame.write(writer)?
-            }
-            Frame::NewConnectionId(new_connection_id_frame) => {
-                VarInt::from(NEW_CONNECTION_ID.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
-                new_connection_id_frame.write(writer)?;
-            }
-            Frame::StopSending(stop_sending_frame) => {
-                VarInt::from(STOP_SENDING.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteStopSendingFrame)?;
-                stop_sending_frame.write(writer)?;
-            }
-            Frame::Ack(ack_frame) => {
-                VarInt::from(ACK.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteAckFrame)?;
-                ack_frame.write(writer)?;
-            }
-            Frame::PathChallenge(path_challenge_frame) => {
-                VarInt::from(PATH_CHALLENGE.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWritePathChallengeFrame)?;
-                path_challenge_frame.write(writer)?;
-            }
-            Frame::PathResponse(path_response_frame) => {
-                VarInt::from(PATH_RESPONSE.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWritePathResponseFrame)?;
-                path_response_frame.write(writer)?;
-            }
-            Frame::Stream(stream_frame) => {
-                let mut flags = StreamFrameFlags::empty();
-                if stream_frame.has_offset() {
-                    flags |= STREAM_FRAME_OFFSET_PRESENT;
-                }
-
-                flags |= STREAM_FRAME_LEN_PRESENT;
-                if stream_frame.finished {
-                    flags |= STREAM_FRAME_FIN;
-                }
-
-                VarInt::from(0x10u8 | flags.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteStreamFrame)?;
-                stream_frame.write(writer)?;
-            }
-            Frame::Crypto(crypto_frame) => {
-                VarInt::from(CRYPTO.bits())
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWriteCryptoFrame)?;
-                crypto_frame.write(writer)?;
-            }
+    /// Whether this frame is sent again when the packet carrying it is lost.
+    ///
+    /// ACK frames are rebuilt from the latest state rather than resent, and PING, PADDING, path
+    /// validation and connection closing frames are only meaningful when they are first sent.
+    pub fn is_retransmittable(&self) -> bool {
+        match self {
+            Frame::Padding
+            | Frame::Ping
+            | Frame::Ack(_)
+            | Frame::PathChallenge(_)
+            | Frame::PathResponse(_)
+            | Frame::ConnectionClose(_)
+            | Frame::ApplicationClose(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Whether this frame may be sent in version 1 Initial and Handshake packets (RFC 9000
+    /// section 12.4), every other frame waits for 0-RTT or 1-RTT packets.
+    pub fn is_allowed_in_handshake_packets(&self) -> bool {
+        match self {
+            Frame::Padding
+            | Frame::Ping
+            | Frame::Ack(_)
+            | Frame::Crypto(_)
+            | Frame::ConnectionClose(_) => true,
+            _ => false,
+        }
+    }
+}
+
+fn write_draft_08_frame<W: Write>(frame: &Frame, writer: &mut W) -> Result<()> {
+    let wire_format = WireFormat::Draft08;
+
+    match frame {
+        Frame::Padding => {
+            let type_flags = VarInt::from(FrameTypeFlags::empty().bits());
+
+            type_flags
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePaddingFrame)?;
+        }
+        Frame::ResetStream(reset_stream_frame) => {
+            VarInt::from(RESET_STREAM.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteResetStreamFrame)?;
+            reset_stream_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::ConnectionClose(connection_close_frame) => {
+            VarInt::from(CONNECTION_CLOSE.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteConnectionCloseFrame)?;
+            connection_close_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::ApplicationClose(application_close_frame) => {
+            VarInt::from(APPLICATION_CLOSE.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteApplicationCloseFrame)?;
+            application_close_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::MaxData(max_data_frame) => {
+            VarInt::from(MAX_DATA.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteMaxDataFrame)?;
+            max_data_frame.write(writer)?;
+        }
+        Frame::MaxStreamData(max_stream_data_frame) => {
+            VarInt::from(MAX_STREAM_DATA.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteMaxStreamDataFrame)?;
+            max_stream_data_frame.write(writer)?;
+        }
+        Frame::MaxStreamId(max_stream_id_frame) => {
+            VarInt::from(MAX_STREAM_ID.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteMaxStreamIdFrame)?;
+            max_stream_id_frame.write(writer)?;
+        }
+        Frame::Ping => {
+            VarInt::from(PING.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePingFrame)?;
+        }
+        Frame::Blocked(blocked_frame) => {
+            VarInt::from(BLOCKED.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteBlockedFrame)?;
+            blocked_frame.write(writer)?;
+        }
+        Frame::StreamBlocked(stream_blocked_frame) => {
+            VarInt::from(STREAM_BLOCKED.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStreamBlockedFrame)?;
+            stream_blocked_frame.write(writer)?;
+        }
+        Frame::StreamIdBlocked(stream_id_blocked_frame) => {
+            VarInt::from(STREAM_ID_BLOCKED.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStreamIdBlockedFrame)?;
+            stream_id_blocked_frame.write(writer)?
+        }
+        Frame::NewConnectionId(new_connection_id_frame) => {
+            VarInt::from(NEW_CONNECTION_ID.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
+            new_connection_id_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::StopSending(stop_sending_frame) => {
+            VarInt::from(STOP_SENDING.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStopSendingFrame)?;
+            stop_sending_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::Ack(ack_frame) => {
+            VarInt::from(ACK.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteAckFrame)?;
+            ack_frame.write(writer)?;
+        }
+        Frame::PathChallenge(path_challenge_frame) => {
+            VarInt::from(PATH_CHALLENGE.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePathChallengeFrame)?;
+            path_challenge_frame.write(writer)?;
+        }
+        Frame::PathResponse(path_response_frame) => {
+            VarInt::from(PATH_RESPONSE.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePathResponseFrame)?;
+            path_response_frame.write(writer)?;
+        }
+        Frame::Stream(stream_frame) => {
+            let flags = stream_frame_flags(stream_frame);
+
+            VarInt::from(0x10u8 | flags.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStreamFrame)?;
+            stream_frame.write(writer)?;
+        }
+        Frame::Crypto(crypto_frame) => {
+            VarInt::from(CRYPTO.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteCryptoFrame)?;
+            crypto_frame.write(writer)?;
+        }
+        Frame::MaxStreams(_)
+        | Frame::StreamsBlocked(_)
+        | Frame::RetireConnectionId(_)
+        | Frame::NewToken(_)
+        | Frame::HandshakeDone => bail!(ErrorKind::FrameIsNotSupportedInWireFormat(
+            frame.name(),
+            wire_format
+        )),
+    }
+
+    Ok(())
+}
+
+fn write_v1_frame<W: Write>(frame: &Frame, writer: &mut W) -> Result<()> {
+    let wire_format = WireFormat::V1;
+
+    match frame {
+        Frame::Padding => {
+            VarInt::from(V1_PADDING)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePaddingFrame)?;
+        }
+        Frame::Ping => {
+            VarInt::from(V1_PING)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePingFrame)?;
+        }
+        Frame::Ack(ack_frame) => {
+            VarInt::from(V1_ACK)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteAckFrame)?;
+            ack_frame.write(writer)?;
+        }
+        Frame::ResetStream(reset_stream_frame) => {
+            VarInt::from(V1_RESET_STREAM)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteResetStreamFrame)?;
+            reset_stream_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::StopSending(stop_sending_frame) => {
+            VarInt::from(V1_STOP_SENDING)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStopSendingFrame)?;
+            stop_sending_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::Crypto(crypto_frame) => {
+            VarInt::from(V1_CRYPTO)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteCryptoFrame)?;
+            crypto_frame.write(writer)?;
+        }
+        Frame::NewToken(new_token_frame) => {
+            VarInt::from(V1_NEW_TOKEN)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteNewTokenFrame)?;
+            new_token_frame.write(writer)?;
+        }
+        Frame::Stream(stream_frame) => {
+            let flags = stream_frame_flags(stream_frame);
+
+            VarInt::from(V1_STREAM | flags.bits())
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStreamFrame)?;
+            stream_frame.write(writer)?;
+        }
+        Frame::MaxData(max_data_frame) => {
+            VarInt::from(V1_MAX_DATA)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteMaxDataFrame)?;
+            max_data_frame.write(writer)?;
+        }
+        Frame::MaxStreamData(max_stream_data_frame) => {
+            VarInt::from(V1_MAX_STREAM_DATA)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteMaxStreamDataFrame)?;
+            max_stream_data_frame.write(writer)?;
+        }
+        Frame::MaxStreams(max_streams_frame) => {
+            let frame_type = match max_streams_frame.stream_type {
+                StreamType::Bidirectional => V1_MAX_STREAMS_BIDI,
+                StreamType::Unidirectional => V1_MAX_STREAMS_UNI,
+            };
+
+            VarInt::from(frame_type)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteMaxStreamsFrame)?;
+            max_streams_frame.write(writer)?;
+        }
+        Frame::Blocked(blocked_frame) => {
+            VarInt::from(V1_DATA_BLOCKED)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteBlockedFrame)?;
+            blocked_frame.write(writer)?;
+        }
+        Frame::StreamBlocked(stream_blocked_frame) => {
+            VarInt::from(V1_STREAM_DATA_BLOCKED)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStreamBlockedFrame)?;
+            stream_blocked_frame.write(writer)?;
+        }
+        Frame::StreamsBlocked(streams_blocked_frame) => {
+            let frame_type = match streams_blocked_frame.stream_type {
+                StreamType::Bidirectional => V1_STREAMS_BLOCKED_BIDI,
+                StreamType::Unidirectional => V1_STREAMS_BLOCKED_UNI,
+            };
+
+            VarInt::from(frame_type)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteStreamsBlockedFrame)?;
+            streams_blocked_frame.write(writer)?;
+        }
+        Frame::NewConnectionId(new_connection_id_frame) => {
+            VarInt::from(V1_NEW_CONNECTION_ID)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
+            new_connection_id_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::RetireConnectionId(retire_connection_id_frame) => {
+            VarInt::from(V1_RETIRE_CONNECTION_ID)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteRetireConnectionIdFrame)?;
+            retire_connection_id_frame.write(writer)?;
+        }
+        Frame::PathChallenge(path_challenge_frame) => {
+            VarInt::from(V1_PATH_CHALLENGE)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePathChallengeFrame)?;
+            path_challenge_frame.write(writer)?;
+        }
+        Frame::PathResponse(path_response_frame) => {
+            VarInt::from(V1_PATH_RESPONSE)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePathResponseFrame)?;
+            path_response_frame.write(writer)?;
+        }
+        Frame::ConnectionClose(connection_close_frame) => {
+            VarInt::from(V1_CONNECTION_CLOSE)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteConnectionCloseFrame)?;
+            connection_close_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::ApplicationClose(application_close_frame) => {
+            VarInt::from(V1_APPLICATION_CLOSE)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteApplicationCloseFrame)?;
+            application_close_frame.write_in_format(writer, wire_format)?;
+        }
+        Frame::HandshakeDone => {
+            VarInt::from(V1_HANDSHAKE_DONE)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteHandshakeDoneFrame)?;
+        }
+        Frame::MaxStreamId(_) | Frame::StreamIdBlocked(_) => bail!(
+            ErrorKind::FrameIsNotSupportedInWireFormat(frame.name(), wire_format)
+        ),
+    }
+
+    Ok(())
+}
+
+impl WritableInFormat for Frame {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()> {
+        trace!("writing frame {:?}", self);
+
+        match wire_format {
+            WireFormat::Draft08 => write_draft_08_frame(self, writer)?,
+            WireFormat::V1 => write_v1_frame(self, writer)?,
         }
 
         debug!("written frame {:?}", self);
@@ -256,12 +620,28 @@ impl Writable for Frame {
     }
 }
 
+impl Writable for Frame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_in_format(writer, WireFormat::Draft08)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Frame;
     use bytes::Bytes;
-    use frames::{CryptoFrame, StreamFrame};
-    use protocol::{self, StreamId};
+    use frames::{AckFrame, ConnectionCloseFrame, CryptoFrame, MaxStreamsFrame,
+                 NewConnectionIdFrame, NewTokenFrame, StreamFrame};
+    use protocol::{self, ConnectionId, ErrorCode, InFormat, Readable, StatelessResetToken,
+                   StreamId, StreamType, WireFormat, Writable};
+
+    fn test_write_read_v1(frame: &Frame) {
+        let bytes = InFormat(frame, WireFormat::V1).bytes().unwrap();
+
+        let read = Frame::from_bytes_with_context(&bytes[..], &WireFormat::V1).unwrap();
+
+        assert_eq!(&read, frame);
+    }
 
     #[test]
     fn write_read_stream_frame() {
@@ -284,4 +664,130 @@ mod tests {
 
         protocol::test_write_read(&crypto_frame).unwrap();
     }
+
+    #[test]
+    fn write_read_v1_stream_frame() {
+        let stream_frame = Frame::Stream(StreamFrame {
+            finished: false,
+            offset: 0u32.into(),
+            stream_id: StreamId::first_unidirectional_client_stream_id(),
+            data: Bytes::from(&[0x78, 0x91][..]),
+        });
+
+        test_write_read_v1(&stream_frame);
+    }
+
+    #[test]
+    fn write_read_v1_connection_close_frame() {
+        let connection_close_frame = Frame::ConnectionClose(ConnectionCloseFrame {
+            error_code: ErrorCode::FrameError(0x08),
+            reason_phrase: "invalid stream frame".to_owned(),
+        });
+
+        test_write_read_v1(&connection_close_frame);
+    }
+
+    #[test]
+    fn write_read_v1_new_connection_id_frame() {
+        let new_connection_id_frame = Frame::NewConnectionId(NewConnectionIdFrame {
+            sequence: 3,
+            retire_prior_to: 2,
+            connection_id: ConnectionId::generate().unwrap(),
+            stateless_reset_token: StatelessResetToken::from([0x42; 16]),
+        });
+
+        test_write_read_v1(&new_connection_id_frame);
+    }
+
+    #[test]
+    fn write_read_new_connection_id_frame_with_eight_byte_connection_id() {
+        let frames = vec![
+            Frame::NewConnectionId(NewConnectionIdFrame {
+                sequence: 1,
+                retire_prior_to: 0,
+                connection_id: ConnectionId::generate_with_len(8).unwrap(),
+                stateless_reset_token: StatelessResetToken::from([0x42; 16]),
+            }),
+            Frame::Ping,
+        ];
+
+        let bytes = frames.bytes().unwrap();
+        let read: Vec<Frame> = Frame::collect_from_bytes(&bytes[..]).unwrap();
+
+        assert_eq!(read, frames);
+    }
+
+    #[test]
+    fn write_read_v1_new_token_frame() {
+        let new_token_frame = Frame::NewToken(NewTokenFrame {
+            token: Bytes::from(&b"address validation token"[..]),
+        });
+
+        test_write_read_v1(&new_token_frame);
+    }
+
+    #[test]
+    fn write_read_v1_max_streams_frame() {
+        let max_streams_frame = Frame::MaxStreams(MaxStreamsFrame {
+            stream_type: StreamType::Unidirectional,
+            maximum_streams: 100,
+        });
+
+        test_write_read_v1(&max_streams_frame);
+    }
+
+    #[test]
+    fn v1_crypto_frame_type_differs_from_draft_08() {
+        let crypto_frame = Frame::Crypto(CryptoFrame {
+            offset: 0u32.into(),
+            data: Bytes::from(&[0x01][..]),
+        });
+
+        let draft_08_bytes = crypto_frame.bytes().unwrap();
+        let v1_bytes = InFormat(&crypto_frame, WireFormat::V1).bytes().unwrap();
+
+        assert_eq!(draft_08_bytes[0], 0x18);
+        assert_eq!(v1_bytes[0], 0x06);
+    }
+
+    #[test]
+    fn padding_and_ack_frames_are_not_ack_eliciting() {
+        assert!(!Frame::Padding.is_ack_eliciting());
+        assert!(
+            !Frame::Ack(AckFrame {
+                ack_delay: 0,
+                ack_ranges_descending: vec![0..1],
+            }).is_ack_eliciting()
+        );
+        assert!(Frame::Ping.is_ack_eliciting());
+    }
+
+    #[test]
+    fn only_stateful_frames_are_retransmittable() {
+        assert!(!Frame::Ping.is_retransmittable());
+        assert!(Frame::HandshakeDone.is_retransmittable());
+        assert!(
+            Frame::Crypto(CryptoFrame {
+                offset: 0u32.into(),
+                data: Bytes::from_static(b"hello"),
+            }).is_retransmittable()
+        );
+    }
+
+    #[test]
+    fn only_handshake_frames_are_allowed_in_handshake_packets() {
+        assert!(Frame::Ping.is_allowed_in_handshake_packets());
+        assert!(
+            Frame::Crypto(CryptoFrame {
+                offset: 0u32.into(),
+                data: Bytes::from_static(b"hello"),
+            }).is_allowed_in_handshake_packets()
+        );
+        assert!(!Frame::HandshakeDone.is_allowed_in_handshake_packets());
+    }
+
+    #[test]
+    fn handshake_done_frame_is_not_supported_in_draft_08() {
+        assert!(Frame::HandshakeDone.bytes().is_err());
+    }
 }
@@ -0,0 +1,49 @@
+use conv::ValueInto;
+use errors::*;
+use protocol::{Readable, StreamType, VarInt, Writable};
+use std::io::{Read, Write};
+
+/// The version 1 replacement of the `MaxStreamIdFrame`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MaxStreamsFrame {
+    /// The type of streams being limited, this is encoded in the frame type.
+    pub stream_type: StreamType,
+    pub maximum_streams: u64,
+}
+
+impl Readable for MaxStreamsFrame {
+    type Context = StreamType;
+
+    fn read_with_context<R: Read>(reader: &mut R, stream_type: &Self::Context) -> Result<Self> {
+        trace!("reading max streams frame");
+
+        let maximum_streams =
+            VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadMaxStreamsFrame)?;
+
+        let max_streams_frame = Self {
+            stream_type: *stream_type,
+            maximum_streams: maximum_streams.into(),
+        };
+
+        debug!("read max streams frame {:?}", max_streams_frame);
+
+        Ok(max_streams_frame)
+    }
+}
+
+impl Writable for MaxStreamsFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing max streams frame {:?}", self);
+
+        let maximum_streams: VarInt = self.maximum_streams
+            .value_into()
+            .chain_err(|| ErrorKind::FailedToWriteMaxStreamsFrame)?;
+        maximum_streams
+            .write(writer)
+            .chain_err(|| ErrorKind::FailedToWriteMaxStreamsFrame)?;
+
+        debug!("written max streams frame {:?}", self);
+
+        Ok(())
+    }
+}
@@ -16,6 +16,9 @@ pub use self::max_stream_data_frame::MaxStreamDataFrame;
 mod max_stream_id_frame;
 pub use self::max_stream_id_frame::MaxStreamIdFrame;
 
+mod max_streams_frame;
+pub use self::max_streams_frame::MaxStreamsFrame;
+
 mod blocked_frame;
 pub use self::blocked_frame::BlockedFrame;
 
@@ -25,9 +28,18 @@ pub use self::stream_blocked_frame::StreamBlockedFrame;
 mod stream_id_blocked_frame;
 pub use self::stream_id_blocked_frame::StreamIdBlockedFrame;
 
+mod streams_blocked_frame;
+pub use self::streams_blocked_frame::StreamsBlockedFrame;
+
 mod new_connection_id_frame;
 pub use self::new_connection_id_frame::NewConnectionIdFrame;
 
+mod retire_connection_id_frame;
+pub use self::retire_connection_id_frame::RetireConnectionIdFrame;
+
+mod new_token_frame;
+pub use self::new_token_frame::NewTokenFrame;
+
 mod stop_sending_frame;
 pub use self::stop_sending_frame::StopSendingFrame;
 
@@ -51,6 +63,3 @@ pub use self::initial_packet_frame::InitialPacketFrame;
 
 mod frame;
 pub use self::frame::Frame;
-
-mod frame_queue;
-pub use self::frame_queue::FrameQueue;
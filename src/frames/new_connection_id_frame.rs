@@ -1,34 +1,44 @@
 use conv::ValueInto;
 use errors::*;
-use protocol::{ConnectionId, Readable, VarInt, Writable};
+use protocol::{ConnectionId, Readable, StatelessResetToken, VarInt, WireFormat, Writable,
+               WritableInFormat};
 use std::io::{Read, Write};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct NewConnectionIdFrame {
     pub sequence: u64,
-    pub length: u8,
+    /// The sequence number below which connection ids should be retired, this is always 0 in
+    /// draft 08.
+    pub retire_prior_to: u64,
+    /// The new connection id, its length is written before it on the wire.
     pub connection_id: ConnectionId,
-    pub stateless_reset_token: u128,
+    pub stateless_reset_token: StatelessResetToken,
 }
 
 impl Readable for NewConnectionIdFrame {
-    type Context = ();
+    type Context = WireFormat;
 
-    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+    fn read_with_context<R: Read>(reader: &mut R, wire_format: &Self::Context) -> Result<Self> {
         trace!("reading new connection id frame");
 
         let sequence =
             VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+        let retire_prior_to = match wire_format {
+            WireFormat::Draft08 => 0,
+            WireFormat::V1 => VarInt::read(reader)
+                .chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?
+                .into(),
+        };
         let length =
-            Readable::read(reader).chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
-        let connection_id =
-            Readable::read(reader).chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+            u8::read(reader).chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+        let connection_id = ConnectionId::read_with_len(reader, length.into())
+            .chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
         let stateless_reset_token =
             Readable::read(reader).chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
 
         let new_connection_id_frame = Self {
             sequence: sequence.into(),
-            length,
+            retire_prior_to,
             connection_id,
             stateless_reset_token,
         };
@@ -39,29 +49,44 @@ impl Readable for NewConnectionIdFrame {
     }
 }
 
-impl Writable for NewConnectionIdFrame {
-    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+impl WritableInFormat for NewConnectionIdFrame {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()> {
         trace!("writing new connection id frame {:?}", self);
 
         let sequence: VarInt = self.sequence
             .value_into()
-            .chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+            .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
         sequence
             .write(writer)
-            .chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+            .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
+
+        if wire_format == WireFormat::V1 {
+            let retire_prior_to: VarInt = self.retire_prior_to
+                .value_into()
+                .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
+            retire_prior_to
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
+        }
 
-        self.length
+        (self.connection_id.len() as u8)
             .write(writer)
-            .chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+            .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
         self.connection_id
             .write(writer)
-            .chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+            .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
         self.stateless_reset_token
             .write(writer)
-            .chain_err(|| ErrorKind::FailedToReadNewConnectionIdFrame)?;
+            .chain_err(|| ErrorKind::FailedToWriteNewConnectionIdFrame)?;
 
         debug!("written new connection id frame {:?}", self);
 
         Ok(())
     }
 }
+
+impl Writable for NewConnectionIdFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_in_format(writer, WireFormat::Draft08)
+    }
+}
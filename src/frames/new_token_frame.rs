@@ -0,0 +1,56 @@
+use bytes::Bytes;
+use conv::ValueInto;
+use errors::*;
+use protocol::{Readable, VarInt, Writable};
+use std::io::{Read, Write};
+
+/// Gives the client a token to validate its address with in the Initial packets of a later
+/// connection.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct NewTokenFrame {
+    pub token: Bytes,
+}
+
+impl Readable for NewTokenFrame {
+    type Context = ();
+
+    fn read_with_context<R: Read>(reader: &mut R, _context: &Self::Context) -> Result<Self> {
+        trace!("reading new token frame");
+
+        let length = VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadNewTokenFrame)?;
+        let token: Bytes = Readable::read(&mut reader.take(length.into_inner()))
+            .chain_err(|| ErrorKind::FailedToReadNewTokenFrame)?;
+
+        // an empty token could never validate anything
+        if token.is_empty() || token.len() as u64 != length.into_inner() {
+            bail!(ErrorKind::FailedToReadNewTokenFrame);
+        }
+
+        let new_token_frame = Self { token };
+        debug!("read new token frame {:?}", new_token_frame);
+
+        Ok(new_token_frame)
+    }
+}
+
+impl Writable for NewTokenFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing new token frame {:?}", self);
+
+        let length: VarInt = self.token
+            .len()
+            .value_into()
+            .chain_err(|| ErrorKind::FailedToWriteNewTokenFrame)?;
+        length
+            .write(writer)
+            .chain_err(|| ErrorKind::FailedToWriteNewTokenFrame)?;
+
+        self.token
+            .write(writer)
+            .chain_err(|| ErrorKind::FailedToWriteNewTokenFrame)?;
+
+        debug!("written new token frame {:?}", self);
+
+        Ok(())
+    }
+}
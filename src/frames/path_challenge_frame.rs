@@ -4,7 +4,7 @@ use std::io::{Read, Write};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PathChallengeFrame {
-    pub data: u8,
+    pub data: u64,
 }
 
 impl Readable for PathChallengeFrame {
@@ -1,5 +1,6 @@
+use conv::ValueFrom;
 use errors::*;
-use protocol::{Readable, StreamId, StreamOffset, Writable};
+use protocol::{Readable, StreamId, StreamOffset, VarInt, WireFormat, Writable, WritableInFormat};
 use std::io::{Read, Write};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -10,15 +11,24 @@ pub struct ResetStreamFrame {
 }
 
 impl Readable for ResetStreamFrame {
-    type Context = ();
+    type Context = WireFormat;
 
-    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+    fn read_with_context<R: Read>(reader: &mut R, wire_format: &Self::Context) -> Result<Self> {
         trace!("reading reset stream frame");
 
         let stream_id =
             Readable::read(reader).chain_err(|| ErrorKind::FailedToReadResetStreamFrame)?;
-        let application_error_code =
-            Readable::read(reader).chain_err(|| ErrorKind::FailedToReadResetStreamFrame)?;
+        let application_error_code = match wire_format {
+            WireFormat::Draft08 => {
+                Readable::read(reader).chain_err(|| ErrorKind::FailedToReadResetStreamFrame)?
+            }
+            WireFormat::V1 => {
+                let application_error_code = VarInt::read(reader)
+                    .chain_err(|| ErrorKind::FailedToReadResetStreamFrame)?;
+                u16::value_from(application_error_code.into_inner())
+                    .chain_err(|| ErrorKind::FailedToReadResetStreamFrame)?
+            }
+        };
         let final_offset =
             Readable::read(reader).chain_err(|| ErrorKind::FailedToReadResetStreamFrame)?;
 
@@ -34,16 +44,17 @@ impl Readable for ResetStreamFrame {
     }
 }
 
-impl Writable for ResetStreamFrame {
-    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+impl WritableInFormat for ResetStreamFrame {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()> {
         trace!("writing reset stream frame {:?}", self);
 
         self.stream_id
             .write(writer)
             .chain_err(|| ErrorKind::FailedToWriteResetStreamFrame)?;
-        self.application_error_code
-            .write(writer)
-            .chain_err(|| ErrorKind::FailedToWriteResetStreamFrame)?;
+        match wire_format {
+            WireFormat::Draft08 => self.application_error_code.write(writer),
+            WireFormat::V1 => VarInt::from(self.application_error_code).write(writer),
+        }.chain_err(|| ErrorKind::FailedToWriteResetStreamFrame)?;
         self.final_offset
             .write(writer)
             .chain_err(|| ErrorKind::FailedToWriteResetStreamFrame)?;
@@ -53,3 +64,9 @@ impl Writable for ResetStreamFrame {
         Ok(())
     }
 }
+
+impl Writable for ResetStreamFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_in_format(writer, WireFormat::Draft08)
+    }
+}
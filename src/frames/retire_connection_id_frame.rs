@@ -0,0 +1,48 @@
+use conv::ValueInto;
+use errors::*;
+use protocol::{Readable, VarInt, Writable};
+use std::io::{Read, Write};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RetireConnectionIdFrame {
+    pub sequence: u64,
+}
+
+impl Readable for RetireConnectionIdFrame {
+    type Context = ();
+
+    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+        trace!("reading retire connection id frame");
+
+        let sequence =
+            VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadRetireConnectionIdFrame)?;
+
+        let retire_connection_id_frame = Self {
+            sequence: sequence.into(),
+        };
+
+        debug!(
+            "read retire connection id frame {:?}",
+            retire_connection_id_frame
+        );
+
+        Ok(retire_connection_id_frame)
+    }
+}
+
+impl Writable for RetireConnectionIdFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing retire connection id frame {:?}", self);
+
+        let sequence: VarInt = self.sequence
+            .value_into()
+            .chain_err(|| ErrorKind::FailedToWriteRetireConnectionIdFrame)?;
+        sequence
+            .write(writer)
+            .chain_err(|| ErrorKind::FailedToWriteRetireConnectionIdFrame)?;
+
+        debug!("written retire connection id frame {:?}", self);
+
+        Ok(())
+    }
+}
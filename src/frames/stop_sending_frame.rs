@@ -1,5 +1,6 @@
+use conv::ValueFrom;
 use errors::*;
-use protocol::{Readable, StreamId, Writable};
+use protocol::{Readable, StreamId, VarInt, WireFormat, Writable, WritableInFormat};
 use std::io::{Read, Write};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -9,15 +10,24 @@ pub struct StopSendingFrame {
 }
 
 impl Readable for StopSendingFrame {
-    type Context = ();
+    type Context = WireFormat;
 
-    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+    fn read_with_context<R: Read>(reader: &mut R, wire_format: &Self::Context) -> Result<Self> {
         trace!("reading stop sending frame");
 
         let stream_id =
             Readable::read(reader).chain_err(|| ErrorKind::FailedToReadStopSendingFrame)?;
-        let application_error_code =
-            Readable::read(reader).chain_err(|| ErrorKind::FailedToReadStopSendingFrame)?;
+        let application_error_code = match wire_format {
+            WireFormat::Draft08 => {
+                Readable::read(reader).chain_err(|| ErrorKind::FailedToReadStopSendingFrame)?
+            }
+            WireFormat::V1 => {
+                let application_error_code = VarInt::read(reader)
+                    .chain_err(|| ErrorKind::FailedToReadStopSendingFrame)?;
+                u16::value_from(application_error_code.into_inner())
+                    .chain_err(|| ErrorKind::FailedToReadStopSendingFrame)?
+            }
+        };
 
         let stop_sending_frame = Self {
             stream_id,
@@ -30,19 +40,26 @@ impl Readable for StopSendingFrame {
     }
 }
 
-impl Writable for StopSendingFrame {
-    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+impl WritableInFormat for StopSendingFrame {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()> {
         trace!("writing stop sending frame {:?}", self);
 
         self.stream_id
             .write(writer)
             .chain_err(|| ErrorKind::FailedToWriteStopSendingFrame)?;
-        self.application_error_code
-            .write(writer)
-            .chain_err(|| ErrorKind::FailedToWriteStopSendingFrame)?;
+        match wire_format {
+            WireFormat::Draft08 => self.application_error_code.write(writer),
+            WireFormat::V1 => VarInt::from(self.application_error_code).write(writer),
+        }.chain_err(|| ErrorKind::FailedToWriteStopSendingFrame)?;
 
         debug!("written stop sending frame {:?}", self);
 
         Ok(())
     }
 }
+
+impl Writable for StopSendingFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.write_in_format(writer, WireFormat::Draft08)
+    }
+}
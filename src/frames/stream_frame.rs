@@ -1,5 +1,5 @@
 use bytes::Bytes;
-use conv::ValueInto;
+use conv::{ConvUtil, ValueInto};
 use errors::*;
 use protocol::{Readable, StreamId, StreamOffset, VarInt, Writable};
 use std::io::{Read, Write};
@@ -16,6 +16,23 @@ impl StreamFrame {
     pub fn has_offset(&self) -> bool {
         !self.offset.is_zero()
     }
+
+    /// Splits the data at `at`, `self` keeps the data before `at` and the remainder is returned as
+    /// a new `StreamFrame`.
+    pub fn split_off(&mut self, at: usize) -> StreamFrame {
+        let remaining_data = self.data.split_off(at);
+
+        let remainder = StreamFrame {
+            finished: self.finished,
+            offset: self.offset + at.value_as::<u64>().unwrap(),
+            stream_id: self.stream_id,
+            data: remaining_data,
+        };
+
+        self.finished = false;
+
+        remainder
+    }
 }
 
 #[derive(Debug)]
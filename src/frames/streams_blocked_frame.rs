@@ -0,0 +1,49 @@
+use conv::ValueInto;
+use errors::*;
+use protocol::{Readable, StreamType, VarInt, Writable};
+use std::io::{Read, Write};
+
+/// The version 1 replacement of the `StreamIdBlockedFrame`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StreamsBlockedFrame {
+    /// The type of streams which are blocked, this is encoded in the frame type.
+    pub stream_type: StreamType,
+    pub stream_limit: u64,
+}
+
+impl Readable for StreamsBlockedFrame {
+    type Context = StreamType;
+
+    fn read_with_context<R: Read>(reader: &mut R, stream_type: &Self::Context) -> Result<Self> {
+        trace!("reading streams blocked frame");
+
+        let stream_limit =
+            VarInt::read(reader).chain_err(|| ErrorKind::FailedToReadStreamsBlockedFrame)?;
+
+        let streams_blocked_frame = Self {
+            stream_type: *stream_type,
+            stream_limit: stream_limit.into(),
+        };
+
+        debug!("read streams blocked frame {:?}", streams_blocked_frame);
+
+        Ok(streams_blocked_frame)
+    }
+}
+
+impl Writable for StreamsBlockedFrame {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing streams blocked frame {:?}", self);
+
+        let stream_limit: VarInt = self.stream_limit
+            .value_into()
+            .chain_err(|| ErrorKind::FailedToWriteStreamsBlockedFrame)?;
+        stream_limit
+            .write(writer)
+            .chain_err(|| ErrorKind::FailedToWriteStreamsBlockedFrame)?;
+
+        debug!("written streams blocked frame {:?}", self);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+use crypto::CryptoState;
+use errors::*;
+use packets::PacketNumber;
+use std::cmp;
+use std::mem;
+use std::time::{Duration, Instant};
+use AeadPair;
+
+/// The keys an incoming 1-RTT packet is opened with, chosen from its key phase bit and packet
+/// number.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReadKeyPhase {
+    Current,
+    /// The keys in use before the last key update, kept briefly for reordered packets.
+    Previous,
+    /// The keys the peer moves to when it next updates its keys.
+    Next,
+}
+
+/// Rotates the 1-RTT keys of a connection, either when enough has been sent with the current
+/// keys or when the peer flips the key phase bit.
+#[derive(Debug)]
+pub struct KeyUpdate {
+    /// The key phase bit of packets sealed with the current write keys.
+    write_key_phase: bool,
+    /// The key phase bit of packets opened with the current read keys.
+    read_key_phase: bool,
+    next_read: Option<CryptoState>,
+    previous_read: Option<CryptoState>,
+    /// When the previous read keys are discarded.
+    previous_read_discard_at: Option<Instant>,
+    /// The lowest packet number opened with the current read keys, lower packet numbers with
+    /// the other key phase were sealed with the previous keys.
+    first_received_in_read_phase: Option<PacketNumber>,
+    /// The first packet sealed with the current write keys.
+    first_sent_in_write_phase: Option<PacketNumber>,
+    /// Whether a packet sealed with the current write keys has been acknowledged, no update
+    /// may be started until one has.
+    write_phase_acknowledged: bool,
+    packets_sealed: u64,
+    bytes_sealed: u64,
+    /// The number of packets which failed authentication over the life of the connection.
+    failed_opens: u64,
+}
+
+impl Default for KeyUpdate {
+    fn default() -> Self {
+        Self {
+            write_key_phase: false,
+            read_key_phase: false,
+            next_read: None,
+            previous_read: None,
+            previous_read_discard_at: None,
+            first_received_in_read_phase: None,
+            first_sent_in_write_phase: None,
+            write_phase_acknowledged: false,
+            packets_sealed: 0,
+            bytes_sealed: 0,
+            failed_opens: 0,
+        }
+    }
+}
+
+impl KeyUpdate {
+    /// The number of packets sealed with the same keys before they are updated when the
+    /// configuration does not specify one.
+    pub const DEFAULT_INTERVAL_PACKETS: u64 = 1 << 20;
+
+    /// The number of bytes sealed with the same keys before they are updated when the
+    /// configuration does not specify one.
+    pub const DEFAULT_INTERVAL_BYTES: u64 = 1 << 30;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The key phase bit outgoing packets are sent with.
+    pub fn key_phase(&self) -> bool {
+        self.write_key_phase
+    }
+
+    /// Prepares the keys the peer will update to once the 1-RTT `keys` are available.
+    pub fn on_keys_installed(&mut self, keys: &AeadPair, receive_label: &str) -> Result<()> {
+        self.next_read = Some(keys.read.with_key_update(receive_label)?);
+
+        Ok(())
+    }
+
+    /// Which keys open the packet `packet_number` received with `key_phase`.
+    pub fn read_key_phase(&self, key_phase: bool, packet_number: PacketNumber) -> ReadKeyPhase {
+        if key_phase == self.read_key_phase {
+            ReadKeyPhase::Current
+        } else if self.first_received_in_read_phase
+            .map_or(false, |first_received| packet_number < first_received)
+        {
+            ReadKeyPhase::Previous
+        } else {
+            ReadKeyPhase::Next
+        }
+    }
+
+    /// The keys for `read_key_phase`, `None` if they have been discarded.
+    pub fn read_keys<'a>(
+        &'a self,
+        keys: &'a AeadPair,
+        read_key_phase: ReadKeyPhase,
+    ) -> Option<&'a CryptoState> {
+        match read_key_phase {
+            ReadKeyPhase::Current => Some(&keys.read),
+            ReadKeyPhase::Previous => self.previous_read.as_ref(),
+            ReadKeyPhase::Next => self.next_read.as_ref(),
+        }
+    }
+
+    /// Records that `packet_number` was opened with the keys for `read_key_phase`.
+    ///
+    /// A packet opened with the next keys completes the peer's key update, the previous read
+    /// keys are kept for `discard_delay` and the write keys are updated in response if this
+    /// endpoint did not start the update.
+    pub fn on_packet_opened(
+        &mut self,
+        keys: &mut AeadPair,
+        read_key_phase: ReadKeyPhase,
+        packet_number: PacketNumber,
+        send_label: &str,
+        receive_label: &str,
+        discard_delay: Duration,
+        now: Instant,
+    ) -> Result<()> {
+        match read_key_phase {
+            ReadKeyPhase::Current => {
+                self.first_received_in_read_phase = Some(
+                    self.first_received_in_read_phase
+                        .map_or(packet_number, |first| cmp::min(first, packet_number)),
+                );
+            }
+            ReadKeyPhase::Previous => {}
+            ReadKeyPhase::Next => {
+                let next_read = match self.next_read.take() {
+                    Some(next_read) => next_read,
+                    None => unreachable!("packets are only opened with keys which exist"),
+                };
+
+                let previous_read = mem::replace(&mut keys.read, next_read);
+                self.next_read = Some(keys.read.with_key_update(receive_label)?);
+                self.previous_read = Some(previous_read);
+                self.previous_read_discard_at = Some(now + discard_delay);
+                self.read_key_phase = !self.read_key_phase;
+                self.first_received_in_read_phase = Some(packet_number);
+
+                debug!("peer updated its keys, key phase is now {}", self.read_key_phase);
+
+                if self.write_key_phase != self.read_key_phase {
+                    self.update_write_keys(keys, send_label)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a packet which failed authentication.
+    ///
+    /// # Returns
+    /// An error once `integrity_limit` packets have failed, the connection cannot continue.
+    pub fn on_open_failed(&mut self, integrity_limit: u64) -> Result<()> {
+        self.failed_opens += 1;
+
+        if self.failed_opens >= integrity_limit {
+            bail!(ErrorKind::AeadIntegrityLimitReached);
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the previous read keys once reordered packets are no longer expected.
+    pub fn discard_expired_keys(&mut self, now: Instant) {
+        if self.previous_read_discard_at
+            .map_or(false, |discard_at| discard_at <= now)
+        {
+            trace!("discarding the previous read keys");
+
+            self.previous_read = None;
+            self.previous_read_discard_at = None;
+        }
+    }
+
+    /// Updates the write keys before sealing another packet if `interval_packets` packets or
+    /// `interval_bytes` bytes have been sealed with them or their confidentiality limit has been
+    /// reached.
+    ///
+    /// # Returns
+    /// An error if the confidentiality limit has been reached but the keys cannot be updated
+    /// yet, the connection cannot continue.
+    pub fn prepare_to_seal(
+        &mut self,
+        keys: &mut AeadPair,
+        interval_packets: u64,
+        interval_bytes: u64,
+        send_label: &str,
+    ) -> Result<()> {
+        let limit_reached = self.packets_sealed >= keys.write.confidentiality_limit();
+        let interval_reached =
+            self.packets_sealed >= interval_packets || self.bytes_sealed >= interval_bytes;
+
+        if !limit_reached && !interval_reached {
+            return Ok(());
+        }
+
+        // an update may not start until the peer has seen the last one through
+        let can_update = self.write_phase_acknowledged
+            && self.write_key_phase == self.read_key_phase
+            && self.next_read.is_some();
+
+        if can_update {
+            debug!("starting a key update");
+
+            self.update_write_keys(keys, send_label)
+        } else if limit_reached {
+            bail!(ErrorKind::AeadConfidentialityLimitReached)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn on_packet_sealed(&mut self, packet_number: PacketNumber, size: usize) {
+        self.packets_sealed += 1;
+        self.bytes_sealed += size as u64;
+
+        if self.first_sent_in_write_phase.is_none() {
+            self.first_sent_in_write_phase = Some(packet_number);
+        }
+    }
+
+    pub fn on_largest_acknowledged(&mut self, largest_acknowledged: PacketNumber) {
+        if self.first_sent_in_write_phase
+            .map_or(false, |first_sent| largest_acknowledged >= first_sent)
+        {
+            self.write_phase_acknowledged = true;
+        }
+    }
+
+    fn update_write_keys(&mut self, keys: &mut AeadPair, send_label: &str) -> Result<()> {
+        keys.write = keys.write.with_key_update(send_label)?;
+
+        self.write_key_phase = !self.write_key_phase;
+        self.first_sent_in_write_phase = None;
+        self.write_phase_acknowledged = false;
+        self.packets_sealed = 0;
+        self.bytes_sealed = 0;
+
+        debug!("updated write keys, key phase is now {}", self.write_key_phase);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyUpdate, ReadKeyPhase};
+    use crypto::CryptoState;
+    use frames::Frame;
+    use packets::PacketNumber;
+    use protocol::ConnectionId;
+    use std::time::{Duration, Instant};
+    use AeadPair;
+
+    const CLIENT_LABEL: &str = "client 1rtt";
+    const SERVER_LABEL: &str = "server 1rtt";
+
+    /// The 1-RTT keys of a client and a server talking to each other.
+    fn key_pairs() -> (AeadPair, AeadPair) {
+        let connection_id = ConnectionId::generate().unwrap();
+        let client_write = || CryptoState::for_handshake(connection_id, "client write").unwrap();
+        let server_write = || CryptoState::for_handshake(connection_id, "server write").unwrap();
+
+        (
+            AeadPair {
+                write: client_write(),
+                read: server_write(),
+            },
+            AeadPair {
+                write: server_write(),
+                read: client_write(),
+            },
+        )
+    }
+
+    fn key_update(keys: &AeadPair, receive_label: &str) -> KeyUpdate {
+        let mut key_update = KeyUpdate::new();
+        key_update.on_keys_installed(keys, receive_label).unwrap();
+        key_update
+    }
+
+    fn seal(keys: &AeadPair, packet_number: u32) -> ::bytes::Bytes {
+        keys.write
+            .seal(PacketNumber::from(packet_number), b"header", &[Frame::Ping])
+            .unwrap()
+    }
+
+    #[test]
+    fn update_is_started_after_interval_once_acknowledged() {
+        let (mut client_keys, _) = key_pairs();
+        let mut client = key_update(&client_keys, SERVER_LABEL);
+
+        client.on_packet_sealed(PacketNumber::from(0u32), 100);
+        client.on_packet_sealed(PacketNumber::from(1u32), 100);
+
+        // nothing sealed with the current keys has been acknowledged yet
+        client
+            .prepare_to_seal(&mut client_keys, 2, u64::max_value(), CLIENT_LABEL)
+            .unwrap();
+        assert!(!client.key_phase());
+
+        client.on_largest_acknowledged(PacketNumber::from(0u32));
+        client
+            .prepare_to_seal(&mut client_keys, 2, u64::max_value(), CLIENT_LABEL)
+            .unwrap();
+        assert!(client.key_phase());
+    }
+
+    #[test]
+    fn peer_key_update_is_detected_and_answered() {
+        let (mut client_keys, mut server_keys) = key_pairs();
+        let mut client = key_update(&client_keys, SERVER_LABEL);
+        let mut server = key_update(&server_keys, CLIENT_LABEL);
+
+        client.on_packet_sealed(PacketNumber::from(0u32), 100);
+        client.on_largest_acknowledged(PacketNumber::from(0u32));
+        client
+            .prepare_to_seal(&mut client_keys, 1, u64::max_value(), CLIENT_LABEL)
+            .unwrap();
+
+        let sealed = seal(&client_keys, 1);
+        let packet_number = PacketNumber::from(1u32);
+        let read_key_phase = server.read_key_phase(client.key_phase(), packet_number);
+        assert_eq!(read_key_phase, ReadKeyPhase::Next);
+
+        server
+            .read_keys(&server_keys, read_key_phase)
+            .unwrap()
+            .open(packet_number, b"header", &sealed[..])
+            .unwrap();
+        server
+            .on_packet_opened(
+                &mut server_keys,
+                read_key_phase,
+                packet_number,
+                SERVER_LABEL,
+                CLIENT_LABEL,
+                Duration::from_secs(1),
+                Instant::now(),
+            )
+            .unwrap();
+
+        // the server answers with its own updated keys which the client can open
+        assert!(server.key_phase());
+        let sealed = seal(&server_keys, 0);
+        let read_key_phase = client.read_key_phase(server.key_phase(), PacketNumber::from(0u32));
+        assert_eq!(read_key_phase, ReadKeyPhase::Next);
+        assert!(
+            client
+                .read_keys(&client_keys, read_key_phase)
+                .unwrap()
+                .open(PacketNumber::from(0u32), b"header", &sealed[..])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn reordered_packet_uses_previous_keys_until_discarded() {
+        let (_, mut server_keys) = key_pairs();
+        let mut server = key_update(&server_keys, CLIENT_LABEL);
+        let now = Instant::now();
+
+        server
+            .on_packet_opened(
+                &mut server_keys,
+                ReadKeyPhase::Current,
+                PacketNumber::from(3u32),
+                SERVER_LABEL,
+                CLIENT_LABEL,
+                Duration::from_secs(1),
+                now,
+            )
+            .unwrap();
+        server
+            .on_packet_opened(
+                &mut server_keys,
+                ReadKeyPhase::Next,
+                PacketNumber::from(5u32),
+                SERVER_LABEL,
+                CLIENT_LABEL,
+                Duration::from_secs(1),
+                now,
+            )
+            .unwrap();
+
+        assert_eq!(
+            server.read_key_phase(false, PacketNumber::from(4u32)),
+            ReadKeyPhase::Previous
+        );
+        assert!(
+            server
+                .read_keys(&server_keys, ReadKeyPhase::Previous)
+                .is_some()
+        );
+
+        server.discard_expired_keys(now + Duration::from_secs(1));
+        assert!(
+            server
+                .read_keys(&server_keys, ReadKeyPhase::Previous)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn integrity_limit_closes_connection() {
+        let mut key_update = KeyUpdate::new();
+
+        assert!(key_update.on_open_failed(2).is_ok());
+        assert!(key_update.on_open_failed(2).is_err());
+    }
+
+    #[test]
+    fn confidentiality_limit_without_acknowledgement_is_an_error() {
+        let (mut client_keys, _) = key_pairs();
+        let mut client = key_update(&client_keys, SERVER_LABEL);
+
+        for packet_number in 0..(1u32 << 23) {
+            client.on_packet_sealed(PacketNumber::from(packet_number), 0);
+        }
+
+        assert!(
+            client
+                .prepare_to_seal(
+                    &mut client_keys,
+                    u64::max_value(),
+                    u64::max_value(),
+                    CLIENT_LABEL,
+                )
+                .is_err()
+        );
+    }
+}
@@ -2,11 +2,13 @@
 #![cfg_attr(feature = "unstable", feature(test))]
 #![allow(dead_code)]
 
+extern crate aes;
 extern crate binary_tree;
 #[macro_use]
 extern crate bitflags;
 extern crate byteorder;
 extern crate bytes;
+extern crate chacha20;
 extern crate chrono;
 extern crate conv;
 extern crate debugit;
@@ -38,7 +40,6 @@ extern crate time;
 extern crate tokio_core;
 #[macro_use]
 extern crate tokio_io;
-extern crate tokio_rustls;
 extern crate untrusted;
 extern crate webpki;
 #[cfg(test)]
@@ -64,14 +65,22 @@ mod errors;
 pub use self::errors::{Error, ErrorKind, Result};
 
 mod protocol;
-pub use self::protocol::ServerId;
+pub use self::protocol::{ErrorCode, ServerId};
 
 mod crypto;
 mod frames;
-mod packets;
 mod primitives;
 mod utils;
 
+mod packets;
+pub use self::packets::{PacketNumber, PacketNumberSpace};
+
+mod recovery;
+pub use self::recovery::{CongestionControl, CongestionController, CongestionControllerFactory,
+                         Cubic, NewReno, RttEstimator};
+#[cfg(feature = "bbr")]
+pub use self::recovery::{Bbr, BbrState};
+
 mod connection_map;
 use self::connection_map::{AddressConnectionIds, ConnectionMap};
 
@@ -81,6 +90,9 @@ pub use self::connection_termination_mode::ConnectionTerminationMode;
 mod new_data_streams;
 pub use self::new_data_streams::NewDataStreams;
 
+mod amplification_limit;
+use self::amplification_limit::AmplificationLimit;
+
 mod connection;
 use self::connection::Connection;
 
@@ -96,9 +108,39 @@ use self::stream_state::{DequeueWriteResult, StreamState};
 mod stream_map;
 use self::stream_map::{StreamMap, StreamMapEntry};
 
+mod packet_number_spaces;
+use self::packet_number_spaces::{AeadPair, PacketNumberSpaceState, PacketNumberSpaces};
+
+mod crypto_stream;
+use self::crypto_stream::CryptoStream;
+
+mod key_update;
+use self::key_update::{KeyUpdate, ReadKeyPhase};
+
+mod early_data;
+pub use self::early_data::EarlyData;
+use self::early_data::EarlyDataState;
+
+mod resumption;
+pub use self::resumption::{InMemorySessionStore, SessionStore, StoredSession};
+use self::resumption::ServerSessionStore;
+
+mod replay_protection;
+pub use self::replay_protection::{AntiReplay, ClientHello, ReplayWindow, SingleUseTickets};
+use self::replay_protection::ClientHelloBuffer;
+
+mod address_validation_token;
+use self::address_validation_token::AddressValidationToken;
+
+mod retry_policy;
+pub use self::retry_policy::RetryPolicy;
+
 mod client_configuration;
 pub use self::client_configuration::ClientConfiguration;
 
+mod alpn_selector;
+pub use self::alpn_selector::AlpnSelector;
+
 mod server_configuration;
 pub use self::server_configuration::ServerConfiguration;
 
@@ -1,6 +1,6 @@
 use errors::*;
 use futures::stream::Stream;
-use futures::Poll;
+use futures::{Async, Poll};
 use std::sync::Arc;
 use {Connection, DataStream, Perspective};
 
@@ -22,6 +22,10 @@ impl<P: Perspective> Stream for NewDataStreams<P> {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        unimplemented!()
+        let (stream_id, stream_state) = try_ready!(self.connection.poll_next_incoming_stream());
+
+        let data_stream = DataStream::new(stream_id, self.connection.clone(), stream_state);
+
+        Ok(Async::Ready(Some(data_stream)))
     }
 }
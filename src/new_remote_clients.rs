@@ -1,16 +1,386 @@
+use bytes::Bytes;
+use debugit::DebugIt;
 use errors::*;
-use futures::stream::Stream;
-use futures::Poll;
-use RemoteClient;
+use futures::stream::{FuturesUnordered, Stream};
+use futures::{Async, Future, Poll, Sink};
+use packets::{IncomingPacket, LongHeaderPacketType, OutgoingPacket, PacketDispatcher,
+              PacketHeader, RetryPacket, VersionNegotiationPacket};
+use protocol::{ConnectionId, EncryptionLevel, Version, WireFormat};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio_core::reactor::Remote;
+use {AddressValidationToken, Connection, RemoteClient, ServerConfiguration, ServerPerspective,
+     SharedConnection};
 
+type PendingHandshake = Box<Future<Item = RemoteClient, Error = Error> + Send>;
+
+/// Clients pad the datagrams carrying their Initial packets to at least this many bytes.
+const MIN_INITIAL_DATAGRAM_LEN: usize = 1200;
+
+/// The clients which have connected to a `Server` and completed their handshake.
 #[derive(Debug)]
-pub struct NewRemoteClients {}
+pub struct NewRemoteClients {
+    packet_dispatcher: Arc<PacketDispatcher>,
+    server_configuration: Arc<ServerConfiguration>,
+    pending_handshakes: DebugIt<FuturesUnordered<PendingHandshake>>,
+    remote: Remote,
+}
+
+impl NewRemoteClients {
+    pub(crate) fn new(
+        packet_dispatcher: Arc<PacketDispatcher>,
+        server_configuration: Arc<ServerConfiguration>,
+        remote: Remote,
+    ) -> Self {
+        Self {
+            packet_dispatcher,
+            server_configuration,
+            pending_handshakes: DebugIt(FuturesUnordered::new()),
+            remote,
+        }
+    }
+
+    /// Opens the address validation token the client at `client_address` sent in `token`.
+    ///
+    /// # Returns
+    /// `None` if the client sent no token or it is not valid for the client.
+    fn open_token(
+        &self,
+        token: &[u8],
+        client_address: SocketAddr,
+    ) -> Option<AddressValidationToken> {
+        if token.is_empty() {
+            return None;
+        }
+
+        match AddressValidationToken::open(
+            token,
+            &self.server_configuration.address_validation_token_key,
+            client_address,
+            SystemTime::now(),
+        ) {
+            Ok(token) => Some(token),
+            Err(error) => {
+                // the token may have come from another server or have expired, the client
+                // can still be validated with a Retry
+                debug!("ignoring token from client {:?}: {}", client_address, error);
+                None
+            }
+        }
+    }
+
+    /// Whether a client which has not validated its address is sent a Retry packet.
+    fn requires_retry(&self) -> bool {
+        // draft 08 has no Retry packets with tokens
+        self.server_configuration.version.wire_format() == Some(WireFormat::V1)
+            && self.server_configuration
+                .retry_policy
+                .requires_retry(self.pending_handshakes.0.len())
+    }
+
+    /// Asks the client at `client_address` to send its Initial packet again with a token, the
+    /// client addressed its first Initial packet in `version` to
+    /// `original_destination_connection_id`.
+    ///
+    /// Nothing is kept about the client, the token carries everything needed once it comes
+    /// back.
+    fn send_retry(
+        &self,
+        client_address: SocketAddr,
+        version: Version,
+        client_connection_id: ConnectionId,
+        original_destination_connection_id: ConnectionId,
+    ) -> Result<()> {
+        let server_configuration = &self.server_configuration;
+
+        let retry_source_connection_id =
+            ConnectionId::generate_with_len(server_configuration.connection_id_len)?;
+
+        let token = AddressValidationToken::new(
+            Some(original_destination_connection_id),
+            server_configuration.retry_token_lifetime,
+        );
+        let sealed_token =
+            token.seal(&server_configuration.address_validation_token_key, client_address)?;
+
+        let retry_packet = RetryPacket::new(
+            version,
+            Some(client_connection_id),
+            Some(retry_source_connection_id),
+            sealed_token,
+            original_destination_connection_id,
+        )?;
+
+        let outgoing_packet = OutgoingPacket {
+            destination_address: client_address,
+            packet_header: PacketHeader::Retry(retry_packet),
+            data: Bytes::new(),
+            encryption_level: EncryptionLevel::Unencrypted,
+            header_protection_key: None,
+        };
+
+        // the client sends its Initial packet again if the Retry does not reach it
+        let mut outgoing_packets = PacketDispatcher::outgoing_sink(&self.packet_dispatcher);
+        if outgoing_packets.start_send(outgoing_packet)?.is_not_ready() {
+            debug!("dropped retry to client {:?} as the socket is busy", client_address);
+            return Ok(());
+        }
+        outgoing_packets.poll_complete()?;
+
+        debug!("sent retry to client {:?}", client_address);
+
+        Ok(())
+    }
+
+    /// Tells the client which sent `incoming_packet` in `version` which versions we support, so
+    /// it can connect again in one of them.
+    fn send_version_negotiation(
+        &self,
+        incoming_packet: &IncomingPacket,
+        version: Version,
+    ) -> Result<()> {
+        let client_address = incoming_packet.source_address;
+        let packet_header = &incoming_packet.packet_header;
+
+        // a spoofed datagram must not be answered with more bytes than it had. Packets in versions
+        // we can not read run to the end of their datagram and clients pad Initial packets
+        // themselves, so the packet's length stands in for the datagram's
+        let datagram_len = incoming_packet.packet_header_bytes.len() + incoming_packet.data.len();
+        if datagram_len < MIN_INITIAL_DATAGRAM_LEN {
+            debug!(
+                "not sending version negotiation to client {:?} for a {} byte datagram",
+                client_address, datagram_len
+            );
+            return Ok(());
+        }
+
+        let server_configuration = &self.server_configuration;
+
+        // short headers do not say which version they are in, so a server only speaks the one
+        // version it is configured with, and the compatible versions it moves clients from
+        let mut supported_versions = server_configuration.supported_versions();
+        if server_configuration.grease_version_negotiation {
+            supported_versions.push(Version::generate_force_negotiation()?);
+        }
+
+        // the client reads the packet in the format of the version it tried, as far as we know it
+        let version_negotiation_packet = VersionNegotiationPacket {
+            destination_connection_id: packet_header.source_connection_id(),
+            source_connection_id: packet_header.destination_connection_id(),
+            supported_versions,
+            wire_format: version.wire_format().unwrap_or(WireFormat::V1),
+        };
+
+        let outgoing_packet = OutgoingPacket {
+            destination_address: client_address,
+            packet_header: PacketHeader::VersionNegotiation(version_negotiation_packet),
+            data: Bytes::new(),
+            encryption_level: EncryptionLevel::Unencrypted,
+            header_protection_key: None,
+        };
+
+        // the client sends its Initial packet again if the Version Negotiation does not reach it
+        let mut outgoing_packets = PacketDispatcher::outgoing_sink(&self.packet_dispatcher);
+        if outgoing_packets.start_send(outgoing_packet)?.is_not_ready() {
+            debug!(
+                "dropped version negotiation to client {:?} as the socket is busy",
+                client_address
+            );
+            return Ok(());
+        }
+        outgoing_packets.poll_complete()?;
+
+        debug!(
+            "sent version negotiation to client {:?} which tried version {}",
+            client_address, version
+        );
+
+        Ok(())
+    }
+
+    /// Creates a new connection for the client which sent `incoming_packet`, returning the
+    /// handshake with that client.
+    ///
+    /// `None` is returned when an earlier packet already created the connection.
+    fn accept(&self, incoming_packet: IncomingPacket) -> Result<Option<PendingHandshake>> {
+        let client_address = incoming_packet.source_address;
+        trace!("accepting new connection from client {:?}", client_address);
+
+        let version = match &incoming_packet.packet_header {
+            PacketHeader::Long(long_header) => Some(long_header.version),
+            PacketHeader::UnsupportedVersion(unsupported_version) => {
+                Some(unsupported_version.version)
+            }
+            _ => None,
+        };
+
+        if let Some(version) = version {
+            if !self.server_configuration
+                .supported_versions()
+                .contains(&version)
+            {
+                self.send_version_negotiation(&incoming_packet, version)?;
+                return Ok(None);
+            }
+        }
+
+        // the client chooses both connection ids, this allows it to keep addressing us with the
+        // destination connection id of its first packet
+        let local_connection_id = incoming_packet
+            .packet_header
+            .destination_connection_id()
+            .ok_or_else(|| ErrorKind::PacketHeaderHasNoConnectionId)?;
+
+        // short headers do not carry the connection id length so we can only read those with the
+        // length we expect
+        let connection_id_len = self.server_configuration.connection_id_len;
+        if local_connection_id.len() != connection_id_len {
+            bail!(ErrorKind::UnexpectedConnectionIdLength(
+                connection_id_len,
+                local_connection_id.len()
+            ));
+        }
+
+        if self.packet_dispatcher
+            .contains_connection(local_connection_id)
+        {
+            self.packet_dispatcher
+                .dispatch_incoming_packet(incoming_packet);
+            return Ok(None);
+        }
+
+        // 0-RTT packets wait here for the connection their Initial packet creates, but never
+        // create one themselves
+        if let PacketHeader::Long(long_header) = &incoming_packet.packet_header {
+            if long_header.packet_type == LongHeaderPacketType::ZeroRttProtected {
+                debug!(
+                    "discarding 0-RTT packet from client {:?} for an unknown connection",
+                    client_address
+                );
+                return Ok(None);
+            }
+        }
+
+        let remote_connection_id = incoming_packet
+            .packet_header
+            .source_connection_id()
+            .ok_or_else(|| ErrorKind::PacketHeaderHasNoConnectionId)?;
+
+        // a client which started in a compatible version is moved to the configured version
+        let initial_version = version.unwrap_or(self.server_configuration.version);
+
+        let token = match &incoming_packet.packet_header {
+            PacketHeader::Long(long_header) => self.open_token(&long_header.token, client_address),
+            _ => None,
+        };
+
+        let address_validated = token.is_some();
+        let (original_destination_connection_id, retry_source_connection_id) =
+            match token.and_then(|token| token.original_destination_connection_id) {
+                // the client is answering our Retry, which picked the id it now addresses us with
+                Some(original_destination_connection_id) => {
+                    (original_destination_connection_id, Some(local_connection_id))
+                }
+                None if token.is_none() && self.requires_retry() => {
+                    self.send_retry(
+                        client_address,
+                        initial_version,
+                        remote_connection_id,
+                        local_connection_id,
+                    )?;
+                    return Ok(None);
+                }
+                None => (local_connection_id, None),
+            };
+
+        let incoming_packets = PacketDispatcher::incoming_stream(
+            &self.packet_dispatcher,
+            local_connection_id,
+            client_address,
+        )?;
+        let outgoing_packets = PacketDispatcher::outgoing_sink(&self.packet_dispatcher);
+
+        let server_perspective = ServerPerspective::new(
+            client_address,
+            self.server_configuration.clone(),
+            incoming_packets,
+            outgoing_packets,
+            self.packet_dispatcher.header_protection_keys(),
+            self.packet_dispatcher.stateless_reset_tokens(),
+            self.packet_dispatcher.stateless_reset_token(local_connection_id),
+            initial_version,
+            original_destination_connection_id,
+            retry_source_connection_id,
+            address_validated,
+            self.remote.clone(),
+        );
+
+        // the connection is deregistered from the dispatcher once it is dropped
+        let connection = Arc::new(Connection::new(
+            local_connection_id,
+            remote_connection_id,
+            server_perspective,
+            client_address,
+        )?);
+
+        self.packet_dispatcher
+            .dispatch_incoming_packet(incoming_packet);
+
+        debug!(
+            "accepted new connection {} from client {:?}",
+            connection.description(),
+            client_address
+        );
+
+        let connection_for_client = connection.clone();
+
+        let when_handshaked = connection
+            .handshake()
+            .map(move |_| RemoteClient::new(connection_for_client));
+
+        Ok(Some(Box::new(when_handshaked)))
+    }
+
+    fn accept_new_connections(&mut self) -> Result<()> {
+        while let Async::Ready(incoming_packet) =
+            self.packet_dispatcher.poll_new_connection_packet()?
+        {
+            match self.accept(incoming_packet) {
+                Ok(Some(pending_handshake)) => self.pending_handshakes.0.push(pending_handshake),
+                Ok(None) => {}
+                Err(error) => warn!("failed to accept new connection: {}", error),
+            }
+        }
+
+        Ok(())
+    }
+}
 
 impl Stream for NewRemoteClients {
     type Item = RemoteClient;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        unimplemented!()
+        loop {
+            self.accept_new_connections()?;
+
+            match self.pending_handshakes.0.poll() {
+                Ok(Async::Ready(Some(remote_client))) => {
+                    return Ok(Async::Ready(Some(remote_client)))
+                }
+                Ok(Async::Ready(None)) | Ok(Async::NotReady) => {}
+                Err(error) => {
+                    // a failed handshake only affects the one client
+                    warn!("failed to perform handshake with client: {}", error);
+                    continue;
+                }
+            }
+
+            // driving the pending handshakes may have read packets for new connections
+            if !self.packet_dispatcher.has_new_connection_packets() {
+                return Ok(Async::NotReady);
+            }
+        }
     }
 }
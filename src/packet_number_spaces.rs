@@ -0,0 +1,513 @@
+use conv::TryFrom;
+use crypto::CryptoState;
+use errors::*;
+use frames::{AckFrame, StreamFrame};
+use packets::{AckManager, IncomingPacket, PacketNumber, PacketNumberSpace, PacketUnpacker};
+use protocol::WireFormat;
+use recovery::{LossDetector, SentPacket};
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use {CryptoStream, EarlyDataState, KeyUpdate};
+
+/// The most packets kept for a space whose keys are not yet available, any more are dropped.
+const MAX_PENDING_PACKETS: usize = 16;
+
+#[derive(Debug)]
+pub struct AeadPair {
+    pub write: CryptoState,
+    pub read: CryptoState,
+}
+
+/// The state kept independently for each `PacketNumberSpace`.
+#[derive(Debug)]
+pub struct PacketNumberSpaceState {
+    /// The packet number the next outgoing packet in this space will be sent with.
+    pub next_packet_number: PacketNumber,
+    /// Outgoing packet numbers are encoded relative to this.
+    pub lowest_unacknowledged: PacketNumber,
+    pub packet_unpacker: PacketUnpacker,
+    /// Acknowledges the packets received in this space.
+    pub ack_manager: AckManager,
+    /// Tracks the packets sent in this space until they are acknowledged or lost.
+    pub loss_detector: LossDetector,
+    /// The keys for the packets in this space, `None` until they are available.
+    pub keys: Option<AeadPair>,
+    /// Rotates `keys` in the application data space.
+    pub key_update: KeyUpdate,
+    /// The client's 0-RTT keys, which are only used in the application data space.
+    pub early_data: EarlyDataState,
+    /// The crypto data of the encryption level this space's packets are protected with.
+    pub crypto_stream: CryptoStream,
+    /// The crypto data waiting to be sent in this space.
+    pub pending_crypto_frames: VecDeque<StreamFrame>,
+    /// The packets received before `keys` were available, processed once they are.
+    pub pending_packets: Vec<IncomingPacket>,
+    /// Set once `keys` are discarded, no packets are sent or received in this space after that.
+    pub keys_discarded: bool,
+}
+
+impl PacketNumberSpaceState {
+    pub fn new(keys: Option<AeadPair>, ack_manager: AckManager) -> Self {
+        // the first packet is sent without any acknowledged packet to be relative to, starting at 0
+        // allows it to be inferred from the shortest partial packet number
+        Self {
+            next_packet_number: PacketNumber::from(0u32),
+            lowest_unacknowledged: PacketNumber::from(0u32),
+            packet_unpacker: PacketUnpacker::new(),
+            ack_manager,
+            loss_detector: LossDetector::new(),
+            keys,
+            key_update: KeyUpdate::new(),
+            early_data: EarlyDataState::new(),
+            crypto_stream: CryptoStream::new(),
+            pending_crypto_frames: VecDeque::new(),
+            pending_packets: Vec::new(),
+            keys_discarded: false,
+        }
+    }
+
+    /// Keeps `incoming_packet` until the keys to read it are available.
+    pub fn buffer_packet(&mut self, incoming_packet: IncomingPacket) {
+        if self.pending_packets.len() < MAX_PENDING_PACKETS {
+            self.pending_packets.push(incoming_packet);
+        } else {
+            debug!("dropping packet received before its keys were available");
+        }
+    }
+
+    /// Moves on to the next outgoing packet number once `next_packet_number` has been used.
+    pub fn advance_next_packet_number(&mut self) -> Result<()> {
+        self.next_packet_number = self.next_packet_number
+            .next()
+            .ok_or_else(|| Error::from_kind(ErrorKind::ReachedMaximumPacketNumber))?;
+
+        Ok(())
+    }
+
+    pub fn on_largest_acknowledged(&mut self, largest_acknowledged: PacketNumber) {
+        self.key_update
+            .on_largest_acknowledged(largest_acknowledged);
+
+        // packet numbers are encoded relative to the largest acknowledged
+        if let Some(lowest_unacknowledged) = largest_acknowledged.next() {
+            self.lowest_unacknowledged = cmp::min(
+                self.next_packet_number,
+                cmp::max(self.lowest_unacknowledged, lowest_unacknowledged),
+            );
+        }
+    }
+
+    /// Processes an ACK frame received in this space, which only acknowledges packets sent in
+    /// this space.
+    ///
+    /// # Returns
+    /// The packets which were newly acknowledged.
+    pub fn on_ack_frame_received(&mut self, ack_frame: &AckFrame) -> Result<Vec<SentPacket>> {
+        if let Some(largest_acknowledged) = ack_frame.largest_acknowledged() {
+            self.on_largest_acknowledged(PacketNumber::try_from(largest_acknowledged)?);
+        }
+
+        self.ack_manager.on_ack_frame_received(ack_frame);
+        self.loss_detector.on_ack_frame_received(ack_frame)
+    }
+
+    /// Records the receipt of `packet_number` at `now`.
+    ///
+    /// # Returns
+    /// `false` if `packet_number` had already been received.
+    pub fn on_packet_received(
+        &mut self,
+        packet_number: PacketNumber,
+        ack_eliciting: bool,
+        now: Instant,
+    ) -> bool {
+        self.ack_manager
+            .on_packet_received(packet_number, ack_eliciting, now)
+    }
+}
+
+/// The state of each of the `PacketNumberSpace`s of a connection.
+#[derive(Debug)]
+pub struct PacketNumberSpaces {
+    initial: PacketNumberSpaceState,
+    handshake: PacketNumberSpaceState,
+    application_data: PacketNumberSpaceState,
+    wire_format: WireFormat,
+    ack_delay_exponent: u8,
+}
+
+impl PacketNumberSpaces {
+    /// Creates the `PacketNumberSpaces` of a new connection, only the Initial keys are known.
+    ///
+    /// Only the acknowledgement of application data is delayed by up to `max_ack_delay`, the
+    /// handshake is acknowledged as quickly as possible.
+    pub fn new(initial_keys: AeadPair, ack_delay_exponent: u8, max_ack_delay: Duration) -> Self {
+        let handshake_ack_manager = || AckManager::new(ack_delay_exponent, Duration::from_secs(0));
+        let wire_format = initial_keys.write.wire_format();
+
+        Self {
+            initial: PacketNumberSpaceState::new(Some(initial_keys), handshake_ack_manager()),
+            handshake: PacketNumberSpaceState::new(None, handshake_ack_manager()),
+            application_data: PacketNumberSpaceState::new(
+                None,
+                AckManager::new(ack_delay_exponent, max_ack_delay),
+            ),
+            wire_format,
+            ack_delay_exponent,
+        }
+    }
+
+    pub fn get(&self, space: PacketNumberSpace) -> &PacketNumberSpaceState {
+        match space {
+            PacketNumberSpace::Initial => &self.initial,
+            PacketNumberSpace::Handshake => &self.handshake,
+            PacketNumberSpace::ApplicationData => &self.application_data,
+        }
+    }
+
+    pub fn get_mut(&mut self, space: PacketNumberSpace) -> &mut PacketNumberSpaceState {
+        match space {
+            PacketNumberSpace::Initial => &mut self.initial,
+            PacketNumberSpace::Handshake => &mut self.handshake,
+            PacketNumberSpace::ApplicationData => &mut self.application_data,
+        }
+    }
+
+    /// The space whose crypto stream holds the crypto data received in `packet_number_space`.
+    ///
+    /// Draft-08 has a single crypto stream, which all of the handshake is sent on.
+    pub fn crypto_stream_space(&self, packet_number_space: PacketNumberSpace) -> PacketNumberSpace {
+        match self.wire_format {
+            WireFormat::Draft08 => PacketNumberSpace::Initial,
+            WireFormat::V1 => packet_number_space,
+        }
+    }
+
+    /// Whether crypto data is waiting to be sent in a space which has its keys.
+    pub fn has_pending_crypto_frames(&self) -> bool {
+        PacketNumberSpace::all().iter().any(|&packet_number_space| {
+            let space_state = self.get(packet_number_space);
+
+            space_state.keys.is_some() && !space_state.pending_crypto_frames.is_empty()
+        })
+    }
+
+    /// The lowest handshake space with an ACK frame due or crypto data to send, these go out
+    /// ahead of anything else.
+    pub fn pending_handshake_space(&self, now: Instant) -> Option<PacketNumberSpace> {
+        [PacketNumberSpace::Initial, PacketNumberSpace::Handshake]
+            .iter()
+            .cloned()
+            .find(|&packet_number_space| {
+                let space_state = self.get(packet_number_space);
+
+                space_state.keys.is_some()
+                    && (space_state.ack_manager.should_send_ack(now)
+                        || !space_state.pending_crypto_frames.is_empty())
+            })
+    }
+
+    /// The space the next packet is sent in.
+    ///
+    /// Acknowledgements and crypto data of the handshake are sent in their own space, anything
+    /// else goes in the most secure space the peer can read.
+    pub fn sending_space(&self, now: Instant) -> PacketNumberSpace {
+        if let Some(packet_number_space) = self.pending_handshake_space(now) {
+            return packet_number_space;
+        }
+
+        // nothing is sent in a space once its keys are discarded
+        if self.application_data.keys.is_some() {
+            PacketNumberSpace::ApplicationData
+        } else if self.handshake.keys.is_some()
+            && (self.initial.keys.is_none()
+                || self.handshake.packet_unpacker.largest_received().is_some())
+        {
+            PacketNumberSpace::Handshake
+        } else {
+            PacketNumberSpace::Initial
+        }
+    }
+
+    /// The space a probe is sent in when the probe timeout fires, the lowest with ack-eliciting
+    /// packets in flight.
+    pub fn probe_space(&self) -> Option<PacketNumberSpace> {
+        PacketNumberSpace::all()
+            .iter()
+            .cloned()
+            .find(|&packet_number_space| {
+                self.get(packet_number_space)
+                    .loss_detector
+                    .has_ack_eliciting_in_flight()
+            })
+    }
+
+    /// Discards the keys of `packet_number_space` once the peer has no more use for them, along
+    /// with everything still waiting to be sent or read in that space (RFC 9001 section 4.9).
+    ///
+    /// # Returns
+    /// The packets which were in flight in the space, they are no longer tracked.
+    pub fn discard_keys(&mut self, packet_number_space: PacketNumberSpace) -> Vec<SentPacket> {
+        let ack_delay_exponent = self.ack_delay_exponent;
+        let space_state = self.get_mut(packet_number_space);
+
+        if space_state.keys_discarded {
+            return Vec::new();
+        }
+
+        space_state.keys = None;
+        space_state.keys_discarded = true;
+        space_state.pending_crypto_frames.clear();
+        space_state.pending_packets.clear();
+        space_state.ack_manager = AckManager::new(ack_delay_exponent, Duration::from_secs(0));
+
+        let next_packet_number = space_state.next_packet_number;
+        space_state
+            .loss_detector
+            .discard_packets_up_to(next_packet_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AeadPair, PacketNumberSpaceState, PacketNumberSpaces};
+    use bytes::Bytes;
+    use crypto::CryptoState;
+    use frames::{AckFrame, Frame, StreamFrame};
+    use packets::{AckManager, PacketNumber, PacketNumberSpace};
+    use protocol::{ConnectionId, StreamId, Version};
+    use recovery::{RttEstimator, SentPacket};
+    use std::time::{Duration, Instant};
+
+    fn space_state() -> PacketNumberSpaceState {
+        PacketNumberSpaceState::new(None, AckManager::new(3, Duration::from_millis(25)))
+    }
+
+    fn packet_number_spaces() -> PacketNumberSpaces {
+        let connection_id = ConnectionId::generate().unwrap();
+        let keys = |send_label, receive_label| AeadPair {
+            write: CryptoState::for_initial(connection_id, send_label, Version::V1).unwrap(),
+            read: CryptoState::for_initial(connection_id, receive_label, Version::V1).unwrap(),
+        };
+
+        let mut packet_number_spaces =
+            PacketNumberSpaces::new(keys("client in", "server in"), 3, Duration::from_millis(25));
+        packet_number_spaces.get_mut(PacketNumberSpace::Handshake).keys =
+            Some(keys("client hs", "server hs"));
+
+        packet_number_spaces
+    }
+
+    fn crypto_frame(offset: u32, data: &'static [u8]) -> StreamFrame {
+        StreamFrame {
+            finished: false,
+            offset: offset.into(),
+            stream_id: StreamId::crypto_stream_id(),
+            data: Bytes::from_static(data),
+        }
+    }
+
+    fn send_packets(space_state: &mut PacketNumberSpaceState, count: u32, now: Instant) {
+        for packet_number in 0..count {
+            space_state.loss_detector.on_packet_sent(SentPacket {
+                packet_number: PacketNumber::from(packet_number),
+                time_sent: now,
+                ack_eliciting: true,
+                size: 1200,
+                retransmittable_frames: vec![Frame::Ping],
+            });
+            space_state.advance_next_packet_number().unwrap();
+        }
+    }
+
+    #[test]
+    fn advance_next_packet_number_increments() {
+        let mut space_state = space_state();
+
+        space_state.advance_next_packet_number().unwrap();
+        space_state.advance_next_packet_number().unwrap();
+
+        assert_eq!(space_state.next_packet_number, PacketNumber::from(2u32));
+    }
+
+    #[test]
+    fn on_largest_acknowledged_does_not_exceed_next_packet_number() {
+        let mut space_state = space_state();
+        space_state.advance_next_packet_number().unwrap();
+
+        space_state.on_largest_acknowledged(PacketNumber::from(10u32));
+
+        assert_eq!(space_state.lowest_unacknowledged, PacketNumber::from(1u32));
+    }
+
+    #[test]
+    fn on_packet_received_detects_duplicates() {
+        let mut space_state = space_state();
+        let now = Instant::now();
+
+        assert!(space_state.on_packet_received(PacketNumber::from(3u32), true, now));
+        assert!(!space_state.on_packet_received(PacketNumber::from(3u32), true, now));
+    }
+
+    #[test]
+    fn crypto_data_is_sent_in_its_own_space() {
+        let mut packet_number_spaces = packet_number_spaces();
+        let now = Instant::now();
+
+        packet_number_spaces
+            .get_mut(PacketNumberSpace::Handshake)
+            .pending_crypto_frames
+            .push_back(crypto_frame(0, b"finished"));
+        assert_eq!(
+            packet_number_spaces.sending_space(now),
+            PacketNumberSpace::Handshake
+        );
+
+        packet_number_spaces
+            .get_mut(PacketNumberSpace::Initial)
+            .pending_crypto_frames
+            .push_back(crypto_frame(0, b"client hello"));
+        assert_eq!(
+            packet_number_spaces.sending_space(now),
+            PacketNumberSpace::Initial
+        );
+    }
+
+    #[test]
+    fn nothing_is_sent_in_a_space_once_its_keys_are_discarded() {
+        let mut packet_number_spaces = packet_number_spaces();
+        let now = Instant::now();
+
+        let initial = packet_number_spaces.get_mut(PacketNumberSpace::Initial);
+        send_packets(initial, 2, now);
+        initial.on_packet_received(PacketNumber::from(0u32), true, now);
+        initial
+            .pending_crypto_frames
+            .push_back(crypto_frame(0, b"client hello"));
+
+        let discarded_packets = packet_number_spaces.discard_keys(PacketNumberSpace::Initial);
+
+        assert_eq!(discarded_packets.len(), 2);
+        assert!(
+            packet_number_spaces
+                .get(PacketNumberSpace::Initial)
+                .keys
+                .is_none()
+        );
+        assert_eq!(
+            packet_number_spaces.sending_space(now),
+            PacketNumberSpace::Handshake
+        );
+        assert_eq!(packet_number_spaces.probe_space(), None);
+        assert!(
+            packet_number_spaces
+                .discard_keys(PacketNumberSpace::Initial)
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn acknowledgements_are_sent_in_the_space_of_the_packets_they_acknowledge() {
+        let mut packet_number_spaces = packet_number_spaces();
+        let now = Instant::now();
+
+        assert_eq!(
+            packet_number_spaces.sending_space(now),
+            PacketNumberSpace::Initial
+        );
+
+        packet_number_spaces
+            .get_mut(PacketNumberSpace::Handshake)
+            .on_packet_received(PacketNumber::from(0u32), true, now);
+        assert_eq!(
+            packet_number_spaces.sending_space(now),
+            PacketNumberSpace::Handshake
+        );
+
+        packet_number_spaces
+            .get_mut(PacketNumberSpace::Initial)
+            .on_packet_received(PacketNumber::from(0u32), true, now);
+        assert_eq!(
+            packet_number_spaces.sending_space(now),
+            PacketNumberSpace::Initial
+        );
+    }
+
+    #[test]
+    fn ack_frame_only_acknowledges_and_detects_loss_in_its_own_space() {
+        let mut packet_number_spaces = packet_number_spaces();
+        let now = Instant::now();
+
+        send_packets(
+            packet_number_spaces.get_mut(PacketNumberSpace::Initial),
+            5,
+            now,
+        );
+        send_packets(
+            packet_number_spaces.get_mut(PacketNumberSpace::Handshake),
+            5,
+            now,
+        );
+
+        // packet 4 is acknowledged, which leaves packets 0 and 1 past the packet threshold
+        let ack_frame = AckFrame {
+            ack_delay: 0,
+            ack_ranges_descending: vec![4..5],
+        };
+
+        let handshake = packet_number_spaces.get_mut(PacketNumberSpace::Handshake);
+        let acknowledged_packets = handshake.on_ack_frame_received(&ack_frame).unwrap();
+        let lost_packets = handshake
+            .loss_detector
+            .detect_lost_packets(now, &RttEstimator::default());
+
+        assert_eq!(acknowledged_packets.len(), 1);
+        assert_eq!(lost_packets.len(), 2);
+        assert_eq!(handshake.lowest_unacknowledged, PacketNumber::from(5u32));
+
+        let initial = packet_number_spaces.get_mut(PacketNumberSpace::Initial);
+
+        assert!(
+            initial
+                .loss_detector
+                .detect_lost_packets(now, &RttEstimator::default())
+                .is_empty()
+        );
+        assert_eq!(initial.lowest_unacknowledged, PacketNumber::from(0u32));
+        assert!(
+            initial
+                .loss_detector
+                .oldest_retransmittable_frames()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn probe_is_sent_in_the_lowest_space_with_packets_in_flight() {
+        let mut packet_number_spaces = packet_number_spaces();
+        let now = Instant::now();
+
+        assert_eq!(packet_number_spaces.probe_space(), None);
+
+        send_packets(
+            packet_number_spaces.get_mut(PacketNumberSpace::Handshake),
+            1,
+            now,
+        );
+        assert_eq!(
+            packet_number_spaces.probe_space(),
+            Some(PacketNumberSpace::Handshake)
+        );
+
+        send_packets(
+            packet_number_spaces.get_mut(PacketNumberSpace::Initial),
+            1,
+            now,
+        );
+        assert_eq!(
+            packet_number_spaces.probe_space(),
+            Some(PacketNumberSpace::Initial)
+        );
+    }
+}
@@ -0,0 +1,366 @@
+use conv::TryFrom;
+use frames::AckFrame;
+use packets::{PacketHistory, PacketNumber};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+/// An ACK frame never acknowledges more than this many ranges, older ranges are left out.
+const MAX_ACK_RANGES: usize = 32;
+
+/// Decides when the packets received in a packet number space are acknowledged and builds the
+/// `AckFrame`s acknowledging them.
+#[derive(Debug)]
+pub struct AckManager {
+    received_packets: PacketHistory,
+    ack_delay_exponent: u8,
+    max_ack_delay: Duration,
+    /// The largest packet number received and when it was received, the ACK delay is measured
+    /// from then.
+    largest_received: Option<(PacketNumber, Instant)>,
+    /// Whether packets have been received since an ACK frame was last sent.
+    ack_pending: bool,
+    /// The number of ack-eliciting packets received since an ACK frame was last sent.
+    unacknowledged_ack_eliciting: usize,
+    /// When an ACK frame must be sent by, `None` if nothing needs to be acknowledged.
+    ack_deadline: Option<Instant>,
+    /// The largest packet number acknowledged by each sent ACK frame by the packet carrying it.
+    sent_acks: BTreeMap<PacketNumber, PacketNumber>,
+}
+
+impl AckManager {
+    /// The ACK delay exponent used when the transport parameters do not specify one.
+    pub const DEFAULT_ACK_DELAY_EXPONENT: u8 = 3;
+
+    /// The maximum time an ack-eliciting packet is left unacknowledged when the configuration
+    /// does not specify one.
+    pub const DEFAULT_MAX_ACK_DELAY_MILLIS: u64 = 25;
+
+    pub fn new(ack_delay_exponent: u8, max_ack_delay: Duration) -> Self {
+        Self {
+            received_packets: PacketHistory::new(),
+            ack_delay_exponent,
+            max_ack_delay,
+            largest_received: None,
+            ack_pending: false,
+            unacknowledged_ack_eliciting: 0,
+            ack_deadline: None,
+            sent_acks: BTreeMap::new(),
+        }
+    }
+
+    /// Records the receipt of `packet_number` at `now`.
+    ///
+    /// # Returns
+    /// `false` if `packet_number` had already been received.
+    pub fn on_packet_received(
+        &mut self,
+        packet_number: PacketNumber,
+        ack_eliciting: bool,
+        now: Instant,
+    ) -> bool {
+        if self.received_packets.is_duplicate(packet_number) {
+            return false;
+        }
+
+        // a packet is out of order unless it directly follows the largest received
+        let out_of_order = self.largest_received
+            .map_or(false, |(largest_received, _)| {
+                largest_received.next() != Some(packet_number)
+            });
+
+        self.received_packets.push_packet_number(packet_number);
+        self.ack_pending = true;
+
+        if self.largest_received
+            .map_or(true, |(largest_received, _)| packet_number > largest_received)
+        {
+            self.largest_received = Some((packet_number, now));
+        }
+
+        if !ack_eliciting {
+            return true;
+        }
+
+        self.unacknowledged_ack_eliciting += 1;
+
+        // gaps are reported straight away so the peer can detect loss, otherwise every second
+        // ack-eliciting packet is acknowledged without waiting
+        let ack_deadline = if out_of_order || self.unacknowledged_ack_eliciting >= 2 {
+            now
+        } else {
+            now + self.max_ack_delay
+        };
+
+        self.ack_deadline = Some(
+            self.ack_deadline
+                .map_or(ack_deadline, |deadline| deadline.min(ack_deadline)),
+        );
+
+        true
+    }
+
+    /// When an ACK frame must be sent by, `None` if no ack-eliciting packets are waiting to be
+    /// acknowledged.
+    pub fn ack_deadline(&self) -> Option<Instant> {
+        self.ack_deadline
+    }
+
+    /// Whether an ACK frame must be sent at `now` rather than waiting for another packet to be
+    /// sent with.
+    pub fn should_send_ack(&self, now: Instant) -> bool {
+        self.ack_deadline.map_or(false, |deadline| deadline <= now)
+    }
+
+    /// Builds an `AckFrame` for the packets received so far if any have been received since an
+    /// ACK frame was last sent.
+    pub fn build_ack_frame(&self, now: Instant) -> Option<AckFrame> {
+        if !self.ack_pending {
+            return None;
+        }
+
+        let ack_delay = self.largest_received.map_or(0, |(_, received_at)| {
+            encode_ack_delay(
+                now.duration_since(received_at),
+                self.ack_delay_exponent,
+            )
+        });
+
+        let ack_ranges: Vec<Range<PacketNumber>> =
+            self.received_packets.received_ranges().collect();
+
+        let ack_ranges_descending: Vec<_> = ack_ranges
+            .into_iter()
+            .rev()
+            .take(MAX_ACK_RANGES)
+            .map(|range| u64::from(range.start)..u64::from(range.end))
+            .collect();
+
+        if ack_ranges_descending.is_empty() {
+            return None;
+        }
+
+        Some(AckFrame {
+            ack_delay,
+            ack_ranges_descending,
+        })
+    }
+
+    /// Records that `ack_frame` was sent in the packet `packet_number`.
+    pub fn on_ack_sent(&mut self, packet_number: PacketNumber, ack_frame: &AckFrame) {
+        self.ack_pending = false;
+        self.unacknowledged_ack_eliciting = 0;
+        self.ack_deadline = None;
+
+        if let Some(largest_acknowledged) = ack_frame
+            .largest_acknowledged()
+            .and_then(|largest_acknowledged| PacketNumber::try_from(largest_acknowledged).ok())
+        {
+            self.sent_acks.insert(packet_number, largest_acknowledged);
+        }
+    }
+
+    /// Stops acknowledging the packets the peer has seen acknowledged by an ACK frame sent in one
+    /// of the packets `ack_frame` acknowledges.
+    pub fn on_ack_frame_received(&mut self, ack_frame: &AckFrame) {
+        let acknowledged: Vec<PacketNumber> = self.sent_acks
+            .keys()
+            .cloned()
+            .filter(|&packet_number| {
+                let packet_number = u64::from(packet_number);
+                ack_frame
+                    .ack_ranges_descending
+                    .iter()
+                    .any(|range| range.start <= packet_number && packet_number < range.end)
+            })
+            .collect();
+
+        let mut ignore_up_to = None;
+
+        for packet_number in acknowledged {
+            if let Some(largest_acknowledged) = self.sent_acks.remove(&packet_number) {
+                ignore_up_to = cmp::max(ignore_up_to, Some(largest_acknowledged));
+            }
+        }
+
+        // the packets below the largest acknowledged which are still tracked were not
+        // acknowledged with it, they are either lost or will never be reported again
+        if let Some(largest_acknowledged) = ack_frame
+            .largest_acknowledged()
+            .and_then(|largest_acknowledged| PacketNumber::try_from(largest_acknowledged).ok())
+        {
+            self.sent_acks = match largest_acknowledged.next() {
+                Some(next) => self.sent_acks.split_off(&next),
+                None => BTreeMap::new(),
+            };
+        }
+
+        if let Some(ignore_up_to) = ignore_up_to {
+            trace!("no longer acknowledging packets up to {:?}", ignore_up_to);
+
+            self.received_packets
+                .ignore_packets_up_to_including(ignore_up_to);
+        }
+    }
+}
+
+fn encode_ack_delay(ack_delay: Duration, ack_delay_exponent: u8) -> u64 {
+    let micros = ack_delay.as_secs() * 1_000_000 + u64::from(ack_delay.subsec_nanos() / 1_000);
+
+    micros
+        .checked_shr(u32::from(ack_delay_exponent))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AckManager;
+    use frames::AckFrame;
+    use packets::PacketNumber;
+    use std::time::{Duration, Instant};
+
+    fn ack_manager() -> AckManager {
+        AckManager::new(3, Duration::from_millis(25))
+    }
+
+    #[test]
+    fn ack_eliciting_packet_in_order_is_delayed_by_max_ack_delay() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        ack_manager.on_packet_received(PacketNumber::from(0u32), true, now);
+
+        assert!(!ack_manager.should_send_ack(now));
+        assert_eq!(
+            ack_manager.ack_deadline(),
+            Some(now + Duration::from_millis(25))
+        );
+        assert!(ack_manager.should_send_ack(now + Duration::from_millis(25)));
+    }
+
+    #[test]
+    fn non_ack_eliciting_packet_does_not_need_acknowledging() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        ack_manager.on_packet_received(PacketNumber::from(0u32), false, now);
+
+        assert_eq!(ack_manager.ack_deadline(), None);
+        assert!(ack_manager.build_ack_frame(now).is_some());
+    }
+
+    #[test]
+    fn out_of_order_packet_is_acknowledged_immediately() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        ack_manager.on_packet_received(PacketNumber::from(0u32), false, now);
+        ack_manager.on_packet_received(PacketNumber::from(2u32), true, now);
+
+        assert!(ack_manager.should_send_ack(now));
+    }
+
+    #[test]
+    fn second_ack_eliciting_packet_is_acknowledged_immediately() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        ack_manager.on_packet_received(PacketNumber::from(0u32), true, now);
+        ack_manager.on_packet_received(PacketNumber::from(1u32), true, now);
+
+        assert!(ack_manager.should_send_ack(now));
+    }
+
+    #[test]
+    fn duplicate_packet_is_rejected() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        assert!(ack_manager.on_packet_received(PacketNumber::from(0u32), true, now));
+        assert!(!ack_manager.on_packet_received(PacketNumber::from(0u32), true, now));
+    }
+
+    #[test]
+    fn build_ack_frame_lists_ranges_descending_with_encoded_delay() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        for &packet_number in &[0u32, 1, 2, 5, 6] {
+            ack_manager.on_packet_received(PacketNumber::from(packet_number), true, now);
+        }
+
+        let ack_frame = ack_manager
+            .build_ack_frame(now + Duration::from_millis(1))
+            .unwrap();
+
+        assert_eq!(
+            ack_frame,
+            AckFrame {
+                ack_delay: 1000 >> 3,
+                ack_ranges_descending: vec![5..7, 0..3],
+            }
+        );
+    }
+
+    #[test]
+    fn sent_ack_clears_pending_ack() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        ack_manager.on_packet_received(PacketNumber::from(0u32), true, now);
+        let ack_frame = ack_manager.build_ack_frame(now).unwrap();
+        ack_manager.on_ack_sent(PacketNumber::from(0u32), &ack_frame);
+
+        assert_eq!(ack_manager.ack_deadline(), None);
+        assert!(ack_manager.build_ack_frame(now).is_none());
+    }
+
+    #[test]
+    fn acknowledged_ack_prunes_history() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        for &packet_number in &[0u32, 1, 2] {
+            ack_manager.on_packet_received(PacketNumber::from(packet_number), true, now);
+        }
+
+        let ack_frame = ack_manager.build_ack_frame(now).unwrap();
+        ack_manager.on_ack_sent(PacketNumber::from(7u32), &ack_frame);
+
+        ack_manager.on_ack_frame_received(&AckFrame {
+            ack_delay: 0,
+            ack_ranges_descending: vec![7..8],
+        });
+
+        ack_manager.on_packet_received(PacketNumber::from(3u32), true, now);
+
+        assert_eq!(
+            ack_manager.build_ack_frame(now).unwrap().ack_ranges_descending,
+            vec![3..4]
+        );
+    }
+
+    #[test]
+    fn ack_frame_forgets_sent_acks_below_largest_acknowledged() {
+        let mut ack_manager = ack_manager();
+        let now = Instant::now();
+
+        for &packet_number in &[0u32, 1, 2] {
+            ack_manager.on_packet_received(PacketNumber::from(packet_number), true, now);
+            let ack_frame = ack_manager.build_ack_frame(now).unwrap();
+            ack_manager.on_ack_sent(PacketNumber::from(packet_number + 5), &ack_frame);
+        }
+
+        // the packet carrying the first ACK frame was lost
+        ack_manager.on_ack_frame_received(&AckFrame {
+            ack_delay: 0,
+            ack_ranges_descending: vec![6..7],
+        });
+
+        assert_eq!(
+            ack_manager.sent_acks.keys().cloned().collect::<Vec<_>>(),
+            vec![PacketNumber::from(7u32)]
+        );
+    }
+}
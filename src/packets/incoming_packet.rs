@@ -1,13 +1,19 @@
 use bytes::Bytes;
-use chrono::{DateTime, UTC};
 use packets::PacketHeader;
 use std::net::SocketAddr;
+use std::time::Instant;
 
 /// An incoming packet before any decryption has taken place.
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct IncomingPacket {
     pub source_address: SocketAddr,
     pub packet_header: PacketHeader,
+    /// The bytes of `packet_header` exactly as they were received.
+    pub packet_header_bytes: Bytes,
     pub data: Bytes,
-    pub received_at: DateTime<UTC>,
+    pub received_at: Instant,
+    /// Set while the header protection is still on the packet, its keys come out of the TLS
+    /// handshake so only the connection can remove it. `packet_header_bytes` then end where the
+    /// protected packet number starts.
+    pub header_protected: bool,
 }
@@ -1,13 +1,105 @@
-use packets::{IncomingPacket, PacketNumber};
-use std::collections::BTreeMap;
+use futures::task::{self, Task};
+use packets::IncomingPacket;
+use smallvec::SmallVec;
+use std::collections::VecDeque;
 
+/// A bounded queue of incoming packets which have yet to be processed.
+///
+/// The task which last found the queue empty is notified when a packet is pushed.
 #[derive(Debug)]
 pub struct IncomingPacketStore {
-    pending_packets: BTreeMap<PacketNumber, IncomingPacket>,
+    pending_packets: VecDeque<IncomingPacket>,
+    capacity: usize,
+    parked_task: Option<Task>,
 }
 
 impl IncomingPacketStore {
-    pub fn packet(&mut self, packet_number: PacketNumber) -> Option<IncomingPacket> {
-        self.pending_packets.remove(&packet_number)
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            pending_packets: VecDeque::with_capacity(capacity),
+            capacity,
+            parked_task: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending_packets.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.pending_packets.len() >= self.capacity
+    }
+
+    /// Queues `incoming_packet`, waking the parked task if there is one.
+    ///
+    /// # Returns
+    /// `false` if the store was full and the packet was discarded.
+    pub fn push_packet(&mut self, incoming_packet: IncomingPacket) -> bool {
+        if self.is_full() {
+            return false;
+        }
+
+        self.pending_packets.push_back(incoming_packet);
+
+        if let Some(parked_task) = self.parked_task.take() {
+            parked_task.notify();
+        }
+
+        true
+    }
+
+    /// Takes the oldest pending packet, parking the current task if there is none.
+    pub fn pop_packet_or_park(&mut self) -> Option<IncomingPacket> {
+        let incoming_packet = self.pending_packets.pop_front();
+        if incoming_packet.is_none() {
+            self.parked_task = Some(task::current());
+        }
+
+        incoming_packet
+    }
+
+    /// Takes all of the pending packets in the order they were received, parking the current
+    /// task if there are none.
+    pub fn take_packets_or_park(&mut self) -> SmallVec<[IncomingPacket; 1]> {
+        if self.is_empty() {
+            self.parked_task = Some(task::current());
+        }
+
+        self.pending_packets.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncomingPacketStore;
+    use bytes::Bytes;
+    use packets::{IncomingPacket, PacketHeader, PartialPacketNumber, ShortHeader};
+    use protocol::{ConnectionId, WireFormat};
+    use std::time::Instant;
+
+    fn incoming_packet() -> IncomingPacket {
+        IncomingPacket {
+            source_address: "10.0.0.1:443".parse().unwrap(),
+            packet_header_bytes: Bytes::new(),
+            packet_header: PacketHeader::Short(ShortHeader {
+                key_phase: false,
+                destination_connection_id: Some(ConnectionId::generate().unwrap()),
+                partial_packet_number: PartialPacketNumber::from(0u8),
+                wire_format: WireFormat::Draft08,
+            }),
+            data: Bytes::new(),
+            received_at: Instant::now(),
+            header_protected: false,
+        }
+    }
+
+    #[test]
+    fn push_packet_discards_packets_when_full() {
+        let mut incoming_packet_store = IncomingPacketStore::with_capacity(2);
+
+        assert!(incoming_packet_store.push_packet(incoming_packet()));
+        assert!(incoming_packet_store.push_packet(incoming_packet()));
+        assert!(incoming_packet_store.is_full());
+        assert!(!incoming_packet_store.push_packet(incoming_packet()));
     }
 }
@@ -1,4 +1,5 @@
 use super::{LongHeaderPacketType, PartialPacketNumber};
+use bytes::Bytes;
 use protocol::{ConnectionId, VarInt, Version};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -7,6 +8,8 @@ pub struct LongHeader {
     pub version: Version,
     pub destination_connection_id: Option<ConnectionId>,
     pub source_connection_id: Option<ConnectionId>,
+    /// The address validation token, only carried by version 1 Initial packets.
+    pub token: Bytes,
     pub payload_length: VarInt,
     pub partial_packet_number: PartialPacketNumber,
 }
@@ -1,5 +1,5 @@
 mod packet_number;
-pub use self::packet_number::{PacketNumber, PartialPacketNumber};
+pub use self::packet_number::{PacketNumber, PartialPacketNumber, PartialPacketNumberLength};
 
 mod short_header;
 pub use self::short_header::ShortHeader;
@@ -13,6 +13,18 @@ pub use self::long_header::LongHeader;
 mod version_negotiation_packet;
 pub use self::version_negotiation_packet::VersionNegotiationPacket;
 
+mod retry_packet;
+pub use self::retry_packet::RetryPacket;
+
+mod unsupported_version_packet;
+pub use self::unsupported_version_packet::UnsupportedVersionPacket;
+
+mod stateless_reset_packet;
+pub use self::stateless_reset_packet::StatelessResetPacket;
+
+mod unknown_connection_packet;
+pub use self::unknown_connection_packet::UnknownConnectionPacket;
+
 mod packet_header;
 pub use self::packet_header::{PacketHeader, PacketHeaderReadContext};
 
@@ -26,16 +38,28 @@ mod outgoing_packet;
 pub use self::outgoing_packet::OutgoingPacket;
 
 mod packet_codec;
-pub use self::packet_codec::PacketCodec;
+pub use self::packet_codec::{remove_deferred_header_protection, PacketCodec};
 
 mod packet_packer;
 pub use self::packet_packer::PacketPacker;
 
+mod packet_unpacker;
+pub use self::packet_unpacker::PacketUnpacker;
+
 mod incoming_packet_store;
 pub use self::incoming_packet_store::IncomingPacketStore;
 
 mod packet_history;
 pub use self::packet_history::PacketHistory;
 
+mod ack_manager;
+pub use self::ack_manager::AckManager;
+
+mod packet_number_space;
+pub use self::packet_number_space::PacketNumberSpace;
+
 mod packet_dispatcher;
-pub use self::packet_dispatcher::PacketDispatcher;
+pub use self::packet_dispatcher::{IncomingPackets, OutgoingPackets, PacketDispatcher};
+
+mod stateless_reset_limiter;
+pub use self::stateless_reset_limiter::StatelessResetLimiter;
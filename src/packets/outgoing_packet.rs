@@ -1,13 +1,18 @@
 use bytes::Bytes;
+use crypto::HeaderProtectionKey;
 use packets::PacketHeader;
 use protocol::EncryptionLevel;
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 /// An outgoing packet after any encryption has taken place.
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone)]
 pub struct OutgoingPacket {
     pub destination_address: SocketAddr,
     pub packet_header: PacketHeader,
     pub data: Bytes,
     pub encryption_level: EncryptionLevel,
+    /// The key the header is protected with once the packet is written, there is no header
+    /// protection in draft 08.
+    pub header_protection_key: Option<Arc<HeaderProtectionKey>>,
 }
@@ -1,63 +1,398 @@
-use bytes::Bytes;
-use chrono::UTC;
+use bytes::{Bytes, BytesMut};
 use conv::ValueFrom;
-use packets::{IncomingPacket, OutgoingPacket, PacketHeader, PacketHeaderReadContext};
-use protocol::{Readable, Writable};
+use crypto::{CryptoState, HeaderProtectionKey, HeaderProtectionKeys, StatelessResetTokens};
+use errors::*;
+use packets::{IncomingPacket, LongHeaderPacketType, OutgoingPacket, PacketHeader,
+              PacketHeaderReadContext, StatelessResetPacket, UnknownConnectionPacket};
+use protocol::{Readable, Role, StatelessResetToken, WireFormat, Writable};
 use smallvec::SmallVec;
 use std::io::{Cursor, Result as IoResult};
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
 use tokio_core::net::UdpCodec;
 
-#[derive(Debug, Clone, Default)]
-pub struct PacketCodec;
+#[derive(Debug, Clone)]
+pub struct PacketCodec {
+    role: Role,
+    wire_format: WireFormat,
+    connection_id_len: usize,
+    header_protection_keys: Arc<HeaderProtectionKeys>,
+    stateless_reset_tokens: Arc<StatelessResetTokens>,
+}
 
-impl UdpCodec for PacketCodec {
-    /// We will usually always only have 1 incoming packet so we optimize for this case
-    type In = SmallVec<[IncomingPacket; 1]>;
+impl PacketCodec {
+    /// Creates a `PacketCodec` which reads short headers and version negotiation packets in
+    /// `wire_format`, short headers are expected to carry connection ids of `connection_id_len`
+    /// bytes.
+    ///
+    /// The header protection of incoming short header packets is removed with the keys in
+    /// `header_protection_keys`, those without a key are checked for the tokens in
+    /// `stateless_reset_tokens`.
+    pub fn new(
+        role: Role,
+        wire_format: WireFormat,
+        connection_id_len: usize,
+        header_protection_keys: Arc<HeaderProtectionKeys>,
+        stateless_reset_tokens: Arc<StatelessResetTokens>,
+    ) -> Self {
+        Self {
+            role,
+            wire_format,
+            connection_id_len,
+            header_protection_keys,
+            stateless_reset_tokens,
+        }
+    }
 
-    /// We don't current support coalescing outgoing packets
-    type Out = OutgoingPacket;
+    fn read_context(&self) -> PacketHeaderReadContext {
+        PacketHeaderReadContext {
+            connection_id_len: self.connection_id_len,
+            wire_format: self.wire_format,
+        }
+    }
 
-    fn decode(&mut self, src: &SocketAddr, mut buf: &[u8]) -> IoResult<Self::In> {
-        let received_at = UTC::now();
+    /// Finds the key to remove the header protection of the packet with `packet_header`.
+    ///
+    /// # Returns
+    /// `None` if the packet has no header protection.
+    fn header_protection_key(
+        &self,
+        packet_header: &PacketHeader,
+    ) -> Result<Option<Arc<HeaderProtectionKey>>> {
+        if packet_header.wire_format() == WireFormat::Draft08 {
+            return Ok(None);
+        }
 
-        let mut incoming_packets = SmallVec::new();
+        match packet_header {
+            PacketHeader::VersionNegotiation(_)
+            | PacketHeader::Retry(_)
+            | PacketHeader::UnsupportedVersion(_) => Ok(None),
+            PacketHeader::Long(long_header)
+                if long_header.packet_type == LongHeaderPacketType::Initial =>
+            {
+                // the initial keys only depend upon the destination connection id of the
+                // client's Initial packets, this allows the header protection of packets for new
+                // connections to be removed
+                let destination_connection_id = long_header
+                    .destination_connection_id
+                    .ok_or_else(|| ErrorKind::PacketHeaderHasNoConnectionId)?;
 
-        while !buf.is_empty() {
-            trace!("decoding incoming packet");
+                let crypto_state = CryptoState::for_initial(
+                    destination_connection_id,
+                    "client in",
+                    long_header.version,
+                )?;
 
-            let mut buf_cursor = Cursor::new(buf);
+                Ok(crypto_state.header_protection_key())
+            }
+            _ => {
+                let connection_id = packet_header.destination_connection_id();
 
-            let packet_header = PacketHeader::read_with_context(
-                &mut buf_cursor,
-                &PacketHeaderReadContext {
-                    has_connection_id: true,
-                },
-            )?;
+                let header_protection_key = connection_id
+                    .and_then(|connection_id| self.header_protection_keys.get(connection_id))
+                    .ok_or_else(|| ErrorKind::NoHeaderProtectionKey(connection_id))?;
+
+                Ok(Some(header_protection_key))
+            }
+        }
+    }
 
-            let data_start_index = usize::value_from(buf_cursor.position()).expect(
-                "the buf cursor should not exceed the value which can be stored by a usize",
-            );
 
-            let data_end_index = packet_header
-                .payload_length()
-                .map(|l| usize::value_from(u64::from(l)).unwrap())
-                .unwrap_or(buf.len());
+    /// Makes sense of the short header packet with `packet_header` at the start of `buf` whose
+    /// header protection could not be removed, short header packets run to the end of the
+    /// datagram so `buf` ends where it does.
+    ///
+    /// # Returns
+    /// A packet with a `StatelessResetPacket` header if `buf` ends in a token a connection
+    /// registered, otherwise one with an `UnknownConnectionPacket` header on a server when
+    /// `unknown_connection` is set, or `None` if the packet should be discarded.
+    fn unprotected_short_header(
+        &self,
+        source_address: SocketAddr,
+        packet_header: &PacketHeader,
+        buf: &[u8],
+        received_at: Instant,
+        unknown_connection: bool,
+    ) -> Option<IncomingPacket> {
+        let destination_connection_id = match packet_header {
+            PacketHeader::Short(short_header) if short_header.wire_format == WireFormat::V1 => {
+                short_header.destination_connection_id
+            }
+            _ => return None,
+        };
 
-            let data = Bytes::from(&buf[data_start_index..data_end_index]);
+        let stateless_reset = StatelessResetToken::from_datagram(buf).and_then(|token| {
+            self.stateless_reset_tokens
+                .get(token)
+                .map(|connection_id| StatelessResetPacket {
+                    connection_id,
+                    stateless_reset_token: token,
+                })
+        });
+
+        let packet_header = match stateless_reset {
+            Some(stateless_reset) => PacketHeader::StatelessReset(stateless_reset),
+            None if unknown_connection && self.role == Role::Server => {
+                PacketHeader::UnknownConnection(UnknownConnectionPacket {
+                    destination_connection_id,
+                    len: buf.len(),
+                })
+            }
+            None => return None,
+        };
+
+        // nothing in the packet can be read without its keys
+        Some(IncomingPacket {
+            source_address,
+            packet_header,
+            packet_header_bytes: Bytes::new(),
+            data: Bytes::new(),
+            received_at,
+            header_protected: false,
+        })
+    }
+
+    /// Decodes the packet at the start of `buf`.
+    ///
+    /// # Returns
+    /// The packet, or `None` if its header protection could not be removed, along with the number
+    /// of bytes of `buf` it occupied.
+    fn decode_packet(
+        &self,
+        source_address: SocketAddr,
+        buf: &[u8],
+        received_at: Instant,
+    ) -> Result<(Option<IncomingPacket>, usize)> {
+        let mut buf_cursor = Cursor::new(buf);
+
+        // the flags and packet number may still be protected, only the rest of the header can
+        // be relied upon
+        let packet_header = PacketHeader::read_with_context(&mut buf_cursor, &self.read_context())?;
+
+        let data_start_index = usize::value_from(buf_cursor.position()).expect(
+            "the buf cursor should not exceed the value which can be stored by a usize",
+        );
+
+        // the protected packet number length does not affect where the packet ends
+        let data_end_index = packet_header
+            .payload_length()
+            .map(|l| data_start_index + usize::value_from(u64::from(l)).unwrap())
+            .unwrap_or(buf.len());
+
+        if data_end_index > buf.len() {
+            bail!(ErrorKind::PayloadLengthExceedsDatagramLength);
+        }
+
+        if has_deferred_header_protection(self.role, &packet_header) {
+            let packet_number_offset = packet_number_offset(&packet_header, data_start_index);
 
             let incoming_packet = IncomingPacket {
-                source_address: *src,
+                source_address,
                 packet_header,
-                data,
+                packet_header_bytes: Bytes::from(&buf[..packet_number_offset]),
+                data: Bytes::from(&buf[packet_number_offset..data_end_index]),
                 received_at,
+                header_protected: true,
             };
 
-            debug!("decoded incoming packet {:?}", incoming_packet);
+            return Ok((Some(incoming_packet), data_end_index));
+        }
+
+        let header_protection_key = match self.header_protection_key(&packet_header) {
+            Ok(header_protection_key) => header_protection_key,
+            Err(error) => {
+                let incoming_packet = self.unprotected_short_header(
+                    source_address,
+                    &packet_header,
+                    buf,
+                    received_at,
+                    true,
+                );
+                if incoming_packet.is_some() {
+                    return Ok((incoming_packet, buf.len()));
+                }
+
+                debug!(
+                    "discarding packet from {:?} whose header protection can not be removed: {}",
+                    source_address, error
+                );
+                return Ok((None, data_end_index));
+            }
+        };
+
+        let (packet_header, packet_header_bytes, data) = match header_protection_key {
+            None => (
+                packet_header,
+                Bytes::from(&buf[..data_start_index]),
+                Bytes::from(&buf[data_start_index..data_end_index]),
+            ),
+            Some(header_protection_key) => {
+                let packet_number_offset = packet_number_offset(&packet_header, data_start_index);
+
+                let mut packet = BytesMut::from(&buf[..data_end_index]);
+
+                let (packet_header, data_start_index) = match remove_header_protection(
+                    &mut packet[..],
+                    packet_number_offset,
+                    &header_protection_key,
+                    &self.read_context(),
+                ) {
+                    Ok(unprotected) => unprotected,
+                    Err(error) => {
+                        let incoming_packet = self.unprotected_short_header(
+                            source_address,
+                            &packet_header,
+                            buf,
+                            received_at,
+                            false,
+                        );
+                        if incoming_packet.is_some() {
+                            return Ok((incoming_packet, buf.len()));
+                        }
+
+                        debug!(
+                            "discarding packet from {:?} whose header protection could not be \
+                             removed: {}",
+                            source_address, error
+                        );
+                        return Ok((None, data_end_index));
+                    }
+                };
+
+                let packet = packet.freeze();
+
+                (
+                    packet_header,
+                    packet.slice(0, data_start_index),
+                    packet.slice(data_start_index, packet.len()),
+                )
+            }
+        };
+
+        let incoming_packet = IncomingPacket {
+            source_address,
+            packet_header,
+            packet_header_bytes,
+            data,
+            received_at,
+            header_protected: false,
+        };
+
+        Ok((Some(incoming_packet), data_end_index))
+    }
+}
+
+/// Whether the header protection of the packet with `packet_header` is left for its connection
+/// to remove. Version 1 Handshake and 0-RTT packets are protected with keys from the TLS
+/// handshake, and the Initial packets a client receives with keys derived from the connection id
+/// it first sent to.
+fn has_deferred_header_protection(role: Role, packet_header: &PacketHeader) -> bool {
+    match packet_header {
+        PacketHeader::Long(long_header)
+            if long_header.version.wire_format() == Some(WireFormat::V1) =>
+        {
+            match long_header.packet_type {
+                LongHeaderPacketType::Handshake | LongHeaderPacketType::ZeroRttProtected => true,
+                LongHeaderPacketType::Initial => role == Role::Client,
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Where the packet number starts in a packet with `packet_header` whose payload starts at
+/// `data_start_index`.
+fn packet_number_offset(packet_header: &PacketHeader, data_start_index: usize) -> usize {
+    let packet_number_len = packet_header
+        .partial_packet_number()
+        .expect("only packets with packet numbers have header protection")
+        .len()
+        .byte_len();
+
+    data_start_index - packet_number_len
+}
+
+/// Removes the header protection from `packet`, returning the unprotected header and the index
+/// its payload starts at.
+fn remove_header_protection(
+    packet: &mut [u8],
+    packet_number_offset: usize,
+    header_protection_key: &HeaderProtectionKey,
+    read_context: &PacketHeaderReadContext,
+) -> Result<(PacketHeader, usize)> {
+    header_protection_key.unprotect(packet, packet_number_offset)?;
+
+    let mut packet_cursor = Cursor::new(&packet[..]);
+    let packet_header = PacketHeader::read_with_context(&mut packet_cursor, read_context)?;
+
+    let data_start_index = usize::value_from(packet_cursor.position())
+        .expect("the packet cursor should not exceed the value which can be stored by a usize");
+
+    Ok((packet_header, data_start_index))
+}
+
+/// Removes the header protection a `PacketCodec` left on `incoming_packet` with the key its
+/// connection has for it.
+pub fn remove_deferred_header_protection(
+    incoming_packet: IncomingPacket,
+    header_protection_key: &HeaderProtectionKey,
+) -> Result<IncomingPacket> {
+    let packet_number_offset = incoming_packet.packet_header_bytes.len();
+
+    let mut packet = BytesMut::with_capacity(packet_number_offset + incoming_packet.data.len());
+    packet.extend_from_slice(&incoming_packet.packet_header_bytes);
+    packet.extend_from_slice(&incoming_packet.data);
+
+    // only long headers are deferred, which carry the length of their connection ids
+    let read_context = PacketHeaderReadContext {
+        connection_id_len: 0,
+        wire_format: incoming_packet.packet_header.wire_format(),
+    };
+
+    let (packet_header, data_start_index) = remove_header_protection(
+        &mut packet[..],
+        packet_number_offset,
+        header_protection_key,
+        &read_context,
+    )?;
+
+    let packet = packet.freeze();
+
+    Ok(IncomingPacket {
+        packet_header,
+        packet_header_bytes: packet.slice(0, data_start_index),
+        data: packet.slice(data_start_index, packet.len()),
+        header_protected: false,
+        ..incoming_packet
+    })
+}
+
+impl UdpCodec for PacketCodec {
+    /// We will usually always only have 1 incoming packet so we optimize for this case
+    type In = SmallVec<[IncomingPacket; 1]>;
+
+    /// We don't current support coalescing outgoing packets
+    type Out = OutgoingPacket;
+
+    fn decode(&mut self, src: &SocketAddr, mut buf: &[u8]) -> IoResult<Self::In> {
+        let received_at = Instant::now();
+
+        let mut incoming_packets = SmallVec::new();
+
+        while !buf.is_empty() {
+            trace!("decoding incoming packet");
+
+            let (incoming_packet, packet_len) = self.decode_packet(*src, buf, received_at)?;
+
+            if let Some(incoming_packet) = incoming_packet {
+                debug!("decoded incoming packet {:?}", incoming_packet);
 
-            incoming_packets.push(incoming_packet);
+                incoming_packets.push(incoming_packet);
+            }
 
-            buf = &buf[data_end_index..];
+            buf = &buf[packet_len..];
         }
 
         Ok(incoming_packets)
@@ -66,13 +401,427 @@ impl UdpCodec for PacketCodec {
     fn encode(&mut self, msg: Self::Out, buf: &mut Vec<u8>) -> SocketAddr {
         trace!("encoding outgoing packet {:?}", msg);
 
+        let packet_start_index = buf.len();
+
         msg.packet_header
             .write(buf)
-            .and(msg.data.write(buf))
-            .expect("there should be no error writing the public header to an in-memory buffer");
+            .expect("there should be no error writing the packet header to an in-memory buffer");
+
+        let data_start_index = buf.len();
+
+        msg.data
+            .write(buf)
+            .expect("there should be no error writing the packet data to an in-memory buffer");
+
+        if let Some(header_protection_key) = &msg.header_protection_key {
+            let packet_number_len = msg.packet_header
+                .partial_packet_number()
+                .expect("only packets with packet numbers have header protection")
+                .len()
+                .byte_len();
+            let packet_number_offset = data_start_index - packet_start_index - packet_number_len;
+
+            header_protection_key
+                .protect(&mut buf[packet_start_index..], packet_number_offset)
+                .expect("packets should be padded so the header protection sample is available");
+        }
 
         debug!("encoded outgoing packet {:?}", msg);
 
         msg.destination_address
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{remove_deferred_header_protection, PacketCodec};
+    use bytes::Bytes;
+    use crypto::{CryptoState, HeaderProtectionKeys, StatelessResetTokens};
+    use frames::Frame;
+    use packets::{LongHeader, LongHeaderPacketType, PacketHeader, PacketNumber, PacketPacker,
+                  PartialPacketNumber, ShortHeader, StatelessResetPacket,
+                  UnknownConnectionPacket};
+    use hex;
+    use protocol::{ConnectionId, EncryptionLevel, Role, StatelessResetToken, StreamOffset,
+                   Version, WireFormat};
+    use rustls;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use tokio_core::net::UdpCodec;
+
+    fn address() -> SocketAddr {
+        "10.0.0.1:443".parse().unwrap()
+    }
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        hex::FromHex::from_hex(hex).unwrap()
+    }
+
+    fn encode(
+        packet_header: PacketHeader,
+        packet_number: PacketNumber,
+        crypto_state: &CryptoState,
+    ) -> Vec<u8> {
+        let mut packet_packer =
+            PacketPacker::new(packet_header, 1200, crypto_state.tag_len()).unwrap();
+        assert_eq!(packet_packer.try_push_frame(Frame::Ping).unwrap(), None);
+
+        let outgoing_packet = packet_packer
+            .pack_packet(
+                packet_number,
+                crypto_state,
+                address(),
+                EncryptionLevel::ForwardSecure,
+            )
+            .unwrap();
+
+        let mut packet_codec = PacketCodec::new(
+            Role::Client,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            Arc::new(HeaderProtectionKeys::new()),
+            Arc::new(StatelessResetTokens::new()),
+        );
+
+        let mut buf = Vec::new();
+        packet_codec.encode(outgoing_packet, &mut buf);
+
+        buf
+    }
+
+    #[test]
+    fn decode_removes_header_protection_of_initial_packets() {
+        let destination_connection_id = ConnectionId::generate().unwrap();
+        let crypto_state =
+            CryptoState::for_initial(destination_connection_id, "client in", Version::V1).unwrap();
+        let packet_number = PacketNumber::from(0x1234u32);
+
+        let packet_header = PacketHeader::Long(LongHeader {
+            packet_type: LongHeaderPacketType::Initial,
+            version: Version::V1,
+            destination_connection_id: Some(destination_connection_id),
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            token: Bytes::new(),
+            payload_length: 0u32.into(),
+            partial_packet_number: PartialPacketNumber::from_packet_number(
+                packet_number,
+                0u32.into(),
+            ).unwrap(),
+        });
+
+        let buf = encode(packet_header.clone(), packet_number, &crypto_state);
+
+        let mut packet_codec = PacketCodec::new(
+            Role::Server,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            Arc::new(HeaderProtectionKeys::new()),
+            Arc::new(StatelessResetTokens::new()),
+        );
+        let incoming_packets = packet_codec.decode(&address(), &buf[..]).unwrap();
+
+        assert_eq!(incoming_packets.len(), 1);
+        assert_eq!(
+            incoming_packets[0].packet_header.partial_packet_number(),
+            packet_header.partial_packet_number()
+        );
+
+        let frames = crypto_state
+            .open(
+                packet_number,
+                &incoming_packets[0].packet_header_bytes[..],
+                &incoming_packets[0].data[..],
+            )
+            .unwrap();
+        assert_eq!(frames[0], Frame::Ping);
+    }
+
+    #[test]
+    fn decode_leaves_header_protection_of_handshake_packets_to_the_connection() {
+        let client_connection_id = ConnectionId::generate().unwrap();
+        let crypto_state =
+            CryptoState::for_initial(client_connection_id, "server hs", Version::V1).unwrap();
+        let packet_number = PacketNumber::from(3u32);
+
+        let packet_header = PacketHeader::Long(LongHeader {
+            packet_type: LongHeaderPacketType::Handshake,
+            version: Version::V1,
+            destination_connection_id: Some(client_connection_id),
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            token: Bytes::new(),
+            payload_length: 0u32.into(),
+            partial_packet_number: PartialPacketNumber::from_packet_number(
+                packet_number,
+                0u32.into(),
+            ).unwrap(),
+        });
+
+        let buf = encode(packet_header.clone(), packet_number, &crypto_state);
+
+        let mut packet_codec = PacketCodec::new(
+            Role::Client,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            Arc::new(HeaderProtectionKeys::new()),
+            Arc::new(StatelessResetTokens::new()),
+        );
+        let mut incoming_packets = packet_codec.decode(&address(), &buf[..]).unwrap();
+
+        assert_eq!(incoming_packets.len(), 1);
+        assert!(incoming_packets[0].header_protected);
+
+        let incoming_packet = remove_deferred_header_protection(
+            incoming_packets.remove(0),
+            &crypto_state.header_protection_key().unwrap(),
+        ).unwrap();
+
+        assert!(!incoming_packet.header_protected);
+        assert_eq!(
+            incoming_packet.packet_header.partial_packet_number(),
+            packet_header.partial_packet_number()
+        );
+
+        let frames = crypto_state
+            .open(
+                packet_number,
+                &incoming_packet.packet_header_bytes[..],
+                &incoming_packet.data[..],
+            )
+            .unwrap();
+        assert_eq!(frames[0], Frame::Ping);
+    }
+
+    #[test]
+    fn decode_leaves_header_protection_of_initial_packets_to_a_client_connection() {
+        // the server's Initial packet of RFC 9001 appendix A.3, whose keys are derived from the
+        // connection id the client first sent to rather than any in its header
+        let buf = from_hex(
+            "cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a\
+             5816b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3\
+             dbcba3f6ea46c5b7684df3548e7ddeb9c3bf9c73cc3f3bded74b562bfb19fb84\
+             022f8ef4cdd93795d77d06edbb7aaf2f58891850abbdca3d20398c276456cbc4\
+             2158407dd074ee",
+        );
+        let original_destination_connection_id =
+            ConnectionId::from_slice(&[0x83, 0x94, 0xc8, 0xf0, 0x3e, 0x51, 0x57, 0x08]).unwrap();
+        let crypto_state =
+            CryptoState::for_initial(original_destination_connection_id, "server in", Version::V1)
+                .unwrap();
+
+        let mut packet_codec = PacketCodec::new(
+            Role::Client,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            Arc::new(HeaderProtectionKeys::new()),
+            Arc::new(StatelessResetTokens::new()),
+        );
+        let mut incoming_packets = packet_codec.decode(&address(), &buf[..]).unwrap();
+
+        assert_eq!(incoming_packets.len(), 1);
+        assert!(incoming_packets[0].header_protected);
+
+        let incoming_packet = remove_deferred_header_protection(
+            incoming_packets.remove(0),
+            &crypto_state.header_protection_key().unwrap(),
+        ).unwrap();
+
+        assert_eq!(
+            &incoming_packet.packet_header_bytes[..],
+            &from_hex("c1000000010008f067a5502a4262b50040750001")[..]
+        );
+
+        let frames = crypto_state
+            .open(
+                PacketNumber::from(1u32),
+                &incoming_packet.packet_header_bytes[..],
+                &incoming_packet.data[..],
+            )
+            .unwrap();
+
+        assert_eq!(frames.len(), 2);
+        match &frames[0] {
+            Frame::Ack(ack_frame) => assert_eq!(ack_frame.largest_acknowledged(), Some(0)),
+            frame => panic!("expected an ACK frame, found {:?}", frame),
+        }
+        match &frames[1] {
+            Frame::Crypto(crypto_frame) => {
+                // the ServerHello
+                assert_eq!(crypto_frame.offset, StreamOffset::from(0u32));
+                assert_eq!(
+                    &crypto_frame.data[..],
+                    &from_hex(
+                        "020000560303eefce7f7b37ba1d1632e96677825ddf73988cfc79825df566dc5430b9\
+                         a045a1200130100002e00330024001d00209d3c940d89690b84d08a60993c144eca68\
+                         4d1081287c834d5311bcf32bb9da1a002b00020304",
+                    )[..]
+                );
+            }
+            frame => panic!("expected a CRYPTO frame, found {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn decode_leaves_header_protection_of_zero_rtt_packets_to_the_connection() {
+        let crypto_state =
+            CryptoState::from_secret(&rustls::ALL_CIPHERSUITES[0], &[1; 32], Version::V1).unwrap();
+        let packet_number = PacketNumber::from(2u32);
+
+        let packet_header = PacketHeader::Long(LongHeader {
+            packet_type: LongHeaderPacketType::ZeroRttProtected,
+            version: Version::V1,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            token: Bytes::new(),
+            payload_length: 0u32.into(),
+            partial_packet_number: PartialPacketNumber::from_packet_number(
+                packet_number,
+                0u32.into(),
+            ).unwrap(),
+        });
+
+        let buf = encode(packet_header.clone(), packet_number, &crypto_state);
+
+        let mut packet_codec = PacketCodec::new(
+            Role::Server,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            Arc::new(HeaderProtectionKeys::new()),
+            Arc::new(StatelessResetTokens::new()),
+        );
+        let mut incoming_packets = packet_codec.decode(&address(), &buf[..]).unwrap();
+
+        assert_eq!(incoming_packets.len(), 1);
+        assert!(incoming_packets[0].header_protected);
+
+        let incoming_packet = remove_deferred_header_protection(
+            incoming_packets.remove(0),
+            &crypto_state.header_protection_key().unwrap(),
+        ).unwrap();
+
+        assert_eq!(
+            incoming_packet.packet_header.partial_packet_number(),
+            packet_header.partial_packet_number()
+        );
+
+        let frames = crypto_state
+            .open(
+                packet_number,
+                &incoming_packet.packet_header_bytes[..],
+                &incoming_packet.data[..],
+            )
+            .unwrap();
+        assert_eq!(frames[0], Frame::Ping);
+    }
+
+    #[test]
+    fn decode_removes_header_protection_of_short_header_packets() {
+        let connection_id = ConnectionId::generate().unwrap();
+        let crypto_state =
+            CryptoState::for_initial(connection_id, "server in", Version::V1).unwrap();
+        let packet_number = PacketNumber::from(7u32);
+
+        let packet_header = PacketHeader::Short(ShortHeader {
+            key_phase: false,
+            destination_connection_id: Some(connection_id),
+            partial_packet_number: PartialPacketNumber::from_packet_number(
+                packet_number,
+                0u32.into(),
+            ).unwrap(),
+            wire_format: WireFormat::V1,
+        });
+
+        let buf = encode(packet_header.clone(), packet_number, &crypto_state);
+
+        let header_protection_keys = Arc::new(HeaderProtectionKeys::new());
+        let mut packet_codec = PacketCodec::new(
+            Role::Client,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            header_protection_keys.clone(),
+            Arc::new(StatelessResetTokens::new()),
+        );
+
+        // the packet is discarded until the connection has registered its key
+        assert!(
+            packet_codec
+                .decode(&address(), &buf[..])
+                .unwrap()
+                .is_empty()
+        );
+
+        header_protection_keys.insert(connection_id, crypto_state.header_protection_key().unwrap());
+        let incoming_packets = packet_codec.decode(&address(), &buf[..]).unwrap();
+
+        assert_eq!(incoming_packets.len(), 1);
+        assert_eq!(incoming_packets[0].packet_header, packet_header);
+    }
+
+    fn stateless_reset(stateless_reset_token: StatelessResetToken) -> Vec<u8> {
+        let mut buf = vec![0x4f; 24];
+        buf.extend_from_slice(stateless_reset_token.bytes());
+
+        buf
+    }
+
+    #[test]
+    fn decode_recognises_stateless_resets_by_registered_tokens() {
+        let connection_id = ConnectionId::generate().unwrap();
+        let stateless_reset_token = StatelessResetToken::from([0x42; 16]);
+
+        let stateless_reset_tokens = Arc::new(StatelessResetTokens::new());
+        let mut packet_codec = PacketCodec::new(
+            Role::Client,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            Arc::new(HeaderProtectionKeys::new()),
+            stateless_reset_tokens.clone(),
+        );
+
+        let buf = stateless_reset(stateless_reset_token);
+
+        // without the token it is just a packet for another connection
+        assert!(
+            packet_codec
+                .decode(&address(), &buf[..])
+                .unwrap()
+                .is_empty()
+        );
+
+        stateless_reset_tokens.insert(connection_id, stateless_reset_token);
+        let incoming_packets = packet_codec.decode(&address(), &buf[..]).unwrap();
+
+        assert_eq!(incoming_packets.len(), 1);
+        assert_eq!(
+            incoming_packets[0].packet_header,
+            PacketHeader::StatelessReset(StatelessResetPacket {
+                connection_id,
+                stateless_reset_token,
+            })
+        );
+    }
+
+    #[test]
+    fn decode_on_server_keeps_packets_for_unknown_connections() {
+        let mut packet_codec = PacketCodec::new(
+            Role::Server,
+            WireFormat::V1,
+            ConnectionId::DEFAULT_LEN,
+            Arc::new(HeaderProtectionKeys::new()),
+            Arc::new(StatelessResetTokens::new()),
+        );
+
+        let buf = stateless_reset(StatelessResetToken::from([0x42; 16]));
+        let incoming_packets = packet_codec.decode(&address(), &buf[..]).unwrap();
+
+        assert_eq!(incoming_packets.len(), 1);
+        assert_eq!(
+            incoming_packets[0].packet_header,
+            PacketHeader::UnknownConnection(UnknownConnectionPacket {
+                destination_connection_id: Some(
+                    ConnectionId::from_slice(&buf[1..1 + ConnectionId::DEFAULT_LEN]).unwrap()
+                ),
+                len: buf.len(),
+            })
+        );
+    }
+}
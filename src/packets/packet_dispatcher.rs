@@ -1,12 +1,34 @@
+use bytes::Bytes;
+use crypto::{HeaderProtectionKeys, StatelessResetTokens};
 use errors::*;
 use futures::sink::Sink;
 use futures::stream::Stream;
-use packets::{IncomingPacketStore, Packet, PacketCodec};
-use protocol::ConnectionId;
+use futures::{Async, AsyncSink, Poll, StartSend};
+use packets::{IncomingPacket, IncomingPacketStore, LongHeader, LongHeaderPacketType,
+              OutgoingPacket, PacketCodec, PacketHeader, PartialPacketNumber,
+              PartialPacketNumberLength, ShortHeader, StatelessResetLimiter,
+              UnknownConnectionPacket};
+use protocol::{ConnectionId, EncryptionLevel, Role, StatelessResetToken, WireFormat};
+use rand::{OsRng, Rng};
+use smallvec::SmallVec;
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio_core::net::{UdpFramed, UdpSocket};
+use {AddressConnectionIds, ConnectionMap};
+
+/// The maximum number of packets which may be queued for a single connection, further packets are
+/// discarded until the connection catches up.
+const MAX_PENDING_PACKETS_PER_CONNECTION: usize = 256;
+
+/// The maximum number of packets which may be queued waiting to be accepted as new connections.
+const MAX_PENDING_NEW_CONNECTION_PACKETS: usize = 64;
+
+/// Stateless resets are kept this short, there is no need for them to be as long as the packets
+/// they answer.
+const MAX_STATELESS_RESET_LEN: usize = 64;
 
 struct DebuggableFramed(UdpFramed<PacketCodec>);
 
@@ -16,40 +38,673 @@ impl Debug for DebuggableFramed {
     }
 }
 
+#[derive(Debug, Default)]
+struct Connections {
+    incoming_packet_stores: HashMap<ConnectionId, IncomingPacketStore>,
+    connection_map: ConnectionMap,
+    /// The connection ids issued to the registered connections after their handshake, by the
+    /// connection they address.
+    issued_connection_ids: HashMap<ConnectionId, ConnectionId>,
+}
+
+/// Demultiplexes the packets received on a single UDP socket to the connections they are for.
 #[derive(Debug)]
 pub struct PacketDispatcher {
-    incoming_packet_stores: HashMap<ConnectionId, IncomingPacketStore>,
-    framed: DebuggableFramed,
+    local_address: SocketAddr,
+    connections: Mutex<Connections>,
+    new_connection_packets: Mutex<IncomingPacketStore>,
+    header_protection_keys: Arc<HeaderProtectionKeys>,
+    stateless_reset_tokens: Arc<StatelessResetTokens>,
+    connection_id_len: usize,
+    stateless_reset_key: [u8; 32],
+    stateless_reset_limiter: Mutex<StatelessResetLimiter>,
+    /// The stateless resets waiting to be sent once the socket is no longer being read.
+    pending_stateless_resets: Mutex<Vec<OutgoingPacket>>,
+    framed: Mutex<DebuggableFramed>,
 }
 
 impl PacketDispatcher {
-    pub fn new(udp_socket: UdpSocket) -> Self {
-        let framed = udp_socket.framed(PacketCodec::default());
+    /// Creates a `PacketDispatcher` for `udp_socket`, short headers are read in `wire_format` with
+    /// connection ids of `connection_id_len` bytes.
+    ///
+    /// Short header packets for unknown connections are answered with up to
+    /// `max_stateless_resets_per_second` stateless resets, their tokens derived with
+    /// `stateless_reset_key`.
+    pub fn new(
+        udp_socket: UdpSocket,
+        wire_format: WireFormat,
+        connection_id_len: usize,
+        stateless_reset_key: [u8; 32],
+        max_stateless_resets_per_second: u32,
+    ) -> Result<Self> {
+        let local_address = udp_socket
+            .local_addr()
+            .chain_err(|| ErrorKind::FailedToGetLocalAddress)?;
 
-        Self {
-            incoming_packet_stores: HashMap::new(),
-            framed: DebuggableFramed(framed),
-        }
+        let header_protection_keys = Arc::new(HeaderProtectionKeys::new());
+        let stateless_reset_tokens = Arc::new(StatelessResetTokens::new());
+
+        let framed = udp_socket.framed(PacketCodec::new(
+            Role::Server,
+            wire_format,
+            connection_id_len,
+            header_protection_keys.clone(),
+            stateless_reset_tokens.clone(),
+        ));
+
+        Ok(Self {
+            local_address,
+            connections: Mutex::default(),
+            new_connection_packets: Mutex::new(IncomingPacketStore::with_capacity(
+                MAX_PENDING_NEW_CONNECTION_PACKETS,
+            )),
+            header_protection_keys,
+            stateless_reset_tokens,
+            connection_id_len,
+            stateless_reset_key,
+            stateless_reset_limiter: Mutex::new(StatelessResetLimiter::new(
+                max_stateless_resets_per_second,
+            )),
+            pending_stateless_resets: Mutex::default(),
+            framed: Mutex::new(DebuggableFramed(framed)),
+        })
     }
 
     pub fn local_addr(&self) -> Result<SocketAddr> {
-        self.framed
+        Ok(self.local_address)
+    }
+
+    /// The keys used to remove the header protection of the packets received by the registered
+    /// connections.
+    pub fn header_protection_keys(&self) -> Arc<HeaderProtectionKeys> {
+        self.header_protection_keys.clone()
+    }
+
+    /// The tokens the registered connections recognise stateless resets from their clients by.
+    pub fn stateless_reset_tokens(&self) -> Arc<StatelessResetTokens> {
+        self.stateless_reset_tokens.clone()
+    }
+
+    /// The token which ends the stateless resets sent for the connection `connection_id`.
+    pub fn stateless_reset_token(&self, connection_id: ConnectionId) -> StatelessResetToken {
+        StatelessResetToken::derive(&self.stateless_reset_key, connection_id)
+    }
+
+    /// Registers the connection `connection_id`, returning a `Stream` of the packets destined for
+    /// it.
+    ///
+    /// The connection is deregistered once the `Stream` is dropped.
+    pub fn incoming_stream(
+        this: &Arc<Self>,
+        connection_id: ConnectionId,
+        remote_address: SocketAddr,
+    ) -> Result<IncomingPackets> {
+        this.register_connection(connection_id, remote_address)?;
+
+        Ok(IncomingPackets {
+            packet_dispatcher: this.clone(),
+            connection_id,
+        })
+    }
+
+    /// A `Sink` which sends packets through the shared UDP socket.
+    pub fn outgoing_sink(this: &Arc<Self>) -> OutgoingPackets {
+        OutgoingPackets {
+            packet_dispatcher: this.clone(),
+        }
+    }
+
+    pub fn contains_connection(&self, connection_id: ConnectionId) -> bool {
+        let connections = self.connections
+            .lock()
+            .expect("failed to lock connections");
+
+        connections.incoming_packet_stores.contains_key(&connection_id)
+    }
+
+    fn register_connection(
+        &self,
+        connection_id: ConnectionId,
+        remote_address: SocketAddr,
+    ) -> Result<()> {
+        let mut connections = self.connections
+            .lock()
+            .expect("failed to lock connections");
+
+        if connections.incoming_packet_stores.contains_key(&connection_id)
+            || !connections
+                .connection_map
+                .insert(connection_id, self.local_address, remote_address)
+        {
+            bail!(ErrorKind::ConnectionIdAlreadyInUse(connection_id));
+        }
+
+        connections.incoming_packet_stores.insert(
+            connection_id,
+            IncomingPacketStore::with_capacity(MAX_PENDING_PACKETS_PER_CONNECTION),
+        );
+
+        debug!(
+            "registered connection {:?} for {:?}",
+            connection_id, remote_address
+        );
+
+        Ok(())
+    }
+
+    fn deregister_connection(&self, connection_id: ConnectionId) {
+        let mut connections = self.connections
+            .lock()
+            .expect("failed to lock connections");
+
+        connections.connection_map.remove_connection(connection_id);
+        self.header_protection_keys.remove(connection_id);
+        self.stateless_reset_tokens.remove(connection_id);
+
+        let header_protection_keys = &self.header_protection_keys;
+        connections
+            .issued_connection_ids
+            .retain(|&issued_connection_id, &mut issued_to| {
+                if issued_to == connection_id {
+                    header_protection_keys.remove(issued_connection_id);
+                }
+                issued_to != connection_id
+            });
+        if connections
+            .incoming_packet_stores
+            .remove(&connection_id)
+            .is_some()
+        {
+            debug!("deregistered connection {:?}", connection_id);
+        }
+    }
+
+    /// Issues another connection id for the registered connection `connection_id`, once its
+    /// handshake has completed. Packets addressed with it are dispatched to the connection.
+    ///
+    /// # Returns
+    /// The new connection id along with the token ending the stateless resets sent for it.
+    fn issue_connection_id(
+        &self,
+        connection_id: ConnectionId,
+    ) -> Result<(ConnectionId, StatelessResetToken)> {
+        let mut connections = self.connections
+            .lock()
+            .expect("failed to lock connections");
+
+        let issued_connection_id = loop {
+            let issued_connection_id = ConnectionId::generate_with_len(self.connection_id_len)?;
+
+            if !connections
+                .incoming_packet_stores
+                .contains_key(&issued_connection_id)
+                && !connections
+                    .issued_connection_ids
+                    .contains_key(&issued_connection_id)
+            {
+                break issued_connection_id;
+            }
+        };
+
+        connections
+            .issued_connection_ids
+            .insert(issued_connection_id, connection_id);
+
+        // short header packets are protected with the same key whichever id addresses them
+        if let Some(header_protection_key) = self.header_protection_keys.get(connection_id) {
+            self.header_protection_keys
+                .insert(issued_connection_id, header_protection_key);
+        }
+
+        debug!(
+            "issued connection id {:?} for connection {:?}",
+            issued_connection_id, connection_id
+        );
+
+        Ok((
+            issued_connection_id,
+            self.stateless_reset_token(issued_connection_id),
+        ))
+    }
+
+    /// Finds the registered connection `incoming_packet` is destined for.
+    fn find_connection_id(
+        &self,
+        connections: &Connections,
+        incoming_packet: &IncomingPacket,
+    ) -> Option<ConnectionId> {
+        let packet_header = &incoming_packet.packet_header;
+
+        if let Some(destination_connection_id) = packet_header.destination_connection_id() {
+            if connections
+                .incoming_packet_stores
+                .contains_key(&destination_connection_id)
+            {
+                return Some(destination_connection_id);
+            }
+
+            if let Some(&connection_id) = connections
+                .issued_connection_ids
+                .get(&destination_connection_id)
+            {
+                return Some(connection_id);
+            }
+        }
+
+        // short header packets may omit the connection id, these can only be matched by address
+        if let PacketHeader::Short(_) = packet_header {
+            match connections
+                .connection_map
+                .get_connection_id(self.local_address, incoming_packet.source_address)
+            {
+                Some(AddressConnectionIds::Single(connection_id)) => return Some(connection_id),
+                Some(AddressConnectionIds::Multiple(_)) => debug!(
+                    "packet from {:?} matches multiple connections",
+                    incoming_packet.source_address
+                ),
+                None => {}
+            }
+        }
+
+        None
+    }
+
+    /// Queues `incoming_packet` for the connection it is destined for.
+    ///
+    /// Initial packets and packets in versions we do not support for unknown connections are
+    /// queued to be accepted as new connections, along with 0-RTT packets which may have
+    /// overtaken the creation of their connection. All other packets for unknown connections are
+    /// discarded.
+    pub fn dispatch_incoming_packet(&self, incoming_packet: IncomingPacket) {
+        let source_address = incoming_packet.source_address;
+
+        let incoming_packet = {
+            let mut connections = self.connections
+                .lock()
+                .expect("failed to lock connections");
+
+            match self.find_connection_id(&connections, &incoming_packet) {
+                Some(connection_id) => {
+                    let incoming_packet_store = connections
+                        .incoming_packet_stores
+                        .get_mut(&connection_id)
+                        .expect("the found connection should have an incoming packet store");
+
+                    trace!(
+                        "dispatching packet from {:?} to connection {:?}",
+                        source_address,
+                        connection_id
+                    );
+                    if !incoming_packet_store.push_packet(incoming_packet) {
+                        warn!(
+                            "discarded packet from {:?} as the queue for connection {:?} is full",
+                            source_address, connection_id
+                        );
+                    }
+
+                    return;
+                }
+                None => incoming_packet,
+            }
+        };
+
+        if matches!(
+            incoming_packet.packet_header,
+            PacketHeader::Long(LongHeader {
+                packet_type: LongHeaderPacketType::Initial,
+                ..
+            })
+                | PacketHeader::Long(LongHeader {
+                    packet_type: LongHeaderPacketType::ZeroRttProtected,
+                    ..
+                })
+                | PacketHeader::UnsupportedVersion(_)
+        ) {
+            trace!(
+                "queueing packet from {:?} for a new connection",
+                source_address
+            );
+
+            let mut new_connection_packets = self.new_connection_packets
+                .lock()
+                .expect("failed to lock new_connection_packets");
+            if !new_connection_packets.push_packet(incoming_packet) {
+                warn!(
+                    "discarded initial packet from {:?} as too many connections are pending",
+                    source_address
+                );
+            }
+        } else {
+            if let PacketHeader::UnknownConnection(unknown_connection) =
+                &incoming_packet.packet_header
+            {
+                match self.stateless_reset(source_address, unknown_connection) {
+                    Ok(Some(stateless_reset)) => {
+                        let mut pending_stateless_resets = self.pending_stateless_resets
+                            .lock()
+                            .expect("failed to lock pending_stateless_resets");
+                        pending_stateless_resets.push(stateless_reset);
+                        return;
+                    }
+                    Ok(None) => {}
+                    Err(error) => warn!("failed to create stateless reset: {}", error),
+                }
+            }
+
+            warn!(
+                "discarded packet from {:?} for an unknown connection",
+                source_address
+            );
+        }
+    }
+
+    /// Creates the stateless reset which tells the endpoint at `destination_address` that the
+    /// connection of `unknown_connection` no longer exists.
+    ///
+    /// # Returns
+    /// `None` when no stateless reset should be sent, as the packet was too short to answer with
+    /// a shorter one or too many have been sent recently.
+    fn stateless_reset(
+        &self,
+        destination_address: SocketAddr,
+        unknown_connection: &UnknownConnectionPacket,
+    ) -> Result<Option<OutgoingPacket>> {
+        let connection_id = match unknown_connection.destination_connection_id {
+            Some(connection_id) => connection_id,
+            None => return Ok(None),
+        };
+
+        // a stateless reset is always shorter than the packet it answers, so an endpoint
+        // answering it with another stateless reset eventually gives up
+        let len = (unknown_connection.len - 1).min(MAX_STATELESS_RESET_LEN);
+
+        // the reset looks like a short header packet with a random connection id and packet
+        // number, ending in the token
+        let header_len = 1 + self.connection_id_len + 1;
+        if len < header_len + StatelessResetToken::LEN
+            || len < StatelessResetToken::MIN_DATAGRAM_LEN
+        {
+            debug!(
+                "not answering packet of {} bytes from {:?} with a stateless reset",
+                unknown_connection.len, destination_address
+            );
+            return Ok(None);
+        }
+
+        {
+            let mut stateless_reset_limiter = self.stateless_reset_limiter
+                .lock()
+                .expect("failed to lock stateless_reset_limiter");
+            if !stateless_reset_limiter.try_acquire(Instant::now()) {
+                debug!(
+                    "not sending stateless reset to {:?} as too many have been sent",
+                    destination_address
+                );
+                return Ok(None);
+            }
+        }
+
+        let mut rng =
+            OsRng::new().chain_err(|| ErrorKind::FailedToCreateCryptographicRandomNumberGenerator)?;
+
+        let packet_header = PacketHeader::Short(ShortHeader {
+            key_phase: rng.gen(),
+            destination_connection_id: Some(ConnectionId::generate_with_rng_and_len(
+                &mut rng,
+                self.connection_id_len,
+            )),
+            partial_packet_number: PartialPacketNumber::from_truncated(
+                rng.gen(),
+                PartialPacketNumberLength::OneByte,
+            ),
+            wire_format: WireFormat::V1,
+        });
+
+        let mut data = vec![0; len - header_len - StatelessResetToken::LEN];
+        rng.fill_bytes(&mut data);
+        data.extend_from_slice(self.stateless_reset_token(connection_id).bytes());
+
+        debug!(
+            "answering packet for unknown connection {:?} from {:?} with a stateless reset",
+            connection_id, destination_address
+        );
+
+        Ok(Some(OutgoingPacket {
+            destination_address,
+            packet_header,
+            data: Bytes::from(data),
+            encryption_level: EncryptionLevel::Unencrypted,
+            header_protection_key: None,
+        }))
+    }
+
+    /// Sends the stateless resets created while reading the socket, they are dropped if the
+    /// socket is busy as the remote endpoint keeps sending packets which would be answered.
+    fn send_stateless_resets(&self, framed: &mut UdpFramed<PacketCodec>) -> Result<()> {
+        let stateless_resets: Vec<_> = {
+            let mut pending_stateless_resets = self.pending_stateless_resets
+                .lock()
+                .expect("failed to lock pending_stateless_resets");
+            pending_stateless_resets.drain(..).collect()
+        };
+
+        if stateless_resets.is_empty() {
+            return Ok(());
+        }
+
+        for stateless_reset in stateless_resets {
+            let destination_address = stateless_reset.destination_address;
+
+            match framed
+                .start_send(stateless_reset)
+                .chain_err(|| ErrorKind::FailedToSendPacketToUdpSocket)?
+            {
+                AsyncSink::Ready => debug!("sent stateless reset to {:?}", destination_address),
+                AsyncSink::NotReady(_) => debug!(
+                    "dropped stateless reset to {:?} as the socket is busy",
+                    destination_address
+                ),
+            }
+        }
+
+        framed
+            .poll_complete()
+            .chain_err(|| ErrorKind::FailedToSendPacketToUdpSocket)?;
+
+        Ok(())
+    }
+
+    /// Reads every packet currently available from the UDP socket and dispatches them.
+    fn poll_dispatch(&self) -> Result<()> {
+        let mut framed = self.framed.lock().expect("failed to lock framed");
+
+        loop {
+            match framed.0.poll() {
+                Ok(Async::Ready(Some(incoming_packets))) => {
+                    for incoming_packet in incoming_packets {
+                        self.dispatch_incoming_packet(incoming_packet);
+                    }
+                }
+                Ok(Async::Ready(None)) => unreachable!("the packets stream should never end"),
+                Ok(Async::NotReady) => {
+                    // the connections are unaffected by stateless resets which are not sent
+                    if let Err(error) = self.send_stateless_resets(&mut framed.0) {
+                        warn!("failed to send stateless resets: {}", error);
+                    }
+
+                    return Ok(());
+                }
+                Err(error) => {
+                    // a single malformed datagram should not affect any other connection
+                    warn!("discarded datagram which could not be decoded: {}", error);
+                }
+            }
+        }
+    }
+
+    pub fn has_new_connection_packets(&self) -> bool {
+        let new_connection_packets = self.new_connection_packets
+            .lock()
+            .expect("failed to lock new_connection_packets");
+
+        !new_connection_packets.is_empty()
+    }
+
+    /// Polls for the next packet which should establish a new connection.
+    pub fn poll_new_connection_packet(&self) -> Poll<IncomingPacket, Error> {
+        self.poll_dispatch()?;
+
+        let mut new_connection_packets = self.new_connection_packets
+            .lock()
+            .expect("failed to lock new_connection_packets");
+
+        Ok(match new_connection_packets.pop_packet_or_park() {
+            Some(incoming_packet) => Async::Ready(incoming_packet),
+            None => Async::NotReady,
+        })
+    }
+
+    fn poll_incoming_packets(
+        &self,
+        connection_id: ConnectionId,
+    ) -> Poll<SmallVec<[IncomingPacket; 1]>, Error> {
+        self.poll_dispatch()
+            .chain_err(|| ErrorKind::FailedToReadIncomingPacket(connection_id))?;
+
+        let mut connections = self.connections
+            .lock()
+            .expect("failed to lock connections");
+
+        let incoming_packet_store = connections
+            .incoming_packet_stores
+            .get_mut(&connection_id)
+            .ok_or_else(|| ErrorKind::UnknownConnectionId(connection_id))?;
+
+        let incoming_packets = incoming_packet_store.take_packets_or_park();
+        if incoming_packets.is_empty() {
+            Ok(Async::NotReady)
+        } else {
+            Ok(Async::Ready(incoming_packets))
+        }
+    }
+}
+
+/// The packets received for a single connection.
+#[derive(Debug)]
+pub struct IncomingPackets {
+    packet_dispatcher: Arc<PacketDispatcher>,
+    connection_id: ConnectionId,
+}
+
+impl Stream for IncomingPackets {
+    type Item = SmallVec<[IncomingPacket; 1]>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let incoming_packets = try_ready!(
+            self.packet_dispatcher
+                .poll_incoming_packets(self.connection_id)
+        );
+
+        Ok(Async::Ready(Some(incoming_packets)))
+    }
+}
+
+impl IncomingPackets {
+    /// Issues another connection id the connection may be addressed with, along with the token
+    /// ending the stateless resets sent for it.
+    pub fn issue_connection_id(&self) -> Result<(ConnectionId, StatelessResetToken)> {
+        self.packet_dispatcher
+            .issue_connection_id(self.connection_id)
+    }
+}
+
+impl Drop for IncomingPackets {
+    fn drop(&mut self) {
+        self.packet_dispatcher
+            .deregister_connection(self.connection_id);
+    }
+}
+
+/// Sends packets through the UDP socket shared by all connections.
+#[derive(Debug, Clone)]
+pub struct OutgoingPackets {
+    packet_dispatcher: Arc<PacketDispatcher>,
+}
+
+impl Sink for OutgoingPackets {
+    type SinkItem = OutgoingPacket;
+    type SinkError = Error;
+
+    fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
+        let mut framed = self.packet_dispatcher
+            .framed
+            .lock()
+            .expect("failed to lock framed");
+
+        let async_sink = framed
             .0
-            .get_ref()
-            .local_addr()
-            .chain_err(|| ErrorKind::FailedToGetLocalAddress)
+            .start_send(item)
+            .chain_err(|| ErrorKind::FailedToSendPacketToUdpSocket)?;
+
+        Ok(async_sink)
     }
 
-    // pub fn incoming_stream(
-    //     &self,
-    //     connection_id: ConnectionId,
-    // ) -> Box<Stream<Item = Packet, Error = Error> + Send> {
+    fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
+        let mut framed = self.packet_dispatcher
+            .framed
+            .lock()
+            .expect("failed to lock framed");
 
-    //     unimplemented!()
-    // }
+        framed
+            .0
+            .poll_complete()
+            .chain_err(|| ErrorKind::FailedToSendPacketToUdpSocket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketDispatcher;
+    use packets::{PacketHeader, PartialPacketNumberLength, UnknownConnectionPacket};
+    use protocol::{ConnectionId, StatelessResetToken, WireFormat, Writable};
+    use tokio_core::net::UdpSocket;
+    use tokio_core::reactor::Core;
+
+    #[test]
+    fn stateless_reset_is_shorter_than_the_packet_it_answers() {
+        let core = Core::new().unwrap();
+        let udp_socket =
+            UdpSocket::bind(&"127.0.0.1:0".parse().unwrap(), &core.handle()).unwrap();
+        let packet_dispatcher =
+            PacketDispatcher::new(udp_socket, WireFormat::V1, 8, [1; 32], 1000).unwrap();
+        let destination_address = "127.0.0.1:4433".parse().unwrap();
 
-    // pub fn outgoing_sink(&self, connection_id: ConnectionId) -> Box<Sink<Packet, Error> + Send> {
+        // the random packet numbers cover both halves of the byte
+        for len in (StatelessResetToken::MIN_DATAGRAM_LEN..200).step_by(3) {
+            let unknown_connection = UnknownConnectionPacket {
+                destination_connection_id: Some(ConnectionId::generate_with_len(8).unwrap()),
+                len,
+            };
 
-    //     unimplemented!()
-    // }
+            let stateless_reset = match packet_dispatcher
+                .stateless_reset(destination_address, &unknown_connection)
+                .unwrap()
+            {
+                Some(stateless_reset) => stateless_reset,
+                None => continue,
+            };
+
+            let packet_header_len = stateless_reset.packet_header.bytes().unwrap().len();
+            assert_eq!(packet_header_len, 1 + 8 + 1);
+            assert!(packet_header_len + stateless_reset.data.len() < len);
+
+            if let PacketHeader::Short(short_header) = stateless_reset.packet_header {
+                assert_eq!(
+                    short_header.partial_packet_number.len(),
+                    PartialPacketNumberLength::OneByte
+                );
+            }
+        }
+    }
 }
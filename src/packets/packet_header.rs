@@ -1,8 +1,10 @@
+use bytes::Bytes;
 use conv::ValueFrom;
 use errors::*;
-use packets::{LongHeader, LongHeaderPacketType, PacketNumber, PartialPacketNumber, ShortHeader,
-              VersionNegotiationPacket};
-use protocol::{ConnectionId, Readable, VarInt, Version, Writable};
+use packets::{LongHeader, LongHeaderPacketType, PacketNumber, PartialPacketNumber,
+              PartialPacketNumberLength, RetryPacket, ShortHeader, StatelessResetPacket,
+              UnknownConnectionPacket, UnsupportedVersionPacket, VersionNegotiationPacket};
+use protocol::{ConnectionId, Readable, VarInt, Version, WireFormat, Writable};
 use std::io::{Read, Write};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -10,6 +12,13 @@ pub enum PacketHeader {
     Long(LongHeader),
     Short(ShortHeader),
     VersionNegotiation(VersionNegotiationPacket),
+    Retry(RetryPacket),
+    /// Only ever read, servers answer these with Version Negotiation packets.
+    UnsupportedVersion(UnsupportedVersionPacket),
+    /// Only ever read, the connection it is for fails.
+    StatelessReset(StatelessResetPacket),
+    /// Only ever read, servers answer these with stateless resets.
+    UnknownConnection(UnknownConnectionPacket),
 }
 
 impl PacketHeader {
@@ -20,6 +29,16 @@ impl PacketHeader {
             PacketHeader::VersionNegotiation(version_negotiation) => {
                 version_negotiation.destination_connection_id
             }
+            PacketHeader::Retry(retry) => retry.destination_connection_id,
+            PacketHeader::UnsupportedVersion(unsupported_version) => {
+                unsupported_version.destination_connection_id
+            }
+            // routes the reset to the connection it is for, rather than the random connection id
+            // the remote endpoint put in its place
+            PacketHeader::StatelessReset(stateless_reset) => Some(stateless_reset.connection_id),
+            PacketHeader::UnknownConnection(unknown_connection) => {
+                unknown_connection.destination_connection_id
+            }
         }
     }
 
@@ -30,6 +49,45 @@ impl PacketHeader {
             PacketHeader::VersionNegotiation(version_negotiation) => {
                 version_negotiation.source_connection_id
             }
+            PacketHeader::Retry(retry) => retry.source_connection_id,
+            PacketHeader::UnsupportedVersion(unsupported_version) => {
+                unsupported_version.source_connection_id
+            }
+            PacketHeader::StatelessReset(_) | PacketHeader::UnknownConnection(_) => None,
+        }
+    }
+
+    pub fn partial_packet_number(&self) -> Option<PartialPacketNumber> {
+        match self {
+            PacketHeader::Long(long_header) => Some(long_header.partial_packet_number),
+            PacketHeader::Short(short_header) => Some(short_header.partial_packet_number),
+            PacketHeader::VersionNegotiation(_)
+            | PacketHeader::Retry(_)
+            | PacketHeader::UnsupportedVersion(_)
+            | PacketHeader::StatelessReset(_)
+            | PacketHeader::UnknownConnection(_) => None,
+        }
+    }
+
+    /// The `WireFormat` this header is encoded in.
+    pub fn wire_format(&self) -> WireFormat {
+        match self {
+            PacketHeader::Long(long_header) => long_header
+                .version
+                .wire_format()
+                .expect("long headers are only created for supported versions"),
+            PacketHeader::Short(short_header) => short_header.wire_format,
+            PacketHeader::VersionNegotiation(version_negotiation) => {
+                version_negotiation.wire_format
+            }
+            PacketHeader::Retry(retry) => retry
+                .version
+                .wire_format()
+                .expect("retry packets are only created for supported versions"),
+            // the version independent fields are laid out as in version 1
+            PacketHeader::UnsupportedVersion(_) => WireFormat::V1,
+            // these are only read in place of version 1 short headers
+            PacketHeader::StatelessReset(_) | PacketHeader::UnknownConnection(_) => WireFormat::V1,
         }
     }
 
@@ -37,7 +95,11 @@ impl PacketHeader {
         match self {
             PacketHeader::Long(long_header) => Some(long_header.payload_length),
             PacketHeader::Short(_) => None,
-            PacketHeader::VersionNegotiation(_) => Some(0u32.into()),
+            PacketHeader::VersionNegotiation(_) | PacketHeader::Retry(_) => Some(0u32.into()),
+            // nothing after the connection ids can be understood
+            PacketHeader::UnsupportedVersion(_)
+            | PacketHeader::StatelessReset(_)
+            | PacketHeader::UnknownConnection(_) => None,
         }
     }
 }
@@ -61,15 +123,260 @@ fn read_connection_id<R: Read>(reader: &mut R, length_flags: u8) -> Result<Optio
         // Non-zero encoded lengths are increased by 3 to get the full length of the connection ID
         let length = length_flags + 3;
 
-        let connection_id = ConnectionId::read(&mut reader.take(length.into()))?;
+        let connection_id = ConnectionId::read_with_len(reader, length.into())?;
+
+        Ok(Some(connection_id))
+    }
+}
+
+/// Gets the 4 bits which encode the length of `connection_id` in draft 08, only lengths of 0 or
+/// 4 to 18 bytes can be encoded.
+fn draft_08_connection_id_length_flags(connection_id: &Option<ConnectionId>) -> Result<u8> {
+    match connection_id {
+        Some(connection_id) if !connection_id.is_empty() => {
+            let length = connection_id.len();
+            if length < 4 || length > 18 {
+                bail!(ErrorKind::ConnectionIdLengthIsNotSupportedInWireFormat(
+                    length,
+                    WireFormat::Draft08
+                ));
+            }
+
+            Ok((length - 3) as u8)
+        }
+        _ => Ok(0),
+    }
+}
+
+/// The fixed bit which must be set in every version 1 packet header.
+const V1_FIXED_BIT: u8 = 0x40;
+const V1_KEY_PHASE: u8 = 0x04;
+const V1_PACKET_NUMBER_LENGTH_MASK: u8 = 0x03;
+
+const V1_LONG_PACKET_TYPE_INITIAL: u8 = 0x00;
+const V1_LONG_PACKET_TYPE_ZERO_RTT_PROTECTED: u8 = 0x01;
+const V1_LONG_PACKET_TYPE_HANDSHAKE: u8 = 0x02;
+const V1_LONG_PACKET_TYPE_RETRY: u8 = 0x03;
+
+/// The bits `packet_type`, one of the version 1 codepoints, is encoded as in `version`. Version 2
+/// rotates the codepoints of version 1 (RFC 9369 section 3.2).
+fn encode_v1_long_packet_type(packet_type: u8, version: Version) -> u8 {
+    if version == Version::V2 {
+        (packet_type + 1) & 0x03
+    } else {
+        packet_type
+    }
+}
+
+/// The version 1 codepoint of the long header packet type encoded in `flags` in `version`.
+fn decode_v1_long_packet_type(flags: u8, version: Version) -> u8 {
+    let packet_type = (flags >> 4) & 0x03;
+
+    if version == Version::V2 {
+        (packet_type + 3) & 0x03
+    } else {
+        packet_type
+    }
+}
+
+fn read_v1_connection_id<R: Read>(reader: &mut R) -> Result<Option<ConnectionId>> {
+    let length = u8::read(reader)?;
+
+    if length == 0 {
+        Ok(None)
+    } else {
+        let connection_id = ConnectionId::read_with_len(reader, length.into())?;
 
         Ok(Some(connection_id))
     }
 }
 
+fn write_v1_connection_id<W: Write>(
+    writer: &mut W,
+    connection_id: &Option<ConnectionId>,
+) -> Result<()> {
+    match connection_id {
+        Some(connection_id) => {
+            u8::value_from(connection_id.bytes().len())
+                .chain_err(|| ErrorKind::FailedToWriteConnectionId(*connection_id))?
+                .write(writer)?;
+            connection_id.write(writer)
+        }
+        None => 0u8.write(writer),
+    }
+}
+
+fn v1_packet_number_length(flags: u8) -> PartialPacketNumberLength {
+    PartialPacketNumberLength::from_byte_len(((flags & V1_PACKET_NUMBER_LENGTH_MASK) + 1).into())
+        .expect("there should only be 2 bits for the packet number length")
+}
+
+fn v1_packet_number_length_flags(partial_packet_number: PartialPacketNumber) -> u8 {
+    let byte_len = partial_packet_number.len().byte_len();
+
+    u8::value_from(byte_len - 1).expect("the packet number length should fit in 2 bits")
+}
+
 #[derive(Debug)]
 pub struct PacketHeaderReadContext {
-    pub has_connection_id: bool,
+    /// Short headers do not indicate the length of their destination connection id, it is
+    /// expected to be this length, a length of 0 means there is no connection id.
+    pub connection_id_len: usize,
+    /// Short header and version negotiation packets do not indicate their version, they are read
+    /// in this format.
+    pub wire_format: WireFormat,
+}
+
+fn read_version_negotiation<R: Read>(
+    reader: &mut R,
+    wire_format: WireFormat,
+    dcil_scil: Option<u8>,
+) -> Result<VersionNegotiationPacket> {
+    let (destination_connection_id, source_connection_id) = match dcil_scil {
+        Some(dcil_scil) => (
+            read_connection_id(reader, (dcil_scil >> 4) & 0xf)?,
+            read_connection_id(reader, dcil_scil & 0xf)?,
+        ),
+        None => (read_v1_connection_id(reader)?, read_v1_connection_id(reader)?),
+    };
+
+    let supported_versions = Version::collect(reader)?;
+
+    Ok(VersionNegotiationPacket {
+        destination_connection_id,
+        source_connection_id,
+        supported_versions,
+        wire_format,
+    })
+}
+
+fn read_v1_long_header<R: Read>(reader: &mut R, flags: u8, version: Version) -> Result<LongHeader> {
+    if flags & V1_FIXED_BIT == 0 {
+        bail!(ErrorKind::FixedBitIsNotSet);
+    }
+
+    let destination_connection_id = read_v1_connection_id(reader)?;
+    let source_connection_id = read_v1_connection_id(reader)?;
+
+    let packet_type = match decode_v1_long_packet_type(flags, version) {
+        V1_LONG_PACKET_TYPE_INITIAL => LongHeaderPacketType::Initial,
+        V1_LONG_PACKET_TYPE_ZERO_RTT_PROTECTED => LongHeaderPacketType::ZeroRttProtected,
+        V1_LONG_PACKET_TYPE_HANDSHAKE => LongHeaderPacketType::Handshake,
+        V1_LONG_PACKET_TYPE_RETRY => unreachable!("retry packets are read by read_v1_retry_packet"),
+        _ => unreachable!("there should only be 2 bits for the packet type"),
+    };
+
+    let token = if packet_type == LongHeaderPacketType::Initial {
+        let token_length = VarInt::read(reader)?;
+        Bytes::read(&mut reader.take(token_length.into()))?
+    } else {
+        Bytes::new()
+    };
+
+    // the length covers both the packet number and the payload
+    let length = VarInt::read(reader)?;
+
+    let packet_number_length = v1_packet_number_length(flags);
+    let partial_packet_number = PartialPacketNumber::read_truncated(reader, packet_number_length)?;
+
+    let payload_length = u64::from(length)
+        .checked_sub(packet_number_length.byte_len() as u64)
+        .ok_or_else(|| Error::from(ErrorKind::PacketLengthIsShorterThanPacketNumber))?;
+    let payload_length = VarInt::value_from(payload_length)?;
+
+    Ok(LongHeader {
+        packet_type,
+        version,
+        destination_connection_id,
+        source_connection_id,
+        token,
+        payload_length,
+        partial_packet_number,
+    })
+}
+
+fn is_v1_retry_packet(flags: u8, version: Version) -> bool {
+    decode_v1_long_packet_type(flags, version) == V1_LONG_PACKET_TYPE_RETRY
+}
+
+fn read_v1_retry_packet<R: Read>(
+    reader: &mut R,
+    flags: u8,
+    version: Version,
+) -> Result<RetryPacket> {
+    if flags & V1_FIXED_BIT == 0 {
+        bail!(ErrorKind::FixedBitIsNotSet);
+    }
+
+    let destination_connection_id = read_v1_connection_id(reader)?;
+    let source_connection_id = read_v1_connection_id(reader)?;
+
+    // retry packets have no length, the token fills the rest of the datagram up to the tag
+    let mut retry_token = Bytes::read(reader)?;
+    if retry_token.len() < RetryPacket::INTEGRITY_TAG_LEN {
+        bail!(ErrorKind::RetryPacketIsTooShort);
+    }
+    let integrity_tag = retry_token.split_off(retry_token.len() - RetryPacket::INTEGRITY_TAG_LEN);
+
+    Ok(RetryPacket {
+        version,
+        destination_connection_id,
+        source_connection_id,
+        retry_token,
+        integrity_tag,
+    })
+}
+
+/// Reads the fields of a long header packet in `version` which every version shares.
+fn read_unsupported_version_packet<R: Read>(
+    reader: &mut R,
+    version: Version,
+) -> Result<UnsupportedVersionPacket> {
+    let destination_connection_id = read_v1_connection_id(reader)?;
+    let source_connection_id = read_v1_connection_id(reader)?;
+
+    Ok(UnsupportedVersionPacket {
+        version,
+        destination_connection_id,
+        source_connection_id,
+    })
+}
+
+fn read_short_header_connection_id<R: Read>(
+    reader: &mut R,
+    context: &PacketHeaderReadContext,
+) -> Result<Option<ConnectionId>> {
+    if context.connection_id_len == 0 {
+        Ok(None)
+    } else {
+        let connection_id = ConnectionId::read_with_len(reader, context.connection_id_len)?;
+
+        Ok(Some(connection_id))
+    }
+}
+
+fn read_v1_short_header<R: Read>(
+    reader: &mut R,
+    flags: u8,
+    context: &PacketHeaderReadContext,
+) -> Result<ShortHeader> {
+    if flags & V1_FIXED_BIT == 0 {
+        bail!(ErrorKind::FixedBitIsNotSet);
+    }
+
+    let key_phase = flags & V1_KEY_PHASE != 0;
+
+    let destination_connection_id = read_short_header_connection_id(reader, context)?;
+
+    let partial_packet_number =
+        PartialPacketNumber::read_truncated(reader, v1_packet_number_length(flags))?;
+
+    Ok(ShortHeader {
+        key_phase,
+        destination_connection_id,
+        partial_packet_number,
+        wire_format: WireFormat::V1,
+    })
 }
 
 impl Readable for PacketHeader {
@@ -78,69 +385,60 @@ impl Readable for PacketHeader {
         trace!("reading packet header");
 
         trace!("reading packet header flags");
-        let flags = u8::read(reader).chain_err(|| ErrorKind::FailedToReadPacketHeaderFlags)?;
-        let flags = PacketHeaderBitFlags::from_bits_truncate(flags);
+        let raw_flags = u8::read(reader).chain_err(|| ErrorKind::FailedToReadPacketHeaderFlags)?;
+        let flags = PacketHeaderBitFlags::from_bits_truncate(raw_flags);
         debug!("read packet header flags {:?}", flags);
 
         let packet_header = if flags.intersects(LONG_HEADER) {
             let version = Version::read(reader)?;
 
-            let dcil_scil = u8::read(reader)?;
-            let destination_connection_id_flags = (dcil_scil >> 4) & 0xf;
-            let source_connection_id_flags = dcil_scil & 0xf;
-
-            let destination_connection_id =
-                read_connection_id(reader, destination_connection_id_flags)?;
-            let source_connection_id = read_connection_id(reader, source_connection_id_flags)?;
-
             if version.is_version_negotiation() {
-                let supported_versions = Version::collect(reader)?;
+                let dcil_scil = match context.wire_format {
+                    WireFormat::Draft08 => Some(u8::read(reader)?),
+                    WireFormat::V1 => None,
+                };
 
-                PacketHeader::VersionNegotiation(VersionNegotiationPacket {
-                    destination_connection_id,
-                    source_connection_id,
-                    supported_versions,
-                })
+                PacketHeader::VersionNegotiation(read_version_negotiation(
+                    reader,
+                    context.wire_format,
+                    dcil_scil,
+                )?)
             } else {
-                let packet_type_flags =
-                    PacketHeaderBitFlags::from_bits_truncate(flags.bits() & 0x7F);
-                let packet_type = match packet_type_flags {
-                    LONG_PACKET_TYPE_INITIAL => LongHeaderPacketType::Initial,
-                    LONG_PACKET_TYPE_RETRY => LongHeaderPacketType::Retry,
-                    LONG_PACKET_TYPE_HANDSHAKE => LongHeaderPacketType::Handshake,
-                    LONG_PACKET_TYPE_ZERO_RTT_PROTECTED => LongHeaderPacketType::ZeroRttProtected,
-                    _ => bail!(ErrorKind::InvalidLongHeaderPacketType(
-                        packet_type_flags.bits()
-                    )),
-                };
-                let payload_length = VarInt::read(reader)?;
-
-                let partial_packet_number = PartialPacketNumber::read(reader)?;
-
-                PacketHeader::Long(LongHeader {
-                    packet_type,
-                    version,
-                    destination_connection_id,
-                    source_connection_id,
-                    payload_length,
-                    partial_packet_number,
-                })
+                match version.wire_format() {
+                    None => PacketHeader::UnsupportedVersion(read_unsupported_version_packet(
+                        reader, version,
+                    )?),
+                    Some(WireFormat::Draft08) => {
+                        PacketHeader::Long(read_draft_08_long_header(reader, flags, version)?)
+                    }
+                    Some(WireFormat::V1) if is_v1_retry_packet(raw_flags, version) => {
+                        PacketHeader::Retry(read_v1_retry_packet(reader, raw_flags, version)?)
+                    }
+                    Some(WireFormat::V1) => {
+                        PacketHeader::Long(read_v1_long_header(reader, raw_flags, version)?)
+                    }
+                }
             }
         } else {
-            let key_phase = flags.intersects(KEY_PHASE);
-
-            let destination_connection_id = if context.has_connection_id {
-                Some(ConnectionId::read(reader)?)
-            } else {
-                None
-            };
-
-            let partial_packet_number = PartialPacketNumber::read(reader)?;
-            PacketHeader::Short(ShortHeader {
-                key_phase,
-                destination_connection_id,
-                partial_packet_number,
-            })
+            match context.wire_format {
+                WireFormat::Draft08 => {
+                    let key_phase = flags.intersects(KEY_PHASE);
+
+                    let destination_connection_id =
+                        read_short_header_connection_id(reader, context)?;
+
+                    let partial_packet_number = PartialPacketNumber::read(reader)?;
+                    PacketHeader::Short(ShortHeader {
+                        key_phase,
+                        destination_connection_id,
+                        partial_packet_number,
+                        wire_format: WireFormat::Draft08,
+                    })
+                }
+                WireFormat::V1 => {
+                    PacketHeader::Short(read_v1_short_header(reader, raw_flags, context)?)
+                }
+            }
         };
 
         debug!("read packet header {:?}", packet_header);
@@ -149,95 +447,238 @@ impl Readable for PacketHeader {
     }
 }
 
+fn read_draft_08_long_header<R: Read>(
+    reader: &mut R,
+    flags: PacketHeaderBitFlags,
+    version: Version,
+) -> Result<LongHeader> {
+    let dcil_scil = u8::read(reader)?;
+    let destination_connection_id_flags = (dcil_scil >> 4) & 0xf;
+    let source_connection_id_flags = dcil_scil & 0xf;
+
+    let destination_connection_id = read_connection_id(reader, destination_connection_id_flags)?;
+    let source_connection_id = read_connection_id(reader, source_connection_id_flags)?;
+
+    let packet_type_flags = PacketHeaderBitFlags::from_bits_truncate(flags.bits() & 0x7F);
+    let packet_type = match packet_type_flags {
+        LONG_PACKET_TYPE_INITIAL => LongHeaderPacketType::Initial,
+        LONG_PACKET_TYPE_RETRY => LongHeaderPacketType::Retry,
+        LONG_PACKET_TYPE_HANDSHAKE => LongHeaderPacketType::Handshake,
+        LONG_PACKET_TYPE_ZERO_RTT_PROTECTED => LongHeaderPacketType::ZeroRttProtected,
+        _ => bail!(ErrorKind::InvalidLongHeaderPacketType(
+            packet_type_flags.bits()
+        )),
+    };
+    let payload_length = VarInt::read(reader)?;
+
+    let partial_packet_number = PartialPacketNumber::read(reader)?;
+
+    Ok(LongHeader {
+        packet_type,
+        version,
+        destination_connection_id,
+        source_connection_id,
+        token: Bytes::new(),
+        payload_length,
+        partial_packet_number,
+    })
+}
+
 impl Writable for PacketHeader {
     fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
         trace!("writing packet header {:?}", self);
 
-        match self {
-            PacketHeader::VersionNegotiation(version_negotiation) => {
-                let flags = LONG_HEADER;
+        match self.wire_format() {
+            WireFormat::Draft08 => write_draft_08_packet_header(self, writer)?,
+            WireFormat::V1 => write_v1_packet_header(self, writer)?,
+        }
 
-                flags
-                    .bits()
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
+        debug!("written packet header {:?}", self);
 
-                Version::NEGOTIATION.write(writer)?;
+        Ok(())
+    }
+}
 
-                // TODO LH compact the connection id lengths
-                let mut dcil_scil = 0u8;
-                if version_negotiation.destination_connection_id.is_some() {
-                    dcil_scil |= 0xf << 4;
-                }
-                if version_negotiation.source_connection_id.is_some() {
-                    dcil_scil |= 0xf;
-                }
-                dcil_scil.write(writer)?;
+fn write_draft_08_packet_header<W: Write>(
+    packet_header: &PacketHeader,
+    writer: &mut W,
+) -> Result<()> {
+    match packet_header {
+        PacketHeader::VersionNegotiation(version_negotiation) => {
+            let flags = LONG_HEADER;
 
-                version_negotiation.destination_connection_id.write(writer)?;
-                version_negotiation.source_connection_id.write(writer)?;
+            flags
+                .bits()
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
 
-                version_negotiation.supported_versions.write(writer)?;
-            }
-            PacketHeader::Long(long_header) => {
-                let mut flags = LONG_HEADER;
-                flags |= match long_header.packet_type {
-                    LongHeaderPacketType::Initial => LONG_PACKET_TYPE_INITIAL,
-                    LongHeaderPacketType::Retry => LONG_PACKET_TYPE_RETRY,
-                    LongHeaderPacketType::Handshake => LONG_PACKET_TYPE_HANDSHAKE,
-                    LongHeaderPacketType::ZeroRttProtected => LONG_PACKET_TYPE_ZERO_RTT_PROTECTED,
-                };
+            Version::NEGOTIATION.write(writer)?;
 
-                flags
-                    .bits()
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
+            let dcil = draft_08_connection_id_length_flags(
+                &version_negotiation.destination_connection_id,
+            )?;
+            let scil =
+                draft_08_connection_id_length_flags(&version_negotiation.source_connection_id)?;
+            ((dcil << 4) | scil).write(writer)?;
 
-                long_header.version.write(writer)?;
+            version_negotiation.destination_connection_id.write(writer)?;
+            version_negotiation.source_connection_id.write(writer)?;
 
-                // TODO LH compact the connection id lengths
-                let mut dcil_scil = 0u8;
-                if long_header.destination_connection_id.is_some() {
-                    dcil_scil |= 0xf << 4;
-                }
-                if long_header.source_connection_id.is_some() {
-                    dcil_scil |= 0xf;
-                }
-                dcil_scil.write(writer)?;
+            version_negotiation.supported_versions.write(writer)?;
+        }
+        PacketHeader::Long(long_header) => {
+            let mut flags = LONG_HEADER;
+            flags |= match long_header.packet_type {
+                LongHeaderPacketType::Initial => LONG_PACKET_TYPE_INITIAL,
+                LongHeaderPacketType::Retry => LONG_PACKET_TYPE_RETRY,
+                LongHeaderPacketType::Handshake => LONG_PACKET_TYPE_HANDSHAKE,
+                LongHeaderPacketType::ZeroRttProtected => LONG_PACKET_TYPE_ZERO_RTT_PROTECTED,
+            };
+
+            flags
+                .bits()
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
+
+            long_header.version.write(writer)?;
 
-                long_header.destination_connection_id.write(writer)?;
-                long_header.source_connection_id.write(writer)?;
-                long_header.payload_length.write(writer)?;
-                long_header.partial_packet_number.write(writer)?;
+            let dcil =
+                draft_08_connection_id_length_flags(&long_header.destination_connection_id)?;
+            let scil = draft_08_connection_id_length_flags(&long_header.source_connection_id)?;
+            ((dcil << 4) | scil).write(writer)?;
+
+            long_header.destination_connection_id.write(writer)?;
+            long_header.source_connection_id.write(writer)?;
+            long_header.payload_length.write(writer)?;
+            long_header.partial_packet_number.write(writer)?;
+        }
+        PacketHeader::Short(short_header) => {
+            let mut flags = PacketHeaderBitFlags::empty();
+            if short_header.key_phase {
+                flags |= KEY_PHASE;
             }
-            PacketHeader::Short(short_header) => {
-                let mut flags = PacketHeaderBitFlags::empty();
-                if short_header.key_phase {
-                    flags |= KEY_PHASE;
-                }
 
-                (flags.bits() | 0x30)
-                    .write(writer)
-                    .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
+            (flags.bits() | 0x30)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
 
-                short_header.destination_connection_id.write(writer)?;
+            short_header.destination_connection_id.write(writer)?;
 
-                short_header.partial_packet_number.write(writer)?;
+            short_header.partial_packet_number.write(writer)?;
+        }
+        PacketHeader::Retry(_) => bail!(ErrorKind::UnsupportedLongHeaderPacketType(
+            LongHeaderPacketType::Retry
+        )),
+        PacketHeader::UnsupportedVersion(unsupported_version) => {
+            bail!(ErrorKind::UnsupportedVersion(unsupported_version.version))
+        }
+        PacketHeader::StatelessReset(_) | PacketHeader::UnknownConnection(_) => {
+            bail!(ErrorKind::PacketHeaderIsOnlyEverRead)
+        }
+    }
+
+    Ok(())
+}
+
+fn write_v1_packet_header<W: Write>(packet_header: &PacketHeader, writer: &mut W) -> Result<()> {
+    match packet_header {
+        PacketHeader::VersionNegotiation(version_negotiation) => {
+            // the remaining bits are unused, the fixed bit is set to avoid confusion with other
+            // protocols multiplexed on the same port
+            (LONG_HEADER.bits() | V1_FIXED_BIT)
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
+
+            Version::NEGOTIATION.write(writer)?;
+
+            write_v1_connection_id(writer, &version_negotiation.destination_connection_id)?;
+            write_v1_connection_id(writer, &version_negotiation.source_connection_id)?;
+
+            version_negotiation.supported_versions.write(writer)?;
+        }
+        PacketHeader::Long(long_header) => {
+            let packet_type = match long_header.packet_type {
+                LongHeaderPacketType::Initial => V1_LONG_PACKET_TYPE_INITIAL,
+                LongHeaderPacketType::ZeroRttProtected => V1_LONG_PACKET_TYPE_ZERO_RTT_PROTECTED,
+                LongHeaderPacketType::Handshake => V1_LONG_PACKET_TYPE_HANDSHAKE,
+                LongHeaderPacketType::Retry => bail!(ErrorKind::UnsupportedLongHeaderPacketType(
+                    LongHeaderPacketType::Retry
+                )),
+            };
+
+            let flags = LONG_HEADER.bits() | V1_FIXED_BIT
+                | (encode_v1_long_packet_type(packet_type, long_header.version) << 4)
+                | v1_packet_number_length_flags(long_header.partial_packet_number);
+
+            flags
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
+
+            long_header.version.write(writer)?;
+
+            write_v1_connection_id(writer, &long_header.destination_connection_id)?;
+            write_v1_connection_id(writer, &long_header.source_connection_id)?;
+
+            if long_header.packet_type == LongHeaderPacketType::Initial {
+                VarInt::value_from(long_header.token.len())?.write(writer)?;
+                long_header.token.write(writer)?;
             }
+
+            // the length covers both the packet number and the payload
+            let packet_number_length = long_header.partial_packet_number.len().byte_len() as u64;
+            VarInt::value_from(u64::from(long_header.payload_length) + packet_number_length)?
+                .write(writer)?;
+
+            long_header.partial_packet_number.write_truncated(writer)?;
         }
+        PacketHeader::Short(short_header) => {
+            let mut flags =
+                V1_FIXED_BIT | v1_packet_number_length_flags(short_header.partial_packet_number);
+            if short_header.key_phase {
+                flags |= V1_KEY_PHASE;
+            }
 
-        debug!("written packet header {:?}", self);
+            flags
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
 
-        Ok(())
+            short_header.destination_connection_id.write(writer)?;
+
+            short_header.partial_packet_number.write_truncated(writer)?;
+        }
+        PacketHeader::Retry(retry) => {
+            let packet_type = encode_v1_long_packet_type(V1_LONG_PACKET_TYPE_RETRY, retry.version);
+
+            (LONG_HEADER.bits() | V1_FIXED_BIT | (packet_type << 4))
+                .write(writer)
+                .chain_err(|| ErrorKind::FailedToWritePacketHeaderFlags)?;
+
+            retry.version.write(writer)?;
+
+            write_v1_connection_id(writer, &retry.destination_connection_id)?;
+            write_v1_connection_id(writer, &retry.source_connection_id)?;
+
+            retry.retry_token.write(writer)?;
+            retry.integrity_tag.write(writer)?;
+        }
+        PacketHeader::UnsupportedVersion(unsupported_version) => {
+            bail!(ErrorKind::UnsupportedVersion(unsupported_version.version))
+        }
+        PacketHeader::StatelessReset(_) | PacketHeader::UnknownConnection(_) => {
+            bail!(ErrorKind::PacketHeaderIsOnlyEverRead)
+        }
     }
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::PacketHeader;
-    use packets::{LongHeader, LongHeaderPacketType, PacketHeaderReadContext, ShortHeader,
-                  VersionNegotiationPacket};
-    use protocol::{self, ConnectionId, Version};
+    use bytes::Bytes;
+    use packets::{LongHeader, LongHeaderPacketType, PacketHeaderReadContext, PartialPacketNumber,
+                  PartialPacketNumberLength, RetryPacket, ShortHeader, VersionNegotiationPacket};
+    use protocol::{self, ConnectionId, Readable, Version, WireFormat, Writable};
 
     #[test]
     pub fn read_write_version_negotiation_packet_header() {
@@ -245,13 +686,15 @@ mod tests {
             destination_connection_id: Some(ConnectionId::generate().unwrap()),
             source_connection_id: Some(ConnectionId::generate().unwrap()),
             supported_versions: vec![Version::DRAFT_IETF_08],
+            wire_format: WireFormat::Draft08,
         };
         let packet_header = PacketHeader::VersionNegotiation(version_negotiation_packet);
 
         protocol::test_write_read_with_context(
             &packet_header,
             &PacketHeaderReadContext {
-                has_connection_id: true,
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::Draft08,
             },
         ).unwrap();
     }
@@ -263,6 +706,7 @@ mod tests {
             destination_connection_id: Some(ConnectionId::generate().unwrap()),
             source_connection_id: Some(ConnectionId::generate().unwrap()),
             version: Version::DRAFT_IETF_08,
+            token: Bytes::new(),
             partial_packet_number: 5u8.into(),
             payload_length: 654234u32.into(),
         };
@@ -271,7 +715,8 @@ mod tests {
         protocol::test_write_read_with_context(
             &packet_header,
             &PacketHeaderReadContext {
-                has_connection_id: true,
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::Draft08,
             },
         ).unwrap();
     }
@@ -282,13 +727,281 @@ mod tests {
             destination_connection_id: Some(ConnectionId::generate().unwrap()),
             partial_packet_number: 3421u16.into(),
             key_phase: true,
+            wire_format: WireFormat::Draft08,
+        };
+        let packet_header = PacketHeader::Short(short_header);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::Draft08,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_v1_version_negotiation_packet_header() {
+        let version_negotiation_packet = VersionNegotiationPacket {
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            source_connection_id: None,
+            supported_versions: vec![Version::V1, Version::DRAFT_IETF_08],
+            wire_format: WireFormat::V1,
+        };
+        let packet_header = PacketHeader::VersionNegotiation(version_negotiation_packet);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_v1_initial_packet_header() {
+        let long_header = LongHeader {
+            packet_type: LongHeaderPacketType::Initial,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            version: Version::V1,
+            token: Bytes::from(&b"address validation token"[..]),
+            partial_packet_number: PartialPacketNumber::from_truncated(
+                0x01_02_03,
+                PartialPacketNumberLength::ThreeBytes,
+            ),
+            payload_length: 1200u32.into(),
+        };
+        let packet_header = PacketHeader::Long(long_header);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_v2_initial_packet_header() {
+        let long_header = LongHeader {
+            packet_type: LongHeaderPacketType::Initial,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            version: Version::V2,
+            token: Bytes::new(),
+            partial_packet_number: 7u8.into(),
+            payload_length: 1200u32.into(),
+        };
+        let packet_header = PacketHeader::Long(long_header);
+
+        // version 2 Initial packets have the packet type of version 1 0-RTT packets
+        let bytes = packet_header.bytes().unwrap();
+        assert_eq!((bytes[0] >> 4) & 0x03, 0x01);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_v2_retry_packet_header() {
+        let retry_packet = RetryPacket::new(
+            Version::V2,
+            Some(ConnectionId::generate().unwrap()),
+            Some(ConnectionId::generate().unwrap()),
+            Bytes::from(&b"address validation token"[..]),
+            ConnectionId::generate().unwrap(),
+        ).unwrap();
+        let packet_header = PacketHeader::Retry(retry_packet);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_v1_handshake_packet_header() {
+        let long_header = LongHeader {
+            packet_type: LongHeaderPacketType::Handshake,
+            destination_connection_id: None,
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            version: Version::V1,
+            token: Bytes::new(),
+            partial_packet_number: 5u8.into(),
+            payload_length: 654234u32.into(),
+        };
+        let packet_header = PacketHeader::Long(long_header);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_v1_retry_packet_header() {
+        let retry_packet = RetryPacket::new(
+            Version::V1,
+            Some(ConnectionId::generate().unwrap()),
+            Some(ConnectionId::generate_with_len(8).unwrap()),
+            Bytes::from(&b"address validation token"[..]),
+            ConnectionId::generate().unwrap(),
+        ).unwrap();
+        let packet_header = PacketHeader::Retry(retry_packet);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_unsupported_version_packet_header() {
+        let destination_connection_id = ConnectionId::generate().unwrap();
+        let source_connection_id = ConnectionId::generate_with_len(4).unwrap();
+
+        let mut bytes = vec![0xc0, 0x1a, 0x2a, 0x3a, 0x4a];
+        bytes.push(destination_connection_id.len() as u8);
+        destination_connection_id.write(&mut bytes).unwrap();
+        bytes.push(source_connection_id.len() as u8);
+        source_connection_id.write(&mut bytes).unwrap();
+        bytes.extend_from_slice(b"a payload in an unknown format");
+
+        let packet_header = PacketHeader::from_bytes_with_context(
+            &bytes[..],
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+
+        match packet_header {
+            PacketHeader::UnsupportedVersion(unsupported_version) => {
+                assert!(unsupported_version.version.is_force_negotiation());
+                assert_eq!(
+                    unsupported_version.destination_connection_id,
+                    Some(destination_connection_id)
+                );
+                assert_eq!(
+                    unsupported_version.source_connection_id,
+                    Some(source_connection_id)
+                );
+            }
+            packet_header => panic!("unexpected packet header {:?}", packet_header),
+        }
+    }
+
+    #[test]
+    pub fn read_write_v1_short_packet_header() {
+        let short_header = ShortHeader {
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            partial_packet_number: 3421u16.into(),
+            key_phase: true,
+            wire_format: WireFormat::V1,
+        };
+        let packet_header = PacketHeader::Short(short_header);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: ConnectionId::DEFAULT_LEN,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_long_packet_header_with_eight_byte_connection_ids() {
+        let long_header = LongHeader {
+            packet_type: LongHeaderPacketType::Handshake,
+            destination_connection_id: Some(ConnectionId::generate_with_len(8).unwrap()),
+            source_connection_id: Some(ConnectionId::generate_with_len(8).unwrap()),
+            version: Version::DRAFT_IETF_08,
+            token: Bytes::new(),
+            partial_packet_number: 5u8.into(),
+            payload_length: 654234u32.into(),
+        };
+        let packet_header = PacketHeader::Long(long_header);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: 8,
+                wire_format: WireFormat::Draft08,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn write_long_packet_header_with_twenty_byte_connection_id_fails() {
+        let long_header = LongHeader {
+            packet_type: LongHeaderPacketType::Handshake,
+            destination_connection_id: Some(ConnectionId::generate_with_len(20).unwrap()),
+            source_connection_id: None,
+            version: Version::DRAFT_IETF_08,
+            token: Bytes::new(),
+            partial_packet_number: 5u8.into(),
+            payload_length: 654234u32.into(),
+        };
+        let packet_header = PacketHeader::Long(long_header);
+
+        assert!(packet_header.bytes().is_err());
+    }
+
+    #[test]
+    pub fn read_write_v1_initial_packet_header_with_eight_byte_connection_ids() {
+        let long_header = LongHeader {
+            packet_type: LongHeaderPacketType::Initial,
+            destination_connection_id: Some(ConnectionId::generate_with_len(8).unwrap()),
+            source_connection_id: Some(ConnectionId::generate_with_len(20).unwrap()),
+            version: Version::V1,
+            token: Bytes::new(),
+            partial_packet_number: 5u8.into(),
+            payload_length: 1200u32.into(),
+        };
+        let packet_header = PacketHeader::Long(long_header);
+
+        protocol::test_write_read_with_context(
+            &packet_header,
+            &PacketHeaderReadContext {
+                connection_id_len: 8,
+                wire_format: WireFormat::V1,
+            },
+        ).unwrap();
+    }
+
+    #[test]
+    pub fn read_write_v1_short_packet_header_with_eight_byte_connection_id() {
+        let short_header = ShortHeader {
+            destination_connection_id: Some(ConnectionId::generate_with_len(8).unwrap()),
+            partial_packet_number: 3421u16.into(),
+            key_phase: false,
+            wire_format: WireFormat::V1,
         };
         let packet_header = PacketHeader::Short(short_header);
 
         protocol::test_write_read_with_context(
             &packet_header,
             &PacketHeaderReadContext {
-                has_connection_id: true,
+                connection_id_len: 8,
+                wire_format: WireFormat::V1,
             },
         ).unwrap();
     }
@@ -1,8 +1,8 @@
-use conv::{TryFrom, TryInto};
+use conv::TryFrom;
 use errors::*;
 use lz_diet::AdjacentBound;
 use primitives::AbsDelta;
-use protocol::{Readable, Writable};
+use protocol::{Readable, WireFormat, Writable};
 use rand::Rng;
 use smallvec::SmallVec;
 use std::io::{Read, Write};
@@ -73,12 +73,17 @@ impl AdjacentBound for PacketNumber {
 pub enum PartialPacketNumberLength {
     OneByte,
     TwoBytes,
+    /// Only used by version 1, draft-08 has no way to encode three bytes.
+    ThreeBytes,
     FourBytes,
 }
 
 /// This represents a partial packet number consisting of only the lower bytes.
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct PartialPacketNumber(u32);
+pub struct PartialPacketNumber {
+    value: u32,
+    len: PartialPacketNumberLength,
+}
 
 impl PacketNumber {
     pub const MAX: PacketNumber = PacketNumber(4611686018427387903);
@@ -161,69 +166,167 @@ impl From<PacketNumber> for u64 {
 }
 
 impl PartialPacketNumberLength {
+    /// The number of bits of the packet number which can be carried, draft-08 uses the leading
+    /// bits to encode the length.
     pub fn available_bits_len(self) -> u8 {
         match self {
             PartialPacketNumberLength::OneByte => 7,
             PartialPacketNumberLength::TwoBytes => 14,
+            PartialPacketNumberLength::ThreeBytes => 24,
             PartialPacketNumberLength::FourBytes => 30,
         }
     }
 
+    /// The number of bits of the packet number which version 1 carries.
     pub fn encoded_bits_len(self) -> u8 {
         match self {
             PartialPacketNumberLength::OneByte => 8,
             PartialPacketNumberLength::TwoBytes => 16,
+            PartialPacketNumberLength::ThreeBytes => 24,
             PartialPacketNumberLength::FourBytes => 32,
         }
     }
 
+    /// The number of bits of the packet number which are carried in `wire_format`.
+    pub fn bits_len(self, wire_format: WireFormat) -> u8 {
+        match wire_format {
+            WireFormat::Draft08 => self.available_bits_len(),
+            WireFormat::V1 => self.encoded_bits_len(),
+        }
+    }
+
+    pub fn byte_len(self) -> usize {
+        match self {
+            PartialPacketNumberLength::OneByte => 1,
+            PartialPacketNumberLength::TwoBytes => 2,
+            PartialPacketNumberLength::ThreeBytes => 3,
+            PartialPacketNumberLength::FourBytes => 4,
+        }
+    }
+
+    pub fn from_byte_len(byte_len: usize) -> Option<Self> {
+        match byte_len {
+            1 => Some(PartialPacketNumberLength::OneByte),
+            2 => Some(PartialPacketNumberLength::TwoBytes),
+            3 => Some(PartialPacketNumberLength::ThreeBytes),
+            4 => Some(PartialPacketNumberLength::FourBytes),
+            _ => None,
+        }
+    }
+
+    /// The shortest draft-08 length which can carry `value`.
+    fn for_value(value: u32) -> Self {
+        let leading_zeros = value.leading_zeros();
+        if leading_zeros >= 25 {
+            PartialPacketNumberLength::OneByte
+        } else if leading_zeros >= 18 {
+            PartialPacketNumberLength::TwoBytes
+        } else {
+            PartialPacketNumberLength::FourBytes
+        }
+    }
+
     fn threshold(self) -> u64 {
         (2 << (self.available_bits_len() - 1)) - 1
     }
+
+    /// Whether a packet number `diff` after the lowest unacknowledged one can be inferred by the
+    /// receiver, it picks the closest candidate so only half of the window can be used.
+    ///
+    /// The draft-08 window is used for both wire formats so the length of a packet number does
+    /// not depend on the wire format, version 1 simply carries more bits in it.
+    fn can_carry(self, diff: u64) -> bool {
+        diff <= self.threshold() >> 1
+    }
 }
 
 impl PartialPacketNumber {
-    pub const MAX: PartialPacketNumber = PartialPacketNumber(0x3FFFFFFF);
+    pub const MAX: PartialPacketNumber = PartialPacketNumber {
+        value: 0x3FFFFFFF,
+        len: PartialPacketNumberLength::FourBytes,
+    };
+
+    /// Creates a `PartialPacketNumber` from the lowest `len` bytes of `value`, as is encoded by
+    /// version 1.
+    pub fn from_truncated(value: u32, len: PartialPacketNumberLength) -> Self {
+        let value = match len {
+            PartialPacketNumberLength::FourBytes => value,
+            _ => value & ((1 << len.encoded_bits_len()) - 1),
+        };
 
+        PartialPacketNumber { value, len }
+    }
+
+    /// The value of the encoded bits.
+    pub fn value(self) -> u32 {
+        self.value
+    }
+
+    /// Calculates the partial packet number `packet_number` is sent as by version 1.
     pub fn from_packet_number(
         packet_number: PacketNumber,
         lowest_unacknowledged: PacketNumber,
     ) -> Result<PartialPacketNumber> {
-        trace!("calculating partial packet number for packet number {:?} with a lowest acknowledged packet number of {:?}", packet_number, lowest_unacknowledged);
+        Self::from_packet_number_in_format(packet_number, lowest_unacknowledged, WireFormat::V1)
+    }
+
+    /// Calculates the partial packet number `packet_number` is sent as in `wire_format`.
+    pub fn from_packet_number_in_format(
+        packet_number: PacketNumber,
+        lowest_unacknowledged: PacketNumber,
+        wire_format: WireFormat,
+    ) -> Result<PartialPacketNumber> {
+        trace!("calculating partial packet number for packet number {:?} with a lowest acknowledged packet number of {:?} in {:?}", packet_number, lowest_unacknowledged, wire_format);
 
         let diff = packet_number
             .0
             .checked_sub(lowest_unacknowledged.0)
             .ok_or_else(|| Error::from_kind(ErrorKind::FailedToBuildPartialPacketNumber))?;
 
-        let partial_packet_number = if diff <= PartialPacketNumberLength::OneByte.threshold() {
-            (packet_number.0 as u8).into()
-        } else if diff <= PartialPacketNumberLength::TwoBytes.threshold() {
-            (packet_number.0 as u16).into()
-        } else if diff <= PartialPacketNumberLength::FourBytes.threshold() {
-            (packet_number.0 as u32)
-                .try_into()
-                .chain_err(|| ErrorKind::FailedToBuildPartialPacketNumber)?
+        let len = if PartialPacketNumberLength::OneByte.can_carry(diff) {
+            PartialPacketNumberLength::OneByte
+        } else if PartialPacketNumberLength::TwoBytes.can_carry(diff) {
+            PartialPacketNumberLength::TwoBytes
+        } else if PartialPacketNumberLength::FourBytes.can_carry(diff) {
+            PartialPacketNumberLength::FourBytes
         } else {
             bail!(ErrorKind::FailedToBuildPartialPacketNumber)
         };
 
-        debug!("calculated partial packet number {:?} for packet number {:?} with a lowest acknowledged packet number of {:?}", partial_packet_number, packet_number, lowest_unacknowledged);
+        // the length is chosen from the distance to the lowest unacknowledged packet rather than
+        // the truncated value, which may need fewer bytes than the receiver needs to infer it
+        let bits_mask = (1u64 << len.bits_len(wire_format)) - 1;
+        let partial_packet_number = PartialPacketNumber {
+            value: (packet_number.0 & bits_mask) as u32,
+            len,
+        };
+
+        debug!("calculated partial packet number {:?} for packet number {:?} with a lowest acknowledged packet number of {:?} in {:?}", partial_packet_number, packet_number, lowest_unacknowledged, wire_format);
 
         Ok(partial_packet_number)
     }
 
+    /// Infers the packet number of a partial packet number received in version 1.
     pub fn infer_packet_number(
         self,
         largest_acknowledged: Option<PacketNumber>,
     ) -> Result<PacketNumber> {
-        trace!("infering packet number from partial packet number {:?} with a largest acknowledged packet number of {:?}", self, largest_acknowledged);
+        self.infer_packet_number_in_format(largest_acknowledged, WireFormat::V1)
+    }
+
+    /// Infers the packet number of a partial packet number received in `wire_format`.
+    pub fn infer_packet_number_in_format(
+        self,
+        largest_acknowledged: Option<PacketNumber>,
+        wire_format: WireFormat,
+    ) -> Result<PacketNumber> {
+        trace!("infering packet number from partial packet number {:?} with a largest acknowledged packet number of {:?} in {:?}", self, largest_acknowledged, wire_format);
 
         let self_as_integer = u32::from(self) as u64;
 
         let packet_number = if let Some(largest_acknowledged) = largest_acknowledged {
             if let Some(next) = largest_acknowledged.next() {
-                let epochs = largest_acknowledged.epochs(self.len().encoded_bits_len());
+                let epochs = largest_acknowledged.epochs(self.len().bits_len(wire_format));
 
                 let possible_packet_numbers = epochs
                     .into_iter()
@@ -243,25 +346,13 @@ impl PartialPacketNumber {
             PacketNumber(self_as_integer)
         };
 
-        debug!("infered packet number {:?} from partial packet number {:?} with a largest acknowledged packet number of {:?}", packet_number, self, largest_acknowledged);
+        debug!("infered packet number {:?} from partial packet number {:?} with a largest acknowledged packet number of {:?} in {:?}", packet_number, self, largest_acknowledged, wire_format);
 
         Ok(packet_number)
     }
 
     pub fn len(self) -> PartialPacketNumberLength {
-        let leading_zeros = self.0.leading_zeros();
-        if leading_zeros >= 25 {
-            PartialPacketNumberLength::OneByte
-        } else if leading_zeros >= 18 {
-            PartialPacketNumberLength::TwoBytes
-        } else if leading_zeros >= 2 {
-            PartialPacketNumberLength::FourBytes
-        } else {
-            unreachable!(
-                "should be impossible to create a PartialPacketNumber of the value '{}'",
-                self.0
-            );
-        }
+        self.len
     }
 }
 
@@ -269,24 +360,37 @@ impl TryFrom<u32> for PartialPacketNumber {
     type Err = Error;
 
     fn try_from(value: u32) -> Result<PartialPacketNumber> {
-        if value > PartialPacketNumber::MAX.0 {
+        if value > PartialPacketNumber::MAX.value {
             bail!(ErrorKind::ValueExceedsTheMaximumPartialPacketNumberValue(
                 value
             ));
         }
-        Ok(PartialPacketNumber(value))
+        Ok(PartialPacketNumber {
+            value,
+            len: PartialPacketNumberLength::for_value(value),
+        })
     }
 }
 
 impl From<u16> for PartialPacketNumber {
     fn from(value: u16) -> PartialPacketNumber {
-        PartialPacketNumber((value & 0x3FFF) as u32)
+        let value = (value & 0x3FFF) as u32;
+
+        PartialPacketNumber {
+            value,
+            len: PartialPacketNumberLength::for_value(value),
+        }
     }
 }
 
 impl From<u8> for PartialPacketNumber {
     fn from(value: u8) -> PartialPacketNumber {
-        PartialPacketNumber(value as u32)
+        let value = value as u32;
+
+        PartialPacketNumber {
+            value,
+            len: PartialPacketNumberLength::for_value(value),
+        }
     }
 }
 
@@ -304,25 +408,27 @@ impl Readable for PartialPacketNumber {
             _ => unreachable!("there should only be 2 bits"),
         };
 
-        let partial_packet_number = match length {
-            PartialPacketNumberLength::OneByte => first_byte.into(),
+        let value = match length {
+            PartialPacketNumberLength::OneByte => first_byte as u32,
             PartialPacketNumberLength::TwoBytes => {
                 let second_byte =
                     u8::read(reader).chain_err(|| ErrorKind::FailedToReadPartialPacketNumber)?;
-                let value = ((first_byte as u16) << 8) | second_byte as u16;
-                value.into()
+                ((first_byte as u32) << 8) | second_byte as u32
+            }
+            PartialPacketNumberLength::ThreeBytes => {
+                unreachable!("draft-08 partial packet numbers are never three bytes")
             }
             PartialPacketNumberLength::FourBytes => {
                 let second_byte =
                     u8::read(reader).chain_err(|| ErrorKind::FailedToReadPartialPacketNumber)?;
                 let last_2_bytes =
                     u16::read(reader).chain_err(|| ErrorKind::FailedToReadPartialPacketNumber)?;
-                let value = ((first_byte as u32) << 24) | ((second_byte as u32) << 16)
-                    | last_2_bytes as u32;
-                value.try_into()?
+                ((first_byte as u32) << 24) | ((second_byte as u32) << 16) | last_2_bytes as u32
             }
         };
 
+        let partial_packet_number = PartialPacketNumber { value, len: length };
+
         debug!("read partial packet number {:?}", partial_packet_number);
 
         Ok(partial_packet_number)
@@ -333,10 +439,17 @@ impl Writable for PartialPacketNumber {
     fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
         trace!("writing partial packet number {:?}", self);
 
-        match self.len() {
-            PartialPacketNumberLength::OneByte => (0x7f & (self.0 as u8)).write(writer),
-            PartialPacketNumberLength::TwoBytes => (0x8000 | (self.0 as u16)).write(writer),
-            PartialPacketNumberLength::FourBytes => (0xC0000000 | self.0).write(writer),
+        match self.len {
+            PartialPacketNumberLength::OneByte => (0x7f & (self.value as u8)).write(writer),
+            PartialPacketNumberLength::TwoBytes => {
+                (0x8000 | (0x3fff & self.value as u16)).write(writer)
+            }
+            PartialPacketNumberLength::ThreeBytes => {
+                bail!(ErrorKind::FailedToWritePartialPacketNumber)
+            }
+            PartialPacketNumberLength::FourBytes => {
+                (0xC0000000 | (0x3fffffff & self.value)).write(writer)
+            }
         }.chain_err(|| ErrorKind::FailedToWritePartialPacketNumber)?;
 
         debug!("written partial packet number {:?}", self);
@@ -345,9 +458,47 @@ impl Writable for PartialPacketNumber {
     }
 }
 
+impl PartialPacketNumber {
+    /// Writes the truncated packet number in `len` bytes without any length prefix, as is encoded
+    /// by version 1.
+    pub fn write_truncated<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing truncated partial packet number {:?}", self);
+
+        let bytes = self.value.bytes_small::<[u8; 4]>()?;
+        writer
+            .write_all(&bytes[(4 - self.len.byte_len())..])
+            .chain_err(|| ErrorKind::FailedToWritePartialPacketNumber)?;
+
+        debug!("written truncated partial packet number {:?}", self);
+
+        Ok(())
+    }
+
+    /// Reads a truncated packet number of `len` bytes without any length prefix, as is encoded
+    /// by version 1.
+    pub fn read_truncated<R: Read>(reader: &mut R, len: PartialPacketNumberLength) -> Result<Self> {
+        trace!("reading truncated partial packet number of length {:?}", len);
+
+        let mut value = 0u32;
+        for _ in 0..len.byte_len() {
+            let byte = u8::read(reader).chain_err(|| ErrorKind::FailedToReadPartialPacketNumber)?;
+            value = (value << 8) | byte as u32;
+        }
+
+        let partial_packet_number = PartialPacketNumber { value, len };
+
+        debug!(
+            "read truncated partial packet number {:?}",
+            partial_packet_number
+        );
+
+        Ok(partial_packet_number)
+    }
+}
+
 impl From<PartialPacketNumber> for u32 {
     fn from(value: PartialPacketNumber) -> u32 {
-        value.0
+        value.value
     }
 }
 
@@ -431,6 +582,77 @@ mod tests {
         assert_eq!(partial_packet_number, PartialPacketNumber::from(0x7u8));
     }
 
+    #[test]
+    fn partial_packet_number_from_packet_number_keeps_length_for_small_truncated_values() {
+        let lowest_unacknowledged = PacketNumber(0xff00);
+        let packet_number = PacketNumber(0x10000);
+
+        let partial_packet_number =
+            PartialPacketNumber::from_packet_number(packet_number, lowest_unacknowledged).unwrap();
+
+        assert_eq!(partial_packet_number.len(), PartialPacketNumberLength::TwoBytes);
+        assert_eq!(
+            partial_packet_number.infer_packet_number(Some(PacketNumber(0xff00))),
+            Ok(packet_number)
+        );
+    }
+
+    fn test_round_trip_in_format(wire_format: WireFormat) {
+        for i in (0..10000).map(|i| i * 7) {
+            let packet_number = PacketNumber(0xfff0 + i);
+            let lowest_unacknowledged = PacketNumber(0xfff0 + i / 2);
+            let partial_packet_number = PartialPacketNumber::from_packet_number_in_format(
+                packet_number,
+                lowest_unacknowledged,
+                wire_format,
+            ).unwrap();
+
+            let mut bytes = Vec::new();
+            let read = match wire_format {
+                WireFormat::Draft08 => {
+                    partial_packet_number.write(&mut bytes).unwrap();
+                    PartialPacketNumber::read(&mut &bytes[..]).unwrap()
+                }
+                WireFormat::V1 => {
+                    partial_packet_number.write_truncated(&mut bytes).unwrap();
+                    PartialPacketNumber::read_truncated(
+                        &mut &bytes[..],
+                        partial_packet_number.len(),
+                    ).unwrap()
+                }
+            };
+
+            let inferred_packet_number = read.infer_packet_number_in_format(
+                Some(lowest_unacknowledged),
+                wire_format,
+            ).unwrap();
+
+            assert_eq!(packet_number, inferred_packet_number);
+        }
+    }
+
+    #[test]
+    fn draft_08_partial_packet_number_round_trips() {
+        test_round_trip_in_format(WireFormat::Draft08);
+    }
+
+    #[test]
+    fn v1_partial_packet_number_round_trips() {
+        test_round_trip_in_format(WireFormat::V1);
+    }
+
+    #[test]
+    fn v1_partial_packet_number_uses_the_whole_byte() {
+        let partial_packet_number = PartialPacketNumber::from_packet_number_in_format(
+            PacketNumber(0xc5),
+            PacketNumber(0xa0),
+            WireFormat::V1,
+        ).unwrap();
+
+        assert_eq!(partial_packet_number.len(), PartialPacketNumberLength::OneByte);
+        assert_eq!(partial_packet_number.value(), 0xc5);
+    }
+
     #[test]
     fn infer_of_first_packet_returns_correct_packet_number() {
         // Act
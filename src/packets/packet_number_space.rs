@@ -0,0 +1,113 @@
+use packets::{LongHeaderPacketType, PacketHeader};
+use protocol::EncryptionLevel;
+
+/// Packet numbers are allocated and acknowledged independently within each of these spaces.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum PacketNumberSpace {
+    /// Initial packets, protected with the keys derived from the client's connection id.
+    Initial,
+    /// Handshake packets, protected with the handshake keys.
+    Handshake,
+    /// 0-RTT and short header packets.
+    ApplicationData,
+}
+
+impl PacketNumberSpace {
+    pub fn all() -> [PacketNumberSpace; 3] {
+        [
+            PacketNumberSpace::Initial,
+            PacketNumberSpace::Handshake,
+            PacketNumberSpace::ApplicationData,
+        ]
+    }
+
+    pub fn for_encryption_level(encryption_level: EncryptionLevel) -> Self {
+        match encryption_level {
+            EncryptionLevel::Unencrypted => PacketNumberSpace::Initial,
+            EncryptionLevel::NonForwardSecure => PacketNumberSpace::Handshake,
+            EncryptionLevel::ForwardSecure => PacketNumberSpace::ApplicationData,
+        }
+    }
+
+    pub fn encryption_level(self) -> EncryptionLevel {
+        match self {
+            PacketNumberSpace::Initial => EncryptionLevel::Unencrypted,
+            PacketNumberSpace::Handshake => EncryptionLevel::NonForwardSecure,
+            PacketNumberSpace::ApplicationData => EncryptionLevel::ForwardSecure,
+        }
+    }
+
+    /// The space of packets with `packet_header`, `None` for packets without a packet number.
+    pub fn for_packet_header(packet_header: &PacketHeader) -> Option<Self> {
+        match packet_header {
+            PacketHeader::Long(long_header) => match long_header.packet_type {
+                LongHeaderPacketType::Initial => Some(PacketNumberSpace::Initial),
+                LongHeaderPacketType::Handshake => Some(PacketNumberSpace::Handshake),
+                LongHeaderPacketType::ZeroRttProtected => Some(PacketNumberSpace::ApplicationData),
+                LongHeaderPacketType::Retry => None,
+            },
+            PacketHeader::Short(_) => Some(PacketNumberSpace::ApplicationData),
+            PacketHeader::VersionNegotiation(_)
+            | PacketHeader::Retry(_)
+            | PacketHeader::UnsupportedVersion(_)
+            | PacketHeader::StatelessReset(_)
+            | PacketHeader::UnknownConnection(_) => None,
+        }
+    }
+
+    /// The type of the long header packets sent in this space, `None` when short headers are
+    /// sent.
+    pub fn long_header_packet_type(self) -> Option<LongHeaderPacketType> {
+        match self {
+            PacketNumberSpace::Initial => Some(LongHeaderPacketType::Initial),
+            PacketNumberSpace::Handshake => Some(LongHeaderPacketType::Handshake),
+            PacketNumberSpace::ApplicationData => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketNumberSpace;
+    use bytes::Bytes;
+    use packets::{LongHeader, PacketHeader, ShortHeader};
+    use protocol::{ConnectionId, Version, WireFormat};
+
+    #[test]
+    fn encryption_level_round_trips() {
+        for &space in PacketNumberSpace::all().iter() {
+            assert_eq!(
+                PacketNumberSpace::for_encryption_level(space.encryption_level()),
+                space
+            );
+        }
+    }
+
+    #[test]
+    fn for_packet_header_lines_up_with_long_header_packet_type() {
+        for &space in PacketNumberSpace::all().iter() {
+            let packet_header = match space.long_header_packet_type() {
+                Some(packet_type) => PacketHeader::Long(LongHeader {
+                    packet_type,
+                    version: Version::V1,
+                    destination_connection_id: Some(ConnectionId::generate().unwrap()),
+                    source_connection_id: Some(ConnectionId::generate().unwrap()),
+                    token: Bytes::new(),
+                    payload_length: 0u32.into(),
+                    partial_packet_number: 1u8.into(),
+                }),
+                None => PacketHeader::Short(ShortHeader {
+                    key_phase: false,
+                    destination_connection_id: Some(ConnectionId::generate().unwrap()),
+                    partial_packet_number: 1u8.into(),
+                    wire_format: WireFormat::V1,
+                }),
+            };
+
+            assert_eq!(
+                PacketNumberSpace::for_packet_header(&packet_header),
+                Some(space)
+            );
+        }
+    }
+}
@@ -1,18 +1,474 @@
+use conv::ValueFrom;
+use crypto::{CryptoState, HeaderProtectionKey};
 use errors::*;
-use frames::Frame;
-use packets::{OutgoingPacket, Packet};
+use frames::{CryptoFrame, Frame, StreamFrame};
+use packets::{OutgoingPacket, PacketHeader, PacketNumber};
+use protocol::{EncryptionLevel, InFormat, VarInt, WireFormat, Writable};
+use std::net::SocketAddr;
 
+/// Packs as many frames as will fit within a single packet.
 #[derive(Debug)]
 pub struct PacketPacker {
+    packet_header: PacketHeader,
+    wire_format: WireFormat,
+    max_payload_len: usize,
+    tag_len: usize,
     frames: Vec<Frame>,
+    frames_len: usize,
 }
 
 impl PacketPacker {
-    pub fn new() -> Self {
-        unimplemented!()
+    /// Creates a new `PacketPacker` which will produce packets no larger than `max_packet_size`.
+    ///
+    /// The payload length of `packet_header` will be overwritten when the packet is packed.
+    pub fn new(packet_header: PacketHeader, max_packet_size: usize, tag_len: usize) -> Result<Self> {
+        let mut packet_header = packet_header;
+
+        // reserve enough space for the largest payload length this packet could have
+        if let PacketHeader::Long(long_header) = &mut packet_header {
+            long_header.payload_length = VarInt::value_from(max_packet_size)?;
+        }
+
+        let packet_header_len = packet_header.bytes()?.len();
+
+        let max_payload_len = max_packet_size
+            .checked_sub(packet_header_len + tag_len)
+            .ok_or_else(|| ErrorKind::MaximumPacketSizeIsTooSmall(max_packet_size))?;
+
+        let wire_format = packet_header.wire_format();
+
+        Ok(Self {
+            packet_header,
+            wire_format,
+            max_payload_len,
+            tag_len,
+            frames: Vec::new(),
+            frames_len: 0,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// The frames packed so far.
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    pub fn remaining_len(&self) -> usize {
+        self.max_payload_len - self.frames_len
+    }
+
+    /// Attempts to add `frame` to the packet.
+    ///
+    /// # Returns
+    /// `Some(frame)` if there was not enough space remaining for the frame.
+    pub fn try_push_frame(&mut self, frame: Frame) -> Result<Option<Frame>> {
+        let frame_len = InFormat(&frame, self.wire_format).bytes()?.len();
+
+        if frame_len > self.remaining_len() {
+            trace!(
+                "frame {:?} of length {} does not fit in the remaining {} bytes",
+                frame,
+                frame_len,
+                self.remaining_len()
+            );
+            return Ok(Some(frame));
+        }
+
+        self.frames_len += frame_len;
+        self.frames.push(frame);
+
+        Ok(None)
+    }
+
+    /// Fills the remainder of the packet with padding.
+    pub fn pad_to_max(&mut self) {
+        let padding_len = self.remaining_len();
+
+        self.frames
+            .extend(::std::iter::repeat(Frame::Padding).take(padding_len));
+        self.frames_len += padding_len;
+    }
+
+    /// Pads the packet so there are enough bytes after the packet number to take the header
+    /// protection sample from, this is as though the packet number was 4 bytes long.
+    fn pad_for_header_protection_sample(&mut self) {
+        let packet_number_len = self.packet_header
+            .partial_packet_number()
+            .map_or(0, |partial_packet_number| {
+                partial_packet_number.len().byte_len()
+            });
+
+        let min_frames_len = (4 + HeaderProtectionKey::SAMPLE_LEN)
+            .saturating_sub(packet_number_len + self.tag_len);
+
+        if self.frames_len < min_frames_len {
+            let padding_len = min_frames_len - self.frames_len;
+
+            self.frames
+                .extend(::std::iter::repeat(Frame::Padding).take(padding_len));
+            self.frames_len += padding_len;
+        }
     }
 
-    pub fn pack_packet(&mut self) -> Result<OutgoingPacket> {
-        unimplemented!()
+    /// Crypto stream data is carried in crypto frames from version 1 onwards.
+    fn stream_frame_to_frame(&self, stream_frame: StreamFrame) -> Frame {
+        if self.wire_format == WireFormat::V1 && stream_frame.stream_id.is_crypto_stream() {
+            Frame::Crypto(CryptoFrame {
+                offset: stream_frame.offset,
+                data: stream_frame.data,
+            })
+        } else {
+            Frame::Stream(stream_frame)
+        }
+    }
+
+    /// Adds as much of `stream_frame` as will fit to the packet.
+    ///
+    /// # Returns
+    /// The remainder of `stream_frame` which did not fit.
+    pub fn push_stream_frame(&mut self, stream_frame: StreamFrame) -> Result<Option<StreamFrame>> {
+        let mut stream_frame = stream_frame;
+
+        // the length of the frame without any data or data length
+        let empty_stream_frame = StreamFrame {
+            data: Default::default(),
+            ..stream_frame.clone()
+        };
+        let empty_frame = self.stream_frame_to_frame(empty_stream_frame);
+        let overhead_len = InFormat(&empty_frame, self.wire_format).bytes()?.len() - 1;
+
+        let available_len = match self.remaining_len().checked_sub(overhead_len) {
+            Some(available_len) => available_len,
+            None => return Ok(Some(stream_frame)),
+        };
+
+        let data_len_len = VarInt::value_from(available_len)?.encoded_len();
+        let max_data_len = available_len.saturating_sub(data_len_len);
+
+        // only split when there is space for some data, an empty frame is only worth sending
+        // when it is the whole frame
+        let remainder = if stream_frame.data.len() > max_data_len {
+            if max_data_len == 0 {
+                return Ok(Some(stream_frame));
+            }
+
+            Some(stream_frame.split_off(max_data_len))
+        } else {
+            None
+        };
+
+        let frame = self.stream_frame_to_frame(stream_frame);
+        if let Some(frame) = self.try_push_frame(frame)? {
+            unreachable!(
+                "the stream frame {:?} should have been split to fit in the packet",
+                frame
+            );
+        }
+
+        Ok(remainder)
+    }
+
+    /// Seals the packed frames, producing an `OutgoingPacket`.
+    pub fn pack_packet(
+        mut self,
+        packet_number: PacketNumber,
+        crypto_state: &CryptoState,
+        destination_address: SocketAddr,
+        encryption_level: EncryptionLevel,
+    ) -> Result<OutgoingPacket> {
+        trace!(
+            "packing packet {:?} with {} frames",
+            packet_number,
+            self.frames.len()
+        );
+
+        let header_protection_key = crypto_state.header_protection_key();
+        if header_protection_key.is_some() {
+            self.pad_for_header_protection_sample();
+        }
+
+        let mut packet_header = self.packet_header;
+
+        if let PacketHeader::Long(long_header) = &mut packet_header {
+            long_header.payload_length = VarInt::value_from(self.frames_len + self.tag_len)?;
+        }
+
+        let packet_header_bytes = packet_header.bytes()?;
+
+        let data = crypto_state.seal(packet_number, &packet_header_bytes[..], &self.frames[..])?;
+
+        let outgoing_packet = OutgoingPacket {
+            destination_address,
+            packet_header,
+            data,
+            encryption_level,
+            header_protection_key,
+        };
+
+        debug!(
+            "packed packet {:?} with {} frames",
+            packet_number,
+            self.frames.len()
+        );
+
+        Ok(outgoing_packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketPacker;
+    use bytes::Bytes;
+    use crypto::{CryptoState, HeaderProtectionKey};
+    use frames::{Frame, StreamFrame};
+    use frames::CryptoFrame;
+    use packets::{LongHeader, LongHeaderPacketType, PacketHeader, PacketNumber,
+                  PartialPacketNumber, ShortHeader};
+    use protocol::{ConnectionId, EncryptionLevel, StreamId, Version, WireFormat, Writable};
+
+    fn crypto_state() -> CryptoState {
+        CryptoState::for_handshake(ConnectionId::generate().unwrap(), "my test label").unwrap()
+    }
+
+    fn long_packet_header(packet_number: PacketNumber) -> PacketHeader {
+        PacketHeader::Long(LongHeader {
+            packet_type: LongHeaderPacketType::Initial,
+            version: Version::DRAFT_IETF_08,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            token: Bytes::new(),
+            payload_length: 0u32.into(),
+            partial_packet_number: PartialPacketNumber::from_packet_number_in_format(
+                packet_number,
+                0u32.into(),
+                WireFormat::Draft08,
+            ).unwrap(),
+        })
+    }
+
+    fn short_packet_header(packet_number: PacketNumber) -> PacketHeader {
+        PacketHeader::Short(ShortHeader {
+            key_phase: false,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            partial_packet_number: PartialPacketNumber::from_packet_number_in_format(
+                packet_number,
+                0u32.into(),
+                WireFormat::Draft08,
+            ).unwrap(),
+            wire_format: WireFormat::Draft08,
+        })
+    }
+
+    #[test]
+    fn pack_packet_seals_frames_which_can_be_opened() {
+        let crypto_state = crypto_state();
+        let packet_number = PacketNumber::from(5u32);
+
+        let mut packet_packer =
+            PacketPacker::new(short_packet_header(packet_number), 1200, crypto_state.tag_len())
+                .unwrap();
+
+        assert_eq!(packet_packer.try_push_frame(Frame::Ping).unwrap(), None);
+        assert_eq!(packet_packer.try_push_frame(Frame::Padding).unwrap(), None);
+
+        let outgoing_packet = packet_packer
+            .pack_packet(
+                packet_number,
+                &crypto_state,
+                "10.0.0.1:443".parse().unwrap(),
+                EncryptionLevel::Unencrypted,
+            )
+            .unwrap();
+
+        let packet_header_bytes = outgoing_packet.packet_header.bytes().unwrap();
+        let frames = crypto_state
+            .open(packet_number, &packet_header_bytes[..], &outgoing_packet.data[..])
+            .unwrap();
+
+        assert_eq!(frames, vec![Frame::Ping, Frame::Padding]);
+    }
+
+    #[test]
+    fn pack_packet_sets_payload_length() {
+        let crypto_state = crypto_state();
+        let packet_number = PacketNumber::from(5u32);
+
+        let mut packet_packer =
+            PacketPacker::new(long_packet_header(packet_number), 1200, crypto_state.tag_len())
+                .unwrap();
+
+        packet_packer.try_push_frame(Frame::Ping).unwrap();
+
+        let outgoing_packet = packet_packer
+            .pack_packet(
+                packet_number,
+                &crypto_state,
+                "10.0.0.1:443".parse().unwrap(),
+                EncryptionLevel::Unencrypted,
+            )
+            .unwrap();
+
+        assert_eq!(
+            outgoing_packet.packet_header.payload_length(),
+            Some((outgoing_packet.data.len() as u32).into())
+        );
+    }
+
+    #[test]
+    fn push_stream_frame_splits_frame_which_does_not_fit() {
+        let crypto_state = crypto_state();
+        let packet_number = PacketNumber::from(5u32);
+
+        let mut packet_packer =
+            PacketPacker::new(short_packet_header(packet_number), 1200, crypto_state.tag_len())
+                .unwrap();
+
+        let stream_frame = StreamFrame {
+            finished: true,
+            offset: 0u32.into(),
+            stream_id: StreamId::first_unidirectional_client_stream_id(),
+            data: Bytes::from(vec![0x12; 2000]),
+        };
+
+        let remainder = packet_packer
+            .push_stream_frame(stream_frame)
+            .unwrap()
+            .expect("there should be a remainder");
+
+        let packed_len = 2000 - remainder.data.len();
+        assert!(packed_len > 0);
+        assert_eq!(u64::from(remainder.offset), packed_len as u64);
+        assert!(remainder.finished);
+        assert!(packet_packer.remaining_len() < 4);
+
+        let outgoing_packet = packet_packer
+            .pack_packet(
+                packet_number,
+                &crypto_state,
+                "10.0.0.1:443".parse().unwrap(),
+                EncryptionLevel::Unencrypted,
+            )
+            .unwrap();
+
+        let packet_len =
+            outgoing_packet.packet_header.bytes().unwrap().len() + outgoing_packet.data.len();
+        assert!(packet_len <= 1200);
+    }
+
+    #[test]
+    fn try_push_frame_returns_frame_when_full() {
+        let mut packet_packer =
+            PacketPacker::new(short_packet_header(5u32.into()), 40, 16).unwrap();
+
+        while packet_packer.remaining_len() > 0 {
+            assert_eq!(packet_packer.try_push_frame(Frame::Ping).unwrap(), None);
+        }
+
+        assert_eq!(
+            packet_packer.try_push_frame(Frame::Ping).unwrap(),
+            Some(Frame::Ping)
+        );
+    }
+
+    #[test]
+    fn push_stream_frame_packs_v1_crypto_stream_data_as_crypto_frames() {
+        let crypto_state =
+            CryptoState::for_initial(ConnectionId::generate().unwrap(), "client in", Version::V1)
+                .unwrap();
+        let packet_number = PacketNumber::from(0u32);
+
+        let packet_header = PacketHeader::Long(LongHeader {
+            packet_type: LongHeaderPacketType::Initial,
+            version: Version::V1,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            source_connection_id: Some(ConnectionId::generate().unwrap()),
+            token: Bytes::new(),
+            payload_length: 0u32.into(),
+            partial_packet_number: PartialPacketNumber::from_packet_number(
+                packet_number,
+                0u32.into(),
+            ).unwrap(),
+        });
+
+        let mut packet_packer =
+            PacketPacker::new(packet_header, 1200, crypto_state.tag_len()).unwrap();
+
+        let stream_frame = StreamFrame {
+            finished: false,
+            offset: 0u32.into(),
+            stream_id: StreamId::crypto_stream_id(),
+            data: Bytes::from(&b"client hello"[..]),
+        };
+
+        assert_eq!(packet_packer.push_stream_frame(stream_frame).unwrap(), None);
+        packet_packer.pad_to_max();
+        assert_eq!(packet_packer.remaining_len(), 0);
+
+        let outgoing_packet = packet_packer
+            .pack_packet(
+                packet_number,
+                &crypto_state,
+                "10.0.0.1:443".parse().unwrap(),
+                EncryptionLevel::Unencrypted,
+            )
+            .unwrap();
+
+        let packet_header_bytes = outgoing_packet.packet_header.bytes().unwrap();
+        assert_eq!(
+            packet_header_bytes.len() + outgoing_packet.data.len(),
+            1200
+        );
+
+        let frames = crypto_state
+            .open(packet_number, &packet_header_bytes[..], &outgoing_packet.data[..])
+            .unwrap();
+
+        assert_eq!(
+            frames[0],
+            Frame::Crypto(CryptoFrame {
+                offset: 0u32.into(),
+                data: Bytes::from(&b"client hello"[..]),
+            })
+        );
+        assert!(frames[1..].iter().all(|frame| *frame == Frame::Padding));
+    }
+
+    #[test]
+    fn pack_packet_pads_v1_packets_to_allow_header_protection_sampling() {
+        let crypto_state =
+            CryptoState::for_initial(ConnectionId::generate().unwrap(), "client in", Version::V1)
+                .unwrap();
+        let packet_number = PacketNumber::from(0u32);
+
+        let packet_header = PacketHeader::Short(ShortHeader {
+            key_phase: false,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            partial_packet_number: PartialPacketNumber::from_packet_number(
+                packet_number,
+                0u32.into(),
+            ).unwrap(),
+            wire_format: WireFormat::V1,
+        });
+
+        let mut packet_packer =
+            PacketPacker::new(packet_header, 1200, crypto_state.tag_len()).unwrap();
+        assert_eq!(packet_packer.try_push_frame(Frame::Ping).unwrap(), None);
+
+        let outgoing_packet = packet_packer
+            .pack_packet(
+                packet_number,
+                &crypto_state,
+                "10.0.0.1:443".parse().unwrap(),
+                EncryptionLevel::ForwardSecure,
+            )
+            .unwrap();
+
+        assert!(outgoing_packet.header_protection_key.is_some());
+
+        // the sample is taken 4 bytes after the start of the 1 byte packet number
+        assert!(outgoing_packet.data.len() >= 3 + HeaderProtectionKey::SAMPLE_LEN);
     }
 }
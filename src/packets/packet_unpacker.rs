@@ -1,11 +1,163 @@
-use std::sync::{Arc, RwLock};
-use handshake::CryptoStage;
+use crypto::CryptoState;
+use errors::*;
+use packets::{IncomingPacket, Packet, PacketContent, PacketNumber};
+use std::cmp;
 
-#[derive(Debug)]
-pub struct PacketUnpacker{    
-    crypto_stage: Arc<RwLock<CryptoStage>>,
+/// Recovers the frames from incoming packets.
+#[derive(Debug, Default)]
+pub struct PacketUnpacker {
+    largest_received: Option<PacketNumber>,
 }
 
 impl PacketUnpacker {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-}
\ No newline at end of file
+    /// The largest `PacketNumber` which has been successfully unpacked.
+    pub fn largest_received(&self) -> Option<PacketNumber> {
+        self.largest_received
+    }
+
+    /// Recovers the full `PacketNumber` of `incoming_packet` from its partial packet number.
+    pub fn infer_packet_number(&self, incoming_packet: &IncomingPacket) -> Result<PacketNumber> {
+        let partial_packet_number = incoming_packet
+            .packet_header
+            .partial_packet_number()
+            .ok_or_else(|| Error::from_kind(ErrorKind::PacketHeaderHasNoPacketNumber))?;
+
+        partial_packet_number.infer_packet_number_in_format(
+            self.largest_received,
+            incoming_packet.packet_header.wire_format(),
+        )
+    }
+
+    /// Recovers the full `PacketNumber` of `incoming_packet` and opens the payload using
+    /// `crypto_state`.
+    pub fn unpack_packet(
+        &mut self,
+        incoming_packet: &IncomingPacket,
+        crypto_state: &CryptoState,
+    ) -> Result<Packet> {
+        trace!("unpacking packet {:?}", incoming_packet);
+
+        let packet_number = self.infer_packet_number(incoming_packet)?;
+
+        let frames = crypto_state.open(
+            packet_number,
+            &incoming_packet.packet_header_bytes[..],
+            &incoming_packet.data[..],
+        )?;
+
+        // only packets which could be authenticated can advance the largest received
+        self.largest_received = Some(
+            self.largest_received
+                .map_or(packet_number, |l| cmp::max(l, packet_number)),
+        );
+
+        let packet = Packet {
+            packet_number,
+            content: PacketContent::Regular { frames },
+        };
+
+        debug!("unpacked packet {:?}", packet);
+
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PacketUnpacker;
+    use crypto::CryptoState;
+    use frames::Frame;
+    use packets::{IncomingPacket, PacketContent, PacketHeader, PacketNumber, PacketPacker,
+                  PartialPacketNumber, ShortHeader};
+    use protocol::{ConnectionId, EncryptionLevel, WireFormat, Writable};
+    use std::time::Instant;
+
+    fn packed_incoming_packet(
+        crypto_state: &CryptoState,
+        packet_number: PacketNumber,
+        frames: Vec<Frame>,
+    ) -> IncomingPacket {
+        let packet_header = PacketHeader::Short(ShortHeader {
+            key_phase: false,
+            destination_connection_id: Some(ConnectionId::generate().unwrap()),
+            partial_packet_number: PartialPacketNumber::from_packet_number_in_format(
+                packet_number,
+                0u32.into(),
+                WireFormat::Draft08,
+            ).unwrap(),
+            wire_format: WireFormat::Draft08,
+        });
+
+        let mut packet_packer =
+            PacketPacker::new(packet_header, 1200, crypto_state.tag_len()).unwrap();
+        for frame in frames {
+            assert_eq!(packet_packer.try_push_frame(frame).unwrap(), None);
+        }
+
+        let source_address = "10.0.0.1:443".parse().unwrap();
+
+        let outgoing_packet = packet_packer
+            .pack_packet(
+                packet_number,
+                crypto_state,
+                source_address,
+                EncryptionLevel::ForwardSecure,
+            )
+            .unwrap();
+
+        IncomingPacket {
+            source_address,
+            packet_header_bytes: outgoing_packet.packet_header.bytes().unwrap().freeze(),
+            packet_header: outgoing_packet.packet_header,
+            data: outgoing_packet.data,
+            received_at: Instant::now(),
+            header_protected: false,
+        }
+    }
+
+    #[test]
+    fn unpack_packet_opens_packed_frames() {
+        let crypto_state =
+            CryptoState::for_handshake(ConnectionId::generate().unwrap(), "my test label").unwrap();
+
+        let incoming_packet =
+            packed_incoming_packet(&crypto_state, 7u32.into(), vec![Frame::Ping]);
+
+        let mut packet_unpacker = PacketUnpacker::new();
+        let packet = packet_unpacker
+            .unpack_packet(&incoming_packet, &crypto_state)
+            .unwrap();
+
+        assert_eq!(packet.packet_number, 7u32.into());
+        assert_eq!(
+            packet.content,
+            PacketContent::Regular {
+                frames: vec![Frame::Ping],
+            }
+        );
+        assert_eq!(packet_unpacker.largest_received(), Some(7u32.into()));
+    }
+
+    #[test]
+    fn unpack_packet_fails_with_wrong_crypto_state() {
+        let crypto_state =
+            CryptoState::for_handshake(ConnectionId::generate().unwrap(), "my test label").unwrap();
+        let other_crypto_state =
+            CryptoState::for_handshake(ConnectionId::generate().unwrap(), "my test label").unwrap();
+
+        let incoming_packet =
+            packed_incoming_packet(&crypto_state, 7u32.into(), vec![Frame::Ping]);
+
+        let mut packet_unpacker = PacketUnpacker::new();
+        assert!(
+            packet_unpacker
+                .unpack_packet(&incoming_packet, &other_crypto_state)
+                .is_err()
+        );
+        assert_eq!(packet_unpacker.largest_received(), None);
+    }
+}
@@ -0,0 +1,178 @@
+use bytes::Bytes;
+use conv::ValueFrom;
+use errors::*;
+use packets::PacketHeader;
+use protocol::{ConnectionId, Version, Writable};
+use ring::aead::{self, SealingKey};
+use ring::constant_time;
+
+/// The key the integrity tag of version 1 Retry packets is computed with (RFC 9001 section 5.8).
+static V1_RETRY_INTEGRITY_KEY: [u8; 16] = [
+    0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68, 0xc8, 0x4e,
+];
+
+/// The nonce the integrity tag of version 1 Retry packets is computed with.
+static V1_RETRY_INTEGRITY_NONCE: [u8; 12] = [
+    0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+];
+
+/// The key the integrity tag of version 2 Retry packets is computed with (RFC 9369 section 3.3.3).
+static V2_RETRY_INTEGRITY_KEY: [u8; 16] = [
+    0x8f, 0xb4, 0xb0, 0x1b, 0x56, 0xac, 0x48, 0xe2, 0x60, 0xfb, 0xcb, 0xce, 0xad, 0x7c, 0xcc, 0x92,
+];
+
+/// The nonce the integrity tag of version 2 Retry packets is computed with.
+static V2_RETRY_INTEGRITY_NONCE: [u8; 12] = [
+    0xd8, 0x69, 0x69, 0xbc, 0x2d, 0x7c, 0x6d, 0x99, 0x90, 0xef, 0xb0, 0x4a,
+];
+
+/// Sent by a server in response to an Initial packet, asking the client to prove it can receive
+/// packets at its address by echoing `retry_token` in its next Initial packet.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RetryPacket {
+    pub version: Version,
+    pub destination_connection_id: Option<ConnectionId>,
+    /// The connection id the client must address the server with from now on.
+    pub source_connection_id: Option<ConnectionId>,
+    pub retry_token: Bytes,
+    /// Binds the packet to the destination connection id of the client's first Initial packet,
+    /// so it can not be injected by an off-path attacker.
+    pub integrity_tag: Bytes,
+}
+
+impl RetryPacket {
+    pub const INTEGRITY_TAG_LEN: usize = 16;
+
+    /// Creates a Retry packet in response to an Initial packet which was sent to
+    /// `original_destination_connection_id`.
+    pub fn new(
+        version: Version,
+        destination_connection_id: Option<ConnectionId>,
+        source_connection_id: Option<ConnectionId>,
+        retry_token: Bytes,
+        original_destination_connection_id: ConnectionId,
+    ) -> Result<Self> {
+        let mut retry_packet = Self {
+            version,
+            destination_connection_id,
+            source_connection_id,
+            retry_token,
+            integrity_tag: Bytes::new(),
+        };
+
+        retry_packet.integrity_tag =
+            retry_packet.compute_integrity_tag(original_destination_connection_id)?;
+
+        Ok(retry_packet)
+    }
+
+    /// Whether this packet was sent in response to an Initial packet which was sent to
+    /// `original_destination_connection_id`.
+    pub fn is_valid(&self, original_destination_connection_id: ConnectionId) -> bool {
+        match self.compute_integrity_tag(original_destination_connection_id) {
+            Ok(integrity_tag) => {
+                constant_time::verify_slices_are_equal(&integrity_tag, &self.integrity_tag).is_ok()
+            }
+            Err(error) => {
+                debug!("failed to compute retry integrity tag: {}", error);
+                false
+            }
+        }
+    }
+
+    fn compute_integrity_tag(
+        &self,
+        original_destination_connection_id: ConnectionId,
+    ) -> Result<Bytes> {
+        // the tag authenticates a pseudo packet made of the original destination connection id
+        // followed by the retry packet without its tag
+        let mut pseudo_packet = Vec::new();
+
+        u8::value_from(original_destination_connection_id.len())
+            .chain_err(|| {
+                ErrorKind::FailedToWriteConnectionId(original_destination_connection_id)
+            })?
+            .write(&mut pseudo_packet)?;
+        original_destination_connection_id.write(&mut pseudo_packet)?;
+
+        PacketHeader::Retry(RetryPacket {
+            integrity_tag: Bytes::new(),
+            ..self.clone()
+        }).write(&mut pseudo_packet)?;
+
+        let (integrity_key, integrity_nonce) = if self.version == Version::V2 {
+            (&V2_RETRY_INTEGRITY_KEY, &V2_RETRY_INTEGRITY_NONCE)
+        } else {
+            (&V1_RETRY_INTEGRITY_KEY, &V1_RETRY_INTEGRITY_NONCE)
+        };
+
+        let sealing_key = SealingKey::new(&aead::AES_128_GCM, &integrity_key[..])
+            .chain_err(|| ErrorKind::FailedToBuildCryptoState)?;
+
+        // the plaintext is empty, so the sealed output is only the tag
+        let mut integrity_tag = vec![0; Self::INTEGRITY_TAG_LEN];
+
+        aead::seal_in_place(
+            &sealing_key,
+            &integrity_nonce[..],
+            &pseudo_packet,
+            &mut integrity_tag,
+            Self::INTEGRITY_TAG_LEN,
+        ).chain_err(|| ErrorKind::FailedToSealData)?;
+
+        Ok(integrity_tag.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPacket;
+    use bytes::Bytes;
+    use protocol::{ConnectionId, Version};
+
+    fn retry_packet(original_destination_connection_id: ConnectionId) -> RetryPacket {
+        retry_packet_in_version(Version::V1, original_destination_connection_id)
+    }
+
+    fn retry_packet_in_version(
+        version: Version,
+        original_destination_connection_id: ConnectionId,
+    ) -> RetryPacket {
+        RetryPacket::new(
+            version,
+            Some(ConnectionId::generate().unwrap()),
+            Some(ConnectionId::generate().unwrap()),
+            Bytes::from(&b"retry token"[..]),
+            original_destination_connection_id,
+        ).unwrap()
+    }
+
+    #[test]
+    fn retry_packet_is_valid_for_original_destination_connection_id() {
+        let original_destination_connection_id = ConnectionId::generate().unwrap();
+
+        let retry_packet = retry_packet(original_destination_connection_id);
+
+        assert_eq!(retry_packet.integrity_tag.len(), RetryPacket::INTEGRITY_TAG_LEN);
+        assert!(retry_packet.is_valid(original_destination_connection_id));
+    }
+
+    #[test]
+    fn retry_packet_integrity_tag_depends_on_version() {
+        let original_destination_connection_id = ConnectionId::generate().unwrap();
+
+        let mut retry_packet =
+            retry_packet_in_version(Version::V2, original_destination_connection_id);
+        assert!(retry_packet.is_valid(original_destination_connection_id));
+
+        retry_packet.version = Version::V1;
+        assert!(!retry_packet.is_valid(original_destination_connection_id));
+    }
+
+    #[test]
+    fn retry_packet_is_invalid_for_other_destination_connection_id() {
+        let retry_packet = retry_packet(ConnectionId::generate().unwrap());
+
+        assert!(!retry_packet.is_valid(ConnectionId::generate().unwrap()));
+    }
+}
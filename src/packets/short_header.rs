@@ -1,9 +1,11 @@
 use packets::PartialPacketNumber;
-use protocol::ConnectionId;
+use protocol::{ConnectionId, WireFormat};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct ShortHeader {
     pub key_phase: bool,
     pub destination_connection_id: Option<ConnectionId>,
     pub partial_packet_number: PartialPacketNumber,
+    /// Short headers do not carry a version, this is the format the header is encoded in.
+    pub wire_format: WireFormat,
 }
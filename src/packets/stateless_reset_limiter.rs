@@ -0,0 +1,66 @@
+use std::time::{Duration, Instant};
+
+/// Limits the number of stateless resets a server sends each second, so packets for unknown
+/// connections can not turn it into an amplifier.
+#[derive(Debug)]
+pub struct StatelessResetLimiter {
+    max_per_second: u32,
+    window_started_at: Option<Instant>,
+    sent_in_window: u32,
+}
+
+impl StatelessResetLimiter {
+    /// Creates a `StatelessResetLimiter` which allows `max_per_second` stateless resets each
+    /// second, none are allowed when it is 0.
+    pub fn new(max_per_second: u32) -> Self {
+        Self {
+            max_per_second,
+            window_started_at: None,
+            sent_in_window: 0,
+        }
+    }
+
+    /// Whether another stateless reset may be sent at `now`, it is counted if so.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        match self.window_started_at {
+            Some(window_started_at) if now - window_started_at < Duration::from_secs(1) => {}
+            _ => {
+                self.window_started_at = Some(now);
+                self.sent_in_window = 0;
+            }
+        }
+
+        if self.sent_in_window >= self.max_per_second {
+            return false;
+        }
+
+        self.sent_in_window += 1;
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatelessResetLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn try_acquire_allows_max_per_second() {
+        let mut stateless_reset_limiter = StatelessResetLimiter::new(2);
+        let now = Instant::now();
+
+        assert!(stateless_reset_limiter.try_acquire(now));
+        assert!(stateless_reset_limiter.try_acquire(now + Duration::from_millis(500)));
+        assert!(!stateless_reset_limiter.try_acquire(now + Duration::from_millis(999)));
+
+        assert!(stateless_reset_limiter.try_acquire(now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn try_acquire_allows_none_when_disabled() {
+        let mut stateless_reset_limiter = StatelessResetLimiter::new(0);
+
+        assert!(!stateless_reset_limiter.try_acquire(Instant::now()));
+    }
+}
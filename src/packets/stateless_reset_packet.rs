@@ -0,0 +1,12 @@
+use protocol::{ConnectionId, StatelessResetToken};
+
+/// A datagram ending in a stateless reset token the remote endpoint of a connection issued, it
+/// has lost the state of the connection.
+///
+/// Only ever read, these look like short header packets until the token is recognised.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct StatelessResetPacket {
+    /// The local connection id of the connection being reset.
+    pub connection_id: ConnectionId,
+    pub stateless_reset_token: StatelessResetToken,
+}
@@ -0,0 +1,13 @@
+use protocol::ConnectionId;
+
+/// A short header packet whose header protection could not be removed, as no connection has
+/// registered a key for its destination connection id.
+///
+/// Only ever read, servers answer these with a stateless reset when they have no such connection.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct UnknownConnectionPacket {
+    pub destination_connection_id: Option<ConnectionId>,
+    /// The number of bytes of the datagram from the start of the packet, a stateless reset sent
+    /// in response must be shorter so two endpoints can not keep resetting each other.
+    pub len: usize,
+}
@@ -0,0 +1,12 @@
+use protocol::{ConnectionId, Version};
+
+/// A long header packet in a version we do not support.
+///
+/// Only the version independent fields can be read, which is all a server needs to answer with
+/// a Version Negotiation packet.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct UnsupportedVersionPacket {
+    pub version: Version,
+    pub destination_connection_id: Option<ConnectionId>,
+    pub source_connection_id: Option<ConnectionId>,
+}
@@ -1,8 +1,11 @@
-use protocol::{ConnectionId, Version};
+use protocol::{ConnectionId, Version, WireFormat};
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct VersionNegotiationPacket {
     pub destination_connection_id: Option<ConnectionId>,
     pub source_connection_id: Option<ConnectionId>,
     pub supported_versions: Vec<Version>,
+    /// Version negotiation packets do not carry a version, this is the format the packet is
+    /// encoded in.
+    pub wire_format: WireFormat,
 }
@@ -1,36 +1,91 @@
+use bytes::Bytes;
+use crypto::{HeaderProtectionKeys, StatelessResetTokens};
 use errors::*;
-use futures::{Future, Poll};
+use futures::Poll;
 use packets::{IncomingPacket, OutgoingPacket};
-use protocol::{ConnectionId, MessageParameters, Role, RoleSpecificTransportParameters};
+use protocol::{ConnectionId, MessageParameters, Role, RoleSpecificTransportParameters,
+               StatelessResetToken, Version};
+use rustls::quic::QuicExt;
 use rustls::Session;
 use smallvec::SmallVec;
-use tokio_rustls::TlsStream;
-use {DataStream, StreamMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_core::reactor::Remote;
+use {ClientHello, CongestionControl, Connection, StreamMap};
 
 pub trait Perspective: Sized {
-    type TlsSession: Session;
-    type HandshakeFuture: Future<
-            Item = TlsStream<DataStream<Self>, Self::TlsSession>,
-            Error = Error,
-        >
-        + Send;
+    type TlsSession: Session + QuicExt + Send;
+    /// The message parameters of the transport parameters the remote endpoint sends.
     type IncomingTransportMessageParameters: MessageParameters;
-    type RoleSpecificTransportParameters: RoleSpecificTransportParameters;
+    /// The transport parameters only the remote endpoint's role sends.
+    type IncomingRoleSpecificTransportParameters: RoleSpecificTransportParameters;
 
-    fn handshake(&self, crypto_stream: DataStream<Self>) -> Self::HandshakeFuture;
+    /// Creates the TLS session the handshake is performed with, a server only creates it once
+    /// the client's `client_hello` has arrived, `None` if it could not be parsed.
+    fn tls_session(&self, client_hello: Option<&ClientHello>) -> Result<Self::TlsSession>;
+
+    /// Called once `tls_session` has written its first crypto data on `connection`.
+    fn on_handshake_started(&self, connection: &Connection<Self>, tls_session: &Self::TlsSession);
+
+    /// Checks what was agreed in the handshake of `tls_session` once it completes on
+    /// `connection`.
+    fn on_handshake_complete(
+        &self,
+        connection: &Connection<Self>,
+        tls_session: &Self::TlsSession,
+    ) -> Result<()>;
+
+    /// The error a failed TLS handshake is reported as.
+    fn handshake_error(&self) -> ErrorKind;
 
     fn client_connection_id(
         local_connection_id: ConnectionId,
         remote_connection_id: ConnectionId,
     ) -> ConnectionId;
 
+    /// The QUIC version the connection uses.
+    fn version(&self) -> Version;
+
+    /// The QUIC version a client first tried to connect in, which differs from `version` once
+    /// the server asked for another in a Version Negotiation packet, or on a server which moved
+    /// the client to `version` from a compatible version.
+    fn initial_version(&self) -> Version;
+
+    /// The versions a client lets the server move it to from `version`, or a server moves
+    /// clients from to `version`.
+    fn compatible_versions(&self) -> &[Version];
+
+    /// The keys the connections register to have the header protection of their incoming
+    /// packets removed.
+    fn header_protection_keys(&self) -> Arc<HeaderProtectionKeys>;
+
+    /// The tokens the connections register to have the stateless resets of their peers
+    /// recognised.
+    fn stateless_reset_tokens(&self) -> Arc<StatelessResetTokens>;
+
+    /// Issues another connection id the peer may address the connection with, along with the
+    /// token ending the stateless resets sent for it. `None` if this endpoint does not send
+    /// stateless resets.
+    fn issue_connection_id(&self) -> Result<Option<(ConnectionId, StatelessResetToken)>>;
+
     fn handshake_send_label() -> &'static str;
 
     fn handshake_receive_label() -> &'static str;
 
-    fn update_secret_send_label() -> &'static str;
+    /// The label used to derive the initial secret for sent packets, which versions 1 and 2
+    /// share.
+    fn initial_send_label() -> &'static str;
 
-    fn update_secret_receive_label() -> &'static str;
+    /// The label used to derive the initial secret for received packets, which versions 1 and 2
+    /// share.
+    fn initial_receive_label() -> &'static str;
+
+    /// The label used to derive the next secret for sent packets in `version` on a key update.
+    fn update_secret_send_label(version: Version) -> &'static str;
+
+    /// The label used to derive the next secret for received packets in `version` on a key
+    /// update.
+    fn update_secret_receive_label(version: Version) -> &'static str;
 
     fn tls_exporter_send_label() -> &'static str;
 
@@ -50,4 +105,49 @@ pub trait Perspective: Sized {
     fn max_incoming_data_per_stream(&self) -> u32;
 
     fn max_incoming_data(&self) -> u32;
+
+    /// The exponent applied to the ACK delay of the ACK frames the connection sends.
+    fn ack_delay_exponent(&self) -> u8;
+
+    /// The longest the connection waits before acknowledging a packet.
+    fn max_ack_delay(&self) -> Duration;
+
+    /// The reactor the connection's timers are registered with.
+    fn remote(&self) -> &Remote;
+
+    /// The congestion controller the connection is created with.
+    fn congestion_control(&self) -> &CongestionControl;
+
+    /// Whether outgoing packets are paced.
+    fn pacing_enabled(&self) -> bool;
+
+    /// The number of datagrams which may be sent back to back while pacing.
+    fn pacing_burst_datagrams(&self) -> usize;
+
+    /// The number of packets sealed with the 1-RTT keys before they are updated.
+    fn key_update_interval_packets(&self) -> u64;
+
+    /// The number of bytes sealed with the 1-RTT keys before they are updated.
+    fn key_update_interval_bytes(&self) -> u64;
+
+    /// The most bytes of 0-RTT packets accepted on the connection.
+    fn max_early_data_size(&self) -> usize;
+
+    /// Whether the remote endpoint is known to receive packets at its address, until it is a
+    /// server sends a client no more than three times the bytes it received.
+    fn address_validated(&self) -> bool;
+
+    /// The configured application protocols, the handshake fails unless one of them is agreed
+    /// when there are any.
+    fn alpn_protocols(&self) -> &[String];
+
+    /// The token a client's Initial packets carry to validate its address, empty if it has none.
+    fn address_validation_token(&self) -> Bytes;
+
+    /// A token the server gives the client for its next connection once the handshake
+    /// completes, `None` if none is given.
+    fn new_token(&self) -> Option<Bytes>;
+
+    /// Keeps the `token` the server sent in a NEW_TOKEN frame for the next connection.
+    fn on_new_token(&self, token: Bytes) -> Result<()>;
 }
@@ -2,26 +2,41 @@ use bytes::Bytes;
 use errors::*;
 use protocol::{Readable, Writable};
 use rand::{OsRng, Rng};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::io::{Read, Write};
 
 /// A unique identifier for a connection.
 /// This will always be required when communicating with the server to identify the client however it is not required when the server communicates with the client as the client can identify the server purely based upon the port opened.
-#[derive(Debug, Copy, Clone, Hash, Eq, PartialEq)]
-pub struct ConnectionId([u8; 18]);
+///
+/// Connection ids are between 0 and `ConnectionId::MAX_LEN` bytes long, two connection ids are
+/// only equal when both their lengths and their bytes match.
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct ConnectionId {
+    // the bytes beyond `len` are always zero so they do not affect equality or hashing
+    bytes: [u8; 20],
+    len: u8,
+}
+
+impl Debug for ConnectionId {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "ConnectionId(")?;
+        for byte in self.bytes() {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
 
 impl Readable for ConnectionId {
     type Context = ();
     fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<ConnectionId> {
         trace!("reading connection id");
 
-        // read a maximum of 18 bytes
-        let bytes: Bytes = Readable::read(&mut reader.take(18))?;
+        // read one byte more than the maximum so overlong connection ids can be detected
+        let max_len = ConnectionId::MAX_LEN as u64;
+        let bytes: Bytes = Readable::read(&mut reader.take(max_len + 1))?;
 
-        let mut inner = [0; 18];
-        let start_offset = inner.len() - bytes.len();
-        inner[start_offset..].copy_from_slice(&bytes[..]);
-
-        let connection_id = ConnectionId(inner);
+        let connection_id = ConnectionId::from_slice(&bytes[..])?;
         debug!("read connection id {:?}", connection_id);
 
         Ok(connection_id)
@@ -31,7 +46,7 @@ impl Readable for ConnectionId {
 impl Writable for ConnectionId {
     fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
         trace!("writing connection id {:?}", self);
-        self.0.write(writer)?;
+        self.bytes().write(writer)?;
         debug!("written connection id {:?}", self);
 
         Ok(())
@@ -39,32 +54,108 @@ impl Writable for ConnectionId {
 }
 
 impl ConnectionId {
+    /// The maximum length of a connection id in bytes.
+    pub const MAX_LEN: usize = 20;
+
+    /// The length of the connection ids generated by `ConnectionId::generate`.
+    pub const DEFAULT_LEN: usize = 18;
+
+    /// Creates a `ConnectionId` from `bytes`, failing if there are more than
+    /// `ConnectionId::MAX_LEN` of them.
+    pub fn from_slice(bytes: &[u8]) -> Result<ConnectionId> {
+        if bytes.len() > Self::MAX_LEN {
+            bail!(ErrorKind::ConnectionIdIsTooLong(bytes.len()));
+        }
+
+        let mut inner = [0; 20];
+        inner[..bytes.len()].copy_from_slice(bytes);
+
+        Ok(ConnectionId {
+            bytes: inner,
+            len: bytes.len() as u8,
+        })
+    }
+
+    /// Reads a `ConnectionId` of exactly `len` bytes.
+    pub fn read_with_len<R: Read>(reader: &mut R, len: usize) -> Result<ConnectionId> {
+        trace!("reading connection id of length {}", len);
+
+        if len > Self::MAX_LEN {
+            bail!(ErrorKind::ConnectionIdIsTooLong(len));
+        }
+
+        let mut inner = [0; 20];
+        reader
+            .read_exact(&mut inner[..len])
+            .chain_err(|| ErrorKind::FailedToReadConnectionId(len))?;
+
+        let connection_id = ConnectionId {
+            bytes: inner,
+            len: len as u8,
+        };
+        debug!("read connection id {:?}", connection_id);
+
+        Ok(connection_id)
+    }
+
     pub fn generate_with_rng<R: Rng>(rng: &mut R) -> ConnectionId {
-        trace!("generating new connection id");
-        let mut bytes = [0; 18];
-        rng.fill_bytes(&mut bytes);
-        let connection_id = ConnectionId(bytes);
+        Self::generate_with_rng_and_len(rng, Self::DEFAULT_LEN)
+    }
+
+    /// Generates a random `ConnectionId` of `len` bytes, `len` must not exceed
+    /// `ConnectionId::MAX_LEN`.
+    pub fn generate_with_rng_and_len<R: Rng>(rng: &mut R, len: usize) -> ConnectionId {
+        assert!(
+            len <= Self::MAX_LEN,
+            "connection ids can not be longer than {} bytes",
+            Self::MAX_LEN
+        );
+
+        trace!("generating new connection id of length {}", len);
+        let mut bytes = [0; 20];
+        rng.fill_bytes(&mut bytes[..len]);
+        let connection_id = ConnectionId {
+            bytes,
+            len: len as u8,
+        };
         debug!("generated new connection id {:?}", connection_id);
 
         connection_id
     }
 
     pub fn generate() -> Result<ConnectionId> {
+        Self::generate_with_len(Self::DEFAULT_LEN)
+    }
+
+    /// Generates a random `ConnectionId` of `len` bytes.
+    pub fn generate_with_len(len: usize) -> Result<ConnectionId> {
+        if len > Self::MAX_LEN {
+            bail!(ErrorKind::ConnectionIdIsTooLong(len));
+        }
+
         let mut rng =
             OsRng::new().chain_err(|| ErrorKind::FailedToCreateCryptographicRandomNumberGenerator)?;
 
-        Ok(ConnectionId::generate_with_rng(&mut rng))
+        Ok(ConnectionId::generate_with_rng_and_len(&mut rng, len))
     }
 
     pub fn bytes(&self) -> &[u8] {
-        &self.0
+        &self.bytes[..self.len()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::ConnectionId;
-    use protocol::{self, Readable};
+    use protocol::{self, Readable, Writable};
 
     #[test]
     fn read_write_connection_id() {
@@ -79,9 +170,49 @@ mod tests {
 
         let connection_id = ConnectionId::from_bytes(&bytes).unwrap();
 
-        assert_eq!(
-            connection_id,
-            ConnectionId([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 21, 54, 213, 17])
-        );
+        assert_eq!(connection_id.len(), 4);
+        assert_eq!(connection_id.bytes(), &bytes[..]);
+    }
+
+    #[test]
+    fn short_connection_id_does_not_equal_zero_extended_connection_id() {
+        let short = ConnectionId::from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+        let zero_extended = ConnectionId::from_slice(&[0, 0, 1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
+
+        assert_ne!(short, zero_extended);
+    }
+
+    #[test]
+    fn read_write_eight_byte_connection_id() {
+        let connection_id = ConnectionId::generate_with_len(8).unwrap();
+
+        let bytes = connection_id.bytes().to_vec();
+        assert_eq!(connection_id.bytes().len(), 8);
+        assert_eq!(connection_id.bytes_vec().unwrap(), bytes);
+
+        protocol::test_write_read(&connection_id).unwrap();
+    }
+
+    #[test]
+    fn read_of_too_long_connection_id_fails() {
+        let bytes = [0u8; 21];
+
+        assert!(ConnectionId::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn read_with_len_reads_exactly_len_bytes() {
+        let bytes = [1, 2, 3, 4, 5, 6, 7, 8, 9];
+
+        let connection_id = ConnectionId::read_with_len(&mut &bytes[..], 8).unwrap();
+
+        assert_eq!(connection_id.bytes(), &bytes[..8]);
+    }
+
+    #[test]
+    fn read_with_len_fails_when_too_few_bytes() {
+        let bytes = [1, 2, 3];
+
+        assert!(ConnectionId::read_with_len(&mut &bytes[..], 8).is_err());
     }
 }
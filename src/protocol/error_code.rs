@@ -3,6 +3,11 @@ use errors::*;
 use protocol::{Readable, Writable};
 use std::io::{Read, Write};
 
+/// Draft 08 has no error code carrying the TLS alert, a crypto error is sent as this and read
+/// back as an internal_error alert.
+const TLS_FATAL_ALERT_GENERATED: u16 = 0x202;
+const INTERNAL_ERROR_ALERT: u8 = 80;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum ErrorCode {
     NoError,
@@ -18,6 +23,61 @@ pub enum ErrorCode {
     ProtocolViolation,
     UnsolicitedPathResponse,
     FrameError(u8),
+    /// The TLS handshake failed with the alert.
+    CryptoError(u8),
+}
+
+impl ErrorCode {
+    /// The TLS alert sent when none of the application protocols the client offered are
+    /// supported.
+    pub const NO_APPLICATION_PROTOCOL_ALERT: u8 = 120;
+
+    /// Gets the `ErrorCode` from a version 1 transport error code and the frame type which
+    /// triggered it.
+    pub fn from_v1_code(code: u64, frame_type: u64) -> Result<Self> {
+        let error_code = match code {
+            0x0 => ErrorCode::NoError,
+            0x1 => ErrorCode::InternalError,
+            0x2 => ErrorCode::ServerBusy,
+            0x3 => ErrorCode::FlowControlError,
+            0x4 => ErrorCode::StreamIdError,
+            0x5 => ErrorCode::StreamStateError,
+            0x6 => ErrorCode::FinalOffsetError,
+            0x7 => match u8::value_from(frame_type) {
+                Ok(frame_type) if frame_type != 0 => ErrorCode::FrameError(frame_type),
+                _ => ErrorCode::FrameFormatError,
+            },
+            0x8 => ErrorCode::TransportParameterError,
+            0xa => ErrorCode::ProtocolViolation,
+            0x100...0x1ff => ErrorCode::CryptoError(u8::value_from(code & 0xff).unwrap()),
+            _ => bail!(ErrorKind::FailedToReadErrorCode),
+        };
+
+        Ok(error_code)
+    }
+
+    /// Gets the version 1 transport error code and the frame type which triggered it.
+    ///
+    /// Version 1 has no version negotiation or unsolicited path response errors, these are
+    /// reported as protocol violations.
+    pub fn v1_code(&self) -> (u64, u64) {
+        match self {
+            ErrorCode::NoError => (0x0, 0),
+            ErrorCode::InternalError => (0x1, 0),
+            ErrorCode::ServerBusy => (0x2, 0),
+            ErrorCode::FlowControlError => (0x3, 0),
+            ErrorCode::StreamIdError => (0x4, 0),
+            ErrorCode::StreamStateError => (0x5, 0),
+            ErrorCode::FinalOffsetError => (0x6, 0),
+            ErrorCode::FrameFormatError => (0x7, 0),
+            ErrorCode::FrameError(frame_type) => (0x7, u64::from(*frame_type)),
+            ErrorCode::TransportParameterError => (0x8, 0),
+            ErrorCode::CryptoError(alert) => (0x100 | u64::from(*alert), 0),
+            ErrorCode::VersionNegotationError
+            | ErrorCode::ProtocolViolation
+            | ErrorCode::UnsolicitedPathResponse => (0xa, 0),
+        }
+    }
 }
 
 impl Readable for ErrorCode {
@@ -45,6 +105,7 @@ impl Readable for ErrorCode {
                 let frame_type = u8::value_from(value & 0xffu16).unwrap();
                 ErrorCode::FrameError(frame_type)
             }
+            TLS_FATAL_ALERT_GENERATED => ErrorCode::CryptoError(INTERNAL_ERROR_ALERT),
             _ => bail!(ErrorKind::FailedToReadErrorCode),
         };
 
@@ -71,6 +132,7 @@ impl Writable for ErrorCode {
             ErrorCode::ProtocolViolation => 0xa,
             ErrorCode::UnsolicitedPathResponse => 0xb,
             ErrorCode::FrameError(frame_type) => (0x1u16 << 8) | u16::from(*frame_type),
+            ErrorCode::CryptoError(_) => TLS_FATAL_ALERT_GENERATED,
         };
 
         bytes
@@ -92,4 +154,12 @@ mod test {
     fn round_trip_frame_error() {
         protocol::test_write_read(&ErrorCode::FrameError(208)).unwrap();
     }
+
+    #[test]
+    fn v1_code_of_crypto_error_carries_alert() {
+        let error_code = ErrorCode::CryptoError(ErrorCode::NO_APPLICATION_PROTOCOL_ALERT);
+
+        assert_eq!(error_code.v1_code(), (0x178, 0));
+        assert_eq!(ErrorCode::from_v1_code(0x178, 0).unwrap(), error_code);
+    }
 }
@@ -36,12 +36,18 @@ pub use self::error_code::ErrorCode;
 mod var_int;
 pub use self::var_int::VarInt;
 
+mod wire_format;
+pub use self::wire_format::{InFormat, WireFormat, WritableInFormat};
+
 mod version;
 pub use self::version::Version;
 
 mod connection_id;
 pub use self::connection_id::ConnectionId;
 
+mod stateless_reset_token;
+pub use self::stateless_reset_token::StatelessResetToken;
+
 mod server_id;
 pub use self::server_id::ServerId;
 
@@ -65,7 +71,8 @@ pub use self::transport_parameters::{ClientHelloMessageParameters,
                                      ClientSpecificTransportParameters,
                                      EncryptedExtensionsMessageParameters, MessageParameters,
                                      RoleSpecificTransportParameters,
-                                     ServerSpecificTransportParameters, TransportParameters};
+                                     ServerSpecificTransportParameters, TransportParameters,
+                                     VersionInformation};
 
 mod stream_offset;
 pub use self::stream_offset::StreamOffset;
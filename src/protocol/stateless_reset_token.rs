@@ -0,0 +1,151 @@
+use errors::*;
+use protocol::{ConnectionId, Readable, Writable};
+use rand::{OsRng, Rng};
+use ring::digest;
+use ring::hmac::{self, SigningKey};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::{Read, Write};
+
+/// Ends the stateless reset an endpoint sends when it receives a packet for a connection it has
+/// lost the state of (RFC 9000 section 10.3).
+///
+/// Tokens are derived from the connection id with a static key, so an endpoint which has lost
+/// everything but the key can still reset the connection.
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub struct StatelessResetToken([u8; 16]);
+
+impl Debug for StatelessResetToken {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "StatelessResetToken(")?;
+        for byte in &self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl Readable for StatelessResetToken {
+    type Context = ();
+    fn read_with_context<R: Read>(reader: &mut R, _: &Self::Context) -> Result<Self> {
+        trace!("reading stateless reset token");
+        let stateless_reset_token = <[u8; 16]>::read(reader).map(StatelessResetToken)?;
+        debug!("read stateless reset token {:?}", stateless_reset_token);
+
+        Ok(stateless_reset_token)
+    }
+}
+
+impl Writable for StatelessResetToken {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing stateless reset token {:?}", self);
+        self.0.write(writer)?;
+        debug!("written stateless reset token {:?}", self);
+
+        Ok(())
+    }
+}
+
+impl From<[u8; 16]> for StatelessResetToken {
+    fn from(bytes: [u8; 16]) -> Self {
+        StatelessResetToken(bytes)
+    }
+}
+
+impl StatelessResetToken {
+    /// The length of a stateless reset token in bytes.
+    pub const LEN: usize = 16;
+
+    /// The shortest datagram which can be a stateless reset, the token follows a short header
+    /// flags byte and at least 4 unpredictable bytes.
+    pub const MIN_DATAGRAM_LEN: usize = 21;
+
+    /// Generates a random key to derive tokens with.
+    pub fn generate_key() -> Result<[u8; 32]> {
+        let mut rng =
+            OsRng::new().chain_err(|| ErrorKind::FailedToCreateCryptographicRandomNumberGenerator)?;
+
+        let mut key = [0; 32];
+        rng.fill_bytes(&mut key);
+
+        Ok(key)
+    }
+
+    /// Derives the token of `connection_id` with the HMAC of `key`.
+    pub fn derive(key: &[u8; 32], connection_id: ConnectionId) -> Self {
+        let signing_key = SigningKey::new(&digest::SHA256, &key[..]);
+        let signature = hmac::sign(&signing_key, connection_id.bytes());
+
+        let mut token = [0; Self::LEN];
+        token.copy_from_slice(&signature.as_ref()[..Self::LEN]);
+
+        StatelessResetToken(token)
+    }
+
+    /// The token a stateless reset in `datagram` would end with, `None` if `datagram` can not
+    /// be a stateless reset.
+    pub fn from_datagram(datagram: &[u8]) -> Option<Self> {
+        // stateless resets are made to look like short header packets
+        if datagram.len() < Self::MIN_DATAGRAM_LEN || datagram[0] & 0x80 != 0 {
+            return None;
+        }
+
+        let mut token = [0; Self::LEN];
+        token.copy_from_slice(&datagram[datagram.len() - Self::LEN..]);
+
+        Some(StatelessResetToken(token))
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatelessResetToken;
+    use protocol::{self, ConnectionId};
+
+    #[test]
+    fn read_write_stateless_reset_token() {
+        protocol::test_write_read(&StatelessResetToken::from([0x42; 16])).unwrap();
+    }
+
+    #[test]
+    fn derive_depends_on_key_and_connection_id() {
+        let key = StatelessResetToken::generate_key().unwrap();
+        let other_key = StatelessResetToken::generate_key().unwrap();
+        let connection_id = ConnectionId::generate().unwrap();
+        let other_connection_id = ConnectionId::generate().unwrap();
+
+        let token = StatelessResetToken::derive(&key, connection_id);
+
+        assert_eq!(token, StatelessResetToken::derive(&key, connection_id));
+        assert_ne!(token, StatelessResetToken::derive(&other_key, connection_id));
+        assert_ne!(token, StatelessResetToken::derive(&key, other_connection_id));
+    }
+
+    #[test]
+    fn from_datagram_takes_last_16_bytes() {
+        let mut datagram = vec![0x40; 5];
+        datagram.extend_from_slice(&[0x42; 16]);
+
+        assert_eq!(
+            StatelessResetToken::from_datagram(&datagram),
+            Some(StatelessResetToken::from([0x42; 16]))
+        );
+    }
+
+    #[test]
+    fn from_datagram_ignores_short_datagrams() {
+        let datagram = [0x40; StatelessResetToken::MIN_DATAGRAM_LEN - 1];
+
+        assert_eq!(StatelessResetToken::from_datagram(&datagram), None);
+    }
+
+    #[test]
+    fn from_datagram_ignores_long_headers() {
+        let datagram = [0xc0; 32];
+
+        assert_eq!(StatelessResetToken::from_datagram(&datagram), None);
+    }
+}
@@ -1,10 +1,11 @@
 use conv::{TryFrom, ValueFrom};
 use errors::*;
-use protocol::{Readable, Version, Writable};
+use protocol::{ConnectionId, Readable, StatelessResetToken, Version, Writable};
 use smallvec::SmallVec;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::io::{Read, Write};
+use std::time::Duration;
 
 pub trait MessageParameters: Debug + Readable + Writable {}
 
@@ -19,14 +20,25 @@ pub trait RoleSpecificTransportParameters: Debug {
         &self,
         transport_parameters: &mut HashMap<TransportParameterId, TransportParameterValue>,
     ) -> Result<()>;
+
+    /// The token which ends the stateless resets of the endpoint, only servers send one.
+    fn stateless_reset_token(&self) -> Option<StatelessResetToken>;
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ServerSpecificTransportParameters {
-    pub stateless_reset_token: Option<[u8; 16]>,
+    /// The token of the connection id the client first addresses the server with, `None` if the
+    /// server does not send stateless resets.
+    pub stateless_reset_token: Option<StatelessResetToken>,
 
     // TODO LH What type is preferred_address?
     pub preferred_address: Option<()>,
+
+    /// The destination connection id of the client's first Initial packet, which lets the
+    /// client check that a Retry packet did not change it.
+    pub original_destination_connection_id: Option<ConnectionId>,
+    /// The source connection id of the Retry packet the server sent, `None` if it sent none.
+    pub retry_source_connection_id: Option<ConnectionId>,
 }
 
 impl RoleSpecificTransportParameters for ServerSpecificTransportParameters {
@@ -36,17 +48,29 @@ impl RoleSpecificTransportParameters for ServerSpecificTransportParameters {
         let stateless_reset_token = try_get_parameter_value(
             &transport_parameters,
             TransportParameterId::StatelessResetToken,
-            <[u8; 16]>::from_bytes,
+            StatelessResetToken::from_bytes,
         )?;
         let preferred_address = try_get_parameter_value(
             &transport_parameters,
             TransportParameterId::PreferredAddress,
             |_| Ok(()),
         )?;
+        let original_destination_connection_id = try_get_parameter_value(
+            &transport_parameters,
+            TransportParameterId::OriginalDestinationConnectionId,
+            ConnectionId::from_bytes,
+        )?;
+        let retry_source_connection_id = try_get_parameter_value(
+            &transport_parameters,
+            TransportParameterId::RetrySourceConnectionId,
+            ConnectionId::from_bytes,
+        )?;
 
         Ok(Self {
             stateless_reset_token,
             preferred_address,
+            original_destination_connection_id,
+            retry_source_connection_id,
         })
     }
 
@@ -63,9 +87,25 @@ impl RoleSpecificTransportParameters for ServerSpecificTransportParameters {
         if self.preferred_address.is_some() {
             transport_parameters.insert(TransportParameterId::PreferredAddress, SmallVec::new());
         }
+        if let Some(value) = self.original_destination_connection_id {
+            transport_parameters.insert(
+                TransportParameterId::OriginalDestinationConnectionId,
+                value.bytes_small()?,
+            );
+        }
+        if let Some(value) = self.retry_source_connection_id {
+            transport_parameters.insert(
+                TransportParameterId::RetrySourceConnectionId,
+                value.bytes_small()?,
+            );
+        }
 
         Ok(())
     }
+
+    fn stateless_reset_token(&self) -> Option<StatelessResetToken> {
+        self.stateless_reset_token
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -84,6 +124,10 @@ impl RoleSpecificTransportParameters for ClientSpecificTransportParameters {
     ) -> Result<()> {
         Ok(())
     }
+
+    fn stateless_reset_token(&self) -> Option<StatelessResetToken> {
+        None
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -97,11 +141,59 @@ pub struct TransportParameters<M, R> {
     pub initial_max_uni_streams: Option<u16>,
     pub max_packet_size: Option<u16>,
     pub ack_delay_exponent: Option<u8>,
+    /// The longest the endpoint delays acknowledging application data, sent in milliseconds.
+    /// `None` for the default of 25 milliseconds.
+    pub max_ack_delay: Option<Duration>,
     pub disable_migration: bool,
+    /// The version the endpoint chose and the versions it would have used, `None` if it does
+    /// not support compatible version negotiation (RFC 9368).
+    pub version_information: Option<VersionInformation>,
 
     pub role_specific_transport_parameters: R,
 }
 
+/// The value of the version_information transport parameter (RFC 9368 section 3).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VersionInformation {
+    /// The version the endpoint's packets were in when it sent the transport parameters.
+    pub chosen_version: Version,
+    /// The versions the endpoint supports, a client lists those it would upgrade to.
+    pub available_versions: Vec<Version>,
+}
+
+impl Readable for VersionInformation {
+    type Context = ();
+
+    fn read_with_context<R: Read>(reader: &mut R, _context: &Self::Context) -> Result<Self> {
+        trace!("reading version information");
+
+        let chosen_version = Version::read(reader)?;
+        let available_versions = Version::collect(reader)?;
+
+        let version_information = VersionInformation {
+            chosen_version,
+            available_versions,
+        };
+
+        debug!("read version information {:?}", version_information);
+
+        Ok(version_information)
+    }
+}
+
+impl Writable for VersionInformation {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        trace!("writing version information {:?}", self);
+
+        self.chosen_version.write(writer)?;
+        self.available_versions.write(writer)?;
+
+        debug!("written version information {:?}", self);
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct ClientHelloMessageParameters {
     pub initial_version: Version,
@@ -202,6 +294,10 @@ pub enum TransportParameterId {
     AckDelayExponent,
     InitialMaxUniStreams,
     DisableMigration,
+    MaxAckDelay,
+    OriginalDestinationConnectionId,
+    RetrySourceConnectionId,
+    VersionInformation,
 }
 
 impl From<TransportParameterId> for u16 {
@@ -218,6 +314,10 @@ impl From<TransportParameterId> for u16 {
             AckDelayExponent => 7,
             InitialMaxUniStreams => 8,
             DisableMigration => 9,
+            MaxAckDelay => 11,
+            OriginalDestinationConnectionId => 13,
+            RetrySourceConnectionId => 16,
+            VersionInformation => 17,
         }
     }
 }
@@ -238,6 +338,10 @@ impl TryFrom<u16> for TransportParameterId {
             7 => AckDelayExponent,
             8 => InitialMaxUniStreams,
             9 => DisableMigration,
+            11 => MaxAckDelay,
+            13 => OriginalDestinationConnectionId,
+            16 => RetrySourceConnectionId,
+            17 => VersionInformation,
             _ => bail!(ErrorKind::InvalidTransportParameterId(value)),
         };
 
@@ -418,12 +522,24 @@ where
             u8::from_bytes,
         )?;
 
+        let max_ack_delay = try_get_parameter_value(
+            &parameters_by_id,
+            TransportParameterId::MaxAckDelay,
+            |bytes| u16::from_bytes(bytes).map(|millis| Duration::from_millis(millis.into())),
+        )?;
+
         let disable_migration = try_get_parameter_value(
             &parameters_by_id,
             TransportParameterId::DisableMigration,
             |_| Ok(true),
         )?.unwrap_or(false);
 
+        let version_information = try_get_parameter_value(
+            &parameters_by_id,
+            TransportParameterId::VersionInformation,
+            VersionInformation::from_bytes,
+        )?;
+
         let role_specific_transport_parameters = RS::from_transport_parameters(&parameters_by_id)?;
 
         let transport_parameters = Self {
@@ -435,7 +551,9 @@ where
             initial_max_uni_streams,
             max_packet_size,
             ack_delay_exponent,
+            max_ack_delay,
             disable_migration,
+            version_information,
             role_specific_transport_parameters,
         };
 
@@ -487,9 +605,20 @@ where
             transport_parameters
                 .insert(TransportParameterId::AckDelayExponent, value.bytes_small()?);
         }
+        if let Some(value) = self.max_ack_delay {
+            let millis = value.as_secs() * 1000 + u64::from(value.subsec_millis());
+            let millis = u16::value_from(millis).unwrap_or(u16::max_value());
+            transport_parameters.insert(TransportParameterId::MaxAckDelay, millis.bytes_small()?);
+        }
         if self.disable_migration {
             transport_parameters.insert(TransportParameterId::DisableMigration, SmallVec::new());
         }
+        if let Some(ref value) = self.version_information {
+            transport_parameters.insert(
+                TransportParameterId::VersionInformation,
+                value.bytes_small()?,
+            );
+        }
 
         self.role_specific_transport_parameters
             .add_transport_parameters(&mut transport_parameters)?;
@@ -518,8 +647,9 @@ where
 mod test {
     use super::{ClientHelloMessageParameters, ClientSpecificTransportParameters,
                 EncryptedExtensionsMessageParameters, ServerSpecificTransportParameters,
-                TransportParameters};
-    use protocol::{self, Version};
+                TransportParameters, VersionInformation};
+    use protocol::{self, ConnectionId, StatelessResetToken, Version};
+    use std::time::Duration;
 
     #[test]
     fn write_read_client_hello() {
@@ -535,7 +665,12 @@ mod test {
             initial_max_uni_streams: Some(8),
             max_packet_size: Some(1024),
             ack_delay_exponent: Some(162),
+            max_ack_delay: Some(Duration::from_millis(40)),
             disable_migration: false,
+            version_information: Some(VersionInformation {
+                chosen_version: Version::V1,
+                available_versions: vec![Version::V1, Version::V2],
+            }),
             role_specific_transport_parameters: ClientSpecificTransportParameters,
         };
 
@@ -557,10 +692,14 @@ mod test {
             initial_max_uni_streams: Some(8),
             max_packet_size: Some(1024),
             ack_delay_exponent: Some(162),
+            max_ack_delay: Some(Duration::from_millis(40)),
             disable_migration: false,
+            version_information: None,
             role_specific_transport_parameters: ServerSpecificTransportParameters {
-                stateless_reset_token: None,
+                stateless_reset_token: Some(StatelessResetToken::from([0x42; 16])),
                 preferred_address: Some(()),
+                original_destination_connection_id: Some(ConnectionId::generate().unwrap()),
+                retry_source_connection_id: Some(ConnectionId::generate_with_len(8).unwrap()),
             },
         };
 
@@ -17,6 +17,16 @@ impl VarInt {
     pub fn into_inner(self) -> u64 {
         self.0
     }
+
+    /// The number of bytes this `VarInt` occupies when written.
+    pub fn encoded_len(self) -> usize {
+        match self.0 {
+            0...63 => 1,
+            64...16383 => 2,
+            16384...1073741823 => 4,
+            _ => 8,
+        }
+    }
 }
 
 impl Display for VarInt {
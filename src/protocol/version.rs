@@ -1,5 +1,6 @@
 use errors::*;
-use protocol::{Readable, Writable};
+use protocol::{Readable, WireFormat, Writable};
+use rand::{OsRng, Rng};
 use std::collections::HashSet;
 use std::fmt::{Display, Formatter, Result as FmtResult};
 use std::io::{Read, Write};
@@ -9,13 +10,37 @@ pub struct Version(u32);
 
 const IETF_DRAFT_MASK: u32 = 0xff000000;
 
-static SUPPORTED_VERSIONS: &'static [Version] = &[Version::DRAFT_IETF_08];
+/// The supported versions in ascending order of preference.
+static SUPPORTED_VERSIONS: &'static [Version] =
+    &[Version::DRAFT_IETF_08, Version::V1, Version::V2];
 
 impl Version {
     pub const NEGOTIATION: Version = Version(0);
 
     pub const DRAFT_IETF_08: Version = Version(0xff000008);
 
+    pub const V1: Version = Version(0x00000001);
+
+    /// QUIC version 2 (RFC 9369), which only differs from version 1 in its constants.
+    pub const V2: Version = Version(0x6b3343cf);
+
+    pub fn supported_versions() -> &'static [Version] {
+        SUPPORTED_VERSIONS
+    }
+
+    pub fn is_supported(self) -> bool {
+        SUPPORTED_VERSIONS.contains(&self)
+    }
+
+    /// The `WireFormat` of this version, `None` if this version is not supported.
+    pub fn wire_format(self) -> Option<WireFormat> {
+        match self {
+            Version::DRAFT_IETF_08 => Some(WireFormat::Draft08),
+            Version::V1 | Version::V2 => Some(WireFormat::V1),
+            _ => None,
+        }
+    }
+
     pub fn is_version_negotiation(self) -> bool {
         self.0 == 0
     }
@@ -24,6 +49,15 @@ impl Version {
         (self.0 & 0x0f0f0f0f) == 0x0a0a0a0a
     }
 
+    /// Generates a random version which forces version negotiation, listing one among the
+    /// supported versions checks peers ignore versions they do not know.
+    pub fn generate_force_negotiation() -> Result<Version> {
+        let mut rng =
+            OsRng::new().chain_err(|| ErrorKind::FailedToCreateCryptographicRandomNumberGenerator)?;
+
+        Ok(Version((rng.next_u32() & 0xf0f0f0f0) | 0x0a0a0a0a))
+    }
+
     pub fn is_ietf_consensus_reserved(self) -> bool {
         const IETF_CONSENSUS_MASK: u32 = 0x0000FFFF;
 
@@ -118,6 +152,14 @@ mod tests {
         assert_eq!(version.is_force_negotiation(), false);
     }
 
+    #[test]
+    pub fn generate_force_negotiation_is_force_negotiation() {
+        let version = Version::generate_force_negotiation().unwrap();
+
+        assert!(version.is_force_negotiation());
+        assert_eq!(version.is_supported(), false);
+    }
+
     #[test]
     pub fn ietf_draft_version_works() {
         let version = Version(0xff00000D);
@@ -135,6 +177,24 @@ mod tests {
         assert_eq!(highest_supported, None);
     }
 
+    #[test]
+    pub fn find_highest_supported_prefers_v1() {
+        let available = hashset![Version::DRAFT_IETF_08, Version::V1];
+
+        let highest_supported = Version::find_highest_supported(&available);
+
+        assert_eq!(highest_supported, Some(Version::V1));
+    }
+
+    #[test]
+    pub fn find_highest_supported_prefers_v2() {
+        let available = hashset![Version::V1, Version::V2];
+
+        let highest_supported = Version::find_highest_supported(&available);
+
+        assert_eq!(highest_supported, Some(Version::V2));
+    }
+
     #[test]
     pub fn find_highest_supported_returns_version_for_supported() {
         let available = hashset![Version::DRAFT_IETF_08];
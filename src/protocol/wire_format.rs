@@ -0,0 +1,46 @@
+use errors::*;
+use protocol::Writable;
+use std::io::Write;
+
+/// The encodings used by the supported QUIC versions.
+///
+/// Values which are encoded differently between versions are read and written according to this.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum WireFormat {
+    /// The encoding of IETF draft 08.
+    Draft08,
+    /// The encoding of QUIC version 1 (RFC 9000), which version 2 shares apart from the
+    /// codepoints of its long header packet types.
+    V1,
+}
+
+impl Default for WireFormat {
+    fn default() -> Self {
+        WireFormat::Draft08
+    }
+}
+
+/// A value which is written differently depending upon the `WireFormat`.
+pub trait WritableInFormat {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()>;
+}
+
+impl<T: WritableInFormat> WritableInFormat for [T] {
+    fn write_in_format<W: Write>(&self, writer: &mut W, wire_format: WireFormat) -> Result<()> {
+        for value in self {
+            value.write_in_format(writer, wire_format)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes a `WritableInFormat` in a specific `WireFormat`, allowing it to be used as a `Writable`.
+#[derive(Debug)]
+pub struct InFormat<'a, T: 'a + ?Sized>(pub &'a T, pub WireFormat);
+
+impl<'a, T: WritableInFormat + ?Sized> Writable for InFormat<'a, T> {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        self.0.write_in_format(writer, self.1)
+    }
+}
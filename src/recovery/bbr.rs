@@ -0,0 +1,659 @@
+use packets::{PacketNumber, PacketNumberSpace};
+use rand::{self, Rng};
+use recovery::{as_secs_f64, initial_congestion_window, CongestionController, RttEstimator};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// The gain which doubles the sending rate every round trip during startup, 2/ln(2).
+const HIGH_GAIN: f64 = 2.885;
+
+/// The pacing gains cycled through while probing for bandwidth, each lasts a min RTT.
+const PROBE_BANDWIDTH_GAINS: [f64; 8] = [1.25, 0.75, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0];
+
+/// The congestion window gain while probing for bandwidth.
+const PROBE_BANDWIDTH_CWND_GAIN: f64 = 2.0;
+
+/// The number of round trips the max bandwidth filter remembers samples for.
+const MAX_BANDWIDTH_FILTER_ROUNDS: u64 = 10;
+
+/// How long a min RTT sample is trusted before probing for a new one.
+const MIN_RTT_FILTER_SECS: u64 = 10;
+
+/// How long the congestion window is held down while probing for the min RTT.
+const PROBE_RTT_DURATION_MILLIS: u64 = 200;
+
+/// The pipe is full once the bandwidth has not grown by this factor for `FULL_BANDWIDTH_ROUNDS`.
+const FULL_BANDWIDTH_GROWTH: f64 = 1.25;
+
+const FULL_BANDWIDTH_ROUNDS: u32 = 3;
+
+/// The smallest congestion window in datagrams, enough to keep ACK frames flowing.
+const MIN_PIPE_CWND_DATAGRAMS: usize = 4;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BbrState {
+    /// The sending rate doubles every round trip until the bandwidth stops growing.
+    Startup,
+    /// The queue built during startup is drained.
+    Drain,
+    /// The sending rate cycles around the estimated bandwidth to discover more.
+    ProbeBandwidth,
+    /// Very little is sent so the min RTT can be measured without a queue.
+    ProbeRtt,
+}
+
+/// The delivery state when a packet was sent, used to sample the delivery rate once it is
+/// acknowledged.
+#[derive(Debug, Clone)]
+struct PacketDeliveryState {
+    delivered: usize,
+    delivered_time: Instant,
+}
+
+/// The maximum bandwidth sampled over the last `MAX_BANDWIDTH_FILTER_ROUNDS` round trips.
+#[derive(Debug, Clone, Default)]
+struct MaxBandwidthFilter {
+    /// Samples by round in decreasing order of bandwidth.
+    samples: VecDeque<(u64, f64)>,
+}
+
+impl MaxBandwidthFilter {
+    fn update(&mut self, round: u64, bandwidth: f64) {
+        while self.samples
+            .back()
+            .map_or(false, |&(_, sample)| sample <= bandwidth)
+        {
+            self.samples.pop_back();
+        }
+        self.samples.push_back((round, bandwidth));
+
+        while self.samples
+            .front()
+            .map_or(false, |&(sample_round, _)| {
+                sample_round + MAX_BANDWIDTH_FILTER_ROUNDS <= round
+            }) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The maximum bandwidth in bytes per second.
+    fn get(&self) -> f64 {
+        self.samples.front().map_or(0.0, |&(_, bandwidth)| bandwidth)
+    }
+}
+
+/// The BBR congestion controller.
+///
+/// Rather than treating loss as a sign of congestion BBR models the path from the maximum
+/// delivery rate and the minimum RTT, keeping about one bandwidth-delay product in flight. This
+/// keeps lossy links busy where loss-based controllers keep shrinking their window.
+#[derive(Debug, Clone)]
+pub struct Bbr {
+    max_datagram_size: usize,
+    state: BbrState,
+    congestion_window: usize,
+    bytes_in_flight: usize,
+    pacing_gain: f64,
+    cwnd_gain: f64,
+    /// The total bytes acknowledged and when the most recent of them were.
+    delivered: usize,
+    delivered_time: Option<Instant>,
+    /// The delivery state of each packet in flight by the space and number it was sent with.
+    sent_packets: HashMap<(PacketNumberSpace, PacketNumber), PacketDeliveryState>,
+    round_count: u64,
+    /// A round trip ends once a packet sent after this many bytes were delivered is
+    /// acknowledged.
+    next_round_delivered: usize,
+    round_start: bool,
+    max_bandwidth: MaxBandwidthFilter,
+    min_rtt: Option<Duration>,
+    min_rtt_stamp: Option<Instant>,
+    min_rtt_expired: bool,
+    full_bandwidth: f64,
+    full_bandwidth_count: u32,
+    filled_pipe: bool,
+    cycle_index: usize,
+    cycle_stamp: Option<Instant>,
+    probe_rtt_done_stamp: Option<Instant>,
+    probe_rtt_round_done: bool,
+    /// The congestion window before probing for the min RTT, restored afterwards.
+    prior_cwnd: usize,
+}
+
+impl Bbr {
+    pub fn new(max_datagram_size: usize) -> Self {
+        Self {
+            max_datagram_size,
+            state: BbrState::Startup,
+            congestion_window: initial_congestion_window(max_datagram_size),
+            bytes_in_flight: 0,
+            pacing_gain: HIGH_GAIN,
+            cwnd_gain: HIGH_GAIN,
+            delivered: 0,
+            delivered_time: None,
+            sent_packets: HashMap::new(),
+            round_count: 0,
+            next_round_delivered: 0,
+            round_start: false,
+            max_bandwidth: MaxBandwidthFilter::default(),
+            min_rtt: None,
+            min_rtt_stamp: None,
+            min_rtt_expired: false,
+            full_bandwidth: 0.0,
+            full_bandwidth_count: 0,
+            filled_pipe: false,
+            cycle_index: 0,
+            cycle_stamp: None,
+            probe_rtt_done_stamp: None,
+            probe_rtt_round_done: false,
+            prior_cwnd: 0,
+        }
+    }
+
+    pub fn state(&self) -> BbrState {
+        self.state
+    }
+
+    /// The estimated bottleneck bandwidth in bytes per second.
+    pub fn max_bandwidth(&self) -> f64 {
+        self.max_bandwidth.get()
+    }
+
+    fn min_pipe_cwnd(&self) -> usize {
+        MIN_PIPE_CWND_DATAGRAMS * self.max_datagram_size
+    }
+
+    /// The congestion window which keeps `gain` bandwidth-delay products in flight.
+    fn target_cwnd(&self, gain: f64) -> usize {
+        let max_bandwidth = self.max_bandwidth.get();
+
+        let min_rtt = match self.min_rtt {
+            Some(min_rtt) if max_bandwidth > 0.0 => min_rtt,
+            _ => return initial_congestion_window(self.max_datagram_size),
+        };
+
+        let bandwidth_delay_product = max_bandwidth * as_secs_f64(min_rtt);
+
+        ((bandwidth_delay_product * gain) as usize).max(self.min_pipe_cwnd())
+    }
+
+    fn update_round(&mut self, packet_delivered: usize) {
+        self.round_start = packet_delivered >= self.next_round_delivered;
+
+        if self.round_start {
+            self.next_round_delivered = self.delivered;
+            self.round_count += 1;
+        }
+    }
+
+    /// Samples the delivery rate from the bytes delivered since `delivery_state` was recorded.
+    fn update_bandwidth(&mut self, delivery_state: &PacketDeliveryState, now: Instant) {
+        let interval = as_secs_f64(now.duration_since(delivery_state.delivered_time));
+
+        if interval <= 0.0 {
+            return;
+        }
+
+        let bandwidth = (self.delivered - delivery_state.delivered) as f64 / interval;
+
+        self.max_bandwidth.update(self.round_count, bandwidth);
+    }
+
+    fn update_min_rtt(&mut self, rtt_estimator: &RttEstimator, now: Instant) {
+        // nothing has been sampled while the estimator still holds the initial RTT
+        if rtt_estimator.min_rtt().is_none() {
+            return;
+        }
+
+        let sample = rtt_estimator.latest_rtt();
+
+        self.min_rtt_expired = self.min_rtt_stamp.map_or(false, |stamp| {
+            now.duration_since(stamp) > Duration::from_secs(MIN_RTT_FILTER_SECS)
+        });
+
+        if self.min_rtt.map_or(true, |min_rtt| sample <= min_rtt) || self.min_rtt_expired {
+            self.min_rtt = Some(sample);
+            self.min_rtt_stamp = Some(now);
+        }
+    }
+
+    fn check_full_pipe(&mut self) {
+        if self.filled_pipe || !self.round_start {
+            return;
+        }
+
+        let max_bandwidth = self.max_bandwidth.get();
+
+        if max_bandwidth >= self.full_bandwidth * FULL_BANDWIDTH_GROWTH {
+            self.full_bandwidth = max_bandwidth;
+            self.full_bandwidth_count = 0;
+            return;
+        }
+
+        self.full_bandwidth_count += 1;
+        self.filled_pipe = self.full_bandwidth_count >= FULL_BANDWIDTH_ROUNDS;
+    }
+
+    fn check_drain(&mut self, now: Instant) {
+        if self.state == BbrState::Startup && self.filled_pipe {
+            debug!("bbr pipe is full, draining the queue");
+
+            self.state = BbrState::Drain;
+            self.pacing_gain = 1.0 / HIGH_GAIN;
+            self.cwnd_gain = HIGH_GAIN;
+        }
+
+        if self.state == BbrState::Drain && self.bytes_in_flight <= self.target_cwnd(1.0) {
+            self.enter_probe_bandwidth(now);
+        }
+    }
+
+    fn enter_startup(&mut self) {
+        self.state = BbrState::Startup;
+        self.pacing_gain = HIGH_GAIN;
+        self.cwnd_gain = HIGH_GAIN;
+    }
+
+    fn enter_probe_bandwidth(&mut self, now: Instant) {
+        debug!("bbr probing for bandwidth");
+
+        self.state = BbrState::ProbeBandwidth;
+        self.cwnd_gain = PROBE_BANDWIDTH_CWND_GAIN;
+
+        // flows start at random points of the cycle, but never in the phase which drains
+        let last_index = PROBE_BANDWIDTH_GAINS.len() - 1;
+        self.cycle_index = last_index - rand::thread_rng().gen_range(0, last_index);
+        self.advance_cycle_phase(now);
+    }
+
+    fn advance_cycle_phase(&mut self, now: Instant) {
+        self.cycle_index = (self.cycle_index + 1) % PROBE_BANDWIDTH_GAINS.len();
+        self.cycle_stamp = Some(now);
+        self.pacing_gain = PROBE_BANDWIDTH_GAINS[self.cycle_index];
+    }
+
+    fn update_probe_bandwidth_cycle(&mut self, now: Instant) {
+        if self.state != BbrState::ProbeBandwidth {
+            return;
+        }
+
+        let is_full_length = match (self.cycle_stamp, self.min_rtt) {
+            (Some(cycle_stamp), Some(min_rtt)) => now.duration_since(cycle_stamp) > min_rtt,
+            _ => false,
+        };
+
+        let should_advance = if self.pacing_gain > 1.0 {
+            is_full_length && self.bytes_in_flight >= self.target_cwnd(self.pacing_gain)
+        } else if self.pacing_gain < 1.0 {
+            is_full_length || self.bytes_in_flight <= self.target_cwnd(1.0)
+        } else {
+            is_full_length
+        };
+
+        if should_advance {
+            self.advance_cycle_phase(now);
+        }
+    }
+
+    fn check_probe_rtt(&mut self, now: Instant) {
+        if self.state != BbrState::ProbeRtt && self.min_rtt_expired {
+            debug!("bbr min rtt expired, probing for a new one");
+
+            self.state = BbrState::ProbeRtt;
+            self.pacing_gain = 1.0;
+            self.cwnd_gain = 1.0;
+            self.prior_cwnd = self.congestion_window;
+            self.probe_rtt_done_stamp = None;
+        }
+
+        if self.state != BbrState::ProbeRtt {
+            return;
+        }
+
+        match self.probe_rtt_done_stamp {
+            None if self.bytes_in_flight <= self.min_pipe_cwnd() => {
+                self.probe_rtt_done_stamp =
+                    Some(now + Duration::from_millis(PROBE_RTT_DURATION_MILLIS));
+                self.probe_rtt_round_done = false;
+                self.next_round_delivered = self.delivered;
+            }
+            None => {}
+            Some(probe_rtt_done_stamp) => {
+                if self.round_start {
+                    self.probe_rtt_round_done = true;
+                }
+
+                if self.probe_rtt_round_done && now >= probe_rtt_done_stamp {
+                    self.min_rtt_stamp = Some(now);
+                    self.min_rtt_expired = false;
+                    self.congestion_window = self.congestion_window.max(self.prior_cwnd);
+
+                    if self.filled_pipe {
+                        self.enter_probe_bandwidth(now);
+                    } else {
+                        self.enter_startup();
+                    }
+                }
+            }
+        }
+    }
+
+    fn update_congestion_window(&mut self, acknowledged: usize) {
+        let target_cwnd = self.target_cwnd(self.cwnd_gain);
+
+        if self.state == BbrState::ProbeRtt {
+            self.congestion_window = self.congestion_window.min(self.min_pipe_cwnd());
+            return;
+        }
+
+        if self.filled_pipe {
+            self.congestion_window = (self.congestion_window + acknowledged).min(target_cwnd);
+        } else if self.congestion_window < target_cwnd
+            || self.delivered < initial_congestion_window(self.max_datagram_size)
+        {
+            self.congestion_window += acknowledged;
+        }
+
+        self.congestion_window = self.congestion_window.max(self.min_pipe_cwnd());
+    }
+}
+
+impl CongestionController for Bbr {
+    fn on_packet_sent(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_number: PacketNumber,
+        size: usize,
+        time_sent: Instant,
+    ) {
+        // the delivery rate is measured from when sending resumed after being idle
+        if self.bytes_in_flight == 0 || self.delivered_time.is_none() {
+            self.delivered_time = Some(time_sent);
+        }
+
+        self.sent_packets.insert(
+            (packet_number_space, packet_number),
+            PacketDeliveryState {
+                delivered: self.delivered,
+                delivered_time: self.delivered_time.unwrap_or(time_sent),
+            },
+        );
+
+        self.bytes_in_flight += size;
+    }
+
+    fn on_packet_acknowledged(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_number: PacketNumber,
+        size: usize,
+        _time_sent: Instant,
+        rtt_estimator: &RttEstimator,
+        now: Instant,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+        self.delivered += size;
+        self.delivered_time = Some(now);
+
+        match self.sent_packets
+            .remove(&(packet_number_space, packet_number))
+        {
+            Some(delivery_state) => {
+                self.update_round(delivery_state.delivered);
+                self.update_bandwidth(&delivery_state, now);
+            }
+            None => self.round_start = false,
+        }
+
+        self.update_min_rtt(rtt_estimator, now);
+        self.check_full_pipe();
+        self.check_drain(now);
+        self.update_probe_bandwidth_cycle(now);
+        self.check_probe_rtt(now);
+        self.update_congestion_window(size);
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_numbers: &[PacketNumber],
+        size: usize,
+        _largest_lost_time_sent: Instant,
+        _now: Instant,
+    ) {
+        // loss is not a congestion signal, only the bytes in flight change
+        self.on_packets_discarded(packet_number_space, packet_numbers, size);
+    }
+
+    fn on_packets_discarded(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_numbers: &[PacketNumber],
+        size: usize,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+
+        for &packet_number in packet_numbers {
+            self.sent_packets
+                .remove(&(packet_number_space, packet_number));
+        }
+    }
+
+    fn on_persistent_congestion(&mut self) {
+        self.congestion_window = self.min_pipe_cwnd();
+
+        debug!(
+            "persistent congestion, congestion window reduced to {} bytes",
+            self.congestion_window
+        );
+    }
+
+    fn congestion_window(&self) -> usize {
+        self.congestion_window
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+
+    /// The bandwidth estimate scaled by the pacing gain of the current state, `None` until the
+    /// bandwidth has been sampled.
+    fn pacing_rate(&self) -> Option<f64> {
+        let max_bandwidth = self.max_bandwidth.get();
+
+        if max_bandwidth > 0.0 {
+            Some(self.pacing_gain * max_bandwidth)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bbr, BbrState, MaxBandwidthFilter};
+    use packets::{PacketNumber, PacketNumberSpace};
+    use recovery::{CongestionController, RttEstimator};
+    use std::time::{Duration, Instant};
+
+    const RTT_MILLIS: u64 = 100;
+
+    fn sampled_rtt_estimator(rtt: Duration) -> RttEstimator {
+        let mut rtt_estimator = RttEstimator::new();
+        rtt_estimator.update(rtt, Duration::from_millis(0), Duration::from_millis(0));
+        rtt_estimator
+    }
+
+    /// Sends `count` datagrams at `sent_at` and acknowledges them a round trip later.
+    ///
+    /// # Returns
+    /// When the datagrams were acknowledged.
+    fn round_trip(
+        bbr: &mut Bbr,
+        count: usize,
+        sent_at: Instant,
+        rtt_estimator: &RttEstimator,
+    ) -> Instant {
+        let acknowledged_at = sent_at + Duration::from_millis(RTT_MILLIS);
+        let packet_numbers: Vec<_> = (0..count as u32).map(PacketNumber::from).collect();
+
+        for &packet_number in &packet_numbers {
+            bbr.on_packet_sent(
+                PacketNumberSpace::ApplicationData,
+                packet_number,
+                1000,
+                sent_at,
+            );
+        }
+        for &packet_number in &packet_numbers {
+            bbr.on_packet_acknowledged(
+                PacketNumberSpace::ApplicationData,
+                packet_number,
+                1000,
+                sent_at,
+                rtt_estimator,
+                acknowledged_at,
+            );
+        }
+
+        acknowledged_at
+    }
+
+    #[test]
+    fn max_bandwidth_filter_forgets_old_rounds() {
+        let mut filter = MaxBandwidthFilter::default();
+
+        filter.update(1, 100.0);
+        filter.update(2, 50.0);
+        assert_eq!(filter.get(), 100.0);
+
+        filter.update(11, 10.0);
+        assert_eq!(filter.get(), 50.0);
+
+        filter.update(12, 60.0);
+        assert_eq!(filter.get(), 60.0);
+    }
+
+    #[test]
+    fn startup_grows_window_by_acknowledged_bytes() {
+        let mut bbr = Bbr::new(1000);
+        let rtt_estimator = sampled_rtt_estimator(Duration::from_millis(RTT_MILLIS));
+
+        round_trip(&mut bbr, 10, Instant::now(), &rtt_estimator);
+
+        assert_eq!(bbr.state(), BbrState::Startup);
+        assert_eq!(bbr.congestion_window(), 20_000);
+        assert_eq!(bbr.bytes_in_flight(), 0);
+        assert!(bbr.max_bandwidth() > 0.0);
+    }
+
+    #[test]
+    fn bandwidth_plateau_ends_startup() {
+        let mut bbr = Bbr::new(1000);
+        let rtt_estimator = sampled_rtt_estimator(Duration::from_millis(RTT_MILLIS));
+
+        // the path delivers 10 datagrams per round trip however many are sent
+        let mut now = Instant::now();
+        for _ in 0..6 {
+            now = round_trip(&mut bbr, 10, now, &rtt_estimator);
+        }
+
+        assert_eq!(bbr.state(), BbrState::ProbeBandwidth);
+
+        // the window settles at twice the 10kB bandwidth-delay product
+        assert_eq!(bbr.congestion_window(), 20_000);
+    }
+
+    #[test]
+    fn expired_min_rtt_enters_probe_rtt() {
+        let mut bbr = Bbr::new(1000);
+        let rtt_estimator = sampled_rtt_estimator(Duration::from_millis(RTT_MILLIS));
+
+        let mut now = Instant::now();
+        for _ in 0..6 {
+            now = round_trip(&mut bbr, 10, now, &rtt_estimator);
+        }
+
+        let later_rtt_estimator = sampled_rtt_estimator(Duration::from_millis(RTT_MILLIS * 2));
+        round_trip(
+            &mut bbr,
+            1,
+            now + Duration::from_secs(11),
+            &later_rtt_estimator,
+        );
+
+        assert_eq!(bbr.state(), BbrState::ProbeRtt);
+        assert_eq!(bbr.congestion_window(), 4_000);
+    }
+
+    #[test]
+    fn loss_does_not_shrink_window() {
+        let mut bbr = Bbr::new(1000);
+        let now = Instant::now();
+
+        bbr.on_packet_sent(
+            PacketNumberSpace::ApplicationData,
+            PacketNumber::from(0u32),
+            1000,
+            now,
+        );
+        bbr.on_packets_lost(
+            PacketNumberSpace::ApplicationData,
+            &[PacketNumber::from(0u32)],
+            1000,
+            now,
+            now + Duration::from_millis(RTT_MILLIS),
+        );
+
+        assert_eq!(bbr.congestion_window(), 10_000);
+        assert_eq!(bbr.bytes_in_flight(), 0);
+        assert!(bbr.sent_packets.is_empty());
+    }
+
+    #[test]
+    fn packet_acknowledged_after_newer_packet_is_lost_is_sampled() {
+        let mut bbr = Bbr::new(1000);
+        let rtt_estimator = sampled_rtt_estimator(Duration::from_millis(RTT_MILLIS));
+        let sent_at = Instant::now();
+
+        for packet_number in 0..2u32 {
+            bbr.on_packet_sent(
+                PacketNumberSpace::ApplicationData,
+                PacketNumber::from(packet_number),
+                1000,
+                sent_at,
+            );
+        }
+
+        bbr.on_packets_lost(
+            PacketNumberSpace::ApplicationData,
+            &[PacketNumber::from(1u32)],
+            1000,
+            sent_at,
+            sent_at,
+        );
+        bbr.on_packet_acknowledged(
+            PacketNumberSpace::ApplicationData,
+            PacketNumber::from(0u32),
+            1000,
+            sent_at,
+            &rtt_estimator,
+            sent_at + Duration::from_millis(RTT_MILLIS),
+        );
+
+        assert!(bbr.max_bandwidth() > 0.0);
+        assert!(bbr.sent_packets.is_empty());
+    }
+
+    #[test]
+    fn discarded_packets_are_forgotten() {
+        let mut bbr = Bbr::new(1000);
+        let now = Instant::now();
+
+        bbr.on_packet_sent(PacketNumberSpace::Initial, PacketNumber::from(0u32), 1000, now);
+        bbr.on_packets_discarded(PacketNumberSpace::Initial, &[PacketNumber::from(0u32)], 1000);
+
+        assert_eq!(bbr.bytes_in_flight(), 0);
+        assert!(bbr.sent_packets.is_empty());
+    }
+}
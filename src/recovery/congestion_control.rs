@@ -0,0 +1,77 @@
+#[cfg(feature = "bbr")]
+use recovery::Bbr;
+use recovery::{CongestionController, Cubic, NewReno};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::sync::Arc;
+
+/// Builds a congestion controller for a new connection from its maximum datagram size.
+pub type CongestionControllerFactory = Fn(usize) -> Box<CongestionController> + Send + Sync;
+
+/// The congestion controller each connection is created with.
+#[derive(Clone)]
+pub enum CongestionControl {
+    /// `NewReno`, the default.
+    NewReno,
+
+    /// `Cubic`, which suits paths with a large bandwidth-delay product.
+    Cubic,
+
+    /// `Bbr`, which keeps lossy links busy.
+    #[cfg(feature = "bbr")]
+    Bbr,
+
+    /// A controller supplied by the application.
+    Custom(Arc<CongestionControllerFactory>),
+}
+
+impl CongestionControl {
+    /// Creates the congestion controller for a connection sending datagrams of up to
+    /// `max_datagram_size` bytes.
+    pub fn build(&self, max_datagram_size: usize) -> Box<CongestionController> {
+        match self {
+            CongestionControl::NewReno => Box::new(NewReno::new(max_datagram_size)),
+            CongestionControl::Cubic => Box::new(Cubic::new(max_datagram_size)),
+            #[cfg(feature = "bbr")]
+            CongestionControl::Bbr => Box::new(Bbr::new(max_datagram_size)),
+            CongestionControl::Custom(factory) => factory(max_datagram_size),
+        }
+    }
+}
+
+impl Default for CongestionControl {
+    fn default() -> Self {
+        CongestionControl::NewReno
+    }
+}
+
+impl Debug for CongestionControl {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            CongestionControl::NewReno => fmt.write_str("NewReno"),
+            CongestionControl::Cubic => fmt.write_str("Cubic"),
+            #[cfg(feature = "bbr")]
+            CongestionControl::Bbr => fmt.write_str("Bbr"),
+            CongestionControl::Custom(_) => fmt.write_str("Custom"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CongestionControl;
+    use recovery::{CongestionController, NewReno};
+    use std::sync::Arc;
+
+    #[test]
+    fn custom_factory_is_given_max_datagram_size() {
+        let congestion_control = CongestionControl::Custom(Arc::new(
+            |max_datagram_size| -> Box<CongestionController> {
+                Box::new(NewReno::new(max_datagram_size / 2))
+            },
+        ));
+
+        let congestion_controller = congestion_control.build(2400);
+
+        assert_eq!(congestion_controller.congestion_window(), 12_000);
+    }
+}
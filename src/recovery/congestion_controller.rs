@@ -0,0 +1,153 @@
+use packets::{PacketNumber, PacketNumberSpace};
+use recovery::RttEstimator;
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+
+/// Persistent congestion is declared once lost packets span this many probe timeouts (RFC 9002
+/// section 7.6.1).
+const PERSISTENT_CONGESTION_THRESHOLD: u32 = 3;
+
+/// Limits how many bytes a connection may have in flight.
+///
+/// Only ack-eliciting packets count towards the bytes in flight, packets carrying nothing but
+/// ACK frames are always sent.
+pub trait CongestionController: Debug + Send {
+    /// The packet `packet_number` of `size` bytes was sent in `packet_number_space` at
+    /// `time_sent`.
+    fn on_packet_sent(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_number: PacketNumber,
+        size: usize,
+        time_sent: Instant,
+    );
+
+    /// The packet `packet_number` of `size` bytes sent in `packet_number_space` at `time_sent`
+    /// was acknowledged at `now`.
+    fn on_packet_acknowledged(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_number: PacketNumber,
+        size: usize,
+        time_sent: Instant,
+        rtt_estimator: &RttEstimator,
+        now: Instant,
+    );
+
+    /// The packets `packet_numbers` sent in `packet_number_space`, totalling `size` bytes, were
+    /// declared lost at `now`, the most recent of them was sent at `largest_lost_time_sent`.
+    fn on_packets_lost(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_numbers: &[PacketNumber],
+        size: usize,
+        largest_lost_time_sent: Instant,
+        now: Instant,
+    );
+
+    /// The packets `packet_numbers` sent in `packet_number_space`, totalling `size` bytes, will
+    /// never be acknowledged or declared lost, such as rejected 0-RTT packets, so they no longer
+    /// count towards the bytes in flight.
+    fn on_packets_discarded(
+        &mut self,
+        packet_number_space: PacketNumberSpace,
+        packet_numbers: &[PacketNumber],
+        size: usize,
+    );
+
+    /// Packets were lost over a long enough period that the path is assumed to have changed.
+    fn on_persistent_congestion(&mut self);
+
+    /// The number of bytes which may be in flight.
+    fn congestion_window(&self) -> usize;
+
+    /// The number of bytes sent in packets which have not been acknowledged or declared lost.
+    fn bytes_in_flight(&self) -> usize;
+
+    /// The rate in bytes per second packets should be paced at, `None` to spread the congestion
+    /// window over the smoothed RTT.
+    fn pacing_rate(&self) -> Option<f64> {
+        None
+    }
+
+    /// Whether a packet of `size` bytes may be sent now.
+    fn can_send(&self, size: usize) -> bool {
+        self.bytes_in_flight() + size <= self.congestion_window()
+    }
+}
+
+/// The initial congestion window for packets of `max_datagram_size` bytes (RFC 9002 section
+/// 7.2).
+pub fn initial_congestion_window(max_datagram_size: usize) -> usize {
+    (10 * max_datagram_size).min((2 * max_datagram_size).max(14_720))
+}
+
+/// The smallest the congestion window is ever reduced to.
+pub fn minimum_congestion_window(max_datagram_size: usize) -> usize {
+    2 * max_datagram_size
+}
+
+/// Whether the ack-eliciting packets lost between `earliest_lost_time_sent` and
+/// `largest_lost_time_sent` indicate persistent congestion.
+///
+/// No congestion is persistent before the RTT has been sampled.
+pub fn is_persistent_congestion(
+    earliest_lost_time_sent: Instant,
+    largest_lost_time_sent: Instant,
+    rtt_estimator: &RttEstimator,
+    max_ack_delay: Duration,
+) -> bool {
+    if rtt_estimator.min_rtt().is_none() {
+        return false;
+    }
+
+    let congestion_period =
+        rtt_estimator.probe_timeout(max_ack_delay) * PERSISTENT_CONGESTION_THRESHOLD;
+
+    largest_lost_time_sent.duration_since(earliest_lost_time_sent) >= congestion_period
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_persistent_congestion;
+    use recovery::RttEstimator;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn persistent_congestion_requires_rtt_sample() {
+        let now = Instant::now();
+
+        assert!(!is_persistent_congestion(
+            now,
+            now + Duration::from_secs(60),
+            &RttEstimator::new(),
+            Duration::from_millis(25),
+        ));
+    }
+
+    #[test]
+    fn persistent_congestion_spans_three_probe_timeouts() {
+        let now = Instant::now();
+        let mut rtt_estimator = RttEstimator::new();
+        rtt_estimator.update(
+            Duration::from_millis(100),
+            Duration::from_millis(0),
+            Duration::from_millis(25),
+        );
+
+        // the probe timeout is 100ms + 4 * 50ms + 25ms
+        let max_ack_delay = Duration::from_millis(25);
+        assert!(!is_persistent_congestion(
+            now,
+            now + Duration::from_millis(974),
+            &rtt_estimator,
+            max_ack_delay,
+        ));
+        assert!(is_persistent_congestion(
+            now,
+            now + Duration::from_millis(975),
+            &rtt_estimator,
+            max_ack_delay,
+        ));
+    }
+}
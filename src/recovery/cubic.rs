@@ -0,0 +1,369 @@
+use packets::{PacketNumber, PacketNumberSpace};
+use recovery::{as_secs_f64, initial_congestion_window, minimum_congestion_window,
+               CongestionController, RttEstimator};
+use std::time::Instant;
+
+/// Scales how quickly the window grows away from `w_max` (RFC 8312 section 5).
+const C: f64 = 0.4;
+
+/// The factor the window is multiplied by when a packet is lost.
+const BETA: f64 = 0.7;
+
+/// The CUBIC congestion controller described in RFC 8312.
+///
+/// After a loss the window grows quickly back towards the size it was at when the loss
+/// happened, levels off around it and then probes beyond it. This makes better use of paths
+/// with a large bandwidth-delay product than `NewReno`.
+#[derive(Debug, Clone)]
+pub struct Cubic {
+    max_datagram_size: usize,
+    congestion_window: usize,
+    bytes_in_flight: usize,
+    slow_start_threshold: usize,
+    /// When the current recovery period started, packets sent before then do not change the
+    /// congestion window.
+    congestion_recovery_start_time: Option<Instant>,
+    /// The window in bytes before the most recent reduction.
+    w_max: f64,
+    /// `w_max` before the most recent reduction, used for fast convergence.
+    w_last_max: f64,
+    /// The window in bytes a NewReno sender would have had since the epoch started.
+    w_est: f64,
+    /// The time in seconds the window takes to grow back to `w_max`.
+    k: f64,
+    /// When the window began growing in congestion avoidance.
+    epoch_start: Option<Instant>,
+    /// Growth of the window smaller than a byte, carried over to the next acknowledgement.
+    window_increase: f64,
+}
+
+impl Cubic {
+    pub fn new(max_datagram_size: usize) -> Self {
+        Self {
+            max_datagram_size,
+            congestion_window: initial_congestion_window(max_datagram_size),
+            bytes_in_flight: 0,
+            slow_start_threshold: usize::max_value(),
+            congestion_recovery_start_time: None,
+            w_max: 0.0,
+            w_last_max: 0.0,
+            w_est: 0.0,
+            k: 0.0,
+            epoch_start: None,
+            window_increase: 0.0,
+        }
+    }
+
+    fn in_congestion_recovery(&self, time_sent: Instant) -> bool {
+        self.congestion_recovery_start_time
+            .map_or(false, |start_time| time_sent <= start_time)
+    }
+
+    /// The window in bytes the cubic function gives `t` seconds after the epoch started.
+    fn w_cubic(&self, t: f64) -> f64 {
+        let max_datagram_size = self.max_datagram_size as f64;
+
+        (C * (t - self.k).powi(3) + self.w_max / max_datagram_size) * max_datagram_size
+    }
+
+    fn congestion_avoidance(&mut self, size: usize, rtt_estimator: &RttEstimator, now: Instant) {
+        let congestion_window = self.congestion_window as f64;
+        let max_datagram_size = self.max_datagram_size as f64;
+
+        let epoch_start = match self.epoch_start {
+            Some(epoch_start) => epoch_start,
+            None => {
+                if congestion_window < self.w_max {
+                    self.k = ((self.w_max - congestion_window) / max_datagram_size / C).cbrt();
+                } else {
+                    self.k = 0.0;
+                    self.w_max = congestion_window;
+                }
+                self.w_est = congestion_window;
+                self.epoch_start = Some(now);
+
+                now
+            }
+        };
+
+        // the window a NewReno sender with the same average throughput would have
+        let alpha = 3.0 * (1.0 - BETA) / (1.0 + BETA);
+        self.w_est += alpha * max_datagram_size * size as f64 / congestion_window;
+
+        let t = as_secs_f64(now.duration_since(epoch_start) + rtt_estimator.smoothed_rtt());
+        let w_cubic = self.w_cubic(t);
+
+        if w_cubic < self.w_est {
+            // TCP-friendly region
+            self.congestion_window = self.w_est as usize;
+        } else if w_cubic > congestion_window {
+            // concave and convex regions
+            self.window_increase +=
+                (w_cubic - congestion_window) * size as f64 / congestion_window;
+
+            let increase = self.window_increase.floor();
+            self.window_increase -= increase;
+            self.congestion_window += increase as usize;
+        }
+    }
+}
+
+impl CongestionController for Cubic {
+    fn on_packet_sent(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_number: PacketNumber,
+        size: usize,
+        _time_sent: Instant,
+    ) {
+        self.bytes_in_flight += size;
+    }
+
+    fn on_packet_acknowledged(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_number: PacketNumber,
+        size: usize,
+        time_sent: Instant,
+        rtt_estimator: &RttEstimator,
+        now: Instant,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+
+        if self.in_congestion_recovery(time_sent) {
+            return;
+        }
+
+        if self.congestion_window < self.slow_start_threshold {
+            // slow start
+            self.congestion_window += size;
+        } else {
+            self.congestion_avoidance(size, rtt_estimator, now);
+        }
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_numbers: &[PacketNumber],
+        size: usize,
+        largest_lost_time_sent: Instant,
+        now: Instant,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+
+        // only one reduction is made for the packets in flight when the first loss was detected
+        if self.in_congestion_recovery(largest_lost_time_sent) {
+            return;
+        }
+
+        self.congestion_recovery_start_time = Some(now);
+        self.epoch_start = None;
+        self.window_increase = 0.0;
+
+        let congestion_window = self.congestion_window as f64;
+
+        // fast convergence releases bandwidth to new flows when the window keeps shrinking
+        if congestion_window < self.w_last_max {
+            self.w_last_max = congestion_window;
+            self.w_max = congestion_window * (1.0 + BETA) / 2.0;
+        } else {
+            self.w_last_max = congestion_window;
+            self.w_max = congestion_window;
+        }
+
+        self.slow_start_threshold = ((congestion_window * BETA) as usize)
+            .max(minimum_congestion_window(self.max_datagram_size));
+        self.congestion_window = self.slow_start_threshold;
+
+        debug!(
+            "congestion window reduced to {} bytes",
+            self.congestion_window
+        );
+    }
+
+    fn on_packets_discarded(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_numbers: &[PacketNumber],
+        size: usize,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+    }
+
+    fn on_persistent_congestion(&mut self) {
+        self.congestion_window = minimum_congestion_window(self.max_datagram_size);
+        self.congestion_recovery_start_time = None;
+        self.epoch_start = None;
+        self.window_increase = 0.0;
+
+        debug!(
+            "persistent congestion, congestion window reduced to {} bytes",
+            self.congestion_window
+        );
+    }
+
+    fn congestion_window(&self) -> usize {
+        self.congestion_window
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cubic;
+    use packets::{PacketNumber, PacketNumberSpace};
+    use recovery::{CongestionController, RttEstimator};
+    use std::time::{Duration, Instant};
+
+    fn rtt_estimator(rtt: Duration) -> RttEstimator {
+        let mut rtt_estimator = RttEstimator::new();
+        rtt_estimator.update(rtt, Duration::from_millis(0), Duration::from_millis(0));
+        rtt_estimator
+    }
+
+    /// A controller which lost a packet at `lost_at` while its window was 10 datagrams.
+    fn cubic_after_loss(lost_at: Instant) -> Cubic {
+        let mut cubic = Cubic::new(1000);
+        cubic.on_packets_lost(PacketNumberSpace::ApplicationData, &[], 0, lost_at, lost_at);
+        cubic
+    }
+
+    /// Sends and acknowledges `count` datagrams sent at `sent_at`, acknowledged at `now`.
+    fn acknowledge(
+        cubic: &mut Cubic,
+        count: usize,
+        sent_at: Instant,
+        rtt_estimator: &RttEstimator,
+        now: Instant,
+    ) {
+        for packet_number in 0..count as u32 {
+            let packet_number = PacketNumber::from(packet_number);
+
+            cubic.on_packet_sent(
+                PacketNumberSpace::ApplicationData,
+                packet_number,
+                1000,
+                sent_at,
+            );
+            cubic.on_packet_acknowledged(
+                PacketNumberSpace::ApplicationData,
+                packet_number,
+                1000,
+                sent_at,
+                rtt_estimator,
+                now,
+            );
+        }
+    }
+
+    #[test]
+    fn slow_start_grows_window_by_acknowledged_bytes() {
+        let mut cubic = Cubic::new(1000);
+        let now = Instant::now();
+
+        acknowledge(&mut cubic, 2, now, &RttEstimator::new(), now);
+
+        assert_eq!(cubic.congestion_window(), 12_000);
+        assert_eq!(cubic.bytes_in_flight(), 0);
+    }
+
+    #[test]
+    fn loss_reduces_window_by_beta() {
+        let cubic = cubic_after_loss(Instant::now());
+
+        assert_eq!(cubic.congestion_window(), 7_000);
+        assert_eq!(cubic.w_max, 10_000.0);
+    }
+
+    #[test]
+    fn loss_during_recovery_is_ignored() {
+        let lost_at = Instant::now();
+        let mut cubic = cubic_after_loss(lost_at);
+
+        cubic.on_packets_lost(
+            PacketNumberSpace::ApplicationData,
+            &[],
+            0,
+            lost_at,
+            lost_at + Duration::from_millis(10),
+        );
+
+        assert_eq!(cubic.congestion_window(), 7_000);
+    }
+
+    #[test]
+    fn fast_convergence_lowers_w_max_when_window_keeps_shrinking() {
+        let lost_at = Instant::now();
+        let mut cubic = cubic_after_loss(lost_at);
+
+        let lost_again_at = lost_at + Duration::from_millis(100);
+        cubic.on_packets_lost(
+            PacketNumberSpace::ApplicationData,
+            &[],
+            0,
+            lost_again_at,
+            lost_again_at,
+        );
+
+        assert_eq!(cubic.congestion_window(), 4_900);
+        assert!((cubic.w_max - 5_950.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn tcp_friendly_region_follows_new_reno_estimate() {
+        let lost_at = Instant::now();
+        let mut cubic = cubic_after_loss(lost_at);
+        let rtt_estimator = rtt_estimator(Duration::from_millis(1));
+
+        // right after the loss the cubic function stays flat while acknowledgements arrive
+        let sent_at = lost_at + Duration::from_millis(1);
+        acknowledge(&mut cubic, 7, sent_at, &rtt_estimator, sent_at);
+
+        assert_eq!(cubic.congestion_window(), cubic.w_est as usize);
+        assert!(cubic.congestion_window() > 7_400);
+    }
+
+    #[test]
+    fn window_grows_back_to_w_max_then_beyond() {
+        let lost_at = Instant::now();
+        let mut cubic = cubic_after_loss(lost_at);
+        let rtt_estimator = rtt_estimator(Duration::from_millis(1));
+
+        let sent_at = lost_at + Duration::from_millis(1);
+        acknowledge(&mut cubic, 1, sent_at, &rtt_estimator, sent_at);
+
+        // K is about 1.96s, by then the window is most of the way back to w_max
+        let k = sent_at + Duration::from_millis(1957);
+        acknowledge(&mut cubic, 7, k, &rtt_estimator, k);
+        assert!(cubic.congestion_window() > 8_500);
+        assert!(cubic.congestion_window() <= 10_000);
+
+        // in the convex region the window probes past w_max
+        let later = sent_at + Duration::from_secs(4);
+        acknowledge(&mut cubic, 10, later, &rtt_estimator, later);
+        assert!(cubic.congestion_window() > 11_000);
+    }
+
+    #[test]
+    fn growth_of_less_than_a_byte_per_ack_accumulates() {
+        let lost_at = Instant::now();
+        let mut cubic = cubic_after_loss(lost_at);
+        let rtt_estimator = rtt_estimator(Duration::from_millis(1));
+
+        // 0.2s into an epoch starting at w_max the cubic function is 3.2 bytes above it, each
+        // acknowledgement grows the window by a fraction of a byte
+        cubic.congestion_window = 10_000;
+        cubic.k = 0.0;
+        cubic.w_est = 0.0;
+        cubic.epoch_start = Some(lost_at);
+
+        let now = lost_at + Duration::from_millis(199);
+        acknowledge(&mut cubic, 10, now, &rtt_estimator, now);
+
+        assert_eq!(cubic.congestion_window(), 10_002);
+    }
+}
@@ -0,0 +1,331 @@
+use conv::TryFrom;
+use errors::*;
+use frames::{AckFrame, Frame};
+use packets::PacketNumber;
+use recovery::{RttEstimator, SentPacket};
+use std::cmp;
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+/// A packet is lost once this many packets sent after it have been acknowledged (RFC 9002
+/// section 6.1.1).
+const PACKET_THRESHOLD: u64 = 3;
+
+/// Tracks the packets sent in a packet number space until they are acknowledged or declared
+/// lost.
+#[derive(Debug, Default)]
+pub struct LossDetector {
+    sent_packets: BTreeMap<PacketNumber, SentPacket>,
+    largest_acknowledged: Option<PacketNumber>,
+    /// When the most recent ack-eliciting packet was sent, the probe timeout is measured from
+    /// then.
+    time_of_last_ack_eliciting_packet: Option<Instant>,
+    /// When the earliest packet which is not yet lost would be lost by the time threshold.
+    loss_time: Option<Instant>,
+}
+
+impl LossDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on_packet_sent(&mut self, sent_packet: SentPacket) {
+        trace!("tracking sent packet {:?}", sent_packet.packet_number);
+
+        if sent_packet.ack_eliciting {
+            self.time_of_last_ack_eliciting_packet = Some(sent_packet.time_sent);
+        }
+
+        self.sent_packets
+            .insert(sent_packet.packet_number, sent_packet);
+    }
+
+    /// Removes the packets acknowledged by `ack_frame`.
+    ///
+    /// # Returns
+    /// The newly acknowledged packets in the order they were sent.
+    pub fn on_ack_frame_received(&mut self, ack_frame: &AckFrame) -> Result<Vec<SentPacket>> {
+        let mut acknowledged_packets = Vec::new();
+
+        for range in &ack_frame.ack_ranges_descending {
+            if range.start >= range.end {
+                continue;
+            }
+
+            let start = PacketNumber::try_from(range.start)?;
+            let end_inclusive = PacketNumber::try_from(range.end - 1)?;
+
+            let packet_numbers: Vec<_> = self.sent_packets
+                .range(start..=end_inclusive)
+                .map(|(&packet_number, _)| packet_number)
+                .collect();
+
+            for packet_number in packet_numbers {
+                if let Some(sent_packet) = self.sent_packets.remove(&packet_number) {
+                    acknowledged_packets.push(sent_packet);
+                }
+            }
+        }
+
+        if let Some(largest_acknowledged) = ack_frame.largest_acknowledged() {
+            let largest_acknowledged = PacketNumber::try_from(largest_acknowledged)?;
+
+            self.largest_acknowledged =
+                cmp::max(self.largest_acknowledged, Some(largest_acknowledged));
+        }
+
+        acknowledged_packets.sort_by_key(|sent_packet| sent_packet.packet_number);
+
+        debug!(
+            "{} packets acknowledged, largest acknowledged {:?}",
+            acknowledged_packets.len(),
+            self.largest_acknowledged
+        );
+
+        Ok(acknowledged_packets)
+    }
+
+    /// Declares the packets sent before the largest acknowledged packet lost once they pass the
+    /// packet or time threshold.
+    ///
+    /// # Returns
+    /// The lost packets in the order they were sent.
+    pub fn detect_lost_packets(
+        &mut self,
+        now: Instant,
+        rtt_estimator: &RttEstimator,
+    ) -> Vec<SentPacket> {
+        self.loss_time = None;
+
+        let largest_acknowledged = match self.largest_acknowledged {
+            Some(largest_acknowledged) => largest_acknowledged,
+            None => return Vec::new(),
+        };
+
+        let loss_delay = rtt_estimator.loss_delay();
+
+        let mut lost_packet_numbers = Vec::new();
+
+        for (&packet_number, sent_packet) in self.sent_packets.range(..largest_acknowledged) {
+            let packets_after = u64::from(largest_acknowledged) - u64::from(packet_number);
+
+            if packets_after >= PACKET_THRESHOLD || sent_packet.time_sent + loss_delay <= now {
+                lost_packet_numbers.push(packet_number);
+            } else {
+                let loss_time = sent_packet.time_sent + loss_delay;
+                self.loss_time = Some(
+                    self.loss_time
+                        .map_or(loss_time, |earliest| cmp::min(earliest, loss_time)),
+                );
+            }
+        }
+
+        let lost_packets: Vec<_> = lost_packet_numbers
+            .into_iter()
+            .filter_map(|packet_number| self.sent_packets.remove(&packet_number))
+            .collect();
+
+        if !lost_packets.is_empty() {
+            debug!("declared {} packets lost", lost_packets.len());
+        }
+
+        lost_packets
+    }
+
+    /// Stops tracking the packets up to and including `largest`, they will be neither
+    /// acknowledged nor declared lost.
+    ///
+    /// # Returns
+    /// The discarded packets in the order they were sent.
+    pub fn discard_packets_up_to(&mut self, largest: PacketNumber) -> Vec<SentPacket> {
+        let packet_numbers: Vec<_> = self.sent_packets
+            .range(..=largest)
+            .map(|(&packet_number, _)| packet_number)
+            .collect();
+
+        packet_numbers
+            .into_iter()
+            .filter_map(|packet_number| self.sent_packets.remove(&packet_number))
+            .collect()
+    }
+
+    /// When the next packet would be declared lost by the time threshold, `None` if no packets
+    /// are waiting on it.
+    pub fn loss_time(&self) -> Option<Instant> {
+        self.loss_time
+    }
+
+    /// When the most recent ack-eliciting packet was sent, `None` if none are waiting to be
+    /// acknowledged.
+    pub fn time_of_last_ack_eliciting_packet(&self) -> Option<Instant> {
+        if self.has_ack_eliciting_in_flight() {
+            self.time_of_last_ack_eliciting_packet
+        } else {
+            None
+        }
+    }
+
+    pub fn has_ack_eliciting_in_flight(&self) -> bool {
+        self.sent_packets
+            .values()
+            .any(|sent_packet| sent_packet.ack_eliciting)
+    }
+
+    /// The retransmittable frames of the oldest packet waiting to be acknowledged, these are sent
+    /// again in probe packets.
+    pub fn oldest_retransmittable_frames(&self) -> Option<&[Frame]> {
+        self.sent_packets
+            .values()
+            .find(|sent_packet| !sent_packet.retransmittable_frames.is_empty())
+            .map(|sent_packet| &sent_packet.retransmittable_frames[..])
+    }
+
+    /// Whether any packet still waiting to be acknowledged carries a frame matching `predicate`.
+    pub fn has_unacknowledged_frame<F>(&self, predicate: F) -> bool
+    where
+        F: Fn(&Frame) -> bool,
+    {
+        self.sent_packets.values().any(|sent_packet| {
+            sent_packet
+                .retransmittable_frames
+                .iter()
+                .any(|frame| predicate(frame))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LossDetector;
+    use frames::{AckFrame, Frame};
+    use packets::PacketNumber;
+    use recovery::{RttEstimator, SentPacket};
+    use std::time::{Duration, Instant};
+
+    fn sent_packet(packet_number: u32, time_sent: Instant) -> SentPacket {
+        SentPacket {
+            packet_number: PacketNumber::from(packet_number),
+            time_sent,
+            ack_eliciting: true,
+            size: 100,
+            retransmittable_frames: vec![Frame::HandshakeDone],
+        }
+    }
+
+    fn ack_frame(ack_ranges_descending: Vec<::std::ops::Range<u64>>) -> AckFrame {
+        AckFrame {
+            ack_delay: 0,
+            ack_ranges_descending,
+        }
+    }
+
+    #[test]
+    fn acknowledged_packets_are_removed() {
+        let mut loss_detector = LossDetector::new();
+        let now = Instant::now();
+
+        for packet_number in 0..3 {
+            loss_detector.on_packet_sent(sent_packet(packet_number, now));
+        }
+
+        let acknowledged = loss_detector
+            .on_ack_frame_received(&ack_frame(vec![2..3, 0..1]))
+            .unwrap();
+
+        let acknowledged: Vec<_> = acknowledged
+            .iter()
+            .map(|sent_packet| sent_packet.packet_number)
+            .collect();
+        assert_eq!(
+            acknowledged,
+            vec![PacketNumber::from(0u32), PacketNumber::from(2u32)]
+        );
+        assert!(loss_detector.has_unacknowledged_frame(|_| true));
+    }
+
+    #[test]
+    fn discarded_packets_are_neither_acknowledged_nor_lost() {
+        let mut loss_detector = LossDetector::new();
+        let now = Instant::now();
+
+        for packet_number in 0..4 {
+            loss_detector.on_packet_sent(sent_packet(packet_number, now));
+        }
+
+        let discarded = loss_detector.discard_packets_up_to(PacketNumber::from(1u32));
+        assert_eq!(discarded.len(), 2);
+
+        loss_detector
+            .on_ack_frame_received(&ack_frame(vec![3..4]))
+            .unwrap();
+
+        let lost = loss_detector.detect_lost_packets(now, &RttEstimator::new());
+        assert!(lost.is_empty());
+        assert!(loss_detector.has_unacknowledged_frame(|_| true));
+    }
+
+    #[test]
+    fn packet_is_lost_by_packet_threshold() {
+        let mut loss_detector = LossDetector::new();
+        let now = Instant::now();
+
+        for packet_number in 0..5 {
+            loss_detector.on_packet_sent(sent_packet(packet_number, now));
+        }
+
+        loss_detector
+            .on_ack_frame_received(&ack_frame(vec![3..5]))
+            .unwrap();
+
+        let lost: Vec<_> = loss_detector
+            .detect_lost_packets(now, &RttEstimator::new())
+            .iter()
+            .map(|sent_packet| sent_packet.packet_number)
+            .collect();
+
+        // packet 0 is 4 behind and packet 1 is 3 behind, packet 2 is only 2 behind
+        assert_eq!(
+            lost,
+            vec![PacketNumber::from(0u32), PacketNumber::from(1u32)]
+        );
+        assert!(loss_detector.loss_time().is_some());
+    }
+
+    #[test]
+    fn packet_is_lost_by_time_threshold() {
+        let mut loss_detector = LossDetector::new();
+        let sent_at = Instant::now();
+
+        let mut rtt_estimator = RttEstimator::new();
+        rtt_estimator.update(
+            Duration::from_millis(100),
+            Duration::from_millis(0),
+            Duration::from_millis(25),
+        );
+
+        loss_detector.on_packet_sent(sent_packet(0, sent_at));
+        loss_detector.on_packet_sent(sent_packet(1, sent_at + Duration::from_millis(100)));
+
+        loss_detector
+            .on_ack_frame_received(&ack_frame(vec![1..2]))
+            .unwrap();
+
+        // the RTT is 100ms so packet 0 is lost 112.5ms after it was sent
+        assert!(
+            loss_detector
+                .detect_lost_packets(sent_at + Duration::from_millis(100), &rtt_estimator)
+                .is_empty()
+        );
+        assert_eq!(
+            loss_detector.loss_time(),
+            Some(sent_at + Duration::from_micros(112_500))
+        );
+
+        let lost =
+            loss_detector.detect_lost_packets(sent_at + Duration::from_millis(200), &rtt_estimator);
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0].packet_number, PacketNumber::from(0u32));
+        assert!(!loss_detector.has_unacknowledged_frame(|_| true));
+        assert!(loss_detector.time_of_last_ack_eliciting_packet().is_none());
+    }
+}
@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+mod rtt_estimator;
+pub use self::rtt_estimator::{RttEstimator, GRANULARITY_MILLIS};
+
+mod sent_packet;
+pub use self::sent_packet::SentPacket;
+
+mod loss_detector;
+pub use self::loss_detector::LossDetector;
+
+mod congestion_controller;
+pub use self::congestion_controller::{initial_congestion_window, is_persistent_congestion,
+                                      minimum_congestion_window, CongestionController};
+
+mod new_reno;
+pub use self::new_reno::NewReno;
+
+mod cubic;
+pub use self::cubic::Cubic;
+
+#[cfg(feature = "bbr")]
+mod bbr;
+#[cfg(feature = "bbr")]
+pub use self::bbr::{Bbr, BbrState};
+
+mod pacer;
+pub use self::pacer::{Pacer, DEFAULT_BURST_DATAGRAMS};
+
+mod congestion_control;
+pub use self::congestion_control::{CongestionControl, CongestionControllerFactory};
+
+/// The length of `duration` in seconds.
+fn as_secs_f64(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1_000_000_000.0
+}
@@ -0,0 +1,252 @@
+use packets::{PacketNumber, PacketNumberSpace};
+use recovery::{initial_congestion_window, minimum_congestion_window, CongestionController,
+               RttEstimator};
+use std::time::Instant;
+
+/// The NewReno congestion controller described in RFC 9002 section 7.
+#[derive(Debug, Clone)]
+pub struct NewReno {
+    max_datagram_size: usize,
+    congestion_window: usize,
+    bytes_in_flight: usize,
+    slow_start_threshold: usize,
+    /// When the current recovery period started, packets sent before then do not change the
+    /// congestion window.
+    congestion_recovery_start_time: Option<Instant>,
+}
+
+impl NewReno {
+    pub fn new(max_datagram_size: usize) -> Self {
+        Self {
+            max_datagram_size,
+            congestion_window: initial_congestion_window(max_datagram_size),
+            bytes_in_flight: 0,
+            slow_start_threshold: usize::max_value(),
+            congestion_recovery_start_time: None,
+        }
+    }
+
+    fn in_congestion_recovery(&self, time_sent: Instant) -> bool {
+        self.congestion_recovery_start_time
+            .map_or(false, |start_time| time_sent <= start_time)
+    }
+}
+
+impl CongestionController for NewReno {
+    fn on_packet_sent(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_number: PacketNumber,
+        size: usize,
+        _time_sent: Instant,
+    ) {
+        self.bytes_in_flight += size;
+    }
+
+    fn on_packet_acknowledged(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_number: PacketNumber,
+        size: usize,
+        time_sent: Instant,
+        _rtt_estimator: &RttEstimator,
+        _now: Instant,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+
+        if self.in_congestion_recovery(time_sent) {
+            return;
+        }
+
+        if self.congestion_window < self.slow_start_threshold {
+            // slow start
+            self.congestion_window += size;
+        } else {
+            // congestion avoidance
+            self.congestion_window += self.max_datagram_size * size / self.congestion_window;
+        }
+    }
+
+    fn on_packets_lost(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_numbers: &[PacketNumber],
+        size: usize,
+        largest_lost_time_sent: Instant,
+        now: Instant,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+
+        // only one reduction is made for the packets in flight when the first loss was detected
+        if self.in_congestion_recovery(largest_lost_time_sent) {
+            return;
+        }
+
+        self.congestion_recovery_start_time = Some(now);
+        self.slow_start_threshold = self.congestion_window / 2;
+        self.congestion_window = self.slow_start_threshold
+            .max(minimum_congestion_window(self.max_datagram_size));
+
+        debug!(
+            "congestion window reduced to {} bytes",
+            self.congestion_window
+        );
+    }
+
+    fn on_packets_discarded(
+        &mut self,
+        _packet_number_space: PacketNumberSpace,
+        _packet_numbers: &[PacketNumber],
+        size: usize,
+    ) {
+        self.bytes_in_flight = self.bytes_in_flight.saturating_sub(size);
+    }
+
+    fn on_persistent_congestion(&mut self) {
+        self.congestion_window = minimum_congestion_window(self.max_datagram_size);
+        self.congestion_recovery_start_time = None;
+
+        debug!(
+            "persistent congestion, congestion window reduced to {} bytes",
+            self.congestion_window
+        );
+    }
+
+    fn congestion_window(&self) -> usize {
+        self.congestion_window
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NewReno;
+    use packets::{PacketNumber, PacketNumberSpace};
+    use recovery::{CongestionController, RttEstimator};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn initial_window_is_ten_datagrams() {
+        let new_reno = NewReno::new(1200);
+
+        assert_eq!(new_reno.congestion_window(), 12_000);
+        assert!(new_reno.can_send(1200));
+    }
+
+    #[test]
+    fn window_limits_bytes_in_flight() {
+        let mut new_reno = NewReno::new(1200);
+        let now = Instant::now();
+
+        for packet_number in 0..10u32 {
+            new_reno.on_packet_sent(
+                PacketNumberSpace::ApplicationData,
+                PacketNumber::from(packet_number),
+                1200,
+                now,
+            );
+        }
+
+        assert_eq!(new_reno.bytes_in_flight(), 12_000);
+        assert!(!new_reno.can_send(1200));
+    }
+
+    #[test]
+    fn slow_start_grows_window_by_acknowledged_bytes() {
+        let mut new_reno = NewReno::new(1200);
+        let now = Instant::now();
+
+        new_reno.on_packet_sent(
+            PacketNumberSpace::ApplicationData,
+            PacketNumber::from(0u32),
+            1200,
+            now,
+        );
+        new_reno.on_packet_acknowledged(
+            PacketNumberSpace::ApplicationData,
+            PacketNumber::from(0u32),
+            1200,
+            now,
+            &RttEstimator::new(),
+            now,
+        );
+
+        assert_eq!(new_reno.congestion_window(), 13_200);
+        assert_eq!(new_reno.bytes_in_flight(), 0);
+    }
+
+    #[test]
+    fn loss_halves_window_once_per_recovery_period() {
+        let mut new_reno = NewReno::new(1200);
+        let sent_at = Instant::now();
+        let lost_at = sent_at + Duration::from_millis(100);
+
+        for packet_number in 0..2u32 {
+            new_reno.on_packet_sent(
+                PacketNumberSpace::ApplicationData,
+                PacketNumber::from(packet_number),
+                1200,
+                sent_at,
+            );
+        }
+
+        new_reno.on_packets_lost(
+            PacketNumberSpace::ApplicationData,
+            &[PacketNumber::from(0u32)],
+            1200,
+            sent_at,
+            lost_at,
+        );
+        assert_eq!(new_reno.congestion_window(), 6_000);
+
+        // the second packet was sent before the recovery period started
+        new_reno.on_packets_lost(
+            PacketNumberSpace::ApplicationData,
+            &[PacketNumber::from(1u32)],
+            1200,
+            sent_at,
+            lost_at + Duration::from_millis(10),
+        );
+        assert_eq!(new_reno.congestion_window(), 6_000);
+        assert_eq!(new_reno.bytes_in_flight(), 0);
+    }
+
+    #[test]
+    fn congestion_avoidance_grows_window_by_a_datagram_per_window() {
+        let mut new_reno = NewReno::new(1200);
+        let sent_at = Instant::now();
+
+        new_reno.on_packets_lost(PacketNumberSpace::ApplicationData, &[], 0, sent_at, sent_at);
+        assert_eq!(new_reno.congestion_window(), 6_000);
+
+        let sent_after_recovery = sent_at + Duration::from_millis(1);
+        new_reno.on_packet_sent(
+            PacketNumberSpace::ApplicationData,
+            PacketNumber::from(0u32),
+            6_000,
+            sent_after_recovery,
+        );
+        new_reno.on_packet_acknowledged(
+            PacketNumberSpace::ApplicationData,
+            PacketNumber::from(0u32),
+            6_000,
+            sent_after_recovery,
+            &RttEstimator::new(),
+            sent_after_recovery,
+        );
+
+        assert_eq!(new_reno.congestion_window(), 7_200);
+    }
+
+    #[test]
+    fn persistent_congestion_collapses_window() {
+        let mut new_reno = NewReno::new(1200);
+
+        new_reno.on_persistent_congestion();
+
+        assert_eq!(new_reno.congestion_window(), 2_400);
+    }
+}
@@ -0,0 +1,166 @@
+use recovery::as_secs_f64;
+use std::time::{Duration, Instant};
+
+/// Packets are paced a little faster than the congestion window over the smoothed RTT so the
+/// window can still be filled (RFC 9002 section 7.7).
+const PACING_GAIN: f64 = 1.25;
+
+/// The number of datagrams sent back to back when pacing is not configured.
+pub const DEFAULT_BURST_DATAGRAMS: usize = 10;
+
+/// A token bucket which spreads packets out rather than sending a whole congestion window at
+/// once.
+///
+/// The bucket refills at the pacing rate and holds at most a burst of datagrams, so an idle
+/// connection may still send a short burst straight away.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    max_datagram_size: usize,
+    burst_datagrams: usize,
+    /// The bytes which may be sent without waiting.
+    tokens: f64,
+    last_refill: Option<Instant>,
+}
+
+impl Pacer {
+    pub fn new(max_datagram_size: usize, burst_datagrams: usize) -> Self {
+        Self {
+            max_datagram_size,
+            burst_datagrams,
+            tokens: 0.0,
+            last_refill: None,
+        }
+    }
+
+    /// The rate in bytes per second which sends `congestion_window` bytes over a little less
+    /// than `smoothed_rtt`.
+    pub fn rate(congestion_window: usize, smoothed_rtt: Duration) -> f64 {
+        let smoothed_rtt = as_secs_f64(smoothed_rtt);
+
+        if smoothed_rtt > 0.0 {
+            PACING_GAIN * congestion_window as f64 / smoothed_rtt
+        } else {
+            ::std::f64::INFINITY
+        }
+    }
+
+    /// The most the bucket holds, a burst which never exceeds the congestion window.
+    fn capacity(&self, congestion_window: usize) -> f64 {
+        let burst = self.burst_datagrams * self.max_datagram_size;
+
+        burst.min(congestion_window).max(self.max_datagram_size) as f64
+    }
+
+    fn refill(&mut self, rate: f64, congestion_window: usize, now: Instant) {
+        let capacity = self.capacity(congestion_window);
+
+        self.tokens = match self.last_refill {
+            Some(last_refill) => {
+                let elapsed = as_secs_f64(now.duration_since(last_refill));
+                (self.tokens + rate * elapsed).min(capacity)
+            }
+            // nothing has been sent yet so the first burst goes straight away
+            None => capacity,
+        };
+        self.last_refill = Some(now);
+    }
+
+    /// When a packet of `size` bytes may be sent when pacing at `rate` bytes per second.
+    ///
+    /// # Returns
+    /// `None` if the packet may be sent now.
+    pub fn send_time(
+        &mut self,
+        size: usize,
+        rate: f64,
+        congestion_window: usize,
+        now: Instant,
+    ) -> Option<Instant> {
+        self.refill(rate, congestion_window, now);
+
+        let size = (size as f64).min(self.capacity(congestion_window));
+
+        if self.tokens >= size || rate.is_infinite() {
+            return None;
+        }
+
+        let wait = (size - self.tokens) / rate;
+
+        Some(now + from_secs_f64(wait))
+    }
+
+    pub fn on_packet_sent(&mut self, size: usize) {
+        self.tokens = (self.tokens - size as f64).max(0.0);
+    }
+}
+
+fn from_secs_f64(secs: f64) -> Duration {
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1_000_000_000.0).ceil() as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pacer;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn rate_spreads_window_over_smoothed_rtt() {
+        let rate = Pacer::rate(10_000, Duration::from_millis(100));
+
+        assert_eq!(rate, 125_000.0);
+    }
+
+    #[test]
+    fn burst_is_sent_straight_away() {
+        let mut pacer = Pacer::new(1000, 3);
+        let now = Instant::now();
+        let rate = Pacer::rate(10_000, Duration::from_millis(100));
+
+        for _ in 0..3 {
+            assert_eq!(pacer.send_time(1000, rate, 10_000, now), None);
+            pacer.on_packet_sent(1000);
+        }
+
+        // the next datagram waits 8ms for the bucket to refill at 125kB/s
+        assert_eq!(
+            pacer.send_time(1000, rate, 10_000, now),
+            Some(now + Duration::from_millis(8))
+        );
+        assert_eq!(
+            pacer.send_time(1000, rate, 10_000, now + Duration::from_millis(8)),
+            None
+        );
+    }
+
+    #[test]
+    fn burst_is_limited_by_congestion_window() {
+        let mut pacer = Pacer::new(1000, 10);
+        let now = Instant::now();
+        let rate = Pacer::rate(2_000, Duration::from_millis(100));
+
+        for _ in 0..2 {
+            assert_eq!(pacer.send_time(1000, rate, 2_000, now), None);
+            pacer.on_packet_sent(1000);
+        }
+
+        assert!(pacer.send_time(1000, rate, 2_000, now).is_some());
+    }
+
+    #[test]
+    fn idle_bucket_refills_to_burst() {
+        let mut pacer = Pacer::new(1000, 2);
+        let now = Instant::now();
+        let rate = Pacer::rate(10_000, Duration::from_millis(100));
+
+        pacer.send_time(1000, rate, 10_000, now);
+        pacer.on_packet_sent(1000);
+        pacer.on_packet_sent(1000);
+
+        let later = now + Duration::from_secs(1);
+        for _ in 0..2 {
+            assert_eq!(pacer.send_time(1000, rate, 10_000, later), None);
+            pacer.on_packet_sent(1000);
+        }
+        assert!(pacer.send_time(1000, rate, 10_000, later).is_some());
+    }
+}
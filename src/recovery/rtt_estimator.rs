@@ -0,0 +1,176 @@
+use std::cmp;
+use std::time::Duration;
+
+/// The RTT assumed before any has been measured (RFC 9002 section 6.2.2).
+const INITIAL_RTT_MILLIS: u64 = 333;
+
+/// The expected timer granularity, no timer is set any shorter than this.
+pub const GRANULARITY_MILLIS: u64 = 1;
+
+/// Estimates the round trip time of a connection from the ACK frames it receives (RFC 9002
+/// section 5).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct RttEstimator {
+    latest_rtt: Duration,
+    smoothed_rtt: Duration,
+    rtt_variance: Duration,
+    min_rtt: Option<Duration>,
+}
+
+impl Default for RttEstimator {
+    fn default() -> Self {
+        let initial_rtt = Duration::from_millis(INITIAL_RTT_MILLIS);
+
+        Self {
+            latest_rtt: initial_rtt,
+            smoothed_rtt: initial_rtt,
+            rtt_variance: initial_rtt / 2,
+            min_rtt: None,
+        }
+    }
+}
+
+impl RttEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The most recent RTT sample.
+    pub fn latest_rtt(&self) -> Duration {
+        self.latest_rtt
+    }
+
+    /// The exponentially weighted moving average of the RTT samples.
+    pub fn smoothed_rtt(&self) -> Duration {
+        self.smoothed_rtt
+    }
+
+    /// The mean deviation of the RTT samples.
+    pub fn rtt_variance(&self) -> Duration {
+        self.rtt_variance
+    }
+
+    /// The smallest RTT sample, `None` until the first sample.
+    pub fn min_rtt(&self) -> Option<Duration> {
+        self.min_rtt
+    }
+
+    /// Updates the estimate with the time between sending a packet and receiving the ACK frame
+    /// which newly acknowledged it as the largest acknowledged.
+    ///
+    /// `ack_delay` is the delay the peer reported in that ACK frame, it is limited to
+    /// `max_ack_delay` and never reduces the sample below `min_rtt`.
+    pub fn update(&mut self, latest_rtt: Duration, ack_delay: Duration, max_ack_delay: Duration) {
+        self.latest_rtt = latest_rtt;
+
+        let min_rtt = match self.min_rtt {
+            Some(min_rtt) => cmp::min(min_rtt, latest_rtt),
+            None => {
+                // the first sample replaces the initial estimate entirely
+                self.min_rtt = Some(latest_rtt);
+                self.smoothed_rtt = latest_rtt;
+                self.rtt_variance = latest_rtt / 2;
+
+                trace!("first rtt sample {:?}", latest_rtt);
+                return;
+            }
+        };
+        self.min_rtt = Some(min_rtt);
+
+        let ack_delay = cmp::min(ack_delay, max_ack_delay);
+
+        let adjusted_rtt = if latest_rtt >= min_rtt + ack_delay {
+            latest_rtt - ack_delay
+        } else {
+            latest_rtt
+        };
+
+        let rtt_variance_sample = if self.smoothed_rtt > adjusted_rtt {
+            self.smoothed_rtt - adjusted_rtt
+        } else {
+            adjusted_rtt - self.smoothed_rtt
+        };
+
+        self.rtt_variance = (self.rtt_variance * 3 + rtt_variance_sample) / 4;
+        self.smoothed_rtt = (self.smoothed_rtt * 7 + adjusted_rtt) / 8;
+
+        trace!("updated rtt estimate {:?}", self);
+    }
+
+    /// The time after the last ack-eliciting packet was sent that a probe is sent if nothing has
+    /// been acknowledged, before any backoff.
+    ///
+    /// `max_ack_delay` is only included for application data, the handshake is acknowledged
+    /// immediately.
+    pub fn probe_timeout(&self, max_ack_delay: Duration) -> Duration {
+        self.smoothed_rtt
+            + cmp::max(
+                self.rtt_variance * 4,
+                Duration::from_millis(GRANULARITY_MILLIS),
+            ) + max_ack_delay
+    }
+
+    /// How long after a packet is sent it is declared lost once a packet sent after it has been
+    /// acknowledged (RFC 9002 section 6.1.2).
+    pub fn loss_delay(&self) -> Duration {
+        cmp::max(
+            cmp::max(self.latest_rtt, self.smoothed_rtt) * 9 / 8,
+            Duration::from_millis(GRANULARITY_MILLIS),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RttEstimator;
+    use std::time::Duration;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn first_sample_replaces_initial_estimate() {
+        let mut rtt_estimator = RttEstimator::new();
+
+        rtt_estimator.update(millis(100), millis(10), millis(25));
+
+        assert_eq!(rtt_estimator.latest_rtt(), millis(100));
+        assert_eq!(rtt_estimator.smoothed_rtt(), millis(100));
+        assert_eq!(rtt_estimator.rtt_variance(), millis(50));
+        assert_eq!(rtt_estimator.min_rtt(), Some(millis(100)));
+    }
+
+    #[test]
+    fn later_samples_are_smoothed_after_removing_ack_delay() {
+        let mut rtt_estimator = RttEstimator::new();
+        rtt_estimator.update(millis(100), millis(0), millis(25));
+
+        rtt_estimator.update(millis(180), millis(20), millis(25));
+
+        // the ack delay brings the sample down to 160ms
+        assert_eq!(rtt_estimator.smoothed_rtt(), millis(107) + millis(1) / 2);
+        assert_eq!(rtt_estimator.rtt_variance(), millis(52) + millis(1) / 2);
+        assert_eq!(rtt_estimator.min_rtt(), Some(millis(100)));
+    }
+
+    #[test]
+    fn ack_delay_is_limited_to_max_ack_delay() {
+        let mut rtt_estimator = RttEstimator::new();
+        rtt_estimator.update(millis(100), millis(0), millis(25));
+
+        rtt_estimator.update(millis(200), millis(100), millis(25));
+
+        // only 25ms of the reported delay is taken off, leaving a 175ms sample
+        assert_eq!(rtt_estimator.smoothed_rtt(), millis(109) + millis(3) / 8);
+    }
+
+    #[test]
+    fn probe_timeout_includes_variance_and_max_ack_delay() {
+        let mut rtt_estimator = RttEstimator::new();
+        rtt_estimator.update(millis(100), millis(0), millis(25));
+
+        assert_eq!(rtt_estimator.probe_timeout(millis(25)), millis(325));
+        assert_eq!(rtt_estimator.probe_timeout(millis(0)), millis(300));
+    }
+}
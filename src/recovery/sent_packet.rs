@@ -0,0 +1,16 @@
+use frames::Frame;
+use packets::PacketNumber;
+use std::time::Instant;
+
+/// A packet which has been sent and is waiting to be acknowledged or declared lost.
+#[derive(Debug, Clone)]
+pub struct SentPacket {
+    pub packet_number: PacketNumber,
+    pub time_sent: Instant,
+    /// Whether the peer must acknowledge the packet.
+    pub ack_eliciting: bool,
+    /// The size of the packet in bytes.
+    pub size: usize,
+    /// The frames which are sent again if the packet is lost.
+    pub retransmittable_frames: Vec<Frame>,
+}
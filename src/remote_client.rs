@@ -1,6 +1,6 @@
 use protocol::StreamType;
 use std::sync::Arc;
-use {Connection, DataStream, NewDataStreams, ServerPerspective};
+use {Connection, DataStream, EarlyData, NewDataStreams, RttEstimator, ServerPerspective};
 
 /// A client which has connected to this `Server`.
 #[derive(Debug)]
@@ -9,6 +9,10 @@ pub struct RemoteClient {
 }
 
 impl RemoteClient {
+    pub(crate) fn new(connection: Arc<Connection<ServerPerspective>>) -> Self {
+        Self { connection }
+    }
+
     fn open_stream(&self, stream_type: StreamType) -> DataStream<ServerPerspective> {
         let (stream_id, stream_state) = self.connection.new_stream(stream_type);
 
@@ -23,7 +27,26 @@ impl RemoteClient {
         self.open_stream(StreamType::Bidirectional)
     }
 
+    /// The streams opened by the client, `DataStream::is_early_data` tells which of them
+    /// arrived as 0-RTT data.
     pub fn incoming_streams(&self) -> NewDataStreams<ServerPerspective> {
         NewDataStreams::new(self.connection.clone())
     }
+
+    /// The application protocol agreed with the client, dispatch on this to serve several
+    /// protocols from one `Server`. `None` if the server negotiates no application protocol.
+    pub fn application_protocol(&self) -> Option<String> {
+        self.connection.application_protocol()
+    }
+
+    /// Whether the 0-RTT data sent by the client was accepted, this is only decided once the
+    /// handshake completes.
+    pub fn early_data(&self) -> EarlyData {
+        self.connection.early_data()
+    }
+
+    /// The current estimate of the round trip time on this connection.
+    pub fn rtt_estimate(&self) -> RttEstimator {
+        self.connection.rtt_estimate()
+    }
 }
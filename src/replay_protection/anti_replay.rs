@@ -0,0 +1,15 @@
+use replay_protection::ClientHello;
+use std::fmt::Debug;
+use std::time::Instant;
+
+/// Refuses 0-RTT data which a server has accepted before.
+///
+/// Nothing stops an attacker who captured the first flight of a connection from sending it to
+/// the server again, so a server only accepts 0-RTT data once it has checked the ClientHello is
+/// not a replay. A single implementation is shared by every connection of a `Server`.
+pub trait AntiReplay: Debug + Send + Sync {
+    /// Whether the 0-RTT data sent with `client_hello` at `now` may be accepted.
+    ///
+    /// A ClientHello which is accepted is remembered so any replay of it is refused.
+    fn check(&self, client_hello: &ClientHello, now: Instant) -> bool;
+}
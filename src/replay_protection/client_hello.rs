@@ -0,0 +1,220 @@
+use byteorder::{NetworkEndian, ReadBytesExt};
+use bytes::Bytes;
+use errors::*;
+use std::io::{Cursor, Read};
+
+const CLIENT_HELLO_MESSAGE_TYPE: u8 = 1;
+const APPLICATION_LAYER_PROTOCOL_NEGOTIATION_EXTENSION_TYPE: u16 = 16;
+const PRE_SHARED_KEY_EXTENSION_TYPE: u16 = 41;
+const EARLY_DATA_EXTENSION_TYPE: u16 = 42;
+
+/// The parts of a TLS 1.3 ClientHello a server decides on before the handshake, whether its
+/// 0-RTT data may be a replay and which application protocol is used.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct ClientHello {
+    random: Bytes,
+    psk_identity: Option<Bytes>,
+    offers_early_data: bool,
+    alpn_protocols: Vec<String>,
+}
+
+impl ClientHello {
+    /// Reads the ClientHello handshake message at the start of `data`.
+    ///
+    /// The pre-shared key extension is always the last extension, if `data` ends before it the
+    /// ClientHello is read as not resuming a session.
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let mut reader = Cursor::new(data);
+
+        let message_type = read_u8(&mut reader)?;
+        if message_type != CLIENT_HELLO_MESSAGE_TYPE {
+            bail!(ErrorKind::FailedToParseClientHello);
+        }
+
+        // the message length and legacy version
+        skip(&mut reader, 3 + 2)?;
+
+        let random = read_bytes(&mut reader, 32)?;
+
+        let session_id_len = read_u8(&mut reader)?;
+        skip(&mut reader, session_id_len.into())?;
+        let cipher_suites_len = read_u16(&mut reader)?;
+        skip(&mut reader, cipher_suites_len.into())?;
+        let compression_methods_len = read_u8(&mut reader)?;
+        skip(&mut reader, compression_methods_len.into())?;
+
+        let mut client_hello = ClientHello {
+            random,
+            psk_identity: None,
+            offers_early_data: false,
+            alpn_protocols: Vec::new(),
+        };
+
+        // a ClientHello without extensions cannot resume a session
+        if read_u16(&mut reader).is_err() {
+            return Ok(client_hello);
+        }
+
+        while let Ok(extension_type) = reader.read_u16::<NetworkEndian>() {
+            let extension_len = read_u16(&mut reader)?;
+
+            match extension_type {
+                APPLICATION_LAYER_PROTOCOL_NEGOTIATION_EXTENSION_TYPE => {
+                    let protocols_end = reader.position() + u64::from(extension_len);
+                    let _protocols_len = read_u16(&mut reader)?;
+
+                    while reader.position() < protocols_end {
+                        let protocol_len = read_u8(&mut reader)?;
+                        let protocol = read_bytes(&mut reader, protocol_len.into())?;
+
+                        // rustls only negotiates protocols with UTF-8 names
+                        if let Ok(protocol) = String::from_utf8(protocol.to_vec()) {
+                            client_hello.alpn_protocols.push(protocol);
+                        }
+                    }
+                }
+                EARLY_DATA_EXTENSION_TYPE => {
+                    client_hello.offers_early_data = true;
+                    skip(&mut reader, extension_len.into())?;
+                }
+                PRE_SHARED_KEY_EXTENSION_TYPE => {
+                    // only the first identity may be used for 0-RTT
+                    let _identities_len = read_u16(&mut reader)?;
+                    let identity_len = read_u16(&mut reader)?;
+                    client_hello.psk_identity = read_bytes(&mut reader, identity_len.into()).ok();
+                    break;
+                }
+                _ => {
+                    if skip(&mut reader, extension_len.into()).is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(client_hello)
+    }
+
+    /// The 32 random bytes chosen by the client, which are unique to each ClientHello.
+    pub fn random(&self) -> &[u8] {
+        &self.random
+    }
+
+    /// The identity of the first pre-shared key offered for resumption, usually the session
+    /// ticket.
+    pub fn psk_identity(&self) -> Option<&[u8]> {
+        self.psk_identity
+            .as_ref()
+            .map(|psk_identity| &psk_identity[..])
+    }
+
+    /// The application protocols the client offered, in its order of preference.
+    pub fn alpn_protocols(&self) -> &[String] {
+        &self.alpn_protocols
+    }
+
+    /// Whether the client sent the early data extension, it is resuming a session and has sent
+    /// 0-RTT packets.
+    pub fn offers_early_data(&self) -> bool {
+        self.offers_early_data && self.psk_identity.is_some()
+    }
+}
+
+fn read_u8(reader: &mut Cursor<&[u8]>) -> Result<u8> {
+    reader
+        .read_u8()
+        .chain_err(|| ErrorKind::FailedToParseClientHello)
+}
+
+fn read_u16(reader: &mut Cursor<&[u8]>) -> Result<u16> {
+    reader
+        .read_u16::<NetworkEndian>()
+        .chain_err(|| ErrorKind::FailedToParseClientHello)
+}
+
+fn read_bytes(reader: &mut Cursor<&[u8]>, len: usize) -> Result<Bytes> {
+    let mut bytes = vec![0; len];
+    reader
+        .read_exact(&mut bytes)
+        .chain_err(|| ErrorKind::FailedToParseClientHello)?;
+
+    Ok(bytes.into())
+}
+
+fn skip(reader: &mut Cursor<&[u8]>, len: u64) -> Result<()> {
+    let position = reader.position() + len;
+    if position > reader.get_ref().len() as u64 {
+        bail!(ErrorKind::FailedToParseClientHello);
+    }
+    reader.set_position(position);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientHello;
+
+    fn client_hello(extensions: &[u8]) -> Vec<u8> {
+        let mut client_hello = vec![1, 0, 0, 0, 0x03, 0x03];
+        client_hello.extend_from_slice(&[7; 32]);
+        // an empty session id, one cipher suite and the null compression method
+        client_hello.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0]);
+        client_hello.extend_from_slice(&[0, extensions.len() as u8]);
+        client_hello.extend_from_slice(extensions);
+        client_hello
+    }
+
+    #[test]
+    fn parse_reads_random() {
+        let client_hello = ClientHello::parse(&client_hello(&[])).unwrap();
+
+        assert_eq!(client_hello.random(), &[7; 32][..]);
+        assert_eq!(client_hello.psk_identity(), None);
+        assert!(!client_hello.offers_early_data());
+    }
+
+    #[test]
+    fn parse_reads_psk_identity_and_early_data() {
+        let extensions = [
+            // server name
+            0, 0, 0, 2, 0xaa, 0xbb,
+            // early data
+            0, 42, 0, 0,
+            // pre-shared key with identity [1, 2, 3]
+            0, 41, 0, 11, 0, 9, 0, 3, 1, 2, 3, 0, 0, 0, 0,
+        ];
+
+        let client_hello = ClientHello::parse(&client_hello(&extensions)).unwrap();
+
+        assert_eq!(client_hello.psk_identity(), Some(&[1, 2, 3][..]));
+        assert!(client_hello.offers_early_data());
+    }
+
+    #[test]
+    fn parse_reads_alpn_protocols() {
+        let extensions = [
+            // application layer protocol negotiation offering "h3" then "hq"
+            0, 16, 0, 8, 0, 6, 2, b'h', b'3', 2, b'h', b'q',
+        ];
+
+        let client_hello = ClientHello::parse(&client_hello(&extensions)).unwrap();
+
+        assert_eq!(client_hello.alpn_protocols(), &["h3".to_owned(), "hq".to_owned()][..]);
+    }
+
+    #[test]
+    fn early_data_without_psk_is_not_offered() {
+        let client_hello = ClientHello::parse(&client_hello(&[0, 42, 0, 0])).unwrap();
+
+        assert!(!client_hello.offers_early_data());
+    }
+
+    #[test]
+    fn parse_rejects_other_messages() {
+        let mut server_hello = client_hello(&[]);
+        server_hello[0] = 2;
+
+        assert!(ClientHello::parse(&server_hello).is_err());
+    }
+}
@@ -0,0 +1,80 @@
+use byteorder::{BigEndian, ByteOrder};
+use bytes::Bytes;
+use utils::DataQueue;
+
+/// The length of the header of a TLS handshake message, its type followed by the 24 bit length
+/// of its body.
+const HANDSHAKE_MESSAGE_HEADER_LEN: usize = 4;
+
+/// Reassembles the first handshake message in the Initial crypto data a server receives, the
+/// ClientHello, from crypto frames which may arrive out of order or split up.
+#[derive(Debug, Default)]
+pub struct ClientHelloBuffer {
+    data_queue: DataQueue,
+    message: Vec<u8>,
+}
+
+impl ClientHelloBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The length of the first handshake message, only the header's until the header has
+    /// arrived.
+    fn message_len(&self) -> usize {
+        if self.message.len() < HANDSHAKE_MESSAGE_HEADER_LEN {
+            return HANDSHAKE_MESSAGE_HEADER_LEN;
+        }
+
+        let body_len = BigEndian::read_u24(&self.message[1..HANDSHAKE_MESSAGE_HEADER_LEN]);
+
+        HANDSHAKE_MESSAGE_HEADER_LEN + body_len as usize
+    }
+
+    /// Buffers the Initial crypto `data` received at `offset`.
+    ///
+    /// # Returns
+    /// The first handshake message, header included, once all of it has arrived.
+    pub fn push(&mut self, offset: u64, data: Bytes) -> Option<&[u8]> {
+        self.data_queue.insert_chunk(offset, false, data);
+
+        loop {
+            let buffered = self.message.len();
+            let message_len = self.message_len();
+
+            if buffered == message_len {
+                break;
+            }
+
+            self.message.resize(message_len, 0);
+            let read = self.data_queue.read(&mut self.message[buffered..]);
+            self.message.truncate(buffered + read);
+
+            if read == 0 {
+                return None;
+            }
+        }
+
+        Some(&self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClientHelloBuffer;
+    use bytes::Bytes;
+
+    #[test]
+    fn push_returns_message_once_it_is_complete() {
+        let mut client_hello_buffer = ClientHelloBuffer::new();
+        let crypto_data = Bytes::from(&[1, 0, 0, 4, 1, 2, 3, 4, 2, 0, 0][..]);
+
+        assert_eq!(client_hello_buffer.push(6, crypto_data.slice(6, 11)), None);
+        assert_eq!(client_hello_buffer.push(0, crypto_data.slice(0, 2)), None);
+        assert_eq!(client_hello_buffer.push(0, crypto_data.slice(0, 5)), None);
+        assert_eq!(
+            client_hello_buffer.push(5, crypto_data.slice(5, 6)),
+            Some(&[1, 0, 0, 4, 1, 2, 3, 4][..])
+        );
+    }
+}
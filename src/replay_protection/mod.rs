@@ -0,0 +1,14 @@
+mod client_hello;
+pub use self::client_hello::ClientHello;
+
+mod client_hello_buffer;
+pub use self::client_hello_buffer::ClientHelloBuffer;
+
+mod anti_replay;
+pub use self::anti_replay::AntiReplay;
+
+mod single_use_tickets;
+pub use self::single_use_tickets::SingleUseTickets;
+
+mod replay_window;
+pub use self::replay_window::ReplayWindow;
@@ -0,0 +1,180 @@
+use replay_protection::{AntiReplay, ClientHello};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::mem;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The number of bits set for each ClientHello, with 10 bits per ClientHello this gives a false
+/// positive rate of about 1%.
+const HASH_COUNT: u64 = 7;
+
+const BITS_PER_CLIENT_HELLO: usize = 10;
+
+#[derive(Debug, Clone)]
+struct BloomFilter {
+    bits: Vec<u64>,
+}
+
+impl BloomFilter {
+    fn new(bit_count: usize) -> Self {
+        Self {
+            bits: vec![0; (bit_count + 63) / 64],
+        }
+    }
+
+    /// The bits for a value hashed to `hashes`, derived by double hashing.
+    fn bit_indices(&self, hashes: (u64, u64)) -> impl Iterator<Item = usize> {
+        let bit_count = self.bits.len() as u64 * 64;
+        let (first, second) = hashes;
+
+        (0..HASH_COUNT)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(second)) % bit_count) as usize)
+    }
+
+    fn contains(&self, hashes: (u64, u64)) -> bool {
+        self.bit_indices(hashes)
+            .all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    fn insert(&mut self, hashes: (u64, u64)) {
+        for bit in self.bit_indices(hashes) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    fn clear(&mut self) {
+        for word in &mut self.bits {
+            *word = 0;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Filters {
+    current: BloomFilter,
+    previous: BloomFilter,
+    /// When `current` started being filled.
+    rotated_at: Option<Instant>,
+}
+
+/// Accepts each ClientHello only once within a time window, the ClientHellos seen are
+/// remembered in a pair of Bloom filters.
+///
+/// Memory use is fixed however many clients connect, but a Bloom filter occasionally reports a
+/// ClientHello it has not seen so some 0-RTT data which is not a replay is refused. A
+/// ClientHello is remembered for at least `window`, so session tickets should be valid for no
+/// longer than `window` or a replay sent once it has passed would be accepted.
+#[derive(Debug)]
+pub struct ReplayWindow {
+    window: Duration,
+    hasher: RandomState,
+    filters: Mutex<Filters>,
+}
+
+impl ReplayWindow {
+    /// Creates a `ReplayWindow` sized for `expected_client_hellos` resumed connections in each
+    /// `window`.
+    pub fn new(window: Duration, expected_client_hellos: usize) -> Self {
+        let bloom_filter = BloomFilter::new(expected_client_hellos.max(1) * BITS_PER_CLIENT_HELLO);
+
+        Self {
+            window,
+            hasher: RandomState::new(),
+            filters: Mutex::new(Filters {
+                current: bloom_filter.clone(),
+                previous: bloom_filter,
+                rotated_at: None,
+            }),
+        }
+    }
+
+    /// The randoms are chosen by the client so they are hashed with a secret key, otherwise
+    /// they could be picked to fill the filters.
+    fn hashes(&self, random: &[u8]) -> (u64, u64) {
+        let hash = |seed: u8| {
+            let mut hasher = self.hasher.build_hasher();
+            seed.hash(&mut hasher);
+            random.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        // a second hash of zero would set the same bit every time
+        (hash(0), hash(1) | 1)
+    }
+}
+
+impl AntiReplay for ReplayWindow {
+    fn check(&self, client_hello: &ClientHello, now: Instant) -> bool {
+        let hashes = self.hashes(client_hello.random());
+
+        let mut filters = self.filters.lock().expect("failed to lock filters");
+
+        match filters.rotated_at {
+            Some(rotated_at) if now.duration_since(rotated_at) >= self.window * 2 => {
+                filters.current.clear();
+                filters.previous.clear();
+                filters.rotated_at = Some(now);
+            }
+            Some(rotated_at) if now.duration_since(rotated_at) >= self.window => {
+                let Filters {
+                    current, previous, ..
+                } = &mut *filters;
+                mem::swap(current, previous);
+                current.clear();
+                filters.rotated_at = Some(now);
+            }
+            Some(_) => {}
+            None => filters.rotated_at = Some(now),
+        }
+
+        if filters.current.contains(hashes) || filters.previous.contains(hashes) {
+            debug!("refusing 0-RTT data sent with a ClientHello which has been seen before");
+            return false;
+        }
+
+        filters.current.insert(hashes);
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReplayWindow;
+    use replay_protection::{AntiReplay, ClientHello};
+    use std::time::{Duration, Instant};
+
+    fn client_hello(random: u8) -> ClientHello {
+        let mut client_hello = vec![1, 0, 0, 0, 0x03, 0x03];
+        client_hello.extend_from_slice(&[random; 32]);
+        client_hello.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0]);
+
+        ClientHello::parse(&client_hello).unwrap()
+    }
+
+    #[test]
+    fn client_hello_is_accepted_once_within_window() {
+        let replay_window = ReplayWindow::new(Duration::from_secs(10), 100);
+        let now = Instant::now();
+
+        assert!(replay_window.check(&client_hello(1), now));
+        assert!(replay_window.check(&client_hello(2), now));
+        assert!(!replay_window.check(&client_hello(1), now + Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn client_hello_is_remembered_for_at_least_window() {
+        let replay_window = ReplayWindow::new(Duration::from_secs(10), 100);
+        let now = Instant::now();
+
+        assert!(replay_window.check(&client_hello(2), now));
+        assert!(replay_window.check(&client_hello(1), now + Duration::from_secs(9)));
+
+        // the filters rotate after 10s, the ClientHello is still in the previous filter
+        assert!(!replay_window.check(&client_hello(1), now + Duration::from_secs(18)));
+
+        // only forgotten once two windows have passed
+        assert!(replay_window.check(&client_hello(1), now + Duration::from_secs(40)));
+    }
+}
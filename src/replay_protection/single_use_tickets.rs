@@ -0,0 +1,110 @@
+use bytes::Bytes;
+use replay_protection::{AntiReplay, ClientHello};
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+struct UsedTickets {
+    identities: HashSet<Bytes>,
+    /// The identities in the order they were used so the expired ones are found first.
+    used_at: VecDeque<(Instant, Bytes)>,
+}
+
+/// Accepts the 0-RTT data sent with each session ticket only once.
+///
+/// Every ticket used for 0-RTT is remembered until it has expired, so memory grows with the
+/// number of resumed connections within `ticket_lifetime`.
+#[derive(Debug)]
+pub struct SingleUseTickets {
+    ticket_lifetime: Duration,
+    used_tickets: Mutex<UsedTickets>,
+}
+
+impl SingleUseTickets {
+    /// TLS 1.3 session tickets are never valid for longer than 7 days (RFC 8446 section 4.6.1).
+    pub const MAX_TICKET_LIFETIME_SECS: u64 = 7 * 24 * 60 * 60;
+
+    /// Creates a `SingleUseTickets` for session tickets which are valid for `ticket_lifetime`.
+    pub fn new(ticket_lifetime: Duration) -> Self {
+        Self {
+            ticket_lifetime,
+            used_tickets: Mutex::default(),
+        }
+    }
+}
+
+impl Default for SingleUseTickets {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(Self::MAX_TICKET_LIFETIME_SECS))
+    }
+}
+
+impl AntiReplay for SingleUseTickets {
+    fn check(&self, client_hello: &ClientHello, now: Instant) -> bool {
+        let psk_identity = match client_hello.psk_identity() {
+            Some(psk_identity) => Bytes::from(psk_identity),
+            None => return false,
+        };
+
+        let mut used_tickets = self.used_tickets
+            .lock()
+            .expect("failed to lock used_tickets");
+
+        // an expired ticket cannot be used to resume a session so it need not be remembered
+        while used_tickets
+            .used_at
+            .front()
+            .map_or(false, |&(used_at, _)| used_at + self.ticket_lifetime <= now)
+        {
+            if let Some((_, expired)) = used_tickets.used_at.pop_front() {
+                used_tickets.identities.remove(&expired);
+            }
+        }
+
+        if !used_tickets.identities.insert(psk_identity.clone()) {
+            debug!("refusing 0-RTT data sent with a ticket which has already been used");
+            return false;
+        }
+
+        used_tickets.used_at.push_back((now, psk_identity));
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SingleUseTickets;
+    use replay_protection::{AntiReplay, ClientHello};
+    use std::time::{Duration, Instant};
+
+    fn client_hello(psk_identity: u8) -> ClientHello {
+        let mut client_hello = vec![1, 0, 0, 0, 0x03, 0x03];
+        client_hello.extend_from_slice(&[psk_identity; 32]);
+        client_hello.extend_from_slice(&[0, 0, 2, 0x13, 0x01, 1, 0, 0, 15]);
+        client_hello.extend_from_slice(&[0, 41, 0, 11, 0, 9, 0, 3, psk_identity, 2, 3, 0, 0, 0, 0]);
+
+        ClientHello::parse(&client_hello).unwrap()
+    }
+
+    #[test]
+    fn each_ticket_is_accepted_once() {
+        let single_use_tickets = SingleUseTickets::default();
+        let now = Instant::now();
+
+        assert!(single_use_tickets.check(&client_hello(1), now));
+        assert!(single_use_tickets.check(&client_hello(2), now));
+        assert!(!single_use_tickets.check(&client_hello(1), now));
+    }
+
+    #[test]
+    fn expired_tickets_are_forgotten() {
+        let single_use_tickets = SingleUseTickets::new(Duration::from_secs(10));
+        let now = Instant::now();
+
+        assert!(single_use_tickets.check(&client_hello(1), now));
+        assert!(!single_use_tickets.check(&client_hello(1), now + Duration::from_secs(9)));
+        assert!(single_use_tickets.check(&client_hello(1), now + Duration::from_secs(10)));
+    }
+}
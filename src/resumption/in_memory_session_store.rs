@@ -0,0 +1,174 @@
+use protocol::ServerId;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use {SessionStore, StoredSession};
+
+#[derive(Debug, Default)]
+struct Sessions {
+    by_server: HashMap<ServerId, StoredSession>,
+    /// The servers with a stored session from the least to the most recently used.
+    recently_used: VecDeque<ServerId>,
+}
+
+impl Sessions {
+    fn touch(&mut self, server_id: &ServerId) {
+        if let Some(index) = self.recently_used.iter().position(|used| used == server_id) {
+            self.recently_used.remove(index);
+        }
+
+        self.recently_used.push_back(server_id.clone());
+    }
+
+    fn forget(&mut self, server_id: &ServerId) {
+        if self.by_server.remove(server_id).is_some() {
+            self.recently_used.retain(|used| used != server_id);
+        }
+    }
+
+    fn insert(&mut self, server_id: &ServerId, session: StoredSession, capacity: usize) {
+        self.by_server.insert(server_id.clone(), session);
+        self.touch(server_id);
+
+        while self.recently_used.len() > capacity {
+            if let Some(evicted) = self.recently_used.pop_front() {
+                debug!("forgetting the session of {:?} to make room", evicted);
+                self.by_server.remove(&evicted);
+            }
+        }
+    }
+}
+
+/// Keeps the sessions of at most `capacity` servers in memory, the least recently used session
+/// is forgotten to make room for another.
+#[derive(Debug)]
+pub struct InMemorySessionStore {
+    capacity: usize,
+    sessions: Mutex<Sessions>,
+}
+
+impl InMemorySessionStore {
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    pub fn new() -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            sessions: Mutex::default(),
+        }
+    }
+}
+
+impl Default for InMemorySessionStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionStore for InMemorySessionStore {
+    fn get(&self, server_id: &ServerId) -> Option<StoredSession> {
+        let mut sessions = self.sessions.lock().expect("failed to lock sessions");
+
+        let session = sessions.by_server.get(server_id).cloned()?;
+        sessions.touch(server_id);
+
+        Some(session)
+    }
+
+    fn put(&self, server_id: &ServerId, session: StoredSession) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut sessions = self.sessions.lock().expect("failed to lock sessions");
+
+        sessions.insert(server_id, session, self.capacity);
+    }
+
+    fn remove(&self, server_id: &ServerId) {
+        let mut sessions = self.sessions.lock().expect("failed to lock sessions");
+
+        sessions.forget(server_id);
+    }
+
+    fn update(&self, server_id: &ServerId, update: &mut FnMut(&mut Option<StoredSession>)) {
+        let mut sessions = self.sessions.lock().expect("failed to lock sessions");
+
+        let mut session = sessions.by_server.get(server_id).cloned();
+        update(&mut session);
+
+        match session {
+            Some(session) if self.capacity > 0 => {
+                sessions.insert(server_id, session, self.capacity)
+            }
+            _ => sessions.forget(server_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InMemorySessionStore;
+    use protocol::ServerId;
+    use {SessionStore, StoredSession};
+
+    fn stored_session(transport_parameters: u8) -> StoredSession {
+        StoredSession {
+            transport_parameters: Some(vec![transport_parameters]),
+            ..StoredSession::default()
+        }
+    }
+
+    #[test]
+    fn least_recently_used_session_is_evicted() {
+        let store = InMemorySessionStore::with_capacity(2);
+        let server_a = ServerId::new("a.example".to_owned(), 443);
+        let server_b = ServerId::new("b.example".to_owned(), 443);
+        let server_c = ServerId::new("c.example".to_owned(), 443);
+
+        store.put(&server_a, stored_session(1));
+        store.put(&server_b, stored_session(2));
+
+        // using a makes b the least recently used
+        assert_eq!(store.get(&server_a), Some(stored_session(1)));
+
+        store.put(&server_c, stored_session(3));
+
+        assert_eq!(store.get(&server_a), Some(stored_session(1)));
+        assert_eq!(store.get(&server_b), None);
+        assert_eq!(store.get(&server_c), Some(stored_session(3)));
+    }
+
+    #[test]
+    fn put_replaces_and_remove_forgets() {
+        let store = InMemorySessionStore::new();
+        let server_id = ServerId::new("example.com".to_owned(), 443);
+
+        store.put(&server_id, stored_session(1));
+        store.put(&server_id, stored_session(2));
+        assert_eq!(store.get(&server_id), Some(stored_session(2)));
+
+        store.remove(&server_id);
+        assert_eq!(store.get(&server_id), None);
+    }
+
+    #[test]
+    fn update_changes_the_stored_session() {
+        let store = InMemorySessionStore::new();
+        let server_id = ServerId::new("example.com".to_owned(), 443);
+
+        store.update(&server_id, &mut |session| {
+            assert_eq!(*session, None);
+            *session = Some(stored_session(1));
+        });
+        assert_eq!(store.get(&server_id), Some(stored_session(1)));
+
+        store.update(&server_id, &mut |session| {
+            assert_eq!(*session, Some(stored_session(1)));
+            *session = None;
+        });
+        assert_eq!(store.get(&server_id), None);
+    }
+}
@@ -0,0 +1,11 @@
+mod stored_session;
+pub use self::stored_session::StoredSession;
+
+mod session_store;
+pub use self::session_store::SessionStore;
+
+mod in_memory_session_store;
+pub use self::in_memory_session_store::InMemorySessionStore;
+
+mod server_session_store;
+pub use self::server_session_store::ServerSessionStore;
@@ -0,0 +1,181 @@
+use protocol::ServerId;
+use rustls::{StoresClientSessions, SupportedCipherSuite, ALL_CIPHERSUITES};
+use std::sync::Arc;
+use {SessionStore, StoredSession};
+
+/// The session of a single server in a `SessionStore`, rustls persists its values through this
+/// and the client remembers the server's transport parameters alongside them.
+#[derive(Debug)]
+pub struct ServerSessionStore {
+    store: Arc<SessionStore>,
+    server_id: ServerId,
+}
+
+impl ServerSessionStore {
+    pub fn new(store: Arc<SessionStore>, server_id: ServerId) -> Self {
+        Self { store, server_id }
+    }
+
+    /// The encoded transport parameters the server sent in the handshake its session is from.
+    pub fn transport_parameters(&self) -> Option<Vec<u8>> {
+        self.store
+            .get(&self.server_id)
+            .and_then(|session| session.transport_parameters)
+    }
+
+    pub fn remember_transport_parameters(&self, transport_parameters: Vec<u8>) {
+        let mut transport_parameters = Some(transport_parameters);
+
+        self.store.update(&self.server_id, &mut |session| {
+            if let Some(transport_parameters) = transport_parameters.take() {
+                session
+                    .get_or_insert_with(StoredSession::default)
+                    .transport_parameters = Some(transport_parameters);
+            }
+        });
+    }
+
+    /// The cipher suite of the session the server's tickets came from.
+    pub fn cipher_suite(&self) -> Option<&'static SupportedCipherSuite> {
+        let cipher_suite = self.store
+            .get(&self.server_id)
+            .and_then(|session| session.cipher_suite)?;
+
+        ALL_CIPHERSUITES
+            .iter()
+            .find(|supported_cipher_suite| supported_cipher_suite.suite.get_u16() == cipher_suite)
+            .cloned()
+    }
+
+    pub fn remember_cipher_suite(&self, supported_cipher_suite: &SupportedCipherSuite) {
+        let cipher_suite = supported_cipher_suite.suite.get_u16();
+
+        self.store.update(&self.server_id, &mut |session| {
+            session
+                .get_or_insert_with(StoredSession::default)
+                .cipher_suite = Some(cipher_suite);
+        });
+    }
+
+    /// Takes the address validation token the server sent most recently, it is not handed out
+    /// again.
+    pub fn take_address_validation_token(&self) -> Option<Vec<u8>> {
+        let mut taken = None;
+
+        self.store.update(&self.server_id, &mut |session| {
+            if let Some(session) = session.as_mut() {
+                taken = session.address_validation_token.take();
+            }
+        });
+
+        taken
+    }
+
+    pub fn remember_address_validation_token(&self, address_validation_token: Vec<u8>) {
+        let mut address_validation_token = Some(address_validation_token);
+
+        self.store.update(&self.server_id, &mut |session| {
+            if let Some(address_validation_token) = address_validation_token.take() {
+                session
+                    .get_or_insert_with(StoredSession::default)
+                    .address_validation_token = Some(address_validation_token);
+            }
+        });
+    }
+}
+
+impl StoresClientSessions for ServerSessionStore {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        let mut value = Some((key, value));
+
+        self.store.update(&self.server_id, &mut |session| {
+            if let Some((key, value)) = value.take() {
+                session
+                    .get_or_insert_with(StoredSession::default)
+                    .tls_values
+                    .insert(key, value);
+            }
+        });
+
+        true
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.store
+            .get(&self.server_id)
+            .and_then(|mut session| session.tls_values.remove(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServerSessionStore;
+    use protocol::ServerId;
+    use rustls::{StoresClientSessions, ALL_CIPHERSUITES};
+    use std::sync::Arc;
+    use {InMemorySessionStore, SessionStore};
+
+    #[test]
+    fn servers_are_stored_separately() {
+        let store: Arc<SessionStore> = Arc::new(InMemorySessionStore::new());
+        let store_a =
+            ServerSessionStore::new(store.clone(), ServerId::new("a.example".to_owned(), 443));
+        let store_b =
+            ServerSessionStore::new(store.clone(), ServerId::new("a.example".to_owned(), 4433));
+
+        assert!(store_a.put(b"session".to_vec(), b"ticket".to_vec()));
+        store_a.remember_transport_parameters(vec![1, 2, 3]);
+
+        assert_eq!(store_a.get(b"session"), Some(b"ticket".to_vec()));
+        assert_eq!(store_b.get(b"session"), None);
+        assert_eq!(store_a.transport_parameters(), Some(vec![1, 2, 3]));
+        assert_eq!(store_b.transport_parameters(), None);
+    }
+
+    #[test]
+    fn tickets_and_transport_parameters_are_stored_together() {
+        let store: Arc<SessionStore> = Arc::new(InMemorySessionStore::new());
+        let server_id = ServerId::new("example.com".to_owned(), 443);
+        let server_store = ServerSessionStore::new(store.clone(), server_id.clone());
+
+        server_store.put(b"session".to_vec(), b"ticket".to_vec());
+        server_store.remember_transport_parameters(vec![1]);
+
+        let session = store.get(&server_id).unwrap();
+        assert_eq!(session.tls_values.get(&b"session"[..]), Some(&b"ticket".to_vec()));
+        assert_eq!(session.transport_parameters, Some(vec![1]));
+
+        store.remove(&server_id);
+
+        assert_eq!(server_store.get(b"session"), None);
+        assert_eq!(server_store.transport_parameters(), None);
+    }
+
+    #[test]
+    fn address_validation_token_is_only_taken_once() {
+        let store: Arc<SessionStore> = Arc::new(InMemorySessionStore::new());
+        let server_store =
+            ServerSessionStore::new(store, ServerId::new("example.com".to_owned(), 443));
+
+        server_store.remember_address_validation_token(vec![1, 2, 3]);
+
+        assert_eq!(server_store.take_address_validation_token(), Some(vec![1, 2, 3]));
+        assert_eq!(server_store.take_address_validation_token(), None);
+    }
+
+    #[test]
+    fn cipher_suite_is_remembered() {
+        let store: Arc<SessionStore> = Arc::new(InMemorySessionStore::new());
+        let server_store =
+            ServerSessionStore::new(store, ServerId::new("example.com".to_owned(), 443));
+
+        assert!(server_store.cipher_suite().is_none());
+
+        server_store.remember_cipher_suite(&ALL_CIPHERSUITES[1]);
+
+        assert_eq!(
+            server_store.cipher_suite().map(|suite| suite.suite),
+            Some(ALL_CIPHERSUITES[1].suite)
+        );
+    }
+}
@@ -0,0 +1,27 @@
+use protocol::ServerId;
+use std::fmt::Debug;
+use StoredSession;
+
+/// Stores the sessions of the servers a client has connected to, so later connections to them
+/// are resumed rather than performing a full handshake.
+///
+/// A store is shared between all the connections using the same `ClientConfiguration`, so it
+/// may be called from any thread. Implement this to keep sessions somewhere other than memory,
+/// for example on disk so they outlive the process.
+pub trait SessionStore: Debug + Send + Sync {
+    /// The session stored for `server_id`, if there is one.
+    fn get(&self, server_id: &ServerId) -> Option<StoredSession>;
+
+    /// Stores `session` for `server_id`, replacing any session stored for it before.
+    fn put(&self, server_id: &ServerId, session: StoredSession);
+
+    /// Forgets the session stored for `server_id`, the next connection to it performs a full
+    /// handshake.
+    fn remove(&self, server_id: &ServerId);
+
+    /// Changes the session stored for `server_id` in place, `None` meaning no session is stored.
+    ///
+    /// The session is stored, or forgotten if `update` leaves `None`, before any other call
+    /// reads it so concurrent updates to the same server are never lost.
+    fn update(&self, server_id: &ServerId, update: &mut FnMut(&mut Option<StoredSession>));
+}
@@ -0,0 +1,18 @@
+use std::collections::HashMap;
+
+/// What a client remembers about a server to resume its TLS session on the next connection.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct StoredSession {
+    /// The values rustls persists for the server, its session tickets among them, keyed the way
+    /// rustls looks them up.
+    pub tls_values: HashMap<Vec<u8>, Vec<u8>>,
+    /// The encoded transport parameters the server sent in the handshake its tickets came from,
+    /// 0-RTT data sent with a ticket has to stay within these.
+    pub transport_parameters: Option<Vec<u8>>,
+    /// The latest token the server sent to skip its address validation next time, each token is
+    /// only used once so the connections can not be linked by it.
+    pub address_validation_token: Option<Vec<u8>>,
+    /// The cipher suite of the session the tickets came from, the 0-RTT keys of the next
+    /// connection are derived in it before the server has agreed on anything.
+    pub cipher_suite: Option<u16>,
+}
@@ -0,0 +1,40 @@
+/// When a server asks new clients to prove they can receive packets at their address before it
+/// does any work for them.
+///
+/// A Retry costs the client a round trip, but until a client's address is validated the server
+/// sends it no more than three times the bytes it received, so its packets can not be used to
+/// flood a spoofed address.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum RetryPolicy {
+    /// Every client without a valid token is sent a Retry packet.
+    Always,
+
+    /// Clients are never sent a Retry packet.
+    Never,
+
+    /// Clients without a valid token are sent a Retry packet once this many handshakes are in
+    /// progress.
+    UnderLoad { max_pending_handshakes: usize },
+}
+
+impl RetryPolicy {
+    /// Whether a client without a valid token is sent a Retry packet when `pending_handshakes`
+    /// handshakes are in progress.
+    pub fn requires_retry(self, pending_handshakes: usize) -> bool {
+        match self {
+            RetryPolicy::Always => true,
+            RetryPolicy::Never => false,
+            RetryPolicy::UnderLoad {
+                max_pending_handshakes,
+            } => pending_handshakes >= max_pending_handshakes,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::UnderLoad {
+            max_pending_handshakes: 100,
+        }
+    }
+}
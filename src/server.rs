@@ -1,17 +1,16 @@
 use errors::*;
-use futures::future::Empty;
-use futures::Stream;
 use packets::PacketDispatcher;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio_core::net::{UdpFramed, UdpSocket};
-use tokio_core::reactor::Handle;
+use tokio_core::net::UdpSocket;
+use tokio_core::reactor::{Handle, Remote};
 use {NewRemoteClients, ServerConfiguration};
 
 #[derive(Debug)]
 pub struct Server {
     packet_dispatcher: Arc<PacketDispatcher>,
     server_configuration: ServerConfiguration,
+    remote: Remote,
 }
 
 impl Server {
@@ -27,9 +26,21 @@ impl Server {
 
         debug!("bound udp socket to {:?}", addr);
 
+        let version = server_configuration.version;
+        let wire_format = version
+            .wire_format()
+            .ok_or_else(|| ErrorKind::UnsupportedVersion(version))?;
+
         Ok(Self {
-            packet_dispatcher: Arc::new(PacketDispatcher::new(udp_socket)),
+            packet_dispatcher: Arc::new(PacketDispatcher::new(
+                udp_socket,
+                wire_format,
+                server_configuration.connection_id_len,
+                server_configuration.stateless_reset_key,
+                server_configuration.max_stateless_resets_per_second,
+            )?),
             server_configuration,
+            remote: handle.remote().clone(),
         })
     }
 
@@ -37,13 +48,12 @@ impl Server {
         self.packet_dispatcher.local_addr()
     }
 
+    /// The clients which connect to this `Server`, each is yielded once its handshake completes.
     pub fn incoming(self) -> NewRemoteClients {
-        unimplemented!()
-        // stream::empty::<(), Error>()
-        //     .map(move |_|{
-        //         let connection_id = ConnectionId::generate(&mut ::rand::thread_rng());
-        //         Connection::new_server(connection_id)
-        //     })
-        //     .boxed()
+        NewRemoteClients::new(
+            self.packet_dispatcher,
+            Arc::new(self.server_configuration),
+            self.remote,
+        )
     }
 }
@@ -1,8 +1,13 @@
 use debugit::DebugIt;
+use packets::AckManager;
+use protocol::{ConnectionId, StatelessResetToken, Version};
+use recovery::DEFAULT_BURST_DATAGRAMS;
 use rustls::{NoClientAuth, ServerConfig as TlsConfig};
 use std::fmt::{Debug, Formatter, Result as FmtResult};
 use std::sync::Arc;
-use ConnectionTerminationMode;
+use std::time::Duration;
+use {AddressValidationToken, AlpnSelector, AntiReplay, CongestionControl,
+     ConnectionTerminationMode, KeyUpdate, RetryPolicy, SingleUseTickets};
 
 lazy_static! {
     static ref DEFAULT_TLS_CONFIG: Arc<TlsConfig> = Arc::new(TlsConfig::new(NoClientAuth::new()));
@@ -13,6 +18,67 @@ pub struct ServerConfiguration {
     pub tls_config: Arc<TlsConfig>,
     pub max_incoming_data_per_stream: u32,
     pub max_incoming_data_per_connection: u32,
+    /// The QUIC version clients are accepted with.
+    pub version: Version,
+    /// The versions clients may also start their connection in, which are moved to `version`
+    /// without another round trip (RFC 9368). Those which do not share the wire format of
+    /// `version` are ignored, and clients must offer `version` in their transport parameters.
+    pub compatible_versions: Vec<Version>,
+    /// The length of the connection ids clients address us with, connections with other
+    /// connection id lengths are rejected.
+    pub connection_id_len: usize,
+    /// The exponent applied to the ACK delay of the ACK frames we send.
+    pub ack_delay_exponent: u8,
+    /// The longest we wait before acknowledging a packet.
+    pub max_ack_delay: Duration,
+    /// The congestion controller each connection limits its sending with.
+    pub congestion_control: CongestionControl,
+    /// Whether packets are spread out over the round trip rather than sent in bursts, turning
+    /// this off suits benchmarks over loopback.
+    pub pacing_enabled: bool,
+    /// The number of datagrams which may be sent back to back while pacing.
+    pub pacing_burst_datagrams: usize,
+    /// The 1-RTT keys are updated after sealing this many packets with them.
+    pub key_update_interval_packets: u64,
+    /// The 1-RTT keys are updated after sealing this many bytes with them.
+    pub key_update_interval_bytes: u64,
+    /// Whether data sent by resuming clients in 0-RTT packets is accepted. 0-RTT data can be
+    /// replayed by an attacker, so only turn this on if the application checks
+    /// `DataStream::is_early_data` before acting on requests which are not idempotent.
+    pub accept_early_data: bool,
+    /// The most bytes of 0-RTT packets accepted from each client, later 0-RTT packets are
+    /// discarded and have to be sent again by the client.
+    pub max_early_data_size: usize,
+    /// Refuses 0-RTT data which may be a replay of 0-RTT data accepted before.
+    pub anti_replay: Arc<AntiReplay>,
+    /// The application protocols this server speaks, in order of preference. A client which
+    /// offers none of them fails its handshake, when empty no protocol is negotiated.
+    pub alpn_protocols: Vec<String>,
+    /// Picks the application protocol of each client, `None` picks the first of
+    /// `alpn_protocols` the client offered.
+    pub alpn_selector: Option<Arc<AlpnSelector>>,
+    /// When new clients are sent a Retry packet to validate their address.
+    pub retry_policy: RetryPolicy,
+    /// The key address validation tokens are sealed with, servers sharing a port or taking over
+    /// from each other must share it. A random key is generated by default.
+    pub address_validation_token_key: [u8; 32],
+    /// How long the token of a Retry packet is accepted for.
+    pub retry_token_lifetime: Duration,
+    /// Whether clients are given a token in a NEW_TOKEN frame once their handshake completes,
+    /// which lets them skip the Retry on their next connection.
+    pub issue_new_tokens: bool,
+    /// How long the tokens of NEW_TOKEN frames are accepted for.
+    pub new_token_lifetime: Duration,
+    /// Whether the Version Negotiation packets sent to clients in versions we do not support
+    /// also list a random version which forces version negotiation, so clients which do not
+    /// ignore unknown versions are found out.
+    pub grease_version_negotiation: bool,
+    /// The key the stateless reset tokens of connections are derived from, servers sharing a
+    /// port or taking over from each other must share it. A random key is generated by default.
+    pub stateless_reset_key: [u8; 32],
+    /// The most stateless resets sent each second in answer to packets for unknown connections,
+    /// none are sent when this is 0.
+    pub max_stateless_resets_per_second: u32,
 }
 
 impl Debug for ServerConfiguration {
@@ -31,6 +97,38 @@ impl Debug for ServerConfiguration {
                 "max_incoming_data_per_connection",
                 &self.max_incoming_data_per_connection,
             )
+            .field("version", &self.version)
+            .field("compatible_versions", &self.compatible_versions)
+            .field("connection_id_len", &self.connection_id_len)
+            .field("ack_delay_exponent", &self.ack_delay_exponent)
+            .field("max_ack_delay", &self.max_ack_delay)
+            .field("congestion_control", &self.congestion_control)
+            .field("pacing_enabled", &self.pacing_enabled)
+            .field("pacing_burst_datagrams", &self.pacing_burst_datagrams)
+            .field(
+                "key_update_interval_packets",
+                &self.key_update_interval_packets,
+            )
+            .field("key_update_interval_bytes", &self.key_update_interval_bytes)
+            .field("accept_early_data", &self.accept_early_data)
+            .field("max_early_data_size", &self.max_early_data_size)
+            .field("anti_replay", &self.anti_replay)
+            .field("alpn_protocols", &self.alpn_protocols)
+            .field("alpn_selector", &self.alpn_selector)
+            .field("retry_policy", &self.retry_policy)
+            .field("address_validation_token_key", &"<redacted>")
+            .field("retry_token_lifetime", &self.retry_token_lifetime)
+            .field("issue_new_tokens", &self.issue_new_tokens)
+            .field("new_token_lifetime", &self.new_token_lifetime)
+            .field(
+                "grease_version_negotiation",
+                &self.grease_version_negotiation,
+            )
+            .field("stateless_reset_key", &"<redacted>")
+            .field(
+                "max_stateless_resets_per_second",
+                &self.max_stateless_resets_per_second,
+            )
             .finish()
     }
 }
@@ -39,6 +137,18 @@ impl ServerConfiguration {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// The versions clients may start their connection in, `version` followed by the
+    /// compatible versions which share its wire format.
+    pub(crate) fn supported_versions(&self) -> Vec<Version> {
+        let wire_format = self.version.wire_format();
+
+        let compatible_versions = self.compatible_versions.iter().cloned().filter(|&version| {
+            version != self.version && version.wire_format() == wire_format
+        });
+
+        Some(self.version).into_iter().chain(compatible_versions).collect()
+    }
 }
 
 impl Default for ServerConfiguration {
@@ -48,6 +158,31 @@ impl Default for ServerConfiguration {
             tls_config: DEFAULT_TLS_CONFIG.clone(),
             max_incoming_data_per_stream: 8192,
             max_incoming_data_per_connection: 65536,
+            version: Version::V1,
+            compatible_versions: Vec::new(),
+            connection_id_len: ConnectionId::DEFAULT_LEN,
+            ack_delay_exponent: AckManager::DEFAULT_ACK_DELAY_EXPONENT,
+            max_ack_delay: Duration::from_millis(AckManager::DEFAULT_MAX_ACK_DELAY_MILLIS),
+            congestion_control: CongestionControl::default(),
+            pacing_enabled: true,
+            pacing_burst_datagrams: DEFAULT_BURST_DATAGRAMS,
+            key_update_interval_packets: KeyUpdate::DEFAULT_INTERVAL_PACKETS,
+            key_update_interval_bytes: KeyUpdate::DEFAULT_INTERVAL_BYTES,
+            accept_early_data: false,
+            max_early_data_size: 16384,
+            anti_replay: Arc::new(SingleUseTickets::default()),
+            alpn_protocols: Vec::new(),
+            alpn_selector: None,
+            retry_policy: RetryPolicy::default(),
+            address_validation_token_key: AddressValidationToken::generate_key()
+                .expect("failed to generate an address validation token key"),
+            retry_token_lifetime: Duration::from_secs(10),
+            issue_new_tokens: true,
+            new_token_lifetime: Duration::from_secs(24 * 60 * 60),
+            grease_version_negotiation: false,
+            stateless_reset_key: StatelessResetToken::generate_key()
+                .expect("failed to generate a stateless reset key"),
+            max_stateless_resets_per_second: 100,
         }
     }
 }
@@ -1,41 +1,115 @@
+use bytes::Bytes;
+use crypto::{HeaderProtectionKeys, StatelessResetTokens};
 use errors::*;
-use futures::{Future, IntoFuture, Poll};
-use packets::{IncomingPacket, OutgoingPacket};
-use protocol::{ClientHelloMessageParameters, ConnectionId, EncryptedExtensionsMessageParameters,
-               Role, ServerSpecificTransportParameters, TransportParameters, Version, Writable};
-use rustls::quic::{QuicExt, ServerQuicExt};
-use rustls::ServerSession;
+use futures::{Async, Poll, Sink, Stream};
+use packets::{IncomingPacket, IncomingPackets, OutgoingPacket, OutgoingPackets};
+use protocol::{ClientHelloMessageParameters, ClientSpecificTransportParameters, ConnectionId,
+               EncryptedExtensionsMessageParameters, Role,
+               ServerSpecificTransportParameters, StatelessResetToken, TransportParameters,
+               Version, VersionInformation, WireFormat, Writable};
+use rustls::quic::ServerQuicExt;
+use rustls::{ServerConfig as TlsConfig, ServerSession};
 use smallvec::SmallVec;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use tokio_rustls::{self, TlsStream};
-use {DataStream, Perspective, ServerConfiguration, StreamMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_core::reactor::Remote;
+use {AddressValidationToken, ClientHello, CongestionControl, Connection, Perspective,
+     ServerConfiguration, StreamMap};
 
 #[derive(Debug)]
 pub struct ServerPerspective {
     client_address: SocketAddr,
     server_configuration: Arc<ServerConfiguration>,
+    incoming_packets: Mutex<IncomingPackets>,
+    outgoing_packets: Mutex<OutgoingPackets>,
+    header_protection_keys: Arc<HeaderProtectionKeys>,
+    stateless_reset_tokens: Arc<StatelessResetTokens>,
+    /// The token ending the stateless resets the server sends for this connection.
+    stateless_reset_token: StatelessResetToken,
+    /// The version of the client's first Initial packet, which the connection moves on from
+    /// when it is one of the configured compatible versions.
+    initial_version: Version,
+    /// The destination connection id of the client's first Initial packet.
+    original_destination_connection_id: ConnectionId,
+    /// The source connection id of the Retry packet the client answered, `None` if it was not
+    /// sent one.
+    retry_source_connection_id: Option<ConnectionId>,
+    /// Whether the client's Initial packets carried a valid token, which proves it received the
+    /// packet the token came in at its address.
+    address_validated: bool,
+    remote: Remote,
 }
 
 impl ServerPerspective {
     pub(crate) fn new(
         client_address: SocketAddr,
         server_configuration: Arc<ServerConfiguration>,
+        incoming_packets: IncomingPackets,
+        outgoing_packets: OutgoingPackets,
+        header_protection_keys: Arc<HeaderProtectionKeys>,
+        stateless_reset_tokens: Arc<StatelessResetTokens>,
+        stateless_reset_token: StatelessResetToken,
+        initial_version: Version,
+        original_destination_connection_id: ConnectionId,
+        retry_source_connection_id: Option<ConnectionId>,
+        address_validated: bool,
+        remote: Remote,
     ) -> Self {
         Self {
             client_address,
             server_configuration,
+            incoming_packets: Mutex::new(incoming_packets),
+            outgoing_packets: Mutex::new(outgoing_packets),
+            header_protection_keys,
+            stateless_reset_tokens,
+            stateless_reset_token,
+            initial_version,
+            original_destination_connection_id,
+            retry_source_connection_id,
+            address_validated,
+            remote,
         }
     }
 
+    /// The TLS config the handshake with a client which sent `client_hello` is performed with,
+    /// it only offers the application protocol picked for the client.
+    fn tls_config_for_client(
+        server_configuration: &ServerConfiguration,
+        client_hello: Option<&ClientHello>,
+    ) -> Result<TlsConfig> {
+        let offered = client_hello.map_or(&[][..], ClientHello::alpn_protocols);
+        let supported = &server_configuration.alpn_protocols;
+
+        let selected = match &server_configuration.alpn_selector {
+            Some(alpn_selector) => alpn_selector.select(offered, supported),
+            None => supported
+                .iter()
+                .find(|protocol| offered.contains(protocol))
+                .cloned(),
+        };
+
+        let selected =
+            selected.ok_or_else(|| ErrorKind::NoCommonApplicationProtocol(offered.to_vec()))?;
+
+        debug!("selected application protocol {:?}", selected);
+
+        let mut tls_config = (*server_configuration.tls_config).clone();
+        tls_config.set_protocols(&[selected]);
+
+        Ok(tls_config)
+    }
+
     fn build_transport_parameters(
         &self,
     ) -> TransportParameters<EncryptedExtensionsMessageParameters, ServerSpecificTransportParameters>
     {
+        let supported_versions = self.server_configuration.supported_versions();
+
         TransportParameters {
             message_parameters: EncryptedExtensionsMessageParameters {
-                negotiated_version: Version::DRAFT_IETF_08,
-                supported_versions: hashset![Version::DRAFT_IETF_08],
+                negotiated_version: self.server_configuration.version,
+                supported_versions: supported_versions.iter().cloned().collect(),
             },
             initial_max_stream_data: self.server_configuration.max_incoming_data_per_stream,
             initial_max_data: self.server_configuration.max_incoming_data_per_connection,
@@ -43,11 +117,18 @@ impl ServerPerspective {
             initial_max_bidi_streams: None,
             initial_max_uni_streams: None,
             max_packet_size: Some(65527),
-            ack_delay_exponent: None,
+            ack_delay_exponent: Some(self.server_configuration.ack_delay_exponent),
+            max_ack_delay: Some(self.server_configuration.max_ack_delay),
             disable_migration: false,
+            version_information: Some(VersionInformation {
+                chosen_version: self.server_configuration.version,
+                available_versions: supported_versions,
+            }),
             role_specific_transport_parameters: ServerSpecificTransportParameters {
-                stateless_reset_token: None,
+                stateless_reset_token: Some(self.stateless_reset_token),
                 preferred_address: None,
+                original_destination_connection_id: Some(self.original_destination_connection_id),
+                retry_source_connection_id: self.retry_source_connection_id,
             },
         }
     }
@@ -55,56 +136,73 @@ impl ServerPerspective {
 
 impl Perspective for ServerPerspective {
     type TlsSession = ServerSession;
-    type HandshakeFuture =
-        Box<Future<Item = TlsStream<DataStream<Self>, Self::TlsSession>, Error = Error> + Send>;
     type IncomingTransportMessageParameters = ClientHelloMessageParameters;
-    type RoleSpecificTransportParameters = ServerSpecificTransportParameters;
+    type IncomingRoleSpecificTransportParameters = ClientSpecificTransportParameters;
 
-    fn handshake(&self, crypto_stream: DataStream<Self>) -> Self::HandshakeFuture {
-        let connection_description = crypto_stream.connection().description();
+    fn tls_session(&self, client_hello: Option<&ClientHello>) -> Result<Self::TlsSession> {
         trace!(
-            "connection {}: performing TLS handshake from server to client {:?}",
-            connection_description,
+            "performing TLS handshake from server to client {:?}",
             self.client_address
         );
 
-        let client_address_for_error = self.client_address;
-        let client_address_for_success = self.client_address;
-
-        let quic_transport_parameters = self.build_transport_parameters();
-
-        let when_connected = quic_transport_parameters
-            .bytes_vec()
-            .map(|quic_transport_parameters| {
-                ServerSession::new_quic(
-                    &self.server_configuration.tls_config,
-                    quic_transport_parameters,
-                )
-            })
-            .map(|server_session| {
-                tokio_rustls::accept_async_with_session(crypto_stream, server_session).chain_err(
-                    move || {
-                        ErrorKind::FailedToPerformTlsHandshakeWithClient(client_address_for_error)
-                    },
-                )
-            })
-            .into_future()
-            .flatten()
-            .and_then(move |tls_stream| {
-                info!(
-                    "connection {}: performed TLS handshake from server to client {:?}",
-                    connection_description, client_address_for_success
-                );
+        let server_configuration = &self.server_configuration;
 
-                {
-                    let (stream, session) = tls_stream.get_ref();
-                    stream.connection().handle_negotiated_session(session)?;
-                }
+        // rustls cannot pick the application protocol during the handshake, so the session only
+        // offers the protocol picked for the client's ClientHello
+        let mut tls_config = if server_configuration.alpn_protocols.is_empty()
+            && server_configuration.alpn_selector.is_none()
+        {
+            (*server_configuration.tls_config).clone()
+        } else {
+            Self::tls_config_for_client(server_configuration, client_hello)?
+        };
 
-                Ok(tls_stream)
+        // the tickets handed out let resuming clients send 0-RTT data, whose size QUIC limits
+        // rather than TLS (RFC 9001 section 4.6.1). The session only accepts the 0-RTT data of
+        // the first ticket the client offers, which is the one the anti-replay strategy checks,
+        // so a ClientHello it refuses is given a session which rejects the 0-RTT data.
+        if server_configuration.accept_early_data {
+            let replayed = client_hello.map_or(false, |client_hello| {
+                // the anti-replay strategy remembers every ClientHello it checks, so it is only
+                // asked about 0-RTT data which would otherwise be accepted
+                client_hello.offers_early_data()
+                    && !server_configuration
+                        .anti_replay
+                        .check(client_hello, Instant::now())
             });
 
-        Box::new(when_connected)
+            tls_config.max_early_data_size = if replayed { 0 } else { u32::max_value() };
+        }
+
+        let quic_transport_parameters = self.build_transport_parameters().bytes_vec()?;
+
+        Ok(ServerSession::new_quic(&Arc::new(tls_config), quic_transport_parameters))
+    }
+
+    fn on_handshake_started(
+        &self,
+        _connection: &Connection<Self>,
+        _tls_session: &ServerSession,
+    ) {
+    }
+
+    fn on_handshake_complete(
+        &self,
+        connection: &Connection<Self>,
+        tls_session: &ServerSession,
+    ) -> Result<()> {
+        info!(
+            "connection {}: performed TLS handshake from server to client {:?}",
+            connection.description(),
+            self.client_address
+        );
+
+        connection.handle_negotiated_session(tls_session)?;
+        connection.check_version_information()
+    }
+
+    fn handshake_error(&self) -> ErrorKind {
+        ErrorKind::FailedToPerformTlsHandshakeWithClient(self.client_address)
     }
 
     fn client_connection_id(
@@ -114,6 +212,34 @@ impl Perspective for ServerPerspective {
         remote_connection_id
     }
 
+    fn version(&self) -> Version {
+        self.server_configuration.version
+    }
+
+    fn initial_version(&self) -> Version {
+        self.initial_version
+    }
+
+    fn compatible_versions(&self) -> &[Version] {
+        &self.server_configuration.compatible_versions
+    }
+
+    fn header_protection_keys(&self) -> Arc<HeaderProtectionKeys> {
+        self.header_protection_keys.clone()
+    }
+
+    fn stateless_reset_tokens(&self) -> Arc<StatelessResetTokens> {
+        self.stateless_reset_tokens.clone()
+    }
+
+    fn issue_connection_id(&self) -> Result<Option<(ConnectionId, StatelessResetToken)>> {
+        let incoming_packets = self.incoming_packets
+            .lock()
+            .expect("failed to lock incoming_packets");
+
+        incoming_packets.issue_connection_id().map(Some)
+    }
+
     fn handshake_send_label() -> &'static str {
         "server hs"
     }
@@ -122,12 +248,28 @@ impl Perspective for ServerPerspective {
         "client hs"
     }
 
-    fn update_secret_send_label() -> &'static str {
-        "server 1rtt"
+    fn initial_send_label() -> &'static str {
+        "server in"
+    }
+
+    fn initial_receive_label() -> &'static str {
+        "client in"
+    }
+
+    fn update_secret_send_label(version: Version) -> &'static str {
+        match version {
+            Version::DRAFT_IETF_08 => "server 1rtt",
+            Version::V2 => "quicv2 ku",
+            _ => "quic ku",
+        }
     }
 
-    fn update_secret_receive_label() -> &'static str {
-        "client 1rtt"
+    fn update_secret_receive_label(version: Version) -> &'static str {
+        match version {
+            Version::DRAFT_IETF_08 => "client 1rtt",
+            Version::V2 => "quicv2 ku",
+            _ => "quic ku",
+        }
     }
 
     fn tls_exporter_send_label() -> &'static str {
@@ -146,11 +288,32 @@ impl Perspective for ServerPerspective {
         &self,
         connection_id: ConnectionId,
     ) -> Poll<SmallVec<[IncomingPacket; 1]>, Error> {
-        unimplemented!()
+        let mut incoming_packets = self.incoming_packets
+            .lock()
+            .expect("failed to lock incoming_packets");
+
+        match incoming_packets
+            .poll()
+            .chain_err(|| ErrorKind::FailedToReadIncomingPacket(connection_id))?
+        {
+            Async::NotReady => Ok(Async::NotReady),
+            Async::Ready(Some(incoming_packets)) => Ok(Async::Ready(incoming_packets)),
+            Async::Ready(None) => unreachable!("the incoming packets stream should never end"),
+        }
     }
 
     fn poll_send_packet(&self, packet: OutgoingPacket) -> Poll<(), Error> {
-        unimplemented!()
+        let mut outgoing_packets = self.outgoing_packets
+            .lock()
+            .expect("failed to lock outgoing_packets");
+
+        if outgoing_packets.start_send(packet)?.is_not_ready() {
+            return Ok(Async::NotReady);
+        }
+
+        outgoing_packets.poll_complete()?;
+
+        Ok(().into())
     }
 
     fn role() -> Role {
@@ -164,4 +327,83 @@ impl Perspective for ServerPerspective {
     fn max_incoming_data(&self) -> u32 {
         self.server_configuration.max_incoming_data_per_connection
     }
+
+    fn ack_delay_exponent(&self) -> u8 {
+        self.server_configuration.ack_delay_exponent
+    }
+
+    fn max_ack_delay(&self) -> Duration {
+        self.server_configuration.max_ack_delay
+    }
+
+    fn remote(&self) -> &Remote {
+        &self.remote
+    }
+
+    fn congestion_control(&self) -> &CongestionControl {
+        &self.server_configuration.congestion_control
+    }
+
+    fn pacing_enabled(&self) -> bool {
+        self.server_configuration.pacing_enabled
+    }
+
+    fn pacing_burst_datagrams(&self) -> usize {
+        self.server_configuration.pacing_burst_datagrams
+    }
+
+    fn key_update_interval_packets(&self) -> u64 {
+        self.server_configuration.key_update_interval_packets
+    }
+
+    fn key_update_interval_bytes(&self) -> u64 {
+        self.server_configuration.key_update_interval_bytes
+    }
+
+    fn max_early_data_size(&self) -> usize {
+        self.server_configuration.max_early_data_size
+    }
+
+    fn address_validated(&self) -> bool {
+        self.address_validated
+    }
+
+    fn alpn_protocols(&self) -> &[String] {
+        &self.server_configuration.alpn_protocols
+    }
+
+    fn address_validation_token(&self) -> Bytes {
+        Bytes::new()
+    }
+
+    fn new_token(&self) -> Option<Bytes> {
+        let server_configuration = &self.server_configuration;
+
+        // draft 08 has no NEW_TOKEN frames
+        if !server_configuration.issue_new_tokens
+            || server_configuration.version.wire_format() != Some(WireFormat::V1)
+        {
+            return None;
+        }
+
+        let token = AddressValidationToken::new(None, server_configuration.new_token_lifetime);
+
+        match token.seal(
+            &server_configuration.address_validation_token_key,
+            self.client_address,
+        ) {
+            Ok(sealed_token) => Some(sealed_token),
+            Err(error) => {
+                warn!(
+                    "failed to seal new token for client {:?}: {}",
+                    self.client_address, error
+                );
+                None
+            }
+        }
+    }
+
+    fn on_new_token(&self, _token: Bytes) -> Result<()> {
+        bail!(ErrorKind::UnexpectedNewTokenFrame)
+    }
 }
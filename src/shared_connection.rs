@@ -2,7 +2,7 @@ use errors::*;
 use futures::future;
 use futures::Future;
 use std::sync::Arc;
-use {Connection, DataStream, Perspective, StreamMapEntry};
+use {Connection, Perspective};
 
 pub trait SharedConnection<P> {
     fn handshake(self) -> Box<Future<Item = (), Error = Error> + Send>;
@@ -13,16 +13,12 @@ where
     P::TlsSession: 'static,
 {
     fn handshake(self) -> Box<Future<Item = (), Error = Error> + Send> {
-        let (stream_id, stream_map_entry) = self.crypto_stream();
-        match stream_map_entry {
-            StreamMapEntry::Dead => {
-                Box::new(future::err(ErrorKind::CryptoStreamAlreadyClosed.into()))
-            }
-            StreamMapEntry::Live(stream_state) => {
-                let crypto_stream = DataStream::new(stream_id, self.clone(), stream_state);
-
-                Box::new((*self).handshake(crypto_stream))
-            }
+        // a client's TLS session is started straight away, so the 0-RTT keys are installed
+        // before any 0-RTT data is written
+        if let Err(error) = self.process_crypto_data() {
+            return Box::new(future::err(error));
         }
+
+        Box::new(future::poll_fn(move || self.poll_handshake()))
     }
 }
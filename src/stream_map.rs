@@ -103,6 +103,31 @@ impl StreamMap {
         stream_map_entry.clone()
     }
 
+    /// Gets the stream an incoming frame is for, opening the stream if this is the first frame
+    /// for it.
+    ///
+    /// # Returns
+    /// The `StreamMapEntry` and whether the stream was newly opened.
+    pub fn get_or_open_incoming_stream(
+        &mut self,
+        stream_id: StreamId,
+        initial_max_incoming_data: u64,
+    ) -> (StreamMapEntry, bool) {
+        if let Some(stream_map_entry) = self.streams.get(&stream_id) {
+            return (stream_map_entry.clone(), false);
+        }
+
+        let state = Arc::new(Mutex::new(StreamState::new(
+            stream_id,
+            Some(initial_max_incoming_data),
+            None,
+        )));
+        let stream_map_entry = StreamMapEntry::Live(state);
+        self.streams.insert(stream_id, stream_map_entry.clone());
+
+        (stream_map_entry, true)
+    }
+
     pub fn forget_stream(&mut self, stream_id: StreamId) -> Result<StreamMapEntry> {
         let stream_map_entry = self.streams
             .get_mut(&stream_id)
@@ -26,6 +26,8 @@ pub struct StreamState {
     has_all_outgoing_data: bool,
     incoming_flow_control: Option<FlowControl>,
     outgoing_flow_control: Option<FlowControl>,
+    /// Whether any incoming data arrived in 0-RTT packets.
+    early_data: bool,
 }
 
 impl StreamState {
@@ -42,6 +44,7 @@ impl StreamState {
             has_all_outgoing_data: false,
             incoming_flow_control: initial_max_incoming_data.map(FlowControl::with_initial_max),
             outgoing_flow_control: initial_max_outgoing_data.map(FlowControl::with_initial_max),
+            early_data: false,
         }
     }
 
@@ -49,6 +52,15 @@ impl StreamState {
         self.stream_id
     }
 
+    /// Whether any of the incoming data arrived in 0-RTT packets, which may have been replayed.
+    pub fn is_early_data(&self) -> bool {
+        self.early_data
+    }
+
+    pub fn on_early_data_received(&mut self) {
+        self.early_data = true;
+    }
+
     pub fn enqueue_write(
         &mut self,
         buf: Bytes,
@@ -94,6 +106,38 @@ impl StreamState {
         }
     }
 
+    /// Queues data received from the remote endpoint so it may be read.
+    pub fn enqueue_read(
+        &mut self,
+        offset: StreamOffset,
+        data: Bytes,
+        finished: bool,
+    ) -> Result<()> {
+        let end_offset = u64::from(offset) + data.len().value_as::<u64>().unwrap();
+
+        if let Some(incoming_flow_control) = &self.incoming_flow_control {
+            if end_offset > incoming_flow_control.max() {
+                bail!(ErrorKind::StreamFlowControlLimitExceeded(self.stream_id));
+            }
+        }
+
+        self.incoming_data.insert_chunk(offset.into(), finished, data);
+
+        Ok(())
+    }
+
+    /// Advances how much data may be sent on this stream.
+    ///
+    /// # Returns
+    /// Whether the maximum was advanced.
+    pub fn advance_max_outgoing_data(&mut self, max: u64) -> bool {
+        self.outgoing_flow_control
+            .as_mut()
+            .map_or(false, |outgoing_flow_control| {
+                outgoing_flow_control.advance_max(max)
+            })
+    }
+
     pub fn poll_read(&mut self, buf: &mut [u8]) -> Async<usize> {
         if buf.is_empty() || self.incoming_data.is_finished() {
             return 0.into();
@@ -1,29 +1,63 @@
 extern crate futures;
 extern crate lz_quic;
+extern crate rustls;
 extern crate tokio_core;
+extern crate tokio_io;
 
-use futures::future;
 use futures::sync::oneshot;
-use futures::Future;
-use lz_quic::{Client, ClientConfiguration, Error as QuicError, Server, ServerConfiguration,
-              ServerId};
+use futures::{Future, Stream};
+use lz_quic::{Client, ClientConfiguration, EarlyData, ErrorCode, ErrorKind, InMemorySessionStore,
+              ReplayWindow, Server, ServerConfiguration, ServerId, SessionStore};
+use rustls::internal::pemfile::{certs, rsa_private_keys};
+use rustls::{ClientConfig, NoClientAuth, ServerConfig};
+use std::io::Write;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{mpsc, Arc};
 use std::thread;
+use std::time::Duration;
 use tokio_core::reactor::Core;
+use tokio_io::io::{read_exact, write_all};
+
+fn server_configuration() -> ServerConfiguration {
+    let certificate_chain = certs(&mut &include_bytes!("certs/localhost.pem")[..])
+        .expect("error reading the server certificate");
+    let mut private_keys = rsa_private_keys(&mut &include_bytes!("certs/localhost.key")[..])
+        .expect("error reading the server private key");
+
+    let mut tls_config = ServerConfig::new(NoClientAuth::new());
+    tls_config.set_single_cert(certificate_chain, private_keys.remove(0));
+
+    ServerConfiguration {
+        tls_config: Arc::new(tls_config),
+        ..ServerConfiguration::default()
+    }
+}
+
+fn client_configuration() -> ClientConfiguration {
+    let mut tls_config = ClientConfig::new();
+    tls_config
+        .root_store
+        .add_pem_file(&mut &include_bytes!("certs/ca.pem")[..])
+        .expect("error adding the test certificate authority");
+
+    ClientConfiguration {
+        tls_config: Arc::new(tls_config),
+        ..ClientConfiguration::default()
+    }
+}
 
 #[test]
-#[ignore]
 pub fn client_connecting_to_server() {
-    let any_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+    let localhost_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
 
     let (server_bound, when_server_bound) = oneshot::channel();
 
-    let server_future = future::lazy(move || {
+    let server_thread = thread::spawn(move || {
         let mut server_event_loop = Core::new().expect("error creating server event loop");
 
         let server = Server::bind(
-            any_address,
-            ServerConfiguration::default(),
+            localhost_address,
+            server_configuration(),
             &server_event_loop.handle(),
         ).expect("error binding server to IP/Port");
 
@@ -31,31 +65,243 @@ pub fn client_connecting_to_server() {
             .send(server.local_addr().expect("failed to get server address"))
             .expect("error marking server as bound");
 
-        let server_future = future::ok::<_, QuicError>(());
-        server_event_loop.run(server_future)
+        let when_client_accepted = server
+            .incoming()
+            .into_future()
+            .map(|(remote_client, _)| remote_client.expect("the server should accept a client"))
+            .map_err(|(error, _)| error);
+
+        server_event_loop
+            .run(when_client_accepted)
+            .expect("error accepting client");
     });
 
-    let when_client_connected = when_server_bound.then(move |result| {
-        let server_addr = result.expect("binding the server should never get cancelled");
+    let server_addr = when_server_bound
+        .wait()
+        .expect("binding the server should never get cancelled");
+
+    let mut client_event_loop = Core::new().expect("error creating client event loop");
+
+    let server_id = ServerId::new("localhost".to_owned(), server_addr.port());
 
-        let mut client_event_loop = Core::new().expect("error creating client event loop");
+    let when_client_connected = Client::connect(
+        server_addr,
+        server_id,
+        client_configuration(),
+        &client_event_loop.handle(),
+    );
 
-        let server_id = ServerId::new("localhost".to_owned(), server_addr.port());
+    client_event_loop
+        .run(when_client_connected)
+        .expect("error connecting client to server");
 
-        let client_configuration = ClientConfiguration::default();
+    server_thread.join().expect("error occurred in server");
+}
+
+#[test]
+pub fn client_sending_early_data_to_server() {
+    let localhost_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+
+    let (server_bound, when_server_bound) = oneshot::channel();
+
+    let server_thread = thread::spawn(move || {
+        let mut server_event_loop = Core::new().expect("error creating server event loop");
 
-        let when_client_connected = Client::connect(
-            server_addr,
-            server_id,
-            client_configuration,
-            &client_event_loop.handle(),
-        ).map(|client| ());
+        let server_configuration = ServerConfiguration {
+            accept_early_data: true,
+            anti_replay: Arc::new(ReplayWindow::new(Duration::from_secs(60), 16)),
+            ..server_configuration()
+        };
 
-        client_event_loop.run(when_client_connected)
+        let server = Server::bind(
+            localhost_address,
+            server_configuration,
+            &server_event_loop.handle(),
+        ).expect("error binding server to IP/Port");
+
+        server_bound
+            .send(server.local_addr().expect("failed to get server address"))
+            .expect("error marking server as bound");
+
+        // each client opens a stream whose first five bytes are echoed back
+        let when_clients_served = server
+            .incoming()
+            .take(2)
+            .map_err(|error| error.to_string())
+            .and_then(|remote_client| {
+                let early_data = remote_client.early_data();
+
+                remote_client
+                    .incoming_streams()
+                    .into_future()
+                    .map_err(|(error, _)| error.to_string())
+                    .and_then(move |(stream, _)| {
+                        let stream = stream.expect("the client should open a stream");
+                        let is_early_data = stream.is_early_data();
+
+                        read_exact(stream, [0; 5])
+                            .and_then(|(stream, data)| write_all(stream, data))
+                            .map(move |(_, data)| (early_data, is_early_data, data))
+                            .map_err(|error| error.to_string())
+                    })
+            })
+            .collect();
+
+        server_event_loop
+            .run(when_clients_served)
+            .expect("error serving clients")
     });
 
-    server_future
-        .join(when_client_connected)
+    let server_addr = when_server_bound
         .wait()
-        .expect("error occurred in server or client");
+        .expect("binding the server should never get cancelled");
+
+    let mut client_event_loop = Core::new().expect("error creating client event loop");
+
+    let server_id = ServerId::new("localhost".to_owned(), server_addr.port());
+    let session_store: Arc<SessionStore> = Arc::new(InMemorySessionStore::new());
+    let client_configuration = || ClientConfiguration {
+        session_store: Some(session_store.clone()),
+        ..client_configuration()
+    };
+
+    // the first connection is given the ticket the second one sends 0-RTT data with
+    let when_echoed = Client::connect(
+        server_addr,
+        server_id.clone(),
+        client_configuration(),
+        &client_event_loop.handle(),
+    ).map_err(|error| error.to_string())
+        .and_then(|client| {
+            write_all(client.open_bidirectional_stream(), *b"hello")
+                .and_then(|(stream, _)| read_exact(stream, [0; 5]))
+                .map(move |(_, echo)| (client, echo))
+                .map_err(|error| error.to_string())
+        });
+
+    let (client, echo) = client_event_loop
+        .run(when_echoed)
+        .expect("error exchanging data with server");
+
+    assert_eq!(&echo, b"hello");
+    assert_eq!(client.early_data(), EarlyData::NotAttempted);
+
+    let (early_streams, when_early_stream) = mpsc::channel();
+
+    let when_client_connected = Client::connect_with_early_data(
+        server_addr,
+        server_id,
+        client_configuration(),
+        &client_event_loop.handle(),
+        move |client| {
+            let mut stream = client.open_bidirectional_stream();
+            stream
+                .write_all(b"early")
+                .expect("error writing early data");
+
+            early_streams
+                .send(stream)
+                .expect("error keeping the early data stream");
+        },
+    );
+
+    let client = client_event_loop
+        .run(when_client_connected)
+        .expect("error connecting client to server");
+
+    let stream = when_early_stream
+        .recv()
+        .expect("the early data stream should have been opened");
+    let (_, echo) = client_event_loop
+        .run(read_exact(stream, [0; 5]))
+        .expect("error reading echo of early data");
+
+    assert_eq!(&echo, b"early");
+    assert_eq!(client.early_data(), EarlyData::Accepted);
+
+    let served_clients = server_thread.join().expect("error occurred in server");
+
+    assert_eq!(
+        served_clients,
+        vec![
+            (EarlyData::NotAttempted, false, *b"hello"),
+            (EarlyData::Accepted, true, *b"early"),
+        ]
+    );
+}
+
+#[test]
+pub fn client_offering_unsupported_application_protocol_is_closed() {
+    let localhost_address = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), 0);
+
+    let (server_bound, when_server_bound) = oneshot::channel();
+    let (client_finished, when_client_finished) = oneshot::channel::<()>();
+
+    let server_thread = thread::spawn(move || {
+        let mut server_event_loop = Core::new().expect("error creating server event loop");
+
+        let server_configuration = ServerConfiguration {
+            alpn_protocols: vec!["hq".to_owned()],
+            ..server_configuration()
+        };
+
+        let server = Server::bind(
+            localhost_address,
+            server_configuration,
+            &server_event_loop.handle(),
+        ).expect("error binding server to IP/Port");
+
+        server_bound
+            .send(server.local_addr().expect("failed to get server address"))
+            .expect("error marking server as bound");
+
+        server_event_loop.handle().spawn(
+            server
+                .incoming()
+                .for_each(|_| -> Result<(), _> { panic!("the server should reject the client") })
+                .map_err(|error| panic!("error accepting clients: {}", error)),
+        );
+
+        server_event_loop
+            .run(when_client_finished)
+            .expect("the client should never get cancelled");
+    });
+
+    let server_addr = when_server_bound
+        .wait()
+        .expect("binding the server should never get cancelled");
+
+    let mut client_event_loop = Core::new().expect("error creating client event loop");
+
+    let server_id = ServerId::new("localhost".to_owned(), server_addr.port());
+    let client_configuration = ClientConfiguration {
+        alpn_protocols: vec!["h3".to_owned()],
+        ..client_configuration()
+    };
+
+    let when_client_connected = Client::connect(
+        server_addr,
+        server_id,
+        client_configuration,
+        &client_event_loop.handle(),
+    );
+
+    let error = client_event_loop
+        .run(when_client_connected)
+        .err()
+        .expect("the server should reject the client");
+
+    // the server closes the connection with the no_application_protocol alert
+    match *error.kind() {
+        ErrorKind::ConnectionClosedByPeer(
+            ErrorCode::CryptoError(ErrorCode::NO_APPLICATION_PROTOCOL_ALERT),
+            _,
+        ) => {}
+        _ => panic!("unexpected error: {}", error),
+    }
+
+    client_finished
+        .send(())
+        .expect("error marking client as finished");
+    server_thread.join().expect("error occurred in server");
 }